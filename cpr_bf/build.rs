@@ -0,0 +1,23 @@
+fn main() {
+    #[cfg(feature = "capi")]
+    generate_header();
+}
+
+/// Generates the C header of the `capi` module. Only that module is parsed,
+/// as the rest of the crate does not declare anything that is exposed to C
+#[cfg(feature = "capi")]
+fn generate_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("Cargo sets the manifest dir");
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("Failed to read cbindgen.toml");
+
+    println!("cargo::rerun-if-changed=src/capi.rs");
+    println!("cargo::rerun-if-changed=cbindgen.toml");
+
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(format!("{}/src/capi.rs", crate_dir))
+        .generate()
+        .expect("Failed to generate the C header")
+        .write_to_file(format!("{}/include/cpr_bf.h", crate_dir));
+}
@@ -0,0 +1,105 @@
+#ifndef CPR_BF_H
+#define CPR_BF_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The result of a call into the C bindings
+typedef enum BfStatus {
+  // The call succeeded
+  BF_STATUS_OK = 0,
+  // A required pointer argument was null
+  BF_STATUS_NULL_POINTER = 1,
+  // The source code was not valid UTF-8
+  BF_STATUS_INVALID_UTF8 = 2,
+  // The program failed to parse or run, see [`bf_vm_last_error`]
+  BF_STATUS_ERROR = 3,
+} BfStatus;
+
+// A Brainfuck VM created with [`bf_vm_new`]
+typedef struct BfVm BfVm;
+
+// Reads a single byte of input for the program. Returns the byte as a value from
+// 0 to 255, or a negative value at the end of the input
+typedef int (*BfReadFn)(void *user_data);
+
+// Writes `len` bytes of output of the program, starting at `data`.
+// Returns zero on success, or any other value if the output could not be written
+typedef int (*BfWriteFn)(void *user_data, const uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a VM that reads input through `read` and writes output through `write`,
+// passing `user_data` to both. Either callback may be null, in which case the
+// input is always empty, or the output is discarded.
+//
+// The returned VM is never null, and must be freed with [`bf_vm_free`]
+//
+// # Safety
+//
+// The callbacks must be safe to call with `user_data` for as long as the VM exists
+struct BfVm *bf_vm_new(BfReadFn read, BfWriteFn write, void *user_data);
+
+// Frees a VM created with [`bf_vm_new`]. Does nothing if `vm` is null
+//
+// # Safety
+//
+// `vm` must be null or a VM returned by [`bf_vm_new`] that has not been freed yet
+void bf_vm_free(struct BfVm *vm);
+
+// Parses and runs the null-terminated Brainfuck source code `code`.
+//
+// The tape is kept between runs. If the program fails, a description of the
+// error is available through [`bf_vm_last_error`]
+//
+// # Safety
+//
+// `vm` must be a valid VM, and `code` a valid null-terminated string
+enum BfStatus bf_vm_run_string(struct BfVm *vm, const char *code);
+
+// The description of the error of the last failed run, as a null-terminated string.
+// Returns null if the last run succeeded, or if no program has been run yet.
+//
+// The string is owned by the VM, and valid until the next run or until the VM is freed
+//
+// # Safety
+//
+// `vm` must be a valid VM
+const char *bf_vm_last_error(const struct BfVm *vm);
+
+// The cells of the tape. The tape holds [`bf_vm_tape_len`] cells, and is valid
+// until the next run or until the VM is freed. Returns null if `vm` is null
+//
+// # Safety
+//
+// `vm` must be a valid VM
+const uint8_t *bf_vm_tape(const struct BfVm *vm);
+
+// The amount of cells of the tape, see [`bf_vm_tape`]. Returns zero if `vm` is null
+//
+// # Safety
+//
+// `vm` must be a valid VM
+size_t bf_vm_tape_len(const struct BfVm *vm);
+
+// The index of the cell of the tape the data pointer points to.
+// This cell might be beyond the end of the tape, if it has not been allocated yet.
+// Returns zero if `vm` is null
+//
+// # Safety
+//
+// `vm` must be a valid VM
+size_t bf_vm_data_ptr(const struct BfVm *vm);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CPR_BF_H */
@@ -1,23 +1,88 @@
 //! Contains various default memory allocators for the Brainfuck Virtual Machine trait
 
-use crate::{BrainfuckAllocator, BrainfuckCell, OutOfBoundsAccess, VMMemoryError};
+use crate::{
+    tape::Tape, BrainfuckAllocator, BrainfuckCell, BrainfuckExecutionError, OutOfBoundsAccess,
+    VMMemoryError,
+};
 
 /// A dynamically allocating Brainfuck allocator.
 /// If accessing an unallocated cell is attempted,
 /// the VM memory is expanded to be abble to support that cell.
+#[derive(Clone, Copy, Debug, Default)]
 pub struct DynamicAllocator;
 
 impl BrainfuckAllocator for DynamicAllocator {
-    fn ensure_capacity<T: BrainfuckCell>(
-        data: &mut Vec<T>,
+    fn ensure_capacity<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
         min_size: usize,
     ) -> Result<(), VMMemoryError> {
         log::trace!("ensure_capacity {} in DynamicAllocator", min_size);
 
         // Ensure we allocate the required amount of memory
-        if data.len() < min_size {
+        if tape.len() < min_size {
             log::trace!("Expanding amount of cells to {}", min_size);
-            data.resize(min_size, T::default());
+            tape.ensure(min_size)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A dynamically allocating Brainfuck allocator that grows the VM memory
+/// in pages of a fixed amount of cells, instead of to exactly the required size.
+/// This avoids frequent reallocation for programs that sweep the data pointer
+/// to the right.
+///
+/// The default page size is 4096 cells. Other page sizes are chosen by passing the
+/// allocator to the VM, e.g.
+/// `VMBuilder::new().with_allocator_instance(PagedAllocator::new(1024))`
+#[derive(Clone, Copy, Debug)]
+pub struct PagedAllocator {
+    page_size: usize,
+}
+
+impl PagedAllocator {
+    /// The page size of [`PagedAllocator::default`]
+    pub const DEFAULT_PAGE_SIZE: usize = 4096;
+
+    /// An allocator growing the memory in pages of `page_size` cells.
+    /// A page size of zero is treated as a page size of one
+    pub fn new(page_size: usize) -> Self {
+        PagedAllocator {
+            page_size: page_size.max(1),
+        }
+    }
+
+    /// The amount of cells the memory grows by at once
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+}
+
+impl Default for PagedAllocator {
+    fn default() -> Self {
+        PagedAllocator::new(PagedAllocator::DEFAULT_PAGE_SIZE)
+    }
+}
+
+impl BrainfuckAllocator for PagedAllocator {
+    fn ensure_capacity<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
+        min_size: usize,
+    ) -> Result<(), VMMemoryError> {
+        log::trace!(
+            "ensure_capacity {} in PagedAllocator with page size {}",
+            min_size,
+            self.page_size
+        );
+
+        if tape.len() < min_size {
+            let new_size = min_size.div_ceil(self.page_size) * self.page_size;
+
+            log::trace!("Expanding amount of cells to {}", new_size);
+            tape.ensure(new_size)?;
         }
 
         Ok(())
@@ -28,11 +93,13 @@ impl BrainfuckAllocator for DynamicAllocator {
 /// that checks whether the attempted access
 /// lies within the bounds of the currently available memory.
 /// If not, it returns an error
+#[derive(Clone, Copy, Debug, Default)]
 pub struct BoundsCheckingStaticAllocator;
 
 impl BrainfuckAllocator for BoundsCheckingStaticAllocator {
-    fn ensure_capacity<T: BrainfuckCell>(
-        data: &mut Vec<T>,
+    fn ensure_capacity<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
         min_size: usize,
     ) -> Result<(), VMMemoryError> {
         log::trace!(
@@ -40,15 +107,15 @@ impl BrainfuckAllocator for BoundsCheckingStaticAllocator {
             min_size
         );
 
-        if min_size > data.len() {
+        if min_size > tape.len() {
             log::info!(
                 "Detected possible out-of-bounds access at index {} (current capacity: {})",
                 min_size - 1,
-                data.len()
+                tape.len()
             );
 
             Err(VMMemoryError::OutOfBounds(OutOfBoundsAccess {
-                capacity: data.len(),
+                capacity: tape.len(),
                 access: min_size,
             }))
         } else {
@@ -58,16 +125,18 @@ impl BrainfuckAllocator for BoundsCheckingStaticAllocator {
 }
 
 /// A non-allocating Brainfuck allocator that does not do any checking.
-/// Any Brainfuck program that accesses cells beyond the preallocated
-/// memory will lead to undefined behaviour.
+/// Any Brainfuck program that writes to cells beyond the preallocated
+/// memory makes the tape panic, see [`Tape::set`].
 ///
-/// This allocator is unsafe. Use [`BoundsCheckingStaticAllocator`] instead,
-/// unless the input program is known to be safe.
+/// Use [`BoundsCheckingStaticAllocator`] instead,
+/// unless the input program is known to stay within the preallocated memory.
+#[derive(Clone, Copy, Debug, Default)]
 pub struct StaticAllocator;
 
 impl BrainfuckAllocator for StaticAllocator {
-    fn ensure_capacity<T: BrainfuckCell>(
-        _: &mut Vec<T>,
+    fn ensure_capacity<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        _: &mut D,
         min_size: usize,
     ) -> Result<(), VMMemoryError> {
         log::trace!("ensure_capacity {} in StaticAllocator", min_size);
@@ -75,3 +144,84 @@ impl BrainfuckAllocator for StaticAllocator {
         Ok(())
     }
 }
+
+/// A non-allocating Brainfuck allocator for a tape of fixed size,
+/// on which the data pointer wraps around at both ends.
+/// Moving left of the first cell lands on the last preallocated cell,
+/// and moving right of the last cell lands on the first one.
+///
+/// Like [`BoundsCheckingStaticAllocator`], accesses outside of the preallocated
+/// memory return an error. This can only happen if no cells were preallocated
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WrappingStaticAllocator;
+
+impl BrainfuckAllocator for WrappingStaticAllocator {
+    fn ensure_capacity<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
+        min_size: usize,
+    ) -> Result<(), VMMemoryError> {
+        log::trace!("ensure_capacity {} in WrappingStaticAllocator", min_size);
+
+        BoundsCheckingStaticAllocator.ensure_capacity(tape, min_size)
+    }
+
+    fn move_data_ptr<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
+        data_ptr: usize,
+        amount: isize,
+    ) -> Result<usize, BrainfuckExecutionError> {
+        if tape.is_empty() {
+            log::warn!("Attempted to move the data pointer on an empty wrapping tape");
+            return Ok(0);
+        }
+
+        let len = tape.len() as i128;
+        let new_ptr = (data_ptr as i128 + amount as i128).rem_euclid(len);
+
+        Ok(new_ptr as usize)
+    }
+}
+
+/// A dynamically allocating Brainfuck allocator for a tape that is infinite
+/// in both directions. Like [`DynamicAllocator`], the tape grows to the right
+/// when needed. Additionally, moving the data pointer left of the first cell
+/// inserts new cells at the front of the tape, instead of resulting in
+/// a [`BrainfuckExecutionError::DataPointerUnderflow`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DoublyInfiniteAllocator;
+
+impl BrainfuckAllocator for DoublyInfiniteAllocator {
+    fn ensure_capacity<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
+        min_size: usize,
+    ) -> Result<(), VMMemoryError> {
+        log::trace!("ensure_capacity {} in DoublyInfiniteAllocator", min_size);
+
+        DynamicAllocator.ensure_capacity(tape, min_size)
+    }
+
+    fn move_data_ptr<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
+        data_ptr: usize,
+        amount: isize,
+    ) -> Result<usize, BrainfuckExecutionError> {
+        if amount >= 0 || amount.unsigned_abs() <= data_ptr {
+            return DynamicAllocator.move_data_ptr(tape, data_ptr, amount);
+        }
+
+        // Grow by at least the current size of the tape, so that programs
+        // sweeping to the left do not copy the whole tape on every move
+        let needed = amount.unsigned_abs() - data_ptr;
+        let grow_by = needed.max(tape.len());
+
+        log::trace!("Extending tape to the left by {} cells", grow_by);
+
+        tape.prepend(grow_by)?;
+
+        Ok(grow_by - needed)
+    }
+}
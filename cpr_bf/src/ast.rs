@@ -0,0 +1,159 @@
+//! Contains a tree representation of Brainfuck programs
+//!
+//! Where a [`Program`] is a flat list of instructions, its abstract syntax tree
+//! nests the body of every loop inside a [`Node::Loop`], and the body of every
+//! procedure of [`crate::extensions::ExtensionSet::PBRAIN`] inside a [`Node::Procedure`].
+//! This makes it easier to write analyses, optimizers and transpilers on top of this crate.
+//!
+//! ```
+//! use cpr_bf::{ast::Node, Program};
+//!
+//! let ast = Program::from("+[->+<]").to_ast().unwrap();
+//!
+//! assert_eq!(
+//!     ast,
+//!     Node::Block(vec![
+//!         Node::Incr,
+//!         Node::Loop(vec![Node::Decr, Node::IncrDP, Node::Incr, Node::DecrDP]),
+//!     ])
+//! );
+//! ```
+
+use alloc::{vec, vec::Vec};
+
+use crate::{Instruction, MissingKind, ParseError, Program, UnmatchedJump};
+
+/// A single node of the abstract syntax tree of a Brainfuck program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    /// A sequence of nodes, executed in order. The root of every tree
+    Block(Vec<Node>),
+
+    /// A loop, executing its body as long as the currently pointed-to cell is not zero
+    Loop(Vec<Node>),
+
+    /// See [`Instruction::IncrDP`]
+    IncrDP,
+
+    /// See [`Instruction::DecrDP`]
+    DecrDP,
+
+    /// See [`Instruction::Incr`]
+    Incr,
+
+    /// See [`Instruction::Decr`]
+    Decr,
+
+    /// See [`Instruction::Output`]
+    Output,
+
+    /// See [`Instruction::Input`]
+    Input,
+
+    /// The definition of a procedure with the given body, see [`Instruction::ProcStart`]
+    Procedure(Vec<Node>),
+
+    /// See [`Instruction::ProcCall`]
+    Call,
+
+    /// See [`Instruction::Fork`]
+    Fork,
+
+    /// Any of the instructions of [`crate::extensions::ExtensionSet::EXTENDED_TYPE_I`]
+    TypeI(Instruction),
+
+    /// See [`Instruction::HostCall`]
+    HostCall,
+
+    /// See [`Instruction::Random`]
+    Random,
+}
+
+impl Node {
+    /// The child nodes of this node. Empty for leaf nodes
+    pub fn children(&self) -> &[Node] {
+        match self {
+            Node::Block(children) | Node::Loop(children) | Node::Procedure(children) => children,
+            _ => &[],
+        }
+    }
+
+    /// The maximum loop nesting depth within this node
+    pub fn depth(&self) -> usize {
+        let max_child_depth = self.children().iter().map(Node::depth).max().unwrap_or(0);
+
+        match self {
+            Node::Loop(_) => max_child_depth + 1,
+            _ => max_child_depth,
+        }
+    }
+
+    /// Builds the abstract syntax tree of `program`. The returned
+    /// node is always a [`Node::Block`].
+    ///
+    /// Returns a [`ParseError`] if the jump instructions in `program` are mismatched
+    pub fn from_program(program: &Program) -> Result<Node, ParseError> {
+        let mut stack: Vec<Vec<Node>> = vec![Vec::new()];
+        let mut open: Vec<Instruction> = Vec::new();
+
+        for &instr in program.instructions() {
+            let node = match instr {
+                Instruction::IncrDP => Node::IncrDP,
+                Instruction::DecrDP => Node::DecrDP,
+                Instruction::Incr => Node::Incr,
+                Instruction::Decr => Node::Decr,
+                Instruction::Output => Node::Output,
+                Instruction::Input => Node::Input,
+                Instruction::ProcCall => Node::Call,
+                Instruction::Fork => Node::Fork,
+                Instruction::HostCall => Node::HostCall,
+                Instruction::Random => Node::Random,
+                Instruction::Exit
+                | Instruction::Store
+                | Instruction::Load
+                | Instruction::ShiftRight
+                | Instruction::ShiftLeft
+                | Instruction::Not
+                | Instruction::Xor
+                | Instruction::And
+                | Instruction::Or => Node::TypeI(instr),
+                Instruction::JumpFwd | Instruction::ProcStart => {
+                    stack.push(Vec::new());
+                    open.push(instr);
+                    continue;
+                }
+                Instruction::JumpBack => {
+                    if open.pop() != Some(Instruction::JumpFwd) {
+                        return Err(unmatched(MissingKind::JumpFwd));
+                    }
+
+                    Node::Loop(stack.pop().unwrap_or_default())
+                }
+                Instruction::ProcEnd => {
+                    if open.pop() != Some(Instruction::ProcStart) {
+                        return Err(unmatched(MissingKind::ProcStart));
+                    }
+
+                    Node::Procedure(stack.pop().unwrap_or_default())
+                }
+            };
+
+            if let Some(current) = stack.last_mut() {
+                current.push(node);
+            }
+        }
+
+        match open.first() {
+            None => Ok(Node::Block(stack.pop().unwrap_or_default())),
+            Some(Instruction::ProcStart) => Err(unmatched(MissingKind::ProcEnd)),
+            Some(_) => Err(unmatched(MissingKind::JumpBack)),
+        }
+    }
+}
+
+fn unmatched(missing: MissingKind) -> ParseError {
+    ParseError::JumpMismatch(UnmatchedJump {
+        missing,
+        position: None,
+    })
+}
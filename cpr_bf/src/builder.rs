@@ -0,0 +1,167 @@
+//! Contains a builder for constructing Brainfuck programs in code
+//!
+//! Programs that are generated, for example by a compiler targeting Brainfuck,
+//! can be built one instruction at a time with a [`ProgramBuilder`], instead of
+//! concatenating and then parsing source code. Jump instructions are checked
+//! for mismatches when the [`Program`] is built.
+//!
+//! ```
+//! use cpr_bf::{builder::ProgramBuilder, BrainfuckVM};
+//!
+//! // Sets the first cell to 8 * 8 + 1, and prints it
+//! let program = ProgramBuilder::new()
+//!     .add(8)
+//!     .loop_start()
+//!     .move_ptr(1)
+//!     .add(8)
+//!     .move_ptr(-1)
+//!     .add(-1)
+//!     .loop_end()
+//!     .move_ptr(1)
+//!     .add(1)
+//!     .emit_output()
+//!     .build()
+//!     .unwrap();
+//!
+//! let mut vm = cpr_bf::VMBuilder::new().with_writer(Vec::new()).build_typed();
+//! vm.run_program(&program).unwrap();
+//!
+//! assert_eq!(vm.into_writer(), b"A");
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::{Instruction, MissingKind, ParseError, Program, UnmatchedJump};
+
+/// Builds a [`Program`] one instruction at a time, see the [module documentation](self)
+#[derive(Clone, Debug, Default)]
+pub struct ProgramBuilder {
+    instructions: Vec<Instruction>,
+    breakpoints: Vec<usize>,
+}
+
+impl ProgramBuilder {
+    /// Creates a builder for an empty program
+    pub fn new() -> Self {
+        ProgramBuilder::default()
+    }
+
+    /// The instructions added so far
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Appends a single instruction, including the instructions of extensions.
+    /// Opening and closing jump instructions are matched when the program is built
+    pub fn instruction(&mut self, instr: Instruction) -> &mut Self {
+        self.instructions.push(instr);
+        self
+    }
+
+    /// Adds `amount` to the currently pointed-to cell, emitting `+` for positive
+    /// amounts and `-` for negative amounts
+    pub fn add(&mut self, amount: isize) -> &mut Self {
+        let instr = if amount < 0 {
+            Instruction::Decr
+        } else {
+            Instruction::Incr
+        };
+
+        self.repeat(instr, amount.unsigned_abs())
+    }
+
+    /// Moves the data pointer by `amount` cells, emitting `>` for positive
+    /// amounts and `<` for negative amounts
+    pub fn move_ptr(&mut self, amount: isize) -> &mut Self {
+        let instr = if amount < 0 {
+            Instruction::DecrDP
+        } else {
+            Instruction::IncrDP
+        };
+
+        self.repeat(instr, amount.unsigned_abs())
+    }
+
+    /// Starts a loop, emitting `[`
+    pub fn loop_start(&mut self) -> &mut Self {
+        self.instruction(Instruction::JumpFwd)
+    }
+
+    /// Ends the innermost loop, emitting `]`
+    pub fn loop_end(&mut self) -> &mut Self {
+        self.instruction(Instruction::JumpBack)
+    }
+
+    /// Writes the currently pointed-to cell, emitting `.`
+    pub fn emit_output(&mut self) -> &mut Self {
+        self.instruction(Instruction::Output)
+    }
+
+    /// Reads into the currently pointed-to cell, emitting `,`
+    pub fn emit_input(&mut self) -> &mut Self {
+        self.instruction(Instruction::Input)
+    }
+
+    /// Sets a breakpoint before the next instruction, like `#` in source code.
+    /// See [`Program::breakpoints`]
+    pub fn breakpoint(&mut self) -> &mut Self {
+        if self.breakpoints.last() != Some(&self.instructions.len()) {
+            self.breakpoints.push(self.instructions.len());
+        }
+
+        self
+    }
+
+    /// Builds the program from the instructions added so far. Returns a
+    /// [`ParseError::JumpMismatch`] without a position if any jump instructions
+    /// are mismatched
+    ///
+    /// ```
+    /// use cpr_bf::{builder::ProgramBuilder, MissingKind, ParseError};
+    ///
+    /// let Err(ParseError::JumpMismatch(unmatched)) = ProgramBuilder::new().loop_start().build()
+    /// else {
+    ///     panic!("Expected a jump mismatch");
+    /// };
+    ///
+    /// assert!(matches!(unmatched.missing, MissingKind::JumpBack));
+    /// ```
+    pub fn build(&self) -> Result<Program, ParseError> {
+        let mut open_stack = Vec::new();
+
+        for &instr in &self.instructions {
+            match instr {
+                Instruction::JumpFwd | Instruction::ProcStart => open_stack.push(instr),
+                Instruction::JumpBack | Instruction::ProcEnd => {
+                    if open_stack.last().copied() != instr.opening_partner() {
+                        return Err(mismatch(instr.missing_partner()));
+                    }
+
+                    open_stack.pop();
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(&unmatched) = open_stack.first() {
+            return Err(mismatch(unmatched.missing_partner()));
+        }
+
+        Ok(Program::from_parts(
+            self.instructions.clone(),
+            self.breakpoints.clone(),
+        ))
+    }
+
+    fn repeat(&mut self, instr: Instruction, count: usize) -> &mut Self {
+        self.instructions.extend(core::iter::repeat_n(instr, count));
+        self
+    }
+}
+
+fn mismatch(missing: Option<MissingKind>) -> ParseError {
+    ParseError::JumpMismatch(UnmatchedJump {
+        missing: missing.unwrap_or(MissingKind::JumpFwd),
+        position: None,
+    })
+}
@@ -0,0 +1,463 @@
+//! Contains a compact, versioned binary format for Brainfuck programs
+//!
+//! Storing a [`Program`] in this format avoids re-parsing large
+//! Brainfuck source files, which mostly consist of comments, on every run.
+//! Repeated instructions are run-length encoded.
+//!
+//! The format consists of:
+//! - The magic bytes `BFC`, followed by a single version byte
+//! - The number of instruction runs, followed by the runs themselves.
+//!   The lower bits of the first byte of every run contain the instruction opcode,
+//!   and the remaining upper bits the length of the run. If those are zero, the length
+//!   follows as a separate number
+//! - The number of breakpoints, followed by the instruction index of each breakpoint
+//!
+//! All numbers are encoded as unsigned LEB128 variable length integers.
+//! Signed numbers are zigzag encoded first.
+//!
+//! Version 1 uses 3 bits for the opcode, which covers the eight standard instructions.
+//! Version 2 uses 5 bits, to make room for the instructions of the [`crate::extensions`].
+//! Programs are only written in version 2 if they use any extension instructions,
+//! so that other programs can still be read by older versions of this crate.
+//!
+//! ```
+//! use cpr_bf::Program;
+//!
+//! let program = Program::from("++++++++[>++++++++<-]>+.");
+//! let bytes = program.to_bytes();
+//!
+//! assert_eq!(Program::from_bytes(&bytes).unwrap().instructions(), program.instructions());
+//! ```
+//!
+//! Compiled programs have a separate format, which stores their optimized operations
+//! so that they can be run without parsing and optimizing them again. It consists of:
+//! - The magic bytes `BFO`, followed by a single version byte
+//! - The number of operations, followed by the operations themselves. Every operation
+//!   is a single opcode byte, followed by its amounts or offsets. Jump targets are not
+//!   stored, but resolved again when decoding
+//!
+//! ```
+//! use cpr_bf::{ir::{CompiledProgram, OptimizationLevel}, Program};
+//!
+//! let program = Program::from("++++++++[>++++++++<-]>+.");
+//! let compiled = CompiledProgram::compile(&program, OptimizationLevel::Full).unwrap();
+//! let bytes = compiled.to_bytes();
+//!
+//! assert_eq!(CompiledProgram::from_bytes(&bytes).unwrap().ops(), compiled.ops());
+//! ```
+
+use alloc::vec::Vec;
+use core::{fmt::Display, iter::repeat_n};
+
+use crate::{ir::CompiledProgram, ir::Op, Instruction, Program};
+
+/// The magic bytes every bytecode file starts with
+pub const MAGIC: &[u8; 3] = b"BFC";
+
+/// The latest version of the bytecode format
+pub const VERSION: u8 = 2;
+
+/// The magic bytes every compiled program starts with
+pub const COMPILED_MAGIC: &[u8; 3] = b"BFO";
+
+/// The latest version of the compiled program format
+pub const COMPILED_VERSION: u8 = 1;
+
+/// The amount of bits of a run's first byte used for the instruction opcode
+/// in the given version of the format
+fn opcode_bits(version: u8) -> u32 {
+    match version {
+        1 => 3,
+        _ => 5,
+    }
+}
+
+/// An error encountered while decoding bytecode
+#[derive(Debug)]
+pub enum BytecodeError {
+    /// The data does not start with [`MAGIC`]
+    InvalidMagic,
+
+    /// The data was written in an unsupported version of the format
+    UnsupportedVersion(u8),
+
+    /// A run has an opcode that does not correspond to any instruction
+    InvalidOpcode(u8),
+
+    /// The data ended unexpectedly
+    UnexpectedEnd,
+
+    /// A variable length integer does not fit in a [`usize`]
+    InvalidInteger,
+
+    /// There is additional data after the end of the program
+    TrailingData,
+
+    /// The jump operations of a compiled program are mismatched
+    JumpMismatch,
+
+    /// The instructions of the program do not fit in memory
+    TooLarge,
+}
+
+impl Display for BytecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BytecodeError::InvalidMagic => write!(f, "Not a Brainfuck bytecode file"),
+            BytecodeError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported bytecode version {}", v)
+            }
+            BytecodeError::InvalidOpcode(op) => write!(f, "Invalid opcode {} in bytecode", op),
+            BytecodeError::UnexpectedEnd => write!(f, "Unexpected end of bytecode"),
+            BytecodeError::InvalidInteger => write!(f, "Invalid integer in bytecode"),
+            BytecodeError::TrailingData => write!(f, "Trailing data after end of bytecode"),
+            BytecodeError::JumpMismatch => write!(f, "Mismatched jumps in compiled program"),
+            BytecodeError::TooLarge => write!(f, "Bytecode program does not fit in memory"),
+        }
+    }
+}
+
+impl core::error::Error for BytecodeError {}
+
+/// Returns whether `data` starts with the bytecode [`MAGIC`]
+pub fn is_bytecode(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encodes `program` in the bytecode format
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut runs: Vec<(Instruction, usize)> = Vec::new();
+
+    for &instr in program.instructions() {
+        match runs.last_mut() {
+            Some((last, len)) if *last == instr => *len += 1,
+            _ => runs.push((instr, 1)),
+        }
+    }
+
+    log::debug!(
+        "Encoding {} instructions as {} runs",
+        program.instructions().len(),
+        runs.len()
+    );
+
+    let version = if program.extensions().is_empty() {
+        1
+    } else {
+        VERSION
+    };
+
+    let bits = opcode_bits(version);
+    let max_short_run = (u8::MAX >> bits) as usize;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + 1 + runs.len() * 2);
+    out.extend_from_slice(MAGIC);
+    out.push(version);
+
+    write_varint(&mut out, runs.len());
+
+    for (instr, len) in runs {
+        if len <= max_short_run {
+            out.push(opcode(instr) | (len as u8) << bits);
+        } else {
+            out.push(opcode(instr));
+            write_varint(&mut out, len);
+        }
+    }
+
+    write_varint(&mut out, program.breakpoints().len());
+
+    for &breakpoint in program.breakpoints() {
+        write_varint(&mut out, breakpoint);
+    }
+
+    out
+}
+
+/// Decodes a program from the bytecode format.
+///
+/// The lengths of the runs are not trusted, so a program that does not fit in
+/// memory results in [`BytecodeError::TooLarge`]
+///
+/// ```
+/// use cpr_bf::{bytecode::BytecodeError, Program};
+///
+/// // A single run of 2^53 `>` instructions
+/// let data = b"BFC\x01\x01\x00\x80\x80\x80\x80\x80\x80\x80\x10";
+///
+/// assert!(matches!(Program::from_bytes(data), Err(BytecodeError::TooLarge)));
+/// ```
+pub fn decode(data: &[u8]) -> Result<Program, BytecodeError> {
+    let rest = data
+        .strip_prefix(MAGIC)
+        .ok_or(BytecodeError::InvalidMagic)?;
+
+    let (&version, mut rest) = rest.split_first().ok_or(BytecodeError::UnexpectedEnd)?;
+
+    if !(1..=VERSION).contains(&version) {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+
+    let bits = opcode_bits(version);
+
+    let num_runs = read_varint(&mut rest)?;
+    let mut instructions = Vec::new();
+
+    for _ in 0..num_runs {
+        let (&byte, remaining) = rest.split_first().ok_or(BytecodeError::UnexpectedEnd)?;
+        rest = remaining;
+
+        let op = byte & ((1 << bits) - 1);
+        let instr = from_opcode(op).ok_or(BytecodeError::InvalidOpcode(op))?;
+        let len = match (byte >> bits) as usize {
+            0 => read_varint(&mut rest)?,
+            short => short,
+        };
+
+        instructions
+            .try_reserve(len)
+            .map_err(|_| BytecodeError::TooLarge)?;
+        instructions.extend(repeat_n(instr, len));
+    }
+
+    let num_breakpoints = read_varint(&mut rest)?;
+    let mut breakpoints = Vec::new();
+
+    for _ in 0..num_breakpoints {
+        breakpoints.push(read_varint(&mut rest)?);
+    }
+
+    if !rest.is_empty() {
+        return Err(BytecodeError::TrailingData);
+    }
+
+    log::debug!("Decoded {} instructions", instructions.len());
+
+    Ok(Program::from_parts(instructions, breakpoints))
+}
+
+/// Encodes `program` in the compiled program format
+pub fn encode_compiled(program: &CompiledProgram) -> Vec<u8> {
+    let mut out = Vec::with_capacity(COMPILED_MAGIC.len() + 1 + program.ops().len() * 2);
+    out.extend_from_slice(COMPILED_MAGIC);
+    out.push(COMPILED_VERSION);
+
+    write_varint(&mut out, program.ops().len());
+
+    for &op in program.ops() {
+        let (opcode, args): (u8, &[isize]) = match op {
+            Op::Add(amount) => (0, &[amount]),
+            Op::Move(amount) => (1, &[amount]),
+            Op::AddAt(offset, amount) => (2, &[offset, amount]),
+            Op::Output => (3, &[]),
+            Op::Input => (4, &[]),
+            Op::SetZero => (5, &[]),
+            Op::Scan(amount) => (6, &[amount]),
+            Op::JumpFwd(_) => (7, &[]),
+            Op::JumpBack(_) => (8, &[]),
+            Op::DefineProc(_) => (9, &[]),
+            Op::EndProc => (10, &[]),
+            Op::CallProc => (11, &[]),
+            Op::Fork => (12, &[]),
+            Op::Exit => (13, &[]),
+            Op::Store => (14, &[]),
+            Op::Load => (15, &[]),
+            Op::ShiftRight => (16, &[]),
+            Op::ShiftLeft => (17, &[]),
+            Op::Not => (18, &[]),
+            Op::Xor => (19, &[]),
+            Op::And => (20, &[]),
+            Op::Or => (21, &[]),
+            Op::HostCall => (22, &[]),
+            Op::Random => (23, &[]),
+        };
+
+        out.push(opcode);
+
+        for &arg in args {
+            write_signed_varint(&mut out, arg);
+        }
+    }
+
+    out
+}
+
+/// Decodes a program from the compiled program format
+pub fn decode_compiled(data: &[u8]) -> Result<CompiledProgram, BytecodeError> {
+    let rest = data
+        .strip_prefix(COMPILED_MAGIC)
+        .ok_or(BytecodeError::InvalidMagic)?;
+
+    let (&version, mut rest) = rest.split_first().ok_or(BytecodeError::UnexpectedEnd)?;
+
+    if version != COMPILED_VERSION {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+
+    let num_ops = read_varint(&mut rest)?;
+    let mut ops = Vec::new();
+
+    for _ in 0..num_ops {
+        let (&opcode, remaining) = rest.split_first().ok_or(BytecodeError::UnexpectedEnd)?;
+        rest = remaining;
+
+        let op = match opcode {
+            0 => Op::Add(read_signed_varint(&mut rest)?),
+            1 => Op::Move(read_signed_varint(&mut rest)?),
+            2 => Op::AddAt(
+                read_signed_varint(&mut rest)?,
+                read_signed_varint(&mut rest)?,
+            ),
+            3 => Op::Output,
+            4 => Op::Input,
+            5 => Op::SetZero,
+            6 => Op::Scan(read_signed_varint(&mut rest)?),
+            7 => Op::JumpFwd(0),
+            8 => Op::JumpBack(0),
+            9 => Op::DefineProc(0),
+            10 => Op::EndProc,
+            11 => Op::CallProc,
+            12 => Op::Fork,
+            13 => Op::Exit,
+            14 => Op::Store,
+            15 => Op::Load,
+            16 => Op::ShiftRight,
+            17 => Op::ShiftLeft,
+            18 => Op::Not,
+            19 => Op::Xor,
+            20 => Op::And,
+            21 => Op::Or,
+            22 => Op::HostCall,
+            23 => Op::Random,
+            _ => return Err(BytecodeError::InvalidOpcode(opcode)),
+        };
+
+        ops.push(op);
+    }
+
+    if !rest.is_empty() {
+        return Err(BytecodeError::TrailingData);
+    }
+
+    log::debug!("Decoded {} operations", ops.len());
+
+    CompiledProgram::from_ops(ops).map_err(|_| BytecodeError::JumpMismatch)
+}
+
+fn opcode(instr: Instruction) -> u8 {
+    match instr {
+        Instruction::IncrDP => 0,
+        Instruction::DecrDP => 1,
+        Instruction::Incr => 2,
+        Instruction::Decr => 3,
+        Instruction::Output => 4,
+        Instruction::Input => 5,
+        Instruction::JumpFwd => 6,
+        Instruction::JumpBack => 7,
+        Instruction::ProcStart => 8,
+        Instruction::ProcEnd => 9,
+        Instruction::ProcCall => 10,
+        Instruction::Fork => 11,
+        Instruction::Exit => 12,
+        Instruction::Store => 13,
+        Instruction::Load => 14,
+        Instruction::ShiftRight => 15,
+        Instruction::ShiftLeft => 16,
+        Instruction::Not => 17,
+        Instruction::Xor => 18,
+        Instruction::And => 19,
+        Instruction::Or => 20,
+        Instruction::HostCall => 21,
+        Instruction::Random => 22,
+    }
+}
+
+/// Every opcode of version 1 is valid, but version 2 has room for more
+fn from_opcode(op: u8) -> Option<Instruction> {
+    match op {
+        0 => Some(Instruction::IncrDP),
+        1 => Some(Instruction::DecrDP),
+        2 => Some(Instruction::Incr),
+        3 => Some(Instruction::Decr),
+        4 => Some(Instruction::Output),
+        5 => Some(Instruction::Input),
+        6 => Some(Instruction::JumpFwd),
+        7 => Some(Instruction::JumpBack),
+        8 => Some(Instruction::ProcStart),
+        9 => Some(Instruction::ProcEnd),
+        10 => Some(Instruction::ProcCall),
+        11 => Some(Instruction::Fork),
+        12 => Some(Instruction::Exit),
+        13 => Some(Instruction::Store),
+        14 => Some(Instruction::Load),
+        15 => Some(Instruction::ShiftRight),
+        16 => Some(Instruction::ShiftLeft),
+        17 => Some(Instruction::Not),
+        18 => Some(Instruction::Xor),
+        19 => Some(Instruction::And),
+        20 => Some(Instruction::Or),
+        21 => Some(Instruction::HostCall),
+        22 => Some(Instruction::Random),
+        _ => None,
+    }
+}
+
+pub(crate) fn write_varint(out: &mut Vec<u8>, val: usize) {
+    write_u64_varint(out, val as u64);
+}
+
+pub(crate) fn read_varint(data: &mut &[u8]) -> Result<usize, BytecodeError> {
+    usize::try_from(read_u64_varint(data)?).map_err(|_| BytecodeError::InvalidInteger)
+}
+
+fn write_signed_varint(out: &mut Vec<u8>, val: isize) {
+    write_i64_varint(out, val as i64);
+}
+
+fn read_signed_varint(data: &mut &[u8]) -> Result<isize, BytecodeError> {
+    isize::try_from(read_i64_varint(data)?).map_err(|_| BytecodeError::InvalidInteger)
+}
+
+pub(crate) fn write_u64_varint(out: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        out.push((val as u8 & 0x7f) | 0x80);
+        val >>= 7;
+    }
+
+    out.push(val as u8);
+}
+
+pub(crate) fn read_u64_varint(data: &mut &[u8]) -> Result<u64, BytecodeError> {
+    let mut val: u64 = 0;
+    let mut shift = 0;
+
+    loop {
+        let (&byte, rest) = data.split_first().ok_or(BytecodeError::UnexpectedEnd)?;
+        *data = rest;
+
+        let bits = (byte & 0x7f) as u64;
+
+        if shift >= u64::BITS || (bits << shift) >> shift != bits {
+            return Err(BytecodeError::InvalidInteger);
+        }
+
+        val |= bits << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(val);
+        }
+
+        shift += 7;
+    }
+}
+
+/// Writes `val` zigzag encoded, so that small negative numbers stay short
+pub(crate) fn write_i64_varint(out: &mut Vec<u8>, val: i64) {
+    write_u64_varint(out, ((val << 1) ^ (val >> (i64::BITS - 1))) as u64);
+}
+
+pub(crate) fn read_i64_varint(data: &mut &[u8]) -> Result<i64, BytecodeError> {
+    let val = read_u64_varint(data)?;
+
+    Ok((val >> 1) as i64 ^ -((val & 1) as i64))
+}
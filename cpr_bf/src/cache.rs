@@ -0,0 +1,193 @@
+//! Contains a cache for compiled Brainfuck programs
+//!
+//! Servers and other long-running applications often run the same program many
+//! times. A [`ProgramCache`] keeps every program it compiles, keyed by a hash of its
+//! source code, so that running it again skips parsing and optimizing it. The source
+//! code is stored next to every program, so that programs whose hashes collide are
+//! never mixed up.
+//!
+//! ```
+//! use cpr_bf::{cache::ProgramCache, BrainfuckVM};
+//!
+//! let mut cache = ProgramCache::new();
+//! let mut vm = cpr_bf::VMBuilder::new().with_writer(Vec::new()).build_typed();
+//!
+//! for _ in 0..3 {
+//!     let program = cache.get_or_compile("++++++++[>++++++++<-]>+.").unwrap();
+//!     vm.reset_memory();
+//!     vm.run_compiled(program).unwrap();
+//! }
+//!
+//! assert_eq!(vm.into_writer(), b"AAA");
+//! assert_eq!((cache.hits(), cache.misses()), (2, 1));
+//! ```
+//!
+//! Compiled programs can be stored outside of the cache with
+//! [`CompiledProgram::to_bytes`], using [`ProgramCache::key`] to identify them.
+//! As the key is not unique, the source code has to be stored and compared as well.
+
+use alloc::{collections::BTreeMap, string::String};
+use core::fmt::Write;
+
+use crate::{
+    dialects::Dialect,
+    ir::{CompiledProgram, OptimizationLevel, Pipeline},
+    ParseError,
+};
+
+/// Caches compiled programs by the hash of their source code,
+/// see the [module documentation](self)
+#[derive(Debug)]
+pub struct ProgramCache {
+    dialect: Dialect,
+    pipeline: Pipeline,
+    /// The cached programs, with the source code they were compiled from
+    programs: BTreeMap<u64, (String, CompiledProgram)>,
+    hits: usize,
+    misses: usize,
+}
+
+impl ProgramCache {
+    /// Creates an empty cache, which parses regular Brainfuck source code and
+    /// compiles it with the default [`OptimizationLevel`]
+    pub fn new() -> Self {
+        ProgramCache {
+            dialect: Dialect::default(),
+            pipeline: Pipeline::from_level(OptimizationLevel::default()),
+            programs: BTreeMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Sets the dialect in which the source code of cached programs is written
+    pub fn with_dialect(self, dialect: Dialect) -> Self {
+        ProgramCache { dialect, ..self }
+    }
+
+    /// Sets the passes with which cached programs are compiled
+    pub fn with_pipeline(self, pipeline: Pipeline) -> Self {
+        ProgramCache { pipeline, ..self }
+    }
+
+    /// The key under which the program with the given source code is cached. Includes
+    /// the dialect and the names of the optimization passes, so keys of differently
+    /// configured caches never match.
+    ///
+    /// The key is a 64-bit FNV-1a hash, which is the same on every platform and
+    /// version of this crate. It is not collision resistant, so programs with different
+    /// source code can have the same key
+    pub fn key(&self, source: &str) -> u64 {
+        let mut hasher = Fnv1a::default();
+
+        // Writing to the hasher never fails
+        let _ = write!(hasher, "{:?}", self.dialect);
+
+        for name in self.pipeline.pass_names() {
+            let _ = write!(hasher, "\0{}", name);
+        }
+
+        let _ = write!(hasher, "\0\0{}", source);
+
+        hasher.0
+    }
+
+    /// Returns the compiled program with the given source code, compiling
+    /// and caching it first if it is not cached yet.
+    ///
+    /// A program cached with the same key, but different source code, is replaced.
+    ///
+    /// Returns a [`ParseError`] if the source code could not be parsed, in which case nothing is cached
+    pub fn get_or_compile(&mut self, source: &str) -> Result<&CompiledProgram, ParseError> {
+        let key = self.key(source);
+
+        if self.get(source).is_some() {
+            log::debug!("Program {:016x} found in cache", key);
+            self.hits += 1;
+        } else {
+            log::debug!("Program {:016x} not cached, compiling", key);
+            self.misses += 1;
+
+            let program = self.pipeline.compile(&self.dialect.parse(source)?)?;
+            self.programs.insert(key, (source.into(), program));
+        }
+
+        Ok(&self.programs[&key].1)
+    }
+
+    /// Returns the cached program with the given source code
+    pub fn get(&self, source: &str) -> Option<&CompiledProgram> {
+        self.programs
+            .get(&self.key(source))
+            .filter(|(cached, _)| cached == source)
+            .map(|(_, program)| program)
+    }
+
+    /// Caches `program` as the compiled program of the given source code, for example
+    /// after loading it from an external cache. Returns the program previously cached
+    /// under the same key, if any, which may have been compiled from other source code
+    pub fn insert(&mut self, source: &str, program: CompiledProgram) -> Option<CompiledProgram> {
+        self.programs
+            .insert(self.key(source), (source.into(), program))
+            .map(|(_, program)| program)
+    }
+
+    /// Removes the cached program with the given source code, returning it if it was cached
+    pub fn remove(&mut self, source: &str) -> Option<CompiledProgram> {
+        self.get(source)?;
+
+        self.programs
+            .remove(&self.key(source))
+            .map(|(_, program)| program)
+    }
+
+    /// Removes every cached program
+    pub fn clear(&mut self) {
+        self.programs.clear();
+    }
+
+    /// The amount of cached programs
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    /// Whether no programs are cached
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// How often [`ProgramCache::get_or_compile`] found a cached program
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// How often [`ProgramCache::get_or_compile`] had to compile a program
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+impl Default for ProgramCache {
+    fn default() -> Self {
+        ProgramCache::new()
+    }
+}
+
+/// The 64-bit FNV-1a hash function
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Write for Fnv1a {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for &byte in s.as_bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+
+        Ok(())
+    }
+}
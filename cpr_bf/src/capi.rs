@@ -0,0 +1,256 @@
+//! Contains the C bindings of this crate, enabled with the `capi` feature
+//!
+//! Build the crate as a shared or static library to use these bindings, for example
+//! to embed the interpreter in a C or C++ program:
+//!
+//! ```sh
+//! cargo rustc -p cpr_bf --lib --release --features capi --crate-type cdylib
+//! cargo rustc -p cpr_bf --lib --release --features capi --crate-type staticlib
+//! ```
+//!
+//! The bindings are declared in the
+//! `include/cpr_bf.h` header, which is generated by cbindgen whenever the crate is
+//! built with this feature.
+//!
+//! A VM is created with [`bf_vm_new`], and must be freed with [`bf_vm_free`]. The VM
+//! reads and writes through the given callbacks, which receive the same user data
+//! pointer. Its cells are bytes, and all other options have their default values.
+//!
+//! ```c
+//! #include <stdio.h>
+//! #include "cpr_bf.h"
+//!
+//! static int write_stdout(void *user_data, const uint8_t *data, size_t len) {
+//!     return fwrite(data, 1, len, stdout) == len ? 0 : 1;
+//! }
+//!
+//! int main(void) {
+//!     BfVm *vm = bf_vm_new(NULL, write_stdout, NULL);
+//!
+//!     if (bf_vm_run_string(vm, "++++++++[>++++++++<-]>+.") != BF_STATUS_OK) {
+//!         fprintf(stderr, "%s\n", bf_vm_last_error(vm));
+//!     }
+//!
+//!     bf_vm_free(vm);
+//! }
+//! ```
+
+use alloc::{boxed::Box, ffi::CString, string::ToString};
+use core::{
+    ffi::{c_char, c_int, c_void, CStr},
+    ptr,
+};
+use std::io;
+
+use crate::{allocators::DynamicAllocator, BrainfuckVM, VMBuilder, VirtualMachine};
+
+/// Reads a single byte of input for the program. Returns the byte as a value from
+/// 0 to 255, or a negative value at the end of the input
+pub type BfReadFn = Option<unsafe extern "C" fn(user_data: *mut c_void) -> c_int>;
+
+/// Writes `len` bytes of output of the program, starting at `data`.
+/// Returns zero on success, or any other value if the output could not be written
+pub type BfWriteFn =
+    Option<unsafe extern "C" fn(user_data: *mut c_void, data: *const u8, len: usize) -> c_int>;
+
+/// The result of a call into the C bindings
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BfStatus {
+    /// The call succeeded
+    Ok = 0,
+
+    /// A required pointer argument was null
+    NullPointer = 1,
+
+    /// The source code was not valid UTF-8
+    InvalidUtf8 = 2,
+
+    /// The program failed to parse or run, see [`bf_vm_last_error`]
+    Error = 3,
+}
+
+/// A Brainfuck VM created with [`bf_vm_new`]
+pub struct BfVm {
+    vm: VirtualMachine<u8, DynamicAllocator, CallbackReader, CallbackWriter>,
+    last_error: Option<CString>,
+}
+
+/// Reads through a [`BfReadFn`]. Without a callback, the input is always empty
+struct CallbackReader {
+    read: BfReadFn,
+    user_data: *mut c_void,
+}
+
+impl io::Read for CallbackReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (Some(read), Some(first)) = (self.read, buf.first_mut()) else {
+            return Ok(0);
+        };
+
+        // SAFETY: The caller of `bf_vm_new` guarantees that the callback
+        // can be called with its user data
+        let byte = unsafe { read(self.user_data) };
+
+        match u8::try_from(byte) {
+            Ok(byte) => {
+                *first = byte;
+                Ok(1)
+            }
+            Err(_) if byte < 0 => Ok(0),
+            Err(_) => Err(io::Error::other("read callback returned an invalid byte")),
+        }
+    }
+}
+
+/// Writes through a [`BfWriteFn`]. Without a callback, all output is discarded
+struct CallbackWriter {
+    write: BfWriteFn,
+    user_data: *mut c_void,
+}
+
+impl io::Write for CallbackWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(write) = self.write else {
+            return Ok(buf.len());
+        };
+
+        // SAFETY: The caller of `bf_vm_new` guarantees that the callback
+        // can be called with its user data
+        match unsafe { write(self.user_data, buf.as_ptr(), buf.len()) } {
+            0 => Ok(buf.len()),
+            _ => Err(io::Error::other("write callback failed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates a VM that reads input through `read` and writes output through `write`,
+/// passing `user_data` to both. Either callback may be null, in which case the
+/// input is always empty, or the output is discarded.
+///
+/// The returned VM is never null, and must be freed with [`bf_vm_free`]
+///
+/// # Safety
+///
+/// The callbacks must be safe to call with `user_data` for as long as the VM exists
+#[no_mangle]
+pub unsafe extern "C" fn bf_vm_new(
+    read: BfReadFn,
+    write: BfWriteFn,
+    user_data: *mut c_void,
+) -> *mut BfVm {
+    let vm = VMBuilder::new()
+        .with_reader(CallbackReader { read, user_data })
+        .with_writer(CallbackWriter { write, user_data })
+        .build_typed();
+
+    Box::into_raw(Box::new(BfVm {
+        vm,
+        last_error: None,
+    }))
+}
+
+/// Frees a VM created with [`bf_vm_new`]. Does nothing if `vm` is null
+///
+/// # Safety
+///
+/// `vm` must be null or a VM returned by [`bf_vm_new`] that has not been freed yet
+#[no_mangle]
+pub unsafe extern "C" fn bf_vm_free(vm: *mut BfVm) {
+    if !vm.is_null() {
+        // SAFETY: Guaranteed by the caller
+        drop(unsafe { Box::from_raw(vm) });
+    }
+}
+
+/// Parses and runs the null-terminated Brainfuck source code `code`.
+///
+/// The tape is kept between runs. If the program fails, a description of the
+/// error is available through [`bf_vm_last_error`]
+///
+/// # Safety
+///
+/// `vm` must be a valid VM, and `code` a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn bf_vm_run_string(vm: *mut BfVm, code: *const c_char) -> BfStatus {
+    // SAFETY: Guaranteed by the caller
+    let Some(vm) = (unsafe { vm.as_mut() }) else {
+        return BfStatus::NullPointer;
+    };
+
+    vm.last_error = None;
+
+    if code.is_null() {
+        return BfStatus::NullPointer;
+    }
+
+    // SAFETY: Guaranteed by the caller
+    let Ok(code) = unsafe { CStr::from_ptr(code) }.to_str() else {
+        return BfStatus::InvalidUtf8;
+    };
+
+    match vm.vm.run_string(code) {
+        Ok(()) => BfStatus::Ok,
+        Err(e) => {
+            // Error messages never contain null bytes
+            vm.last_error = CString::new(e.to_string()).ok();
+            BfStatus::Error
+        }
+    }
+}
+
+/// The description of the error of the last failed run, as a null-terminated string.
+/// Returns null if the last run succeeded, or if no program has been run yet.
+///
+/// The string is owned by the VM, and valid until the next run or until the VM is freed
+///
+/// # Safety
+///
+/// `vm` must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn bf_vm_last_error(vm: *const BfVm) -> *const c_char {
+    // SAFETY: Guaranteed by the caller
+    unsafe { vm.as_ref() }
+        .and_then(|vm| vm.last_error.as_ref())
+        .map_or(ptr::null(), |error| error.as_ptr())
+}
+
+/// The cells of the tape. The tape holds [`bf_vm_tape_len`] cells, and is valid
+/// until the next run or until the VM is freed. Returns null if `vm` is null
+///
+/// # Safety
+///
+/// `vm` must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn bf_vm_tape(vm: *const BfVm) -> *const u8 {
+    // SAFETY: Guaranteed by the caller
+    unsafe { vm.as_ref() }.map_or(ptr::null(), |vm| vm.vm.memory().as_ptr())
+}
+
+/// The amount of cells of the tape, see [`bf_vm_tape`]. Returns zero if `vm` is null
+///
+/// # Safety
+///
+/// `vm` must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn bf_vm_tape_len(vm: *const BfVm) -> usize {
+    // SAFETY: Guaranteed by the caller
+    unsafe { vm.as_ref() }.map_or(0, |vm| vm.vm.memory().len())
+}
+
+/// The index of the cell of the tape the data pointer points to.
+/// This cell might be beyond the end of the tape, if it has not been allocated yet.
+/// Returns zero if `vm` is null
+///
+/// # Safety
+///
+/// `vm` must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn bf_vm_data_ptr(vm: *const BfVm) -> usize {
+    // SAFETY: Guaranteed by the caller
+    unsafe { vm.as_ref() }.map_or(0, |vm| vm.vm.data_ptr())
+}
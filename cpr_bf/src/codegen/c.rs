@@ -0,0 +1,200 @@
+//! Contains a code generator emitting C source code
+//!
+//! The generated program uses a statically allocated tape, and has the same
+//! I/O semantics as the default [`crate::VirtualMachine`]: 8-bit cells are written
+//! to stdout as raw bytes, wider cells as UTF-8 encoded code points, and reading
+//! past the end of stdin leaves the current cell unchanged. Moving the data pointer outside of the
+//! tape is not checked.
+//!
+//! ```
+//! use cpr_bf::{codegen::c::CGenerator, ir::OptimizationLevel, Program};
+//!
+//! let program = Program::parse("+[-]>,.").unwrap();
+//! let source = CGenerator::new()
+//!     .with_tape_size(1000)
+//!     .generate(&program.compile(OptimizationLevel::Full).unwrap())
+//!     .unwrap();
+//!
+//! assert!(source.contains("static cell_t tape[1000];"));
+//! ```
+
+use alloc::{format, string::String};
+
+use super::{check_supported, CellType, CodegenError, Emitter};
+use crate::ir::{CompiledProgram, Op};
+
+/// Emits C source code for Brainfuck programs
+#[derive(Clone, Copy, Debug)]
+pub struct CGenerator {
+    cell_type: CellType,
+    tape_size: usize,
+}
+
+impl Default for CGenerator {
+    /// Construct a generator for 30000 8-bit cells
+    fn default() -> Self {
+        CGenerator {
+            cell_type: CellType::U8,
+            tape_size: 30000,
+        }
+    }
+}
+
+impl CGenerator {
+    /// Construct a generator for 30000 8-bit cells
+    pub fn new() -> Self {
+        CGenerator::default()
+    }
+
+    /// Changes the type of the memory cells of the generated program
+    pub fn with_cell_type(self, cell_type: CellType) -> Self {
+        CGenerator { cell_type, ..self }
+    }
+
+    /// Changes the amount of memory cells of the generated program
+    pub fn with_tape_size(self, tape_size: usize) -> Self {
+        CGenerator { tape_size, ..self }
+    }
+
+    /// Generates a complete C program running `program`
+    pub fn generate(&self, program: &CompiledProgram) -> Result<String, CodegenError> {
+        log::debug!(
+            "Generating C code for {} operations with {:?}",
+            program.ops().len(),
+            self
+        );
+
+        check_supported(program)?;
+
+        let mut out = Emitter::new();
+
+        out.line("/* Generated by cpr_bf */");
+        out.blank();
+        out.line("#include <stdint.h>");
+        out.line("#include <stdio.h>");
+        out.blank();
+        out.line(format!("typedef uint{}_t cell_t;", self.cell_type.bits()));
+        out.blank();
+        out.line(format!("static cell_t tape[{}];", self.tape_size));
+        self.emit_helpers(&mut out, program);
+        out.blank();
+        out.line("int main(void) {");
+        out.indent += 1;
+        out.line("cell_t *ptr = tape;");
+        out.blank();
+
+        for op in program.ops() {
+            self.emit_op(&mut out, *op);
+        }
+
+        out.blank();
+        out.line("return 0;");
+        out.indent -= 1;
+        out.line("}");
+
+        Ok(out.finish())
+    }
+
+    /// Emits the functions used for I/O, if `program` performs any
+    fn emit_helpers(&self, out: &mut Emitter, program: &CompiledProgram) {
+        if program.ops().contains(&Op::Output) {
+            out.blank();
+
+            let helper = match self.cell_type {
+                CellType::U8 => RAW_OUTPUT_HELPER,
+                _ => OUTPUT_HELPER,
+            };
+
+            for line in helper {
+                out.line(line);
+            }
+        }
+
+        if program.ops().contains(&Op::Input) {
+            out.blank();
+
+            for line in INPUT_HELPER {
+                out.line(line);
+            }
+        }
+    }
+
+    /// Adds `amount` to `target`, wrapped to the range of the cell type
+    fn emit_add(&self, out: &mut Emitter, target: &str, amount: isize) {
+        match self.cell_type.wrap(amount) {
+            0 => {}
+            wrapped if amount > 0 => out.line(format!("{} += {};", target, wrapped)),
+            _ => out.line(format!("{} -= {};", target, self.cell_type.wrap(-amount))),
+        }
+    }
+
+    fn emit_op(&self, out: &mut Emitter, op: Op) {
+        match op {
+            Op::Add(amount) => self.emit_add(out, "*ptr", amount),
+            Op::AddAt(offset, amount) => self.emit_add(out, &format!("ptr[{}]", offset), amount),
+            Op::Move(amount) if amount > 0 => out.line(format!("ptr += {};", amount)),
+            Op::Move(amount) => out.line(format!("ptr -= {};", amount.unsigned_abs())),
+            Op::Output => out.line("output(*ptr);"),
+            Op::Input => out.line("input(ptr);"),
+            Op::SetZero => out.line("*ptr = 0;"),
+            Op::Scan(amount) if amount > 0 => out.line(format!("while (*ptr) ptr += {};", amount)),
+            Op::Scan(amount) => out.line(format!("while (*ptr) ptr -= {};", amount.unsigned_abs())),
+            Op::JumpFwd(_) => {
+                out.line("while (*ptr) {");
+                out.indent += 1;
+            }
+            Op::JumpBack(_) => {
+                out.indent = out.indent.saturating_sub(1);
+                out.line("}");
+            }
+            _ => {
+                unreachable!("Extension operations are rejected before generating code")
+            }
+        }
+    }
+}
+
+const OUTPUT_HELPER: &[&str] = &[
+    "/* Writes the cell as a UTF-8 encoded code point */",
+    "static void output(cell_t cell) {",
+    "    uint_least64_t c = cell;",
+    "",
+    "    if (c > 0x10FFFF || (c >= 0xD800 && c <= 0xDFFF)) {",
+    "        c = 0xFFFD;",
+    "    }",
+    "",
+    "    if (c < 0x80) {",
+    "        putchar((int)c);",
+    "    } else if (c < 0x800) {",
+    "        putchar((int)(0xC0 | (c >> 6)));",
+    "        putchar((int)(0x80 | (c & 0x3F)));",
+    "    } else if (c < 0x10000) {",
+    "        putchar((int)(0xE0 | (c >> 12)));",
+    "        putchar((int)(0x80 | ((c >> 6) & 0x3F)));",
+    "        putchar((int)(0x80 | (c & 0x3F)));",
+    "    } else {",
+    "        putchar((int)(0xF0 | (c >> 18)));",
+    "        putchar((int)(0x80 | ((c >> 12) & 0x3F)));",
+    "        putchar((int)(0x80 | ((c >> 6) & 0x3F)));",
+    "        putchar((int)(0x80 | (c & 0x3F)));",
+    "    }",
+    "}",
+];
+
+const RAW_OUTPUT_HELPER: &[&str] = &[
+    "/* Writes the cell as a raw byte */",
+    "static void output(cell_t cell) {",
+    "    putchar((int)cell);",
+    "}",
+];
+
+const INPUT_HELPER: &[&str] = &[
+    "/* Reads a single byte into the cell, leaving it unchanged at the end of the input */",
+    "static void input(cell_t *cell) {",
+    "    int c = getchar();",
+    "",
+    "    if (c != EOF) {",
+    "        *cell = (cell_t)c;",
+    "    }",
+    "}",
+];
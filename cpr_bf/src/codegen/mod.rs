@@ -0,0 +1,128 @@
+//! Contains code generators, which translate Brainfuck programs into other languages
+//!
+//! Every generator works on a [`crate::ir::CompiledProgram`], so the optimizations
+//! applied while compiling the program carry over to the generated code.
+//! Programs using any of the [`crate::extensions`] cannot be translated, and
+//! are rejected with a [`CodegenError`].
+
+use alloc::string::{String, ToString};
+use core::fmt::Display;
+
+use crate::ir::{CompiledProgram, Op};
+
+pub mod c;
+pub mod rust;
+#[cfg(feature = "wasm-codegen")]
+pub mod wasm;
+
+/// The type of the memory cells in generated code.
+/// Arithmetic on the cells always wraps around on overflow
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CellType {
+    /// Unsigned 8-bit cells
+    #[default]
+    U8,
+
+    /// Unsigned 16-bit cells
+    U16,
+
+    /// Unsigned 32-bit cells
+    U32,
+
+    /// Unsigned 64-bit cells
+    U64,
+}
+
+impl CellType {
+    /// The size of a cell of this type, in bits
+    pub fn bits(self) -> u32 {
+        match self {
+            CellType::U8 => 8,
+            CellType::U16 => 16,
+            CellType::U32 => 32,
+            CellType::U64 => 64,
+        }
+    }
+
+    /// Reduces `amount` modulo the number of values of this type,
+    /// so that it can be added to a cell as an unsigned value
+    fn wrap(self, amount: isize) -> u64 {
+        match self {
+            CellType::U64 => amount as u64,
+            _ => (amount as u64) & ((1 << self.bits()) - 1),
+        }
+    }
+}
+
+/// An error encountered while generating code
+#[derive(Debug)]
+pub enum CodegenError {
+    /// The program contains an operation that cannot be translated, see [`Op::is_extension`]
+    UnsupportedOp(Op),
+}
+
+impl Display for CodegenError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CodegenError::UnsupportedOp(op) => {
+                write!(f, "Operation {:?} is not supported by code generators", op)
+            }
+        }
+    }
+}
+
+impl core::error::Error for CodegenError {}
+
+/// Checks that every operation of `program` can be translated
+fn check_supported(program: &CompiledProgram) -> Result<(), CodegenError> {
+    match program.ops().iter().find(|op| op.is_extension()) {
+        Some(&op) => {
+            log::error!("Cannot generate code for {:?}", op);
+            Err(CodegenError::UnsupportedOp(op))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Writes indented lines of generated source code
+struct Emitter {
+    out: String,
+    indent: usize,
+}
+
+impl Emitter {
+    fn new() -> Self {
+        Emitter {
+            out: String::new(),
+            indent: 0,
+        }
+    }
+
+    /// Writes a single line at the current indentation.
+    /// Empty lines are written without indentation
+    fn line(&mut self, line: impl Display) {
+        use core::fmt::Write;
+
+        let line = line.to_string();
+
+        if line.is_empty() {
+            return self.blank();
+        }
+
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+
+        // Writing to a String cannot fail
+        let _ = writeln!(self.out, "{}", line);
+    }
+
+    /// Writes an empty line
+    fn blank(&mut self) {
+        self.out.push('\n');
+    }
+
+    fn finish(self) -> String {
+        self.out
+    }
+}
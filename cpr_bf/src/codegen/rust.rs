@@ -0,0 +1,266 @@
+//! Contains a code generator emitting Rust source code
+//!
+//! The generated code mirrors the default [`crate::VirtualMachine`]: the tape starts
+//! with a configurable amount of cells and grows to the right whenever needed, like the
+//! [`crate::allocators::DynamicAllocator`], cell arithmetic wraps around, 8-bit cells are
+//! written as raw bytes and wider cells as UTF-8 encoded code points, and reading past the
+//! end of the input leaves the current cell unchanged. Moving the data pointer below zero results in an error.
+//!
+//! By default, a complete program with a `fn main()` reading from stdin and writing
+//! to stdout is generated. The program itself is always contained in a public
+//! function taking a reader and a writer, so the generated code can also be
+//! included in another crate with [`RustGenerator::without_main`].
+//!
+//! ```
+//! use cpr_bf::{codegen::rust::RustGenerator, ir::OptimizationLevel, Program};
+//!
+//! let program = Program::parse(",[.,]").unwrap();
+//! let source = RustGenerator::new()
+//!     .with_function_name("cat")
+//!     .without_main()
+//!     .generate(&program.compile(OptimizationLevel::Full).unwrap())
+//!     .unwrap();
+//!
+//! assert!(source.contains("pub fn cat("));
+//! assert!(!source.contains("fn main()"));
+//! ```
+
+use alloc::{format, string::String, vec::Vec};
+
+use super::{check_supported, CellType, CodegenError, Emitter};
+use crate::ir::{CompiledProgram, Op};
+
+/// Emits Rust source code for Brainfuck programs
+#[derive(Clone, Debug)]
+pub struct RustGenerator {
+    cell_type: CellType,
+    tape_size: usize,
+    function_name: String,
+    main: bool,
+}
+
+impl Default for RustGenerator {
+    /// Construct a generator for a complete program with 8-bit cells,
+    /// and an initial tape of 16 cells
+    fn default() -> Self {
+        RustGenerator {
+            cell_type: CellType::U8,
+            tape_size: 16,
+            function_name: String::from("run"),
+            main: true,
+        }
+    }
+}
+
+impl RustGenerator {
+    /// Construct a generator for a complete program with 8-bit cells,
+    /// and an initial tape of 16 cells
+    pub fn new() -> Self {
+        RustGenerator::default()
+    }
+
+    /// Changes the type of the memory cells of the generated code
+    pub fn with_cell_type(self, cell_type: CellType) -> Self {
+        RustGenerator { cell_type, ..self }
+    }
+
+    /// Changes the amount of memory cells the tape of the generated code starts with
+    pub fn with_tape_size(self, tape_size: usize) -> Self {
+        RustGenerator { tape_size, ..self }
+    }
+
+    /// Changes the name of the function containing the program. Defaults to `run`
+    pub fn with_function_name(self, name: &str) -> Self {
+        RustGenerator {
+            function_name: String::from(name),
+            ..self
+        }
+    }
+
+    /// Omits the `fn main()` calling the program with stdin and stdout
+    pub fn without_main(self) -> Self {
+        RustGenerator {
+            main: false,
+            ..self
+        }
+    }
+
+    /// Generates the Rust source code running `program`
+    pub fn generate(&self, program: &CompiledProgram) -> Result<String, CodegenError> {
+        log::debug!(
+            "Generating Rust code for {} operations with {:?}",
+            program.ops().len(),
+            self
+        );
+
+        check_supported(program)?;
+
+        let mut out = Emitter::new();
+
+        out.line("// Generated by cpr_bf");
+        out.blank();
+        out.line("use std::io::{self, Read, Write};");
+        out.blank();
+        out.line(format!("type Cell = u{};", self.cell_type.bits()));
+        out.blank();
+
+        let ops: Vec<Op> = program.ops().iter().flat_map(|op| op.expand()).collect();
+
+        for (helper, used) in [
+            (
+                MOVE_RIGHT_HELPER,
+                ops.iter().any(|op| matches!(op, Op::Move(n) if *n > 0)),
+            ),
+            (
+                MOVE_LEFT_HELPER,
+                ops.iter().any(|op| matches!(op, Op::Move(n) if *n < 0)),
+            ),
+            (
+                match self.cell_type {
+                    CellType::U8 => RAW_OUTPUT_HELPER,
+                    _ => OUTPUT_HELPER,
+                },
+                ops.contains(&Op::Output),
+            ),
+            (INPUT_HELPER, ops.contains(&Op::Input)),
+        ] {
+            if used {
+                for line in helper {
+                    out.line(line);
+                }
+
+                out.blank();
+            }
+        }
+
+        // Programs without I/O or pointer movement leave some of these unused
+        out.line("#[allow(unused)]");
+        out.line(format!(
+            "pub fn {}(input: &mut impl Read, output: &mut impl Write) -> io::Result<()> {{",
+            self.function_name,
+        ));
+        out.indent += 1;
+        out.line(format!(
+            "let mut tape: Vec<Cell> = vec![0; {}];",
+            self.tape_size.max(1)
+        ));
+        out.line("let mut ptr: usize = 0;");
+        out.blank();
+
+        for op in program.ops() {
+            self.emit_op(&mut out, *op);
+        }
+
+        out.blank();
+        out.line("output.flush()");
+        out.indent -= 1;
+        out.line("}");
+
+        if self.main {
+            out.blank();
+            out.line("fn main() -> io::Result<()> {");
+            out.indent += 1;
+            out.line(format!(
+                "{}(&mut io::stdin().lock(), &mut io::stdout().lock())",
+                self.function_name
+            ));
+            out.indent -= 1;
+            out.line("}");
+        }
+
+        Ok(out.finish())
+    }
+
+    fn emit_op(&self, out: &mut Emitter, op: Op) {
+        match op {
+            Op::Add(amount) => match self.cell_type.wrap(amount) {
+                0 => {}
+                wrapped if amount > 0 => {
+                    out.line(format!("tape[ptr] = tape[ptr].wrapping_add({});", wrapped))
+                }
+                _ => out.line(format!(
+                    "tape[ptr] = tape[ptr].wrapping_sub({});",
+                    self.cell_type.wrap(-amount)
+                )),
+            },
+            Op::Move(amount) if amount > 0 => {
+                out.line(format!("ptr = move_right(&mut tape, ptr, {});", amount))
+            }
+            Op::Move(amount) => {
+                out.line(format!("ptr = move_left(ptr, {})?;", amount.unsigned_abs()))
+            }
+            Op::Output => out.line("write_cell(output, tape[ptr])?;"),
+            Op::Input => out.line("read_cell(input, &mut tape[ptr])?;"),
+            Op::SetZero => out.line("tape[ptr] = 0;"),
+            Op::Scan(_) | Op::AddAt(_, _) => {
+                for op in op.expand() {
+                    self.emit_op(out, op);
+                }
+            }
+            Op::JumpFwd(_) => {
+                out.line("while tape[ptr] != 0 {");
+                out.indent += 1;
+            }
+            Op::JumpBack(_) => {
+                out.indent = out.indent.saturating_sub(1);
+                out.line("}");
+            }
+            _ => {
+                unreachable!("Extension operations are rejected before generating code")
+            }
+        }
+    }
+}
+
+const MOVE_RIGHT_HELPER: &[&str] = &[
+    "/// Moves the data pointer to the right, growing the tape if needed",
+    "fn move_right(tape: &mut Vec<Cell>, ptr: usize, amount: usize) -> usize {",
+    "    let ptr = ptr + amount;",
+    "",
+    "    if ptr >= tape.len() {",
+    "        tape.resize(ptr + 1, 0);",
+    "    }",
+    "",
+    "    ptr",
+    "}",
+];
+
+const MOVE_LEFT_HELPER: &[&str] = &[
+    "/// Moves the data pointer to the left",
+    "fn move_left(ptr: usize, amount: usize) -> io::Result<usize> {",
+    "    ptr.checked_sub(amount)",
+    "        .ok_or_else(|| io::Error::other(\"Data pointer underflow!\"))",
+    "}",
+];
+
+const OUTPUT_HELPER: &[&str] = &[
+    "/// Writes the cell as a UTF-8 encoded code point",
+    "fn write_cell(output: &mut impl Write, cell: Cell) -> io::Result<()> {",
+    "    let c = u32::try_from(cell)",
+    "        .ok()",
+    "        .and_then(char::from_u32)",
+    "        .unwrap_or(char::REPLACEMENT_CHARACTER);",
+    "",
+    "    write!(output, \"{}\", c)",
+    "}",
+];
+
+const RAW_OUTPUT_HELPER: &[&str] = &[
+    "/// Writes the cell as a raw byte",
+    "fn write_cell(output: &mut impl Write, cell: Cell) -> io::Result<()> {",
+    "    output.write_all(&[cell])",
+    "}",
+];
+
+const INPUT_HELPER: &[&str] = &[
+    "/// Reads a single byte into the cell, leaving it unchanged at the end of the input",
+    "fn read_cell(input: &mut impl Read, cell: &mut Cell) -> io::Result<()> {",
+    "    let mut buf = [0_u8; 1];",
+    "",
+    "    if input.read(&mut buf)? == 1 {",
+    "        *cell = Cell::from(buf[0]);",
+    "    }",
+    "",
+    "    Ok(())",
+    "}",
+];
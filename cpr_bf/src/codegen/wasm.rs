@@ -0,0 +1,340 @@
+//! Contains a code generator emitting WebAssembly modules, enabled with the `wasm-codegen` feature
+//!
+//! The generated module imports two functions from the host, through which
+//! all I/O is performed:
+//! - `env.read_byte: () -> i32`, returning the next byte of the input,
+//!   or a negative value at the end of the input
+//! - `env.write_byte: (i32) -> ()`, writing the lowest byte of its argument
+//!
+//! The tape is the exported linear memory `memory` of the module, and the
+//! program runs when the exported function `run` is called. As with the default
+//! [`crate::VirtualMachine`], 8-bit cells are written as raw bytes, wider cells
+//! are written as UTF-8 encoded code points, and reading past the end of the input
+//! leaves the current cell unchanged.
+//! The tape size is rounded up to whole WebAssembly pages, and moving the data
+//! pointer outside of the tape traps once the pointed-to cell is accessed.
+//!
+//! ```
+//! use cpr_bf::{codegen::wasm::WasmGenerator, ir::OptimizationLevel, Program};
+//!
+//! let program = Program::parse("++++++++[>++++++++<-]>+.").unwrap();
+//! let module = WasmGenerator::new()
+//!     .generate(&program.compile(OptimizationLevel::Full).unwrap())
+//!     .unwrap();
+//!
+//! assert_eq!(&module[..4], b"\0asm");
+//! ```
+
+use alloc::vec::Vec;
+
+use wasm_encoder::{
+    BlockType, CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection,
+    ImportSection, Instruction, MemArg, MemorySection, MemoryType, Module, TypeSection, ValType,
+};
+
+use super::{check_supported, CellType, CodegenError};
+use crate::ir::{CompiledProgram, Op};
+
+/// The size of a WebAssembly page, in bytes
+const PAGE_SIZE: u64 = 65536;
+
+const READ_BYTE_FN: u32 = 0;
+const WRITE_BYTE_FN: u32 = 1;
+const WRITE_CELL_FN: u32 = 2;
+const RUN_FN: u32 = 3;
+
+/// The local holding the data pointer, as a byte offset into the memory
+const PTR_LOCAL: u32 = 0;
+
+/// Emits WebAssembly modules for Brainfuck programs
+#[derive(Clone, Copy, Debug)]
+pub struct WasmGenerator {
+    cell_type: CellType,
+    tape_size: usize,
+}
+
+impl Default for WasmGenerator {
+    /// Construct a generator for (at least) 30000 8-bit cells
+    fn default() -> Self {
+        WasmGenerator {
+            cell_type: CellType::U8,
+            tape_size: 30000,
+        }
+    }
+}
+
+impl WasmGenerator {
+    /// Construct a generator for (at least) 30000 8-bit cells
+    pub fn new() -> Self {
+        WasmGenerator::default()
+    }
+
+    /// Changes the type of the memory cells of the generated module
+    pub fn with_cell_type(self, cell_type: CellType) -> Self {
+        WasmGenerator { cell_type, ..self }
+    }
+
+    /// Changes the minimum amount of memory cells of the generated module
+    pub fn with_tape_size(self, tape_size: usize) -> Self {
+        WasmGenerator { tape_size, ..self }
+    }
+
+    /// Generates the binary WebAssembly module running `program`
+    pub fn generate(&self, program: &CompiledProgram) -> Result<Vec<u8>, CodegenError> {
+        log::debug!(
+            "Generating WebAssembly for {} operations with {:?}",
+            program.ops().len(),
+            self
+        );
+
+        check_supported(program)?;
+
+        let mut types = TypeSection::new();
+        types.ty().function([], [ValType::I32]);
+        types.ty().function([ValType::I32], []);
+        types.ty().function([ValType::I64], []);
+        types.ty().function([], []);
+
+        let mut imports = ImportSection::new();
+        imports.import("env", "read_byte", EntityType::Function(0));
+        imports.import("env", "write_byte", EntityType::Function(1));
+
+        let mut functions = FunctionSection::new();
+        functions.function(2);
+        functions.function(3);
+
+        let tape_bytes = self.tape_size as u64 * self.cell_bytes();
+        let pages = tape_bytes.div_ceil(PAGE_SIZE).max(1);
+
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType {
+            minimum: pages,
+            maximum: Some(pages),
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+
+        let mut exports = ExportSection::new();
+        exports.export("memory", ExportKind::Memory, 0);
+        exports.export("run", ExportKind::Func, RUN_FN);
+
+        let mut code = CodeSection::new();
+        code.function(&write_cell_function());
+        code.function(&self.run_function(program));
+
+        let mut module = Module::new();
+        module
+            .section(&types)
+            .section(&imports)
+            .section(&functions)
+            .section(&memories)
+            .section(&exports)
+            .section(&code);
+
+        Ok(module.finish())
+    }
+
+    fn cell_bytes(&self) -> u64 {
+        u64::from(self.cell_type.bits() / 8)
+    }
+
+    fn mem_arg(&self) -> MemArg {
+        MemArg {
+            offset: 0,
+            align: self.cell_bytes().trailing_zeros(),
+            memory_index: 0,
+        }
+    }
+
+    /// Loads the current cell onto the stack
+    fn load_cell(&self, f: &mut Function) {
+        f.instruction(&Instruction::LocalGet(PTR_LOCAL));
+        f.instruction(&match self.cell_type {
+            CellType::U8 => Instruction::I32Load8U(self.mem_arg()),
+            CellType::U16 => Instruction::I32Load16U(self.mem_arg()),
+            CellType::U32 => Instruction::I32Load(self.mem_arg()),
+            CellType::U64 => Instruction::I64Load(self.mem_arg()),
+        });
+    }
+
+    /// Stores the value on top of the stack in the cell at the address below it
+    fn store_cell(&self, f: &mut Function) {
+        f.instruction(&match self.cell_type {
+            CellType::U8 => Instruction::I32Store8(self.mem_arg()),
+            CellType::U16 => Instruction::I32Store16(self.mem_arg()),
+            CellType::U32 => Instruction::I32Store(self.mem_arg()),
+            CellType::U64 => Instruction::I64Store(self.mem_arg()),
+        });
+    }
+
+    /// Pushes a constant of the type cells are loaded as
+    fn cell_const(&self, f: &mut Function, value: u64) {
+        f.instruction(&match self.cell_type {
+            CellType::U64 => Instruction::I64Const(value as i64),
+            _ => Instruction::I32Const(value as u32 as i32),
+        });
+    }
+
+    /// Pushes whether the current cell is not zero
+    fn cell_nonzero(&self, f: &mut Function) {
+        self.load_cell(f);
+
+        if self.cell_type == CellType::U64 {
+            f.instruction(&Instruction::I64Const(0));
+            f.instruction(&Instruction::I64Ne);
+        }
+    }
+
+    fn run_function(&self, program: &CompiledProgram) -> Function {
+        let mut f = Function::new([(2, ValType::I32)]);
+
+        for op in program.ops().iter().flat_map(|op| op.expand()) {
+            match op {
+                Op::Add(amount) => {
+                    f.instruction(&Instruction::LocalGet(PTR_LOCAL));
+                    self.load_cell(&mut f);
+                    self.cell_const(&mut f, self.cell_type.wrap(amount));
+                    f.instruction(&match self.cell_type {
+                        CellType::U64 => Instruction::I64Add,
+                        _ => Instruction::I32Add,
+                    });
+                    self.store_cell(&mut f);
+                }
+                Op::Move(amount) => {
+                    let bytes = amount.wrapping_mul(self.cell_bytes() as isize);
+
+                    f.instruction(&Instruction::LocalGet(PTR_LOCAL));
+                    f.instruction(&Instruction::I32Const(bytes as i32));
+                    f.instruction(&Instruction::I32Add);
+                    f.instruction(&Instruction::LocalSet(PTR_LOCAL));
+                }
+                Op::Output if self.cell_type == CellType::U8 => {
+                    self.load_cell(&mut f);
+                    f.instruction(&Instruction::Call(WRITE_BYTE_FN));
+                }
+                Op::Output => {
+                    self.load_cell(&mut f);
+
+                    if self.cell_type != CellType::U64 {
+                        f.instruction(&Instruction::I64ExtendI32U);
+                    }
+
+                    f.instruction(&Instruction::Call(WRITE_CELL_FN));
+                }
+                Op::Input => {
+                    // The local after the data pointer holds the byte read
+                    let byte = PTR_LOCAL + 1;
+
+                    f.instruction(&Instruction::Call(READ_BYTE_FN));
+                    f.instruction(&Instruction::LocalTee(byte));
+                    f.instruction(&Instruction::I32Const(0));
+                    f.instruction(&Instruction::I32GeS);
+                    f.instruction(&Instruction::If(BlockType::Empty));
+                    f.instruction(&Instruction::LocalGet(PTR_LOCAL));
+                    f.instruction(&Instruction::LocalGet(byte));
+
+                    if self.cell_type == CellType::U64 {
+                        f.instruction(&Instruction::I64ExtendI32U);
+                    }
+
+                    self.store_cell(&mut f);
+                    f.instruction(&Instruction::End);
+                }
+                Op::SetZero => {
+                    f.instruction(&Instruction::LocalGet(PTR_LOCAL));
+                    self.cell_const(&mut f, 0);
+                    self.store_cell(&mut f);
+                }
+                Op::JumpFwd(_) => {
+                    f.instruction(&Instruction::Block(BlockType::Empty));
+                    self.cell_nonzero(&mut f);
+                    f.instruction(&Instruction::I32Eqz);
+                    f.instruction(&Instruction::BrIf(0));
+                    f.instruction(&Instruction::Loop(BlockType::Empty));
+                }
+                Op::JumpBack(_) => {
+                    self.cell_nonzero(&mut f);
+                    f.instruction(&Instruction::BrIf(0));
+                    f.instruction(&Instruction::End);
+                    f.instruction(&Instruction::End);
+                }
+                _ => {
+                    unreachable!("Extension operations are rejected before generating code")
+                }
+            }
+        }
+
+        f.instruction(&Instruction::End);
+        f
+    }
+}
+
+/// Builds the function writing its `i64` argument as a UTF-8 encoded code point,
+/// replacing invalid code points with the replacement character
+fn write_cell_function() -> Function {
+    const CELL: u32 = 0;
+    const C: u32 = 1;
+
+    let mut f = Function::new([(1, ValType::I32)]);
+
+    let write_shifted = |f: &mut Function, shift: i32, mask: i32, prefix: i32| {
+        f.instruction(&Instruction::LocalGet(C));
+        f.instruction(&Instruction::I32Const(shift));
+        f.instruction(&Instruction::I32ShrU);
+        f.instruction(&Instruction::I32Const(mask));
+        f.instruction(&Instruction::I32And);
+        f.instruction(&Instruction::I32Const(prefix));
+        f.instruction(&Instruction::I32Or);
+        f.instruction(&Instruction::Call(WRITE_BYTE_FN));
+    };
+
+    let below = |f: &mut Function, limit: i32| {
+        f.instruction(&Instruction::LocalGet(C));
+        f.instruction(&Instruction::I32Const(limit));
+        f.instruction(&Instruction::I32LtU);
+        f.instruction(&Instruction::If(BlockType::Empty));
+    };
+
+    // c = cell > 0x10FFFF || (cell - 0xD800) < 0x800 ? 0xFFFD : cell
+    f.instruction(&Instruction::I32Const(0xFFFD));
+    f.instruction(&Instruction::LocalGet(CELL));
+    f.instruction(&Instruction::I32WrapI64);
+    f.instruction(&Instruction::LocalGet(CELL));
+    f.instruction(&Instruction::I64Const(0x10FFFF));
+    f.instruction(&Instruction::I64GtU);
+    f.instruction(&Instruction::LocalGet(CELL));
+    f.instruction(&Instruction::I64Const(0xD800));
+    f.instruction(&Instruction::I64Sub);
+    f.instruction(&Instruction::I64Const(0x800));
+    f.instruction(&Instruction::I64LtU);
+    f.instruction(&Instruction::I32Or);
+    f.instruction(&Instruction::Select);
+    f.instruction(&Instruction::LocalSet(C));
+
+    below(&mut f, 0x80);
+    write_shifted(&mut f, 0, 0x7F, 0);
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+
+    below(&mut f, 0x800);
+    write_shifted(&mut f, 6, 0x1F, 0xC0);
+    write_shifted(&mut f, 0, 0x3F, 0x80);
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+
+    below(&mut f, 0x10000);
+    write_shifted(&mut f, 12, 0x0F, 0xE0);
+    write_shifted(&mut f, 6, 0x3F, 0x80);
+    write_shifted(&mut f, 0, 0x3F, 0x80);
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+
+    write_shifted(&mut f, 18, 0x07, 0xF0);
+    write_shifted(&mut f, 12, 0x3F, 0x80);
+    write_shifted(&mut f, 6, 0x3F, 0x80);
+    write_shifted(&mut f, 0, 0x3F, 0x80);
+
+    f.instruction(&Instruction::End);
+    f
+}
@@ -0,0 +1,236 @@
+//! Contains a suite of conformance tests for Brainfuck VMs
+//!
+//! The suite consists of classic test programs, most of them by Daniel B. Cristofani,
+//! covering the parts of Brainfuck where implementations commonly go wrong: the
+//! size of the tape, cell wrapping, the handling of newlines and the end of the input,
+//! deeply nested loops, and unmatched brackets. [`run_conformance`] runs all of them
+//! on a VM, which is useful to verify a VM with a custom allocator or cell type.
+//!
+//! ```
+//! use cpr_bf::{conformance, VMBuilder};
+//!
+//! let mut vm = VMBuilder::new().build();
+//! let report = conformance::run_conformance(vm.as_mut());
+//!
+//! assert!(report.all_passed(), "{}", report);
+//! ```
+//!
+//! Every test program consists of standard Brainfuck instructions only, and runs with
+//! its own input and output. The VM is reset with [`BrainfuckVM::reset_all`] before
+//! every test. Note that a VM with a step limit or a timeout may fail the larger tests.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+use crate::{BrainfuckExecutionError, BrainfuckVM, Program};
+
+/// The depth of the nested loops of the deep nesting test
+const NESTING_DEPTH: usize = 1000;
+
+/// What a test program should do
+enum Expected {
+    /// Succeed and write any one of the given outputs
+    Output(&'static [&'static [u8]]),
+
+    /// Fail without writing anything
+    Error,
+}
+
+/// A single test program of the suite
+struct ConformanceTest {
+    name: &'static str,
+    description: &'static str,
+    code: String,
+    input: &'static [u8],
+    expected: Expected,
+}
+
+/// The test programs of the suite, in the order they are run
+fn tests() -> Vec<ConformanceTest> {
+    let deep_nesting = [
+        "+",
+        &"[".repeat(NESTING_DEPTH),
+        "-",
+        &"]".repeat(NESTING_DEPTH),
+        "++++++++[>++++++++<-]>+.",
+    ]
+    .concat();
+
+    Vec::from([
+        ConformanceTest {
+            name: "hello world",
+            description: "Prints \"Hello World!\" followed by a newline",
+            code: "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.".to_string(),
+            input: b"",
+            expected: Expected::Output(&[b"Hello World!\n"]),
+        },
+        ConformanceTest {
+            name: "input and output",
+            description: "Reads a newline followed by the end of the input, and prints two \
+                identical lines describing how they were read. \"LK\" means the cell is left \
+                unchanged at the end of the input, \"LB\" that it is set to 0, and \"LA\" that \
+                it is set to -1",
+            code: ">,>+++++++++,>+++++++++++[<++++++<++++++<+>>>-]<<.>.<<-.>.>.<<.".to_string(),
+            input: b"\n",
+            expected: Expected::Output(&[b"LK\nLK\n", b"LB\nLB\n", b"LA\nLA\n"]),
+        },
+        ConformanceTest {
+            name: "tape size",
+            description: "Moves to cell 30000, and prints \"#\" followed by a newline from there",
+            code: "++++[>++++++<-]>[>+++++>+++++++<<-]>>++++<[[>[[>>+<<-]<]>>>-]>-[>+>+<<-]>]+++++[>+++++++<<++>-]>.<<.".to_string(),
+            input: b"",
+            expected: Expected::Output(&[b"#\n"]),
+        },
+        ConformanceTest {
+            name: "obscure problems",
+            description: "Prints \"H\" followed by a newline, if empty loops, characters that \
+                are not instructions and loops that are skipped right away are handled correctly",
+            code: "[]++++++++++[>>+>+>++++++[<<+<+++>>>-]<<<<-]\"A*$\";?@![#>>+<<]>[>>]<<<<[>++<[-]]>.>.".to_string(),
+            input: b"",
+            expected: Expected::Output(&[b"H\n"]),
+        },
+        ConformanceTest {
+            name: "cell wrapping",
+            description: "Decrements a zero cell and increments it again in a loop, which \
+                should wrap back to zero and end the loop, and prints \"W\" afterwards",
+            code: "-[+>]++++++++[<++++++++++>-]<+++++++.".to_string(),
+            input: b"",
+            expected: Expected::Output(&[b"W"]),
+        },
+        ConformanceTest {
+            name: "deep nesting",
+            description: "Runs 1000 nested loops, and prints \"A\" afterwards",
+            code: deep_nesting,
+            input: b"",
+            expected: Expected::Output(&[b"A"]),
+        },
+        ConformanceTest {
+            name: "unmatched [",
+            description: "Is rejected for its unmatched \"[\" before printing anything",
+            code: "+++++[>+++++++>++<<-]>.>.[".to_string(),
+            input: b"",
+            expected: Expected::Error,
+        },
+        ConformanceTest {
+            name: "unmatched ]",
+            description: "Is rejected for its unmatched \"]\" before printing anything",
+            code: "+++++[>+++++++>++<<-]>.>.][".to_string(),
+            input: b"",
+            expected: Expected::Error,
+        },
+    ])
+}
+
+/// The result of a single test of the suite
+#[derive(Debug)]
+pub struct ConformanceResult {
+    /// The name of the test
+    pub name: &'static str,
+
+    /// What the test program does on a conforming VM
+    pub description: &'static str,
+
+    /// Whether the VM passed the test
+    pub passed: bool,
+
+    /// Everything the test program wrote
+    pub output: Vec<u8>,
+
+    /// The error the test program failed with, if any
+    pub error: Option<BrainfuckExecutionError>,
+}
+
+/// The results of all tests of the suite, see [`run_conformance`]
+#[derive(Debug)]
+pub struct ConformanceReport {
+    /// The result of every test, in the order they were run
+    pub results: Vec<ConformanceResult>,
+}
+
+impl ConformanceReport {
+    /// Whether the VM passed every test
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(|result| result.passed)
+    }
+
+    /// The tests the VM passed
+    pub fn passed(&self) -> impl Iterator<Item = &ConformanceResult> {
+        self.results.iter().filter(|result| result.passed)
+    }
+
+    /// The tests the VM failed
+    pub fn failed(&self) -> impl Iterator<Item = &ConformanceResult> {
+        self.results.iter().filter(|result| !result.passed)
+    }
+}
+
+impl Display for ConformanceReport {
+    /// Writes one line per test, with the output and error of every failed test
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, result) in self.results.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            let verdict = if result.passed { "PASS" } else { "FAIL" };
+            write!(f, "{} {}", verdict, result.name)?;
+
+            if result.passed {
+                continue;
+            }
+
+            write!(
+                f,
+                ": got output {:?}",
+                String::from_utf8_lossy(&result.output)
+            )?;
+
+            if let Some(e) = &result.error {
+                write!(f, " and error \"{}\"", e)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs every test of the suite on `vm`, see the [module documentation](self)
+pub fn run_conformance(vm: &mut dyn BrainfuckVM) -> ConformanceReport {
+    log::info!("Running conformance tests");
+
+    let results = tests().into_iter().map(|test| run_test(vm, test)).collect();
+
+    ConformanceReport { results }
+}
+
+/// Runs a single test on a freshly reset `vm`
+fn run_test(vm: &mut dyn BrainfuckVM, test: ConformanceTest) -> ConformanceResult {
+    log::debug!("Running conformance test \"{}\"", test.name);
+
+    vm.reset_all(false);
+
+    let program = Program::from(test.code.as_str());
+    let mut input = test.input;
+    let mut output = Vec::new();
+
+    let error = vm
+        .run_program_with_io(&program, &mut input, &mut output)
+        .err();
+
+    let passed = match (&test.expected, &error) {
+        (Expected::Output(accepted), None) => accepted.contains(&output.as_slice()),
+        (Expected::Error, Some(_)) => output.is_empty(),
+        _ => false,
+    };
+
+    ConformanceResult {
+        name: test.name,
+        description: test.description,
+        passed,
+        output,
+        error,
+    }
+}
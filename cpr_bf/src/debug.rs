@@ -0,0 +1,390 @@
+//! Contains a debugger for the Brainfuck Virtual Machine
+//!
+//! The [`DebuggerVM`] wraps a [`VirtualMachine`], and runs programs until
+//! a breakpoint or a watchpoint is hit. Breakpoints can be set at instruction
+//! indices manually, or by placing the `#` debug instruction in the Brainfuck
+//! source code. Watchpoints stop the program right after it reads or writes
+//! one of the watched cells. Whenever the debugger stops, the tape, data pointer
+//! and instruction pointer of the VM can be inspected.
+//!
+//! ```
+//! use cpr_bf::{debug::{DebuggerVM, StopReason, WatchMode}, Program, VMBuilder};
+//!
+//! let program = Program::parse("+++#>++").unwrap();
+//! let mut debugger = DebuggerVM::new(VMBuilder::new().build_typed());
+//!
+//! debugger.load_program(&program).unwrap();
+//! debugger.add_watchpoint(1..2, WatchMode::Write);
+//!
+//! assert!(matches!(debugger.continue_execution(), StopReason::Breakpoint(3)));
+//! assert_eq!(debugger.tape(), &[3]);
+//!
+//! let StopReason::Watchpoint(hit) = debugger.continue_execution() else {
+//!     panic!("Expected a watchpoint to be hit");
+//! };
+//!
+//! assert_eq!((hit.cell, hit.instr_ptr, hit.old, hit.new), (1, 4, 0, 1));
+//! ```
+
+use alloc::{collections::BTreeSet, vec::Vec};
+use core::ops::Range;
+
+use crate::{
+    io::{Read, Write},
+    ir::{Op, OptimizationLevel},
+    BfResult, BrainfuckAllocator, BrainfuckCell, BrainfuckExecutionError, BrainfuckVM,
+    ExecutionState, Instruction, Program, VirtualMachine,
+};
+
+/// The reason the [`DebuggerVM`] stopped running the loaded program
+#[derive(Debug)]
+pub enum StopReason<T: BrainfuckCell> {
+    /// A breakpoint was hit. The instruction at the given index has not been executed yet
+    Breakpoint(usize),
+
+    /// A watched cell was accessed. The instruction responsible has already been executed
+    Watchpoint(WatchpointHit<T>),
+
+    /// The program has finished, or no program is loaded
+    Halted,
+
+    /// The program encountered an error
+    Error(BrainfuckExecutionError),
+}
+
+/// Which accesses of the watched cells stop the program, see [`DebuggerVM::add_watchpoint`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchMode {
+    /// Stop when a watched cell is read
+    Read,
+
+    /// Stop when a watched cell is written
+    Write,
+
+    /// Stop when a watched cell is read or written
+    ReadWrite,
+}
+
+impl WatchMode {
+    fn matches(self, access: CellAccess) -> bool {
+        match self {
+            WatchMode::Read => access == CellAccess::Read,
+            WatchMode::Write => access == CellAccess::Write,
+            WatchMode::ReadWrite => true,
+        }
+    }
+}
+
+/// How an instruction accessed a cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellAccess {
+    /// The instruction only used the value of the cell,
+    /// like the output instruction and the jump instructions
+    Read,
+
+    /// The instruction changed the value of the cell, or replaced it
+    /// like the input instruction. The new value can equal the old one
+    Write,
+}
+
+/// Cells watched by the [`DebuggerVM`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// The indices of the watched cells, within [`DebuggerVM::tape`]
+    pub cells: Range<usize>,
+
+    /// Which accesses of the cells stop the program
+    pub mode: WatchMode,
+}
+
+/// Describes the access of a watched cell, see [`StopReason::Watchpoint`]
+#[derive(Clone, Copy, Debug)]
+pub struct WatchpointHit<T: BrainfuckCell> {
+    /// The index of the accessed cell, within [`DebuggerVM::tape`]
+    pub cell: usize,
+
+    /// How the cell was accessed
+    pub access: CellAccess,
+
+    /// The index of the instruction that accessed the cell
+    pub instr_ptr: usize,
+
+    /// The value of the cell before the instruction was executed
+    pub old: T,
+
+    /// The value of the cell after the instruction was executed
+    pub new: T,
+}
+
+/// A debugger wrapping a [`VirtualMachine`]
+pub struct DebuggerVM<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> {
+    vm: VirtualMachine<T, A, R, W>,
+    instructions: Vec<Instruction>,
+    breakpoints: BTreeSet<usize>,
+    /// The breakpoints of the `#` instructions of the loaded program
+    source_breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    stopped_at: Option<usize>,
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> DebuggerVM<T, A, R, W> {
+    /// Wraps the given VM in a debugger without any breakpoints or watchpoints
+    pub fn new(vm: VirtualMachine<T, A, R, W>) -> Self {
+        DebuggerVM {
+            vm,
+            instructions: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            source_breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            stopped_at: None,
+        }
+    }
+
+    /// Loads the given program into the VM.
+    ///
+    /// The program is compiled without any optimizations, so that every
+    /// instruction index corresponds to exactly one step of the VM.
+    /// All `#` debug instructions in the program are added as breakpoints, replacing
+    /// those of the previously loaded program. Breakpoints added with
+    /// [`DebuggerVM::add_breakpoint`] are kept
+    ///
+    /// ```
+    /// use cpr_bf::{debug::DebuggerVM, Program, VMBuilder};
+    ///
+    /// let mut debugger = DebuggerVM::new(VMBuilder::new().build_typed());
+    ///
+    /// debugger.load_program(&Program::parse("+#+").unwrap()).unwrap();
+    /// debugger.add_breakpoint(3);
+    /// debugger.load_program(&Program::parse("++#+").unwrap()).unwrap();
+    ///
+    /// assert_eq!(debugger.breakpoints().collect::<Vec<_>>(), [2, 3]);
+    /// ```
+    pub fn load_program(&mut self, program: &Program) -> BfResult {
+        log::info!("Loading program into debugger");
+
+        let compiled = program.compile(OptimizationLevel::None)?;
+
+        self.vm.load_compiled(&compiled)?;
+        self.instructions = program.instructions().to_vec();
+        self.source_breakpoints = program.breakpoints().iter().copied().collect();
+        self.stopped_at = None;
+
+        Ok(())
+    }
+
+    /// Adds a breakpoint at the given instruction index
+    pub fn add_breakpoint(&mut self, instr_ptr: usize) {
+        log::debug!("Adding breakpoint at {}", instr_ptr);
+
+        self.breakpoints.insert(instr_ptr);
+    }
+
+    /// Removes the breakpoint at the given instruction index.
+    /// Returns whether a breakpoint was present
+    pub fn remove_breakpoint(&mut self, instr_ptr: usize) -> bool {
+        log::debug!("Removing breakpoint at {}", instr_ptr);
+
+        let removed = self.breakpoints.remove(&instr_ptr);
+
+        self.source_breakpoints.remove(&instr_ptr) || removed
+    }
+
+    /// Removes all breakpoints
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.source_breakpoints.clear();
+    }
+
+    /// The instruction indices at which a breakpoint is set, in ascending order
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.union(&self.source_breakpoints).copied()
+    }
+
+    /// Watches the cells at the given indices within [`DebuggerVM::tape`].
+    /// The program is stopped right after it accesses one of these cells in the given way.
+    ///
+    /// Note that with an allocator that extends the tape to the left, such as
+    /// [`crate::allocators::DoublyInfiniteAllocator`], the indices of the cells
+    /// shift whenever the tape is extended
+    pub fn add_watchpoint(&mut self, cells: Range<usize>, mode: WatchMode) {
+        log::debug!("Adding watchpoint on cells {:?} ({:?})", cells, mode);
+
+        self.watchpoints.push(Watchpoint { cells, mode });
+    }
+
+    /// Removes the watchpoints on exactly the given cells.
+    /// Returns whether any watchpoint was present
+    pub fn remove_watchpoint(&mut self, cells: Range<usize>) -> bool {
+        log::debug!("Removing watchpoints on cells {:?}", cells);
+
+        let len = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.cells != cells);
+
+        self.watchpoints.len() != len
+    }
+
+    /// Removes all watchpoints
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// The watchpoints, in the order they were added
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> + '_ {
+        self.watchpoints.iter()
+    }
+
+    /// Executes a single instruction, ignoring any breakpoints and watchpoints
+    pub fn step(&mut self) -> ExecutionState {
+        self.stopped_at = None;
+        self.vm.step()
+    }
+
+    /// Runs the loaded program until a breakpoint is hit, or the program stops.
+    ///
+    /// If the debugger is currently stopped at a breakpoint, that breakpoint is
+    /// not hit again, so that calling this repeatedly continues the program
+    pub fn continue_execution(&mut self) -> StopReason<T> {
+        loop {
+            if let Some(reason) = self.continue_for(u64::MAX) {
+                return reason;
+            }
+        }
+    }
+
+    /// Like [`DebuggerVM::continue_execution`], but executes at most `max_steps`
+    /// instructions. Returns [`None`] if the program is still running after that,
+    /// so that callers can stay responsive while running long programs
+    pub fn continue_for(&mut self, max_steps: u64) -> Option<StopReason<T>> {
+        for _ in 0..max_steps {
+            let instr_ptr = self.vm.instr_ptr;
+
+            if self.stopped_at != Some(instr_ptr)
+                && instr_ptr < self.instructions.len()
+                && (self.breakpoints.contains(&instr_ptr)
+                    || self.source_breakpoints.contains(&instr_ptr))
+            {
+                log::info!("Hit breakpoint at {}", instr_ptr);
+
+                self.stopped_at = Some(instr_ptr);
+                return Some(StopReason::Breakpoint(instr_ptr));
+            }
+
+            self.stopped_at = None;
+
+            let watched = self
+                .watched_access(instr_ptr)
+                .map(|(cell, access)| (cell, access, self.cell(cell)));
+
+            match (self.vm.step(), watched) {
+                (ExecutionState::Error(e), _) => return Some(StopReason::Error(e)),
+                (_, Some((cell, access, old))) => {
+                    log::info!("Hit watchpoint on cell {} at {}", cell, instr_ptr);
+
+                    return Some(StopReason::Watchpoint(WatchpointHit {
+                        cell,
+                        access,
+                        instr_ptr,
+                        old,
+                        new: self.cell(cell),
+                    }));
+                }
+                (ExecutionState::Running, None) => {}
+                (ExecutionState::Halted, None) => return Some(StopReason::Halted),
+            }
+        }
+
+        None
+    }
+
+    /// The watched cell accessed by the operation at `instr_ptr`, and how
+    /// it is accessed. [`None`] if no watchpoint applies to the operation
+    fn watched_access(&self, instr_ptr: usize) -> Option<(usize, CellAccess)> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+
+        let op = *self.vm.program.as_ref()?.ops().get(instr_ptr)?;
+        let (offset, access) = op_access(op)?;
+        let cell = self.vm.data_ptr.checked_add_signed(offset)?;
+
+        self.watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.cells.contains(&cell) && watchpoint.mode.matches(access))
+            .then_some((cell, access))
+    }
+
+    /// The value of the cell at index `cell`. Cells that have
+    /// not been allocated yet are zero
+    fn cell(&self, cell: usize) -> T {
+        self.vm.data.get(cell).copied().unwrap_or_default()
+    }
+
+    /// The currently allocated memory cells of the VM
+    pub fn tape(&self) -> &[T] {
+        &self.vm.data
+    }
+
+    /// The current data pointer of the VM
+    pub fn data_ptr(&self) -> usize {
+        self.vm.data_ptr
+    }
+
+    /// The index of the next instruction to be executed
+    pub fn instr_ptr(&self) -> usize {
+        self.vm.instr_ptr
+    }
+
+    /// The instructions of the loaded program
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// The next instruction to be executed, if any
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.instructions.get(self.vm.instr_ptr).copied()
+    }
+
+    /// The wrapped VM
+    pub fn vm(&self) -> &VirtualMachine<T, A, R, W> {
+        &self.vm
+    }
+
+    /// Mutable access to the wrapped VM
+    pub fn vm_mut(&mut self) -> &mut VirtualMachine<T, A, R, W> {
+        &mut self.vm
+    }
+
+    /// Consumes the debugger, returning the wrapped VM
+    pub fn into_inner(self) -> VirtualMachine<T, A, R, W> {
+        self.vm
+    }
+}
+
+/// The cell accessed by `op`, relative to the data pointer, and how it is accessed.
+/// [`None`] if the operation does not access the current thread's tape
+fn op_access(op: Op) -> Option<(isize, CellAccess)> {
+    match op {
+        Op::AddAt(offset, _) => Some((offset, CellAccess::Write)),
+        Op::Add(_)
+        | Op::SetZero
+        | Op::Input
+        | Op::Load
+        | Op::ShiftRight
+        | Op::ShiftLeft
+        | Op::Not
+        | Op::Xor
+        | Op::And
+        | Op::Or
+        | Op::HostCall
+        | Op::Random => Some((0, CellAccess::Write)),
+        Op::Output
+        | Op::Scan(_)
+        | Op::JumpFwd(_)
+        | Op::JumpBack(_)
+        | Op::DefineProc(_)
+        | Op::CallProc
+        | Op::Store => Some((0, CellAccess::Read)),
+        Op::Move(_) | Op::EndProc | Op::Fork | Op::Exit => None,
+    }
+}
@@ -0,0 +1,61 @@
+//! Contains front-ends for Brainfuck dialects, which either use a different syntax
+//! for the same eight instructions, or add instructions from one of the [`crate::extensions`]
+//!
+//! Every dialect is parsed into a regular [`Program`], so the VM and the optimizer
+//! work the same regardless of the dialect. Dialects with extensions additionally
+//! need those to be enabled on the VM, see [`Dialect::extensions`].
+//!
+//! ```
+//! use cpr_bf::dialects::Dialect;
+//!
+//! let program = Dialect::Ook.parse("Ook. Ook. Ook! Ook.").unwrap();
+//!
+//! assert_eq!(program.instructions(), Dialect::Brainfuck.parse("+.").unwrap().instructions());
+//! ```
+
+use crate::{extensions::ExtensionSet, ParseError, Program};
+
+pub mod ook;
+
+/// The supported source code dialects
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// Regular Brainfuck source code, see [`Program::parse`]
+    #[default]
+    Brainfuck,
+
+    /// Ook! source code, see [`ook::parse`]
+    Ook,
+
+    /// Brainfuck source code with the procedures of [`ExtensionSet::PBRAIN`]
+    PBrain,
+
+    /// Brainfuck source code with the threads of [`ExtensionSet::BRAINFORK`]
+    Brainfork,
+
+    /// Brainfuck source code with the instructions of [`ExtensionSet::EXTENDED_TYPE_I`]
+    ExtendedTypeI,
+}
+
+impl Dialect {
+    /// Parses `input` as source code in this dialect
+    pub fn parse(self, input: &str) -> Result<Program, ParseError> {
+        match self {
+            Dialect::Brainfuck => Program::parse(input),
+            Dialect::Ook => ook::parse(input),
+            Dialect::PBrain | Dialect::Brainfork | Dialect::ExtendedTypeI => {
+                Program::parse_with_extensions(input, self.extensions())
+            }
+        }
+    }
+
+    /// The extensions used by programs in this dialect
+    pub fn extensions(self) -> ExtensionSet {
+        match self {
+            Dialect::Brainfuck | Dialect::Ook => ExtensionSet::NONE,
+            Dialect::PBrain => ExtensionSet::PBRAIN,
+            Dialect::Brainfork => ExtensionSet::BRAINFORK,
+            Dialect::ExtendedTypeI => ExtensionSet::EXTENDED_TYPE_I,
+        }
+    }
+}
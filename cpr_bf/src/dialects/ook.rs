@@ -0,0 +1,151 @@
+//! Contains a parser for [Ook!](https://esolangs.org/wiki/Ook!), a Brainfuck
+//! dialect in which every instruction is a pair of `Ook.`, `Ook?` and `Ook!` tokens
+//!
+//! | Ook!        | Brainfuck |
+//! |-------------|-----------|
+//! | `Ook. Ook?` | `>`       |
+//! | `Ook? Ook.` | `<`       |
+//! | `Ook. Ook.` | `+`       |
+//! | `Ook! Ook!` | `-`       |
+//! | `Ook! Ook.` | `.`       |
+//! | `Ook. Ook!` | `,`       |
+//! | `Ook! Ook?` | `[`       |
+//! | `Ook? Ook!` | `]`       |
+//!
+//! Tokens are separated by whitespace, which may be omitted.
+//! `Ook? Ook?` does not correspond to any instruction, and is rejected.
+
+use alloc::vec::Vec;
+
+use crate::{Instruction, MissingKind, ParseError, Program, SourcePosition, UnmatchedJump};
+
+/// The punctuation of a single Ook! token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+    Period,
+    Question,
+    Exclamation,
+}
+
+/// Parses the given Ook! source code into a [`Program`].
+///
+/// Returns a [`ParseError::InvalidToken`] with the position of the first invalid
+/// token or token pair, and a [`ParseError::JumpMismatch`] for mismatched loops
+///
+/// ```
+/// use cpr_bf::dialects::ook;
+///
+/// let program = ook::parse("Ook. Ook. Ook! Ook? Ook! Ook! Ook? Ook!").unwrap();
+///
+/// assert_eq!(program.instructions().len(), 4);
+/// assert!(ook::parse("Ook. Ook").is_err());
+/// ```
+pub fn parse(input: &str) -> Result<Program, ParseError> {
+    let mut instructions = Vec::new();
+    let mut source_positions = Vec::new();
+    let mut open_stack = Vec::new();
+    let mut pending: Option<(Token, SourcePosition)> = None;
+    let mut position = SourcePosition {
+        offset: 0,
+        line: 1,
+        column: 1,
+    };
+
+    let mut rest = input;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            advance(&mut position, c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let token_pos = position;
+
+        let token = rest
+            .strip_prefix("Ook")
+            .and_then(|after| match after.chars().next()? {
+                '.' => Some(Token::Period),
+                '?' => Some(Token::Question),
+                '!' => Some(Token::Exclamation),
+                _ => None,
+            })
+            .ok_or(ParseError::InvalidToken {
+                position: token_pos,
+            })?;
+
+        for c in rest[..4].chars() {
+            advance(&mut position, c);
+        }
+
+        rest = &rest[4..];
+
+        let Some((first, pair_pos)) = pending.take() else {
+            pending = Some((token, token_pos));
+            continue;
+        };
+
+        let instr =
+            instruction(first, token).ok_or(ParseError::InvalidToken { position: pair_pos })?;
+
+        match instr {
+            Instruction::JumpFwd => open_stack.push(pair_pos),
+            Instruction::JumpBack if open_stack.pop().is_none() => {
+                return Err(ParseError::JumpMismatch(UnmatchedJump {
+                    missing: MissingKind::JumpFwd,
+                    position: Some(pair_pos),
+                }));
+            }
+            _ => {}
+        }
+
+        instructions.push(instr);
+        source_positions.push(pair_pos);
+    }
+
+    if let Some((_, pair_pos)) = pending {
+        log::debug!("Incomplete token pair at {}", pair_pos);
+
+        return Err(ParseError::InvalidToken { position: pair_pos });
+    }
+
+    if let Some(&unmatched) = open_stack.first() {
+        return Err(ParseError::JumpMismatch(UnmatchedJump {
+            missing: MissingKind::JumpBack,
+            position: Some(unmatched),
+        }));
+    }
+
+    Ok(Program {
+        source_len: Some(input.len()),
+        source_positions,
+        ..Program::from_parts(instructions, Vec::new())
+    })
+}
+
+/// The instruction corresponding to the token pair `first` `second`
+fn instruction(first: Token, second: Token) -> Option<Instruction> {
+    match (first, second) {
+        (Token::Period, Token::Question) => Some(Instruction::IncrDP),
+        (Token::Question, Token::Period) => Some(Instruction::DecrDP),
+        (Token::Period, Token::Period) => Some(Instruction::Incr),
+        (Token::Exclamation, Token::Exclamation) => Some(Instruction::Decr),
+        (Token::Exclamation, Token::Period) => Some(Instruction::Output),
+        (Token::Period, Token::Exclamation) => Some(Instruction::Input),
+        (Token::Exclamation, Token::Question) => Some(Instruction::JumpFwd),
+        (Token::Question, Token::Exclamation) => Some(Instruction::JumpBack),
+        (Token::Question, Token::Question) => None,
+    }
+}
+
+/// Moves `position` past the character `c`
+fn advance(position: &mut SourcePosition, c: char) {
+    position.offset += c.len_utf8();
+
+    if c == '\n' {
+        position.line += 1;
+        position.column = 1;
+    } else {
+        position.column += 1;
+    }
+}
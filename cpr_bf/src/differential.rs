@@ -0,0 +1,341 @@
+//! Contains a differential tester, running a program on every execution backend
+//!
+//! Every backend should give a program the same meaning, but each takes a different
+//! route there: the interpreter runs the instructions one by one without optimizations,
+//! or the operations of the optimized IR, and the JIT compiles those to native code.
+//! A [`DifferentialTest`] runs the same program and input on all of them, and compares
+//! whether they succeed, everything they write, and the final tape.
+//!
+//! ```
+//! use cpr_bf::differential::{Agreement, DifferentialTest};
+//!
+//! let program = ",[->+>+<<]>[-<+>]>.".into();
+//! let agreement = DifferentialTest::new().run(&program, b"A").unwrap();
+//!
+//! assert_eq!(
+//!     agreement,
+//!     Agreement::Identical {
+//!         output: b"A".to_vec(),
+//!         tape: vec![65, 0, 65],
+//!     }
+//! );
+//! ```
+//!
+//! Some differences between the backends are allowed, and make the result
+//! [`Agreement::Inconclusive`] instead of a [`Mismatch`]:
+//! - Optimizations may remove pointer movements that cancel each other out, such as
+//!   `<>`, and the JIT fails as soon as the data pointer leaves its tape, even if no cell
+//!   is accessed there. Whether a program leaves the tape can therefore differ between
+//!   backends
+//! - Optimized operations can take multiple instructions in a single step, so the
+//!   step limit can be reached by some backends, but not by others
+//! - Brainfork threads are interleaved one step at a time, so their output depends on
+//!   how many instructions each step takes
+//!
+//! The interpreter runs with byte cells, wrapping arithmetic and with the current cell
+//! left unchanged at the end of the input, like the JIT. Programs using extensions are
+//! only run on the interpreter, with those extensions enabled. The random number
+//! generator is seeded with the same value for every backend.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Display;
+
+use crate::{
+    extensions::ExtensionSet, ir::OptimizationLevel, BfResult, BrainfuckExecutionError,
+    BrainfuckVM, Program, VMBuilder,
+};
+
+/// A way of running programs, see the [module documentation](self)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The interpreter, running the program compiled with the given optimization level
+    Interpreter(OptimizationLevel),
+
+    /// The JIT compiler of the [`crate::jit`] module, running the fully optimized program
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Backend::Interpreter(level) => write!(f, "interpreter ({:?} optimization)", level),
+            #[cfg(feature = "jit")]
+            Backend::Jit => write!(f, "JIT"),
+        }
+    }
+}
+
+/// The result of running a program on a single backend
+#[derive(Debug)]
+pub struct BackendRun {
+    /// The backend the program ran on
+    pub backend: Backend,
+
+    /// Whether the program succeeded
+    pub result: BfResult,
+
+    /// Everything the program wrote before it stopped
+    pub output: Vec<u8>,
+
+    /// The final values of the cells, without the zero cells at the end of the tape
+    pub tape: Vec<u64>,
+}
+
+/// How the backends agree on a program, see [`DifferentialTest::run`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Agreement {
+    /// Every backend succeeded, with the same output and final tape.
+    /// The tape does not include the zero cells at its end
+    Identical { output: Vec<u8>, tape: Vec<u64> },
+
+    /// Every backend failed. The errors, and the output and tape up to the
+    /// errors, are not compared, as optimizations may change when a program fails
+    Failed,
+
+    /// The backends differ in a way that is allowed, see the [module documentation](self)
+    Inconclusive,
+}
+
+/// The backends disagree on a program, see [`DifferentialTest::run`]
+#[derive(Debug)]
+pub struct Mismatch {
+    /// The result of every backend the program ran on
+    pub runs: Vec<BackendRun>,
+}
+
+impl Display for Mismatch {
+    /// Writes the result, output and tape of every backend on a separate line
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Backends disagree:")?;
+
+        for run in &self.runs {
+            let result = match &run.result {
+                Ok(()) => String::from("ok"),
+                Err(e) => format!("error \"{}\"", e),
+            };
+
+            write!(
+                f,
+                "\n  {}: {}, output {:?}, tape {:?}",
+                run.backend, result, run.output, run.tape
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl core::error::Error for Mismatch {}
+
+/// Runs programs on every backend and compares the results,
+/// see the [module documentation](self)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DifferentialTest {
+    max_cells: usize,
+    max_steps: u64,
+}
+
+impl Default for DifferentialTest {
+    /// Runs programs on a tape of 30000 cells, for up to 10 million steps
+    fn default() -> Self {
+        DifferentialTest {
+            max_cells: 30_000,
+            max_steps: 10_000_000,
+        }
+    }
+}
+
+impl DifferentialTest {
+    /// Runs programs on a tape of 30000 cells, for up to 10 million steps
+    pub fn new() -> Self {
+        DifferentialTest::default()
+    }
+
+    /// Changes the amount of cells of the tape. At least one cell is always available
+    pub fn with_max_cells(self, max_cells: usize) -> Self {
+        DifferentialTest {
+            max_cells: max_cells.max(1),
+            ..self
+        }
+    }
+
+    /// Changes the amount of steps the interpreter runs a program for. As the JIT has
+    /// no step limit, a program only runs on the JIT if it stops within this limit on
+    /// every other backend
+    pub fn with_max_steps(self, max_steps: u64) -> Self {
+        DifferentialTest { max_steps, ..self }
+    }
+
+    /// Runs `program` with `input` on every backend, and compares the results.
+    /// Returns a [`Mismatch`] with the result of every backend if they disagree
+    pub fn run(&self, program: &Program, input: &[u8]) -> Result<Agreement, Mismatch> {
+        log::info!("Running program on every backend");
+
+        let runs: Vec<BackendRun> = [
+            OptimizationLevel::None,
+            OptimizationLevel::Basic,
+            OptimizationLevel::Full,
+        ]
+        .into_iter()
+        .map(|level| self.run_interpreter(program, input, level))
+        .collect();
+
+        #[cfg(feature = "jit")]
+        let runs = if program.extensions().is_empty() && !runs.iter().any(hit_step_limit) {
+            runs.into_iter()
+                .chain(self.run_jit(program, input))
+                .collect()
+        } else {
+            runs
+        };
+
+        compare(program, runs)
+    }
+
+    /// Runs `program` on the interpreter, compiled with the given optimization level
+    fn run_interpreter(
+        &self,
+        program: &Program,
+        mut input: &[u8],
+        level: OptimizationLevel,
+    ) -> BackendRun {
+        let mut vm = VMBuilder::new()
+            .with_optimization_level(level)
+            .with_extensions(program.extensions())
+            .with_max_cells(self.max_cells)
+            .with_max_steps(self.max_steps)
+            .with_rng_seed(0)
+            .with_reader(&[][..])
+            .with_writer(Vec::new())
+            .build_typed();
+
+        let mut output = Vec::new();
+        let result = vm.run_program_with_io(program, &mut input, &mut output);
+
+        BackendRun {
+            backend: Backend::Interpreter(level),
+            result,
+            output,
+            tape: trim_zeros(vm.cells_as_u64()),
+        }
+    }
+
+    /// Runs the fully optimized `program` on the JIT. Returns [`None`] if
+    /// the JIT is not supported on this machine
+    #[cfg(feature = "jit")]
+    fn run_jit(&self, program: &Program, mut input: &[u8]) -> Option<BackendRun> {
+        use crate::jit::{JitError, JitProgram};
+
+        let compiled = match program.compile(OptimizationLevel::Full) {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                return Some(BackendRun {
+                    backend: Backend::Jit,
+                    result: Err(e.into()),
+                    output: Vec::new(),
+                    tape: Vec::new(),
+                })
+            }
+        };
+
+        let jit = match JitProgram::compile(&compiled) {
+            Ok(jit) => jit,
+            Err(e @ JitError::UnsupportedHost(_)) => {
+                log::warn!("Not running on the JIT: {}", e);
+                return None;
+            }
+            Err(e) => panic!("Failed to JIT compile a program without extensions: {}", e),
+        };
+
+        let mut tape = alloc::vec![0_u8; self.max_cells];
+        let mut output = Vec::new();
+        let result = jit.run(&mut tape, &mut input, &mut output);
+
+        Some(BackendRun {
+            backend: Backend::Jit,
+            result,
+            output,
+            tape: trim_zeros(tape.into_iter().map(u64::from).collect()),
+        })
+    }
+}
+
+/// Runs `program` with `input` on every backend with the default [`DifferentialTest`],
+/// and panics with the results of every backend if they disagree.
+///
+/// ```
+/// use cpr_bf::differential;
+///
+/// differential::assert_consistent(&"+[>,.<-]".into(), b"");
+/// ```
+pub fn assert_consistent(program: &Program, input: &[u8]) {
+    if let Err(mismatch) = DifferentialTest::new().run(program, input) {
+        panic!("{}", mismatch);
+    }
+}
+
+/// Compares the results of all backends, see [`DifferentialTest::run`]
+fn compare(program: &Program, runs: Vec<BackendRun>) -> Result<Agreement, Mismatch> {
+    if program.extensions().contains(ExtensionSet::BRAINFORK) {
+        return Ok(Agreement::Inconclusive);
+    }
+
+    let failed = runs.iter().filter(|run| run.result.is_err()).count();
+
+    if failed == runs.len() {
+        return Ok(Agreement::Failed);
+    }
+
+    if failed > 0 {
+        let allowed = runs
+            .iter()
+            .filter_map(|run| run.result.as_ref().err())
+            .all(|e| {
+                matches!(
+                    e.kind(),
+                    BrainfuckExecutionError::MemoryError(_)
+                        | BrainfuckExecutionError::DataPointerOverflow
+                        | BrainfuckExecutionError::DataPointerUnderflow
+                        | BrainfuckExecutionError::StepLimitExceeded
+                )
+            });
+
+        return match allowed {
+            true => Ok(Agreement::Inconclusive),
+            false => Err(Mismatch { runs }),
+        };
+    }
+
+    let first = &runs[0];
+
+    if runs
+        .iter()
+        .any(|run| run.output != first.output || run.tape != first.tape)
+    {
+        return Err(Mismatch { runs });
+    }
+
+    Ok(Agreement::Identical {
+        output: first.output.clone(),
+        tape: first.tape.clone(),
+    })
+}
+
+/// Whether the program ran out of steps on the given backend
+#[cfg(feature = "jit")]
+fn hit_step_limit(run: &BackendRun) -> bool {
+    run.result
+        .as_ref()
+        .is_err_and(|e| matches!(e.kind(), BrainfuckExecutionError::StepLimitExceeded))
+}
+
+/// Removes the zero cells at the end of a tape, as backends allocate different amounts of cells
+fn trim_zeros(mut tape: Vec<u64>) -> Vec<u64> {
+    let len = tape
+        .iter()
+        .rposition(|&cell| cell != 0)
+        .map_or(0, |last| last + 1);
+    tape.truncate(len);
+    tape
+}
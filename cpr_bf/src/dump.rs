@@ -0,0 +1,169 @@
+//! Contains a hexdump-style formatter for the tape of a Brainfuck VM
+//!
+//! [`format_tape`] lays out the cells of a tape in rows, each starting with the
+//! index of its first cell in hexadecimal, followed by the values of its cells
+//! in hexadecimal and their ASCII representation. The cell at the data pointer
+//! is highlighted by surrounding it with brackets.
+//!
+//! ```
+//! use cpr_bf::dump::{self, DumpOptions};
+//!
+//! let tape: [u8; 12] = [0x48, 0x69, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+//! let dump = dump::format_tape(&tape, 1, DumpOptions::new().with_cells_per_row(4));
+//!
+//! // Rows of only zero cells at the end of the tape are left out
+//! assert_eq!(dump, "0000  48[69]00 00  |Hi..|\n");
+//! ```
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+use crate::BrainfuckCell;
+
+/// How [`format_tape`] lays out the tape
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DumpOptions {
+    cells_per_row: usize,
+    context_rows: Option<usize>,
+    trim_zeros: bool,
+    ascii: bool,
+}
+
+impl Default for DumpOptions {
+    /// Shows 16 cells per row with their ASCII representation,
+    /// leaving out the rows of zero cells at the end of the tape
+    fn default() -> Self {
+        DumpOptions {
+            cells_per_row: 16,
+            context_rows: None,
+            trim_zeros: true,
+            ascii: true,
+        }
+    }
+}
+
+impl DumpOptions {
+    /// Shows 16 cells per row with their ASCII representation,
+    /// leaving out the rows of zero cells at the end of the tape
+    pub fn new() -> Self {
+        DumpOptions::default()
+    }
+
+    /// Changes the amount of cells shown in every row. At least one cell is always shown
+    pub fn with_cells_per_row(self, cells_per_row: usize) -> Self {
+        DumpOptions {
+            cells_per_row: cells_per_row.max(1),
+            ..self
+        }
+    }
+
+    /// Only shows the row of the data pointer, and at most `context_rows`
+    /// rows before and after it, instead of the whole tape
+    pub fn with_context_rows(self, context_rows: usize) -> Self {
+        DumpOptions {
+            context_rows: Some(context_rows),
+            ..self
+        }
+    }
+
+    /// Whether the rows of zero cells at the end of the tape are left out.
+    /// The row of the data pointer is always shown
+    pub fn with_trimmed_zeros(self, trim_zeros: bool) -> Self {
+        DumpOptions { trim_zeros, ..self }
+    }
+
+    /// Whether every row ends with the ASCII representation of its cells.
+    /// Cells that are not printable ASCII characters are shown as `.`
+    pub fn with_ascii(self, ascii: bool) -> Self {
+        DumpOptions { ascii, ..self }
+    }
+}
+
+/// Formats `cells` as a hexdump in the given layout, highlighting
+/// the cell at `data_ptr`. Every row ends with a newline.
+///
+/// All cells are shown with the same amount of hexadecimal digits, enough to fit
+/// the largest value shown. Cells are converted with [`BrainfuckCell::to_u64`].
+/// If the data pointer is beyond the end of `cells`, the cells up to it are
+/// shown as zero, as they have not been allocated yet
+pub fn format_tape<T: BrainfuckCell>(cells: &[T], data_ptr: usize, options: DumpOptions) -> String {
+    let len = cells.len().max(data_ptr.saturating_add(1));
+    let values: Vec<u64> = (0..len)
+        .map(|i| cells.get(i).map_or(0, |cell| cell.to_u64()))
+        .collect();
+
+    let per_row = options.cells_per_row;
+    let ptr_row = data_ptr / per_row;
+
+    let mut end_row = if options.trim_zeros {
+        values
+            .iter()
+            .rposition(|&value| value != 0)
+            .map_or(0, |last| last / per_row + 1)
+            .max(ptr_row + 1)
+    } else {
+        len.div_ceil(per_row)
+    };
+
+    let mut start_row = 0;
+
+    if let Some(context) = options.context_rows {
+        start_row = ptr_row.saturating_sub(context);
+        end_row = end_row.min(ptr_row + context + 1);
+    }
+
+    let shown = &values[start_row * per_row..(end_row * per_row).min(len)];
+
+    let hex_width = hex_digits(shown.iter().copied().max().unwrap_or(0)).max(2);
+    let offset_width = hex_digits(((end_row - 1) * per_row) as u64).max(4);
+
+    let mut out = String::new();
+
+    for row in start_row..end_row {
+        let start = row * per_row;
+        let row_cells = &values[start..(start + per_row).min(len)];
+
+        let _ = write!(out, "{:0offset_width$x} ", start);
+
+        for (i, cell) in row_cells.iter().enumerate() {
+            let separator = match start + i {
+                index if index == data_ptr => '[',
+                index if index == data_ptr + 1 && i > 0 => ']',
+                _ => ' ',
+            };
+
+            let _ = write!(out, "{}{:0hex_width$x}", separator, cell);
+        }
+
+        let closed = start + row_cells.len() == data_ptr + 1;
+        out.push(if closed { ']' } else { ' ' });
+
+        if options.ascii {
+            let missing = per_row - row_cells.len();
+            out.extend(core::iter::repeat_n(' ', missing * (hex_width + 1) + 1));
+
+            out.push('|');
+            out.extend(row_cells.iter().map(|&cell| ascii(cell)));
+            out.push('|');
+        } else {
+            out.truncate(out.trim_end().len());
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+/// The amount of hexadecimal digits needed to show `value`
+fn hex_digits(value: u64) -> usize {
+    (u64::BITS - value.leading_zeros()).div_ceil(4).max(1) as usize
+}
+
+/// The ASCII representation of a cell in the hexdump
+fn ascii(value: u64) -> char {
+    match u8::try_from(value) {
+        Ok(byte) if byte.is_ascii_graphic() || byte == b' ' => char::from(byte),
+        _ => '.',
+    }
+}
@@ -0,0 +1,309 @@
+//! Contains the optional extensions to the Brainfuck language
+//!
+//! Extensions add instructions on top of the eight standard Brainfuck instructions.
+//! They are disabled by default, in which case their characters are ignored like any
+//! other comment. Programs using them are parsed with [`Program::parse_with_extensions`],
+//! and can only be run by a VM that has the extensions enabled through
+//! [`VMBuilder::with_extensions`].
+//!
+//! The available extensions are:
+//! - [`ExtensionSet::PBRAIN`]: procedures, from pbrain. `(` and `)` enclose the body
+//!   of a procedure, which is identified by the value of the current cell when the
+//!   definition is reached. Defining a procedure does not run it. `:` calls the
+//!   procedure identified by the value of the current cell
+//! - [`ExtensionSet::BRAINFORK`]: threads, from Brainfork. `Y` forks the running thread.
+//!   The new thread continues after the `Y` with its own copy of the tape, in which the
+//!   current cell is set to zero, while the original thread keeps its cell unchanged.
+//!   The VM runs all threads by interleaving them one operation at a time, and the
+//!   program ends once all threads have finished. Since the threads share the reader
+//!   and writer of the VM, their output is merged in the order it was written
+//! - [`ExtensionSet::EXTENDED_TYPE_I`]: the Extended Brainfuck Type I instructions, which
+//!   operate on a single storage cell. `@` ends the program, `$` copies the current cell
+//!   into the storage cell, and `!` copies the storage cell into the current cell.
+//!   `}` and `{` shift the bits of the current cell one position to the right or left,
+//!   `~` inverts its bits, and `^`, `&` and `|` combine it with the storage cell using
+//!   a bitwise XOR, AND or OR. Threads share the same storage cell
+//! - [`ExtensionSet::HOST_CALL`]: calls into the host program. `%` calls the host
+//!   function identified by the value of the current cell, which is registered with
+//!   [`VMBuilder::with_host_function`]. The function gets mutable access to the tape
+//!   through a [`HostCall`], and can for example read its arguments from the cells
+//!   after the current cell and write its result back to the tape
+//! - [`ExtensionSet::RANDOM`]: random numbers. `?` stores a random byte in the current
+//!   cell. The random numbers are generated by a pseudo-random number generator, which
+//!   can be seeded with [`VMBuilder::with_rng_seed`] to make runs reproducible
+//!
+//! ```
+//! use cpr_bf::{extensions::ExtensionSet, BrainfuckVM, VMBuilder};
+//!
+//! let mut vm = VMBuilder::new()
+//!     .with_extensions(ExtensionSet::PBRAIN)
+//!     .with_writer(Vec::new())
+//!     .build_typed();
+//!
+//! // Defines procedure 1, which adds 2 to the next cell, and calls it twice
+//! vm.run_string("+(>++<)::>.").unwrap();
+//!
+//! assert_eq!(vm.writer(), &[4]);
+//! ```
+//!
+//! Multiple extensions can be combined:
+//!
+//! ```
+//! use cpr_bf::{extensions::ExtensionSet, BrainfuckVM, VMBuilder};
+//!
+//! let mut vm = VMBuilder::new()
+//!     .with_extensions(ExtensionSet::PBRAIN | ExtensionSet::BRAINFORK)
+//!     .with_writer(Vec::new())
+//!     .build_typed();
+//!
+//! // Only the original thread keeps the current cell, and prints 1. Both threads print 2
+//! vm.run_string("+Y[.[-]]++.").unwrap();
+//!
+//! assert_eq!(vm.writer(), &[1, 2, 2]);
+//! ```
+//!
+//! With the Extended Type I instructions, values can be copied between cells through
+//! the storage cell:
+//!
+//! ```
+//! use cpr_bf::{extensions::ExtensionSet, BrainfuckVM, VMBuilder};
+//!
+//! let mut vm = VMBuilder::new()
+//!     .with_extensions(ExtensionSet::EXTENDED_TYPE_I)
+//!     .with_writer(Vec::new())
+//!     .build_typed();
+//!
+//! // Copies 3 into the next cell, doubles it and prints it. Nothing after the `@` is run
+//! vm.run_string("+++$>!{.@.").unwrap();
+//!
+//! assert_eq!(vm.writer(), &[6]);
+//! ```
+//!
+//! Host functions make it possible to call into Rust code from Brainfuck programs:
+//!
+//! ```
+//! use cpr_bf::{extensions::ExtensionSet, BrainfuckVM, VMBuilder};
+//!
+//! let mut vm = VMBuilder::new()
+//!     .with_extensions(ExtensionSet::HOST_CALL)
+//!     .with_host_function(1, |call| {
+//!         // Multiplies the two cells after the current cell
+//!         let product = call.get(1).unwrap_or(0) * call.get(2).unwrap_or(0);
+//!         call.set_cell(product);
+//!         Ok(())
+//!     })
+//!     .with_writer(Vec::new())
+//!     .build_typed();
+//!
+//! vm.run_string("+>++++++>+++++++<<%.").unwrap();
+//!
+//! assert_eq!(vm.writer(), &[42]);
+//! ```
+//!
+//! [`Program::parse_with_extensions`]: crate::Program::parse_with_extensions
+//! [`VMBuilder::with_extensions`]: crate::VMBuilder::with_extensions
+//! [`VMBuilder::with_host_function`]: crate::VMBuilder::with_host_function
+//! [`VMBuilder::with_rng_seed`]: crate::VMBuilder::with_rng_seed
+
+use core::{
+    fmt::{Debug, Display},
+    ops::{BitOr, BitOrAssign},
+};
+
+use crate::{tape::Tape, BrainfuckCell};
+
+/// A set of language extensions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtensionSet(u8);
+
+impl ExtensionSet {
+    /// No extensions, only the standard Brainfuck instructions
+    pub const NONE: ExtensionSet = ExtensionSet(0);
+
+    /// Procedure definitions and calls with `(`, `)` and `:`
+    pub const PBRAIN: ExtensionSet = ExtensionSet(1 << 0);
+
+    /// Forking threads with `Y`
+    pub const BRAINFORK: ExtensionSet = ExtensionSet(1 << 1);
+
+    /// The storage cell and bitwise instructions `@$!}{~^&|`
+    pub const EXTENDED_TYPE_I: ExtensionSet = ExtensionSet(1 << 2);
+
+    /// Calling host functions with `%`
+    pub const HOST_CALL: ExtensionSet = ExtensionSet(1 << 3);
+
+    /// Random numbers with `?`
+    pub const RANDOM: ExtensionSet = ExtensionSet(1 << 4);
+
+    /// Every extension, with its name
+    const NAMED: &'static [(ExtensionSet, &'static str)] = &[
+        (ExtensionSet::PBRAIN, "pbrain"),
+        (ExtensionSet::BRAINFORK, "brainfork"),
+        (ExtensionSet::EXTENDED_TYPE_I, "extended type I"),
+        (ExtensionSet::HOST_CALL, "host call"),
+        (ExtensionSet::RANDOM, "random"),
+    ];
+
+    /// Whether every extension in `other` is part of this set
+    pub const fn contains(self, other: ExtensionSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether this set contains no extensions
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The extensions that are part of either set
+    pub const fn union(self, other: ExtensionSet) -> ExtensionSet {
+        ExtensionSet(self.0 | other.0)
+    }
+
+    /// The extensions in this set that are not part of `other`
+    pub const fn difference(self, other: ExtensionSet) -> ExtensionSet {
+        ExtensionSet(self.0 & !other.0)
+    }
+}
+
+impl BitOr for ExtensionSet {
+    type Output = ExtensionSet;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl BitOrAssign for ExtensionSet {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
+impl Display for ExtensionSet {
+    /// Writes the names of the extensions, separated by commas
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "none");
+        }
+
+        let names = ExtensionSet::NAMED
+            .iter()
+            .filter(|(extension, _)| self.contains(*extension))
+            .map(|(_, name)| name);
+
+        for (i, name) in names.enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{}", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The tape of the running program, as seen by a host function of
+/// [`ExtensionSet::HOST_CALL`]. Cells are addressed relative to the data pointer
+pub struct HostCall<'a, T: BrainfuckCell> {
+    tape: &'a mut dyn Tape<T>,
+    data_ptr: usize,
+}
+
+impl<'a, T: BrainfuckCell> HostCall<'a, T> {
+    /// The data pointer must point into `tape`
+    pub(crate) fn new(tape: &'a mut dyn Tape<T>, data_ptr: usize) -> Self {
+        HostCall { tape, data_ptr }
+    }
+
+    /// The index of the current cell within [`HostCall::tape`]
+    pub fn data_ptr(&self) -> usize {
+        self.data_ptr
+    }
+
+    /// The value of the current cell, which identifies the called host function
+    pub fn cell(&self) -> T {
+        self.tape.get(self.data_ptr).unwrap_or_default()
+    }
+
+    /// Changes the value of the current cell
+    pub fn set_cell(&mut self, value: T) {
+        self.tape.set(self.data_ptr, value);
+    }
+
+    /// The value of the cell `offset` cells away from the current cell.
+    /// [`None`] if that cell is outside of the allocated tape
+    pub fn get(&self, offset: isize) -> Option<T> {
+        let index = self.data_ptr.checked_add_signed(offset)?;
+
+        self.tape.get(index)
+    }
+
+    /// Changes the value of the cell `offset` cells away from the current cell.
+    /// Returns false without changing anything if that cell is outside of the allocated tape
+    pub fn set(&mut self, offset: isize, value: T) -> bool {
+        match self.data_ptr.checked_add_signed(offset) {
+            Some(index) if index < self.tape.len() => {
+                self.tape.set(index, value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The allocated cells of the tape. Cells beyond its end are zero
+    pub fn tape(&self) -> &dyn Tape<T> {
+        self.tape
+    }
+
+    /// Mutable access to the allocated cells of the tape
+    pub fn tape_mut(&mut self) -> &mut dyn Tape<T> {
+        self.tape
+    }
+}
+
+impl<T: BrainfuckCell> Debug for HostCall<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HostCall")
+            .field("data_ptr", &self.data_ptr)
+            .field("tape_len", &self.tape.len())
+            .finish()
+    }
+}
+
+/// The pseudo-random number generator of [`ExtensionSet::RANDOM`], using SplitMix64.
+/// Fast and small, but not suitable for anything security related
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    /// Seeds a new generator differently for every VM, unless the `std` feature
+    /// is disabled, in which case every VM uses the same seed
+    pub(crate) fn from_entropy() -> Self {
+        #[cfg(feature = "std")]
+        {
+            use std::hash::{BuildHasher, RandomState};
+
+            Rng::new(RandomState::new().hash_one(0_u8))
+        }
+
+        #[cfg(not(feature = "std"))]
+        {
+            Rng::new(0x5EED)
+        }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
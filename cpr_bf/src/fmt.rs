@@ -0,0 +1,202 @@
+//! Contains a formatter and a minifier for Brainfuck source code
+//!
+//! [`format`] lays out source code with one loop body per indentation level,
+//! and [`minify`] strips everything but the instructions. Both keep every instruction
+//! and `#` breakpoint in its original order, so the result parses into exactly the
+//! same [`crate::Program`] as the original source code.
+//!
+//! ```
+//! use cpr_bf::{fmt::{self, FormatStyle}, Program};
+//!
+//! let source = "Prints A: ++++++++[>++++++++<-]>+.";
+//! let formatted = fmt::format(source, FormatStyle::new().with_indent(2));
+//!
+//! assert_eq!(formatted, "Prints A:\n++++++++[\n  >++++++++<-\n]\n>+.\n");
+//! assert_eq!(fmt::minify(source), "++++++++[>++++++++<-]>+.");
+//!
+//! let original = Program::from(source);
+//! assert_eq!(Program::from(formatted.as_str()).instructions(), original.instructions());
+//! ```
+//!
+//! Neither function supports the Ook! dialect, whose instructions consist of
+//! whole words. Since the input of `--bang-input` programs is not Brainfuck
+//! source code, it should be split off before formatting or minifying.
+
+use alloc::string::String;
+
+use crate::{extensions::ExtensionSet, Instruction};
+
+/// How [`format`] lays out source code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FormatStyle {
+    indent: usize,
+    line_width: usize,
+    extensions: ExtensionSet,
+}
+
+impl Default for FormatStyle {
+    /// Indents by 4 spaces and wraps lines at 80 characters,
+    /// without any extensions
+    fn default() -> Self {
+        FormatStyle {
+            indent: 4,
+            line_width: 80,
+            extensions: ExtensionSet::NONE,
+        }
+    }
+}
+
+impl FormatStyle {
+    /// Indents by 4 spaces and wraps lines at 80 characters,
+    /// without any extensions
+    pub fn new() -> Self {
+        FormatStyle::default()
+    }
+
+    /// Changes the amount of spaces every nested loop body is indented by
+    pub fn with_indent(self, indent: usize) -> Self {
+        FormatStyle { indent, ..self }
+    }
+
+    /// Changes the width at which lines of instructions are wrapped, including their
+    /// indentation. Comments are never wrapped
+    pub fn with_line_width(self, line_width: usize) -> Self {
+        FormatStyle { line_width, ..self }
+    }
+
+    /// Formats the instructions of the given extensions as instructions instead of
+    /// comments. Procedure definitions of [`ExtensionSet::PBRAIN`] are indented like loops
+    pub fn with_extensions(self, extensions: ExtensionSet) -> Self {
+        FormatStyle { extensions, ..self }
+    }
+}
+
+/// Strips everything but the standard Brainfuck instructions and `#` breakpoints from `source`
+pub fn minify(source: &str) -> String {
+    minify_with_extensions(source, ExtensionSet::NONE)
+}
+
+/// Like [`minify`], but also keeps the instructions of the given extensions
+pub fn minify_with_extensions(source: &str, extensions: ExtensionSet) -> String {
+    source.chars().filter(|&c| is_code(c, extensions)).collect()
+}
+
+/// Formats `source` in the given style.
+///
+/// Every loop body is put on its own lines, indented one level deeper than the loop.
+/// Instructions are wrapped once their line is full, and every comment is put on
+/// its own line, with its whitespace collapsed into single spaces
+pub fn format(source: &str, style: FormatStyle) -> String {
+    let mut formatter = Formatter {
+        style,
+        out: String::with_capacity(source.len()),
+        depth: 0,
+        line_len: 0,
+    };
+
+    let mut comment = String::new();
+
+    for c in source.chars() {
+        if !is_code(c, style.extensions) {
+            comment.push(c);
+            continue;
+        }
+
+        formatter.comment(&comment);
+        comment.clear();
+
+        match Instruction::from_char(c, style.extensions) {
+            Some(Instruction::JumpFwd | Instruction::ProcStart) => {
+                formatter.code(c);
+                formatter.end_line();
+                formatter.depth += 1;
+            }
+            Some(Instruction::JumpBack | Instruction::ProcEnd) => {
+                formatter.end_line();
+                formatter.depth = formatter.depth.saturating_sub(1);
+                formatter.code(c);
+                formatter.end_line();
+            }
+            _ => formatter.code(c),
+        }
+    }
+
+    formatter.comment(&comment);
+    formatter.end_line();
+
+    formatter.out
+}
+
+/// Whether `c` is kept by the formatter and the minifier
+fn is_code(c: char, extensions: ExtensionSet) -> bool {
+    c == '#' || Instruction::from_char(c, extensions).is_some()
+}
+
+/// Writes formatted source code one line at a time
+struct Formatter {
+    style: FormatStyle,
+    out: String,
+    depth: usize,
+
+    /// The length of the current line, including its indentation.
+    /// Zero if no line was started yet
+    line_len: usize,
+}
+
+impl Formatter {
+    /// Appends an instruction to the current line, wrapping it if it is full
+    fn code(&mut self, c: char) {
+        if self.line_len >= self.style.line_width.max(self.indentation() + 1) {
+            self.end_line();
+        }
+
+        if self.line_len == 0 {
+            self.start_line();
+        }
+
+        self.out.push(c);
+        self.line_len += 1;
+    }
+
+    /// Puts a comment on its own line. Does nothing for comments
+    /// consisting of only whitespace
+    fn comment(&mut self, comment: &str) {
+        let mut words = comment.split_whitespace().peekable();
+
+        if words.peek().is_none() {
+            return;
+        }
+
+        self.end_line();
+        self.start_line();
+
+        for (i, word) in words.enumerate() {
+            if i > 0 {
+                self.out.push(' ');
+            }
+
+            self.out.push_str(word);
+        }
+
+        self.out.push('\n');
+        self.line_len = 0;
+    }
+
+    fn start_line(&mut self) {
+        let indentation = self.indentation();
+
+        self.out.extend(core::iter::repeat_n(' ', indentation));
+        self.line_len = indentation;
+    }
+
+    fn end_line(&mut self) {
+        if self.line_len > 0 {
+            self.out.push('\n');
+            self.line_len = 0;
+        }
+    }
+
+    fn indentation(&self) -> usize {
+        self.depth * self.style.indent
+    }
+}
@@ -0,0 +1,209 @@
+//! Contains the I/O traits used by the Brainfuck Virtual Machine
+//!
+//! With the `std` feature enabled (the default), these are simply the
+//! [`std::io::Read`] and [`std::io::Write`] traits, and the VM reads from
+//! stdin and writes to stdout unless configured otherwise. On
+//! `wasm32-unknown-unknown`, where these do not exist, a VM built without
+//! a reader or writer instead behaves as if its input is always empty,
+//! and discards all output.
+//!
+//! Without the `std` feature, this module provides minimal replacements of
+//! these traits, which can be implemented for the I/O devices of the target
+//! platform. A VM built without a reader or writer then behaves as if its
+//! input is always empty, and discards all output.
+
+#[cfg(feature = "std")]
+pub use std::io::{Error, Read, Write};
+
+#[cfg(not(feature = "std"))]
+pub use no_std_io::{Empty, Error, Read, Sink, Write};
+
+pub(crate) use defaults::{default_reader, default_writer, DefaultReader, DefaultWriter};
+
+/// Whether a non-blocking reader returned `e` because no input is available yet.
+/// With the `std` feature, this is an error of kind [`std::io::ErrorKind::WouldBlock`]
+pub(crate) fn is_would_block(e: &Error) -> bool {
+    #[cfg(feature = "std")]
+    return e.kind() == std::io::ErrorKind::WouldBlock;
+
+    #[cfg(not(feature = "std"))]
+    return *e == Error::WOULD_BLOCK;
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+mod defaults {
+    /// The reader used by a [`crate::VMBuilder`] when none is configured
+    pub(crate) type DefaultReader = std::io::Stdin;
+
+    /// The writer used by a [`crate::VMBuilder`] when none is configured
+    pub(crate) type DefaultWriter = std::io::Stdout;
+
+    pub(crate) fn default_reader() -> DefaultReader {
+        std::io::stdin()
+    }
+
+    pub(crate) fn default_writer() -> DefaultWriter {
+        std::io::stdout()
+    }
+}
+
+#[cfg(all(feature = "std", target_arch = "wasm32", target_os = "unknown"))]
+mod defaults {
+    /// The reader used by a [`crate::VMBuilder`] when none is configured
+    pub(crate) type DefaultReader = std::io::Empty;
+
+    /// The writer used by a [`crate::VMBuilder`] when none is configured
+    pub(crate) type DefaultWriter = std::io::Sink;
+
+    pub(crate) fn default_reader() -> DefaultReader {
+        std::io::empty()
+    }
+
+    pub(crate) fn default_writer() -> DefaultWriter {
+        std::io::sink()
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod defaults {
+    use super::{Empty, Sink};
+
+    /// The reader used by a [`crate::VMBuilder`] when none is configured
+    pub(crate) type DefaultReader = Empty;
+
+    /// The writer used by a [`crate::VMBuilder`] when none is configured
+    pub(crate) type DefaultWriter = Sink;
+
+    pub(crate) fn default_reader() -> DefaultReader {
+        Empty
+    }
+
+    pub(crate) fn default_writer() -> DefaultWriter {
+        Sink
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod no_std_io {
+    use alloc::vec::Vec;
+    use core::fmt::Display;
+
+    /// An error that occurred while reading or writing
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Error {
+        message: &'static str,
+    }
+
+    impl Error {
+        /// The error a non-blocking reader returns when no input is available yet,
+        /// mirroring `std::io::ErrorKind::WouldBlock`
+        pub const WOULD_BLOCK: Error = Error::new("operation would block");
+
+        /// Creates a new error with the given description
+        pub const fn new(message: &'static str) -> Self {
+            Error { message }
+        }
+    }
+
+    impl Display for Error {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "{}", self.message)
+        }
+    }
+
+    impl core::error::Error for Error {}
+
+    /// A source of bytes, mirroring `std::io::Read`
+    pub trait Read {
+        /// Reads bytes into `buf`, returning the amount of bytes read.
+        /// Returning zero signals the end of the input
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+    }
+
+    /// A destination for bytes, mirroring `std::io::Write`
+    pub trait Write {
+        /// Writes bytes from `buf`, returning the amount of bytes written
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error>;
+
+        /// Flushes any buffered output
+        fn flush(&mut self) -> Result<(), Error>;
+
+        /// Writes all bytes from `buf`
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+            while !buf.is_empty() {
+                match self.write(buf)? {
+                    0 => return Err(Error::new("failed to write whole buffer")),
+                    n => buf = &buf[n..],
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            (**self).read(buf)
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            (**self).flush()
+        }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            let amount = buf.len().min(self.len());
+            let (read, rest) = self.split_at(amount);
+
+            buf[..amount].copy_from_slice(read);
+            *self = rest;
+
+            Ok(amount)
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    /// A reader that is always at the end of its input
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct Empty;
+
+    impl Read for Empty {
+        fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Error> {
+            Ok(0)
+        }
+    }
+
+    /// A writer that discards everything written to it
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct Sink;
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+}
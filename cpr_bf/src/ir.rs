@@ -0,0 +1,925 @@
+//! Contains the intermediate representation that Brainfuck programs
+//! are compiled to before being run by the Brainfuck Virtual Machine
+//!
+//! A [`Program`] is lowered into a [`CompiledProgram`], a list of [`Op`]s.
+//! During this process, a number of optimization [`Pass`]es can be applied,
+//! which replace sequences of Brainfuck instructions with single operations.
+//! The passes to apply can either be chosen manually, or by selecting an [`OptimizationLevel`].
+//!
+//! Custom passes can be written by implementing [`OptimizationPass`], and combined
+//! with the built-in passes in a [`Pipeline`].
+
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use core::fmt::{Debug, Display};
+
+use crate::{
+    bytecode::{self, BytecodeError},
+    extensions::ExtensionSet,
+    Instruction, MissingKind, ParseError, Program, SourcePosition, UnmatchedJump,
+};
+
+/// An operation, together with the index of the first [`Program`]
+/// instruction it was lowered from
+type SourcedOp = (Op, usize);
+
+/// A single operation of a [`CompiledProgram`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Op {
+    /// Adds the given amount to the cell that the data pointer currently points to,
+    /// wrapping around on overflow. Negative amounts are subtracted
+    Add(isize),
+
+    /// Moves the data pointer by the given amount. Negative amounts move the data pointer
+    /// to the left
+    Move(isize),
+
+    /// Adds the second amount to the cell at the given offset from the data pointer,
+    /// without moving the data pointer. Created by [`Pass::FuseOffsets`]
+    AddAt(isize, isize),
+
+    /// See [`Instruction::Output`]
+    Output,
+
+    /// See [`Instruction::Input`]
+    Input,
+
+    /// Sets the cell that the data pointer currently points to to zero
+    SetZero,
+
+    /// Moves the data pointer by the given amount until it points to a cell that is zero.
+    /// Replaces scan loops such as `[>]` and `[<<]`
+    Scan(isize),
+
+    /// If the value in the currently pointed-to cell is zero, jumps to the operation after
+    /// the matching [`Op::JumpBack`], found at the given index
+    JumpFwd(usize),
+
+    /// If the value in the currently pointed-to cell is not zero, jumps to the operation after
+    /// the matching [`Op::JumpFwd`], found at the given index
+    JumpBack(usize),
+
+    /// Defines the procedure starting at the next operation, and jumps to the operation after
+    /// the matching [`Op::EndProc`], found at the given index. See [`Instruction::ProcStart`]
+    DefineProc(usize),
+
+    /// See [`Instruction::ProcEnd`]
+    EndProc,
+
+    /// See [`Instruction::ProcCall`]
+    CallProc,
+
+    /// See [`Instruction::Fork`]
+    Fork,
+
+    /// See [`Instruction::Exit`]
+    Exit,
+
+    /// See [`Instruction::Store`]
+    Store,
+
+    /// See [`Instruction::Load`]
+    Load,
+
+    /// See [`Instruction::ShiftRight`]
+    ShiftRight,
+
+    /// See [`Instruction::ShiftLeft`]
+    ShiftLeft,
+
+    /// See [`Instruction::Not`]
+    Not,
+
+    /// See [`Instruction::Xor`]
+    Xor,
+
+    /// See [`Instruction::And`]
+    And,
+
+    /// See [`Instruction::Or`]
+    Or,
+
+    /// See [`Instruction::HostCall`]
+    HostCall,
+
+    /// See [`Instruction::Random`]
+    Random,
+}
+
+impl Op {
+    /// Whether this operation belongs to one of the [`crate::extensions`].
+    /// These are only supported by the VM, not by the code generators or the JIT
+    pub fn is_extension(self) -> bool {
+        !self.required_extensions().is_empty()
+    }
+
+    /// The extensions that need to be enabled to run this operation,
+    /// like [`Instruction::required_extensions`]
+    pub fn required_extensions(self) -> ExtensionSet {
+        match self {
+            Op::DefineProc(_) | Op::EndProc | Op::CallProc => ExtensionSet::PBRAIN,
+            Op::Fork => ExtensionSet::BRAINFORK,
+            Op::Exit
+            | Op::Store
+            | Op::Load
+            | Op::ShiftRight
+            | Op::ShiftLeft
+            | Op::Not
+            | Op::Xor
+            | Op::And
+            | Op::Or => ExtensionSet::EXTENDED_TYPE_I,
+            Op::HostCall => ExtensionSet::HOST_CALL,
+            Op::Random => ExtensionSet::RANDOM,
+            Op::Add(_)
+            | Op::Move(_)
+            | Op::AddAt(_, _)
+            | Op::Output
+            | Op::Input
+            | Op::SetZero
+            | Op::Scan(_)
+            | Op::JumpFwd(_)
+            | Op::JumpBack(_) => ExtensionSet::NONE,
+        }
+    }
+
+    /// Expands an [`Op::Scan`] or [`Op::AddAt`] back into the operations it replaced,
+    /// for backends without a dedicated implementation. Returns any other operation unchanged
+    pub(crate) fn expand(self) -> Vec<Op> {
+        match self {
+            Op::Scan(amount) => vec![Op::JumpFwd(0), Op::Move(amount), Op::JumpBack(0)],
+            Op::AddAt(offset, amount) => vec![Op::Move(offset), Op::Add(amount), Op::Move(-offset)],
+            op => vec![op],
+        }
+    }
+}
+
+/// An optimization pass that can be applied while compiling a [`Program`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Fuses runs of `+`/`-` and `>`/`<` instructions into single operations.
+    ///
+    /// Note that fused pointer movements are applied at once, so a program that
+    /// temporarily moves the data pointer below zero (e.g. `<>` at the first cell)
+    /// no longer results in a [`crate::BrainfuckExecutionError::DataPointerUnderflow`]
+    FuseRuns,
+
+    /// Replaces clear loops (`[-]` and `[+]`) with a single [`Op::SetZero`].
+    ///
+    /// Clear loops only behave the same as [`Op::SetZero`] under the default
+    /// [`crate::OverflowPolicy::Wrapping`], so this pass is left out for VMs
+    /// using another policy
+    ClearLoops,
+
+    /// Removes loops that can never run, because the current cell is known to be zero
+    /// when they are reached. This is the case for loops directly following another
+    /// loop, and for loops before the first operation that changes the tape, such as
+    /// comment loops at the start of a program.
+    ///
+    /// The latter assumes that every cell is zero when the program starts, so the pass
+    /// is not part of any [`OptimizationLevel`], whose programs may run on any memory.
+    /// See [`OptimizationLevel::zeroed_tape_passes`]
+    DeadLoops,
+
+    /// Replaces scan loops (`[>]`, `[<]` and loops moving by a larger amount after
+    /// [`Pass::FuseRuns`]) with a single [`Op::Scan`], which searches the tape for
+    /// a zero cell without executing the loop one step at a time
+    ScanLoops,
+
+    /// Defers the pointer movements of straight-line code, turning every addition
+    /// in between into an [`Op::AddAt`] relative to the data pointer. The net movement
+    /// is applied once, before the next operation that is not an addition or movement.
+    ///
+    /// Like with [`Pass::FuseRuns`], movements that cancel each other out are removed,
+    /// even if they temporarily move the data pointer below zero
+    FuseOffsets,
+}
+
+impl Pass {
+    /// Applies this pass to operations paired with the instruction they were lowered from.
+    /// Operations replacing multiple others keep the instruction of the first one
+    fn apply_sourced(self, ops: Vec<SourcedOp>) -> Vec<SourcedOp> {
+        match self {
+            Pass::FuseRuns => fuse_runs(ops),
+            Pass::ClearLoops => clear_loops(ops),
+            Pass::DeadLoops => remove_dead_loops(ops),
+            Pass::ScanLoops => scan_loops(ops),
+            Pass::FuseOffsets => fuse_offsets(ops),
+        }
+    }
+}
+
+impl OptimizationPass for Pass {
+    fn name(&self) -> &str {
+        match self {
+            Pass::FuseRuns => "FuseRuns",
+            Pass::ClearLoops => "ClearLoops",
+            Pass::DeadLoops => "DeadLoops",
+            Pass::ScanLoops => "ScanLoops",
+            Pass::FuseOffsets => "FuseOffsets",
+        }
+    }
+
+    fn apply(&self, ops: Vec<Op>) -> Vec<Op> {
+        let ops = ops.into_iter().map(|op| (op, 0)).collect();
+
+        self.apply_sourced(ops)
+            .into_iter()
+            .map(|(op, _)| op)
+            .collect()
+    }
+
+    fn apply_with_sources(&self, ops: Vec<Op>, sources: &mut Vec<usize>) -> Vec<Op> {
+        if sources.len() != ops.len() {
+            sources.clear();
+            return self.apply(ops);
+        }
+
+        let ops = ops.into_iter().zip(sources.drain(..)).collect();
+        let (ops, applied_sources) = self.apply_sourced(ops).into_iter().unzip();
+
+        *sources = applied_sources;
+        ops
+    }
+}
+
+/// An optimization pass over the operations of a program, applied by a [`Pipeline`].
+/// The built-in passes are listed in [`Pass`]
+///
+/// ```
+/// use cpr_bf::{ir::{Op, OptimizationPass, Pass, Pipeline}, Program};
+///
+/// /// Removes all output operations
+/// struct Silence;
+///
+/// impl OptimizationPass for Silence {
+///     fn name(&self) -> &str {
+///         "Silence"
+///     }
+///
+///     fn apply(&self, ops: Vec<Op>) -> Vec<Op> {
+///         ops.into_iter().filter(|&op| op != Op::Output).collect()
+///     }
+/// }
+///
+/// let pipeline = Pipeline::new().with_pass(Silence).with_pass(Pass::FuseRuns);
+/// let compiled = pipeline.compile(&Program::from("++.++.")).unwrap();
+///
+/// assert_eq!(compiled.ops(), [Op::Add(4)]);
+/// ```
+pub trait OptimizationPass {
+    /// The name of this pass, used in log messages
+    fn name(&self) -> &str;
+
+    /// Applies this pass to the given operations, returning the optimized operations.
+    ///
+    /// Jump targets are not yet resolved at this point, so every jump operation
+    /// and procedure definition holds a placeholder target. The returned operations
+    /// must keep them properly matched, or compiling fails with a [`ParseError`]
+    fn apply(&self, ops: Vec<Op>) -> Vec<Op>;
+
+    /// Like [`OptimizationPass::apply`], but also keeps track of where every operation
+    /// came from. `sources` holds the index of the [`Program`] instruction each of
+    /// `ops` was lowered from, and must be updated to match the returned operations.
+    /// These are used to map runtime errors back to the source code, see
+    /// [`CompiledProgram::source_position`].
+    ///
+    /// The default implementation clears `sources`, after which the operations
+    /// can no longer be traced back to the source code
+    fn apply_with_sources(&self, ops: Vec<Op>, sources: &mut Vec<usize>) -> Vec<Op> {
+        sources.clear();
+        self.apply(ops)
+    }
+}
+
+/// An ordered list of optimization passes, used to compile a [`Program`] into
+/// a [`CompiledProgram`]. See [`OptimizationPass`] to add custom passes
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn OptimizationPass>>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline without any passes
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Creates a pipeline with the passes applied at the given optimization level
+    pub fn from_level(level: OptimizationLevel) -> Self {
+        level
+            .passes()
+            .iter()
+            .fold(Pipeline::new(), |pipeline, &pass| pipeline.with_pass(pass))
+    }
+
+    /// Appends `pass` to the end of this pipeline
+    pub fn with_pass(mut self, pass: impl OptimizationPass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// The names of the passes in this pipeline, in order
+    pub fn pass_names(&self) -> impl Iterator<Item = &str> {
+        self.passes.iter().map(|pass| pass.name())
+    }
+
+    /// Compiles `program`, applying the passes of this pipeline in order.
+    ///
+    /// Returns a [`ParseError`] if the jump instructions in `program` are mismatched,
+    /// or if a pass did not keep them matched
+    pub fn compile(&self, program: &Program) -> Result<CompiledProgram, ParseError> {
+        CompiledProgram::compile_with(program, self.passes.iter().map(|pass| pass.as_ref()))
+    }
+}
+
+impl Debug for Pipeline {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.pass_names()).finish()
+    }
+}
+
+/// The amount of optimization applied when compiling a [`Program`]
+/// into a [`CompiledProgram`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OptimizationLevel {
+    /// Every instruction is translated into exactly one operation
+    None,
+
+    /// Applies [`Pass::FuseRuns`]
+    Basic,
+
+    /// Applies all available passes, except [`Pass::DeadLoops`]
+    #[default]
+    Full,
+}
+
+impl OptimizationLevel {
+    /// The optimization passes applied at this level, in order
+    pub fn passes(self) -> &'static [Pass] {
+        match self {
+            OptimizationLevel::None => &[],
+            OptimizationLevel::Basic => &[Pass::FuseRuns],
+            OptimizationLevel::Full => &[
+                Pass::FuseRuns,
+                Pass::ClearLoops,
+                Pass::ScanLoops,
+                Pass::FuseOffsets,
+            ],
+        }
+    }
+
+    /// The optimization passes applied at this level to programs that are known to start
+    /// on a tape of zeroed cells. Adds [`Pass::DeadLoops`] to the passes of
+    /// [`OptimizationLevel::Full`]
+    ///
+    /// ```
+    /// use cpr_bf::{ir::{OptimizationLevel, Pass}, BrainfuckVM};
+    ///
+    /// assert_eq!(OptimizationLevel::Full.zeroed_tape_passes()[0], Pass::DeadLoops);
+    ///
+    /// // The second program does not start on a zeroed tape, so its loop is kept
+    /// let mut vm = cpr_bf::VMBuilder::new().with_writer(Vec::new()).build_typed();
+    /// vm.run_string("+++").unwrap();
+    /// vm.run_string("[>+<-]>.").unwrap();
+    ///
+    /// assert_eq!(vm.writer(), &[3]);
+    /// ```
+    pub fn zeroed_tape_passes(self) -> &'static [Pass] {
+        match self {
+            OptimizationLevel::Full => &[
+                Pass::DeadLoops,
+                Pass::FuseRuns,
+                Pass::ClearLoops,
+                Pass::ScanLoops,
+                Pass::FuseOffsets,
+            ],
+            level => level.passes(),
+        }
+    }
+}
+
+/// A [`Program`] that has been compiled into the operations
+/// that are actually executed by the VM.
+///
+/// Construct one with [`CompiledProgram::compile`], and run it with
+/// [`crate::BrainfuckVM::run_compiled`]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompiledProgram {
+    ops: Vec<Op>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    source_map: Vec<SourcePosition>,
+}
+
+impl CompiledProgram {
+    /// Compiles `program` with the given optimization level.
+    ///
+    /// Returns a [`ParseError`] if the jump instructions in `program` are mismatched
+    pub fn compile(
+        program: &Program,
+        level: OptimizationLevel,
+    ) -> Result<CompiledProgram, ParseError> {
+        log::debug!("Using optimization level {:?}", level);
+
+        CompiledProgram::compile_with_passes(program, level.passes())
+    }
+
+    /// Compiles `program`, applying the given optimization passes in order.
+    ///
+    /// Returns a [`ParseError`] if the jump instructions in `program` are mismatched
+    pub fn compile_with_passes(
+        program: &Program,
+        passes: &[Pass],
+    ) -> Result<CompiledProgram, ParseError> {
+        CompiledProgram::compile_with(
+            program,
+            passes.iter().map(|pass| pass as &dyn OptimizationPass),
+        )
+    }
+
+    /// Lowers `program`, applies the given passes, and resolves the jump targets.
+    /// Logs the effect of every pass, and the time it took if it can be measured
+    fn compile_with<'a>(
+        program: &Program,
+        passes: impl Iterator<Item = &'a dyn OptimizationPass>,
+    ) -> Result<CompiledProgram, ParseError> {
+        log::debug!(
+            "Compiling program of {} instructions",
+            program.instructions.len()
+        );
+
+        let mut ops = lower(&program.instructions);
+        let mut sources: Vec<usize> = (0..ops.len()).collect();
+
+        for pass in passes {
+            let num_ops = ops.len();
+
+            #[cfg(all(
+                feature = "std",
+                not(all(target_arch = "wasm32", target_os = "unknown"))
+            ))]
+            let start = std::time::Instant::now();
+
+            ops = pass.apply_with_sources(ops, &mut sources);
+
+            #[cfg(all(
+                feature = "std",
+                not(all(target_arch = "wasm32", target_os = "unknown"))
+            ))]
+            log::debug!(
+                "Optimization pass {} took {:?}: {} -> {} operations",
+                pass.name(),
+                start.elapsed(),
+                num_ops,
+                ops.len()
+            );
+
+            #[cfg(not(all(
+                feature = "std",
+                not(all(target_arch = "wasm32", target_os = "unknown"))
+            )))]
+            log::debug!(
+                "Optimization pass {}: {} -> {} operations",
+                pass.name(),
+                num_ops,
+                ops.len()
+            );
+        }
+
+        link_jumps(&mut ops)?;
+
+        log::debug!("Compiled program into {} operations", ops.len());
+
+        // Either every operation has a position, or none of them
+        let source_map = if sources.len() == ops.len() {
+            sources
+                .into_iter()
+                .map(|source| program.source_position(source))
+                .collect::<Option<Vec<_>>>()
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        Ok(CompiledProgram { ops, source_map })
+    }
+
+    /// The operations making up this program
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// The extensions used by the operations of this program
+    pub fn extensions(&self) -> ExtensionSet {
+        self.ops.iter().fold(ExtensionSet::NONE, |used, op| {
+            used | op.required_extensions()
+        })
+    }
+
+    /// The position in the source code of the instruction that the operation at
+    /// `index` was lowered from. An operation replacing multiple instructions has the
+    /// position of the first one. [`None`] if the program was not parsed from source
+    /// code, if it was compiled with a custom [`OptimizationPass`] that does not
+    /// implement [`OptimizationPass::apply_with_sources`], or if it was decoded
+    /// from bytecode, which does not store any positions
+    ///
+    /// ```
+    /// use cpr_bf::{ir::{CompiledProgram, Op, OptimizationLevel}, Program};
+    ///
+    /// let program = Program::from("+++\n[-]\n>>.");
+    /// let compiled = CompiledProgram::compile(&program, OptimizationLevel::Full).unwrap();
+    ///
+    /// assert_eq!(compiled.ops()[1], Op::SetZero);
+    /// assert_eq!(compiled.source_position(1).unwrap().line, 2);
+    ///
+    /// assert_eq!(compiled.ops()[2], Op::Move(2));
+    /// assert_eq!(compiled.source_position(2).unwrap().column, 1);
+    /// ```
+    pub fn source_position(&self, index: usize) -> Option<SourcePosition> {
+        self.source_map.get(index).copied()
+    }
+
+    /// Writes this program back out as Brainfuck source code. This is also what the
+    /// [`Display`] implementation writes.
+    ///
+    /// Every operation is put on its own line, indented by the depth of the loops it is
+    /// in. Operations that replace multiple instructions are followed by a comment
+    /// describing them. [`Op::SetZero`] is written as `[-]`, which only behaves the same
+    /// with the default [`crate::OverflowPolicy::Wrapping`].
+    ///
+    /// ```
+    /// use cpr_bf::{ir::{CompiledProgram, OptimizationLevel}, Program};
+    ///
+    /// let program = Program::from("++++++++[>++++++++<-]>+.[-]");
+    /// let compiled = CompiledProgram::compile(&program, OptimizationLevel::Full).unwrap();
+    ///
+    /// assert_eq!(
+    ///     compiled.to_source(),
+    ///     "++++++++ add 8\n\
+    ///      [\n    \
+    ///          >++++++++< add 8 to the cell 1 to the right\n    \
+    ///          -\n\
+    ///      ]\n\
+    ///      >+< add 1 to the cell 1 to the right\n\
+    ///      >\n\
+    ///      .\n\
+    ///      [-] set the cell to zero\n"
+    /// );
+    ///
+    /// let recompiled = Program::from(compiled.to_source().as_str()).compile(OptimizationLevel::Full).unwrap();
+    /// assert_eq!(recompiled.ops(), compiled.ops());
+    /// ```
+    pub fn to_source(&self) -> String {
+        format!("{}", self)
+    }
+
+    /// Encodes this program in the compiled program format
+    /// described in the [`crate::bytecode`] module
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bytecode::encode_compiled(self)
+    }
+
+    /// Decodes a program from the compiled program format
+    /// described in the [`crate::bytecode`] module
+    pub fn from_bytes(data: &[u8]) -> Result<CompiledProgram, BytecodeError> {
+        bytecode::decode_compiled(data)
+    }
+
+    /// Creates a program from operations with unresolved jump targets
+    pub(crate) fn from_ops(mut ops: Vec<Op>) -> Result<CompiledProgram, ParseError> {
+        link_jumps(&mut ops)?;
+
+        Ok(CompiledProgram {
+            ops,
+            source_map: Vec::new(),
+        })
+    }
+}
+
+impl Display for CompiledProgram {
+    /// Writes this program as Brainfuck source code, see [`CompiledProgram::to_source`]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut depth: usize = 0;
+
+        for &op in &self.ops {
+            if matches!(op, Op::JumpBack(_) | Op::EndProc) {
+                depth = depth.saturating_sub(1);
+            }
+
+            write!(f, "{:indent$}", "", indent = depth * 4)?;
+            write_op(f, op)?;
+
+            // Comments only consist of lowercase letters, digits and spaces,
+            // none of which are instructions of any extension
+            match op {
+                Op::Add(amount) if amount.unsigned_abs() != 1 => {
+                    write!(f, " {} {}", add_verb(amount), amount.unsigned_abs())?
+                }
+                Op::Move(amount) if amount.unsigned_abs() != 1 => {
+                    write!(f, " move {} {}", direction(amount), amount.unsigned_abs())?
+                }
+                Op::AddAt(offset, amount) => write!(
+                    f,
+                    " {} {} {} the cell {} to the {}",
+                    add_verb(amount),
+                    amount.unsigned_abs(),
+                    if amount < 0 { "from" } else { "to" },
+                    offset.unsigned_abs(),
+                    direction(offset)
+                )?,
+                Op::SetZero => write!(f, " set the cell to zero")?,
+                Op::Scan(amount) => write!(
+                    f,
+                    " scan {} in steps of {}",
+                    direction(amount),
+                    amount.unsigned_abs()
+                )?,
+                _ => {}
+            }
+
+            writeln!(f)?;
+
+            if matches!(op, Op::JumpFwd(_) | Op::DefineProc(_)) {
+                depth += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the source code of the instructions that `op` replaced
+fn write_op(f: &mut core::fmt::Formatter<'_>, op: Op) -> core::fmt::Result {
+    let repeat = |f: &mut core::fmt::Formatter<'_>, amount: isize, pos: char, neg: char| {
+        let c = if amount < 0 { neg } else { pos };
+
+        (0..amount.unsigned_abs()).try_for_each(|_| write!(f, "{}", c))
+    };
+
+    match op {
+        Op::Add(amount) => repeat(f, amount, '+', '-'),
+        Op::Move(amount) => repeat(f, amount, '>', '<'),
+        Op::AddAt(_, _) | Op::Scan(_) => op.expand().into_iter().try_for_each(|op| write_op(f, op)),
+        Op::SetZero => write!(f, "[-]"),
+        Op::Output => write_instr(f, Instruction::Output),
+        Op::Input => write_instr(f, Instruction::Input),
+        Op::JumpFwd(_) => write_instr(f, Instruction::JumpFwd),
+        Op::JumpBack(_) => write_instr(f, Instruction::JumpBack),
+        Op::DefineProc(_) => write_instr(f, Instruction::ProcStart),
+        Op::EndProc => write_instr(f, Instruction::ProcEnd),
+        Op::CallProc => write_instr(f, Instruction::ProcCall),
+        Op::Fork => write_instr(f, Instruction::Fork),
+        Op::Exit => write_instr(f, Instruction::Exit),
+        Op::Store => write_instr(f, Instruction::Store),
+        Op::Load => write_instr(f, Instruction::Load),
+        Op::ShiftRight => write_instr(f, Instruction::ShiftRight),
+        Op::ShiftLeft => write_instr(f, Instruction::ShiftLeft),
+        Op::Not => write_instr(f, Instruction::Not),
+        Op::Xor => write_instr(f, Instruction::Xor),
+        Op::And => write_instr(f, Instruction::And),
+        Op::Or => write_instr(f, Instruction::Or),
+        Op::HostCall => write_instr(f, Instruction::HostCall),
+        Op::Random => write_instr(f, Instruction::Random),
+    }
+}
+
+fn write_instr(f: &mut core::fmt::Formatter<'_>, instr: Instruction) -> core::fmt::Result {
+    write!(f, "{}", instr.to_char())
+}
+
+fn add_verb(amount: isize) -> &'static str {
+    if amount < 0 {
+        "subtract"
+    } else {
+        "add"
+    }
+}
+
+fn direction(amount: isize) -> &'static str {
+    if amount < 0 {
+        "left"
+    } else {
+        "right"
+    }
+}
+
+/// Translates every instruction into its equivalent operation.
+/// Jump targets are left unresolved, see [`link_jumps`]
+fn lower(instructions: &[Instruction]) -> Vec<Op> {
+    instructions
+        .iter()
+        .map(|instr| match instr {
+            Instruction::IncrDP => Op::Move(1),
+            Instruction::DecrDP => Op::Move(-1),
+            Instruction::Incr => Op::Add(1),
+            Instruction::Decr => Op::Add(-1),
+            Instruction::Output => Op::Output,
+            Instruction::Input => Op::Input,
+            Instruction::JumpFwd => Op::JumpFwd(0),
+            Instruction::JumpBack => Op::JumpBack(0),
+            Instruction::ProcStart => Op::DefineProc(0),
+            Instruction::ProcEnd => Op::EndProc,
+            Instruction::ProcCall => Op::CallProc,
+            Instruction::Fork => Op::Fork,
+            Instruction::Exit => Op::Exit,
+            Instruction::Store => Op::Store,
+            Instruction::Load => Op::Load,
+            Instruction::ShiftRight => Op::ShiftRight,
+            Instruction::ShiftLeft => Op::ShiftLeft,
+            Instruction::Not => Op::Not,
+            Instruction::Xor => Op::Xor,
+            Instruction::And => Op::And,
+            Instruction::Or => Op::Or,
+            Instruction::HostCall => Op::HostCall,
+            Instruction::Random => Op::Random,
+        })
+        .collect()
+}
+
+/// Fuses consecutive [`Op::Add`] and [`Op::Move`] operations, and removes
+/// the ones that cancel each other out completely
+fn fuse_runs(ops: Vec<SourcedOp>) -> Vec<SourcedOp> {
+    let mut fused: Vec<SourcedOp> = Vec::with_capacity(ops.len());
+
+    for (op, source) in ops {
+        match (fused.last_mut(), op) {
+            (Some((Op::Add(total), _)), Op::Add(n)) | (Some((Op::Move(total), _)), Op::Move(n)) => {
+                *total += n;
+
+                if *total == 0 {
+                    fused.pop();
+                }
+            }
+            _ => fused.push((op, source)),
+        }
+    }
+
+    fused
+}
+
+/// Replaces every `[-]` and `[+]` loop with an [`Op::SetZero`]
+fn clear_loops(ops: Vec<SourcedOp>) -> Vec<SourcedOp> {
+    let mut cleared: Vec<SourcedOp> = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        cleared.push(op);
+
+        if let [.., (Op::JumpFwd(_), source), (Op::Add(1 | -1), _), (Op::JumpBack(_), _)] =
+            *cleared.as_slice()
+        {
+            cleared.truncate(cleared.len() - 3);
+            cleared.push((Op::SetZero, source));
+        }
+    }
+
+    cleared
+}
+
+/// Replaces the additions and movements of straight-line code with [`Op::AddAt`]s,
+/// followed by a single [`Op::Move`]
+fn fuse_offsets(ops: Vec<SourcedOp>) -> Vec<SourcedOp> {
+    let mut fused: Vec<SourcedOp> = Vec::with_capacity(ops.len());
+    let mut offset = 0_isize;
+
+    // The first movement of the deferred net movement
+    let mut move_source = 0;
+
+    for (op, source) in ops {
+        match op {
+            Op::Move(amount) => {
+                if offset == 0 {
+                    move_source = source;
+                }
+
+                offset += amount;
+            }
+            Op::Add(amount) if offset != 0 => fused.push((Op::AddAt(offset, amount), source)),
+            Op::Add(_) => fused.push((op, source)),
+            _ => {
+                if offset != 0 {
+                    fused.push((Op::Move(offset), move_source));
+                    offset = 0;
+                }
+
+                fused.push((op, source));
+            }
+        }
+    }
+
+    if offset != 0 {
+        fused.push((Op::Move(offset), move_source));
+    }
+
+    fused
+}
+
+/// Replaces every loop only moving the data pointer with an [`Op::Scan`]
+fn scan_loops(ops: Vec<SourcedOp>) -> Vec<SourcedOp> {
+    let mut scanned: Vec<SourcedOp> = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        scanned.push(op);
+
+        if let [.., (Op::JumpFwd(_), source), (Op::Move(amount), _), (Op::JumpBack(_), _)] =
+            *scanned.as_slice()
+        {
+            scanned.truncate(scanned.len() - 3);
+            scanned.push((Op::Scan(amount), source));
+        }
+    }
+
+    scanned
+}
+
+/// Removes every loop that is reached while the current cell is known to be zero,
+/// see [`Pass::DeadLoops`]
+fn remove_dead_loops(ops: Vec<SourcedOp>) -> Vec<SourcedOp> {
+    let mut live: Vec<SourcedOp> = Vec::with_capacity(ops.len());
+
+    // Whether no operation has changed any cell yet, and whether
+    // the current cell is known to be zero
+    let mut tape_zero = true;
+    let mut cell_zero = true;
+    let mut i = 0;
+
+    while i < ops.len() {
+        let (op, source) = ops[i];
+
+        if let (Op::JumpFwd(_), true) = (op, cell_zero) {
+            if let Some(end) = matching_jump_back(&ops, i) {
+                log::trace!("Removing dead loop of {} operations", end + 1 - i);
+                i = end + 1;
+                continue;
+            }
+        }
+
+        match op {
+            Op::Move(_) => cell_zero = tape_zero,
+            Op::Output => {}
+            Op::JumpBack(_) | Op::SetZero => cell_zero = true,
+            _ => {
+                tape_zero = false;
+                cell_zero = false;
+            }
+        }
+
+        live.push((op, source));
+        i += 1;
+    }
+
+    live
+}
+
+/// Finds the index of the [`Op::JumpBack`] matching the [`Op::JumpFwd`] at `open`,
+/// before the jump targets have been resolved
+fn matching_jump_back(ops: &[SourcedOp], open: usize) -> Option<usize> {
+    let mut depth = 0_usize;
+
+    for (i, (op, _)) in ops.iter().enumerate().skip(open + 1) {
+        match op {
+            Op::JumpFwd(_) => depth += 1,
+            Op::JumpBack(_) if depth == 0 => return Some(i),
+            Op::JumpBack(_) => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Resolves the targets of all jump operations, and of all procedure definitions
+fn link_jumps(ops: &mut [Op]) -> Result<(), ParseError> {
+    let mut open_stack = Vec::new();
+
+    for i in 0..ops.len() {
+        match ops[i] {
+            Op::JumpFwd(_) | Op::DefineProc(_) => open_stack.push(i),
+            Op::JumpBack(_) => match open_stack.pop().map(|open| (open, ops[open])) {
+                Some((open, Op::JumpFwd(_))) => {
+                    ops[open] = Op::JumpFwd(i);
+                    ops[i] = Op::JumpBack(open);
+                }
+                _ => return Err(unmatched(MissingKind::JumpFwd)),
+            },
+            Op::EndProc => match open_stack.pop().map(|open| (open, ops[open])) {
+                Some((open, Op::DefineProc(_))) => ops[open] = Op::DefineProc(i),
+                _ => return Err(unmatched(MissingKind::ProcStart)),
+            },
+            _ => {}
+        }
+    }
+
+    match open_stack.first().map(|&open| ops[open]) {
+        None => Ok(()),
+        Some(Op::DefineProc(_)) => Err(unmatched(MissingKind::ProcEnd)),
+        Some(_) => Err(unmatched(MissingKind::JumpBack)),
+    }
+}
+
+fn unmatched(missing: MissingKind) -> ParseError {
+    ParseError::JumpMismatch(UnmatchedJump {
+        missing,
+        position: None,
+    })
+}
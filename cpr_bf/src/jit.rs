@@ -0,0 +1,404 @@
+//! Contains a JIT compiler for Brainfuck programs, enabled with the `jit` feature
+//!
+//! A [`JitProgram`] is compiled from a [`CompiledProgram`] into native code
+//! for the host machine with [Cranelift](https://cranelift.dev). It runs on a
+//! tape of byte-sized cells, and uses the same [`Read`] and [`Write`] traits
+//! as the interpreter for its input and output.
+//!
+//! Compared to the [`crate::VirtualMachine`], the JIT has a fixed-size tape,
+//! only supports byte-sized cells with wrapping arithmetic, and leaves the
+//! current cell unchanged when reading past the end of the input.
+//!
+//! ```
+//! use cpr_bf::{ir::OptimizationLevel, jit::JitProgram, Program};
+//!
+//! let program = Program::parse("++++++++[>++++++++<-]>+.").unwrap();
+//! let jit = JitProgram::compile(&program.compile(OptimizationLevel::Full).unwrap()).unwrap();
+//!
+//! let mut tape = [0_u8; 16];
+//! let mut output = Vec::new();
+//!
+//! jit.run(&mut tape, &mut std::io::empty(), &mut output).unwrap();
+//!
+//! assert_eq!(output, b"A");
+//! ```
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Display, mem};
+use std::io;
+
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, Block, InstBuilder, MemFlags, Value},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{Linkage, Module};
+
+use crate::{
+    io::{Read, Write},
+    ir::{CompiledProgram, Op},
+    BfResult, BrainfuckExecutionError, OutOfBoundsAccess, VMMemoryError,
+};
+
+/// The status returned by the compiled code and the I/O callbacks
+const STATUS_OK: i32 = 0;
+const STATUS_IO_ERROR: i32 = 1;
+const STATUS_OUT_OF_BOUNDS: i32 = 2;
+
+/// An error encountered while compiling a program to native code
+#[derive(Debug)]
+pub enum JitError {
+    /// The host machine is not supported by Cranelift
+    UnsupportedHost(String),
+
+    /// Cranelift failed to compile the program
+    CompilationFailed(String),
+
+    /// The program contains an operation that cannot be compiled, see [`Op::is_extension`]
+    UnsupportedOp(Op),
+}
+
+impl Display for JitError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            JitError::UnsupportedHost(msg) => write!(f, "Unsupported host machine: {}", msg),
+            JitError::CompilationFailed(msg) => write!(f, "JIT compilation failed: {}", msg),
+            JitError::UnsupportedOp(op) => {
+                write!(f, "Operation {:?} is not supported by the JIT", op)
+            }
+        }
+    }
+}
+
+impl core::error::Error for JitError {}
+
+impl From<cranelift_module::ModuleError> for JitError {
+    fn from(value: cranelift_module::ModuleError) -> Self {
+        JitError::CompilationFailed(value.to_string())
+    }
+}
+
+/// The state shared between the compiled code and the I/O callbacks.
+///
+/// The compiled code stores the data pointer in the first field
+/// whenever it stops with an error
+#[repr(C)]
+struct RunContext<'a> {
+    data_ptr: isize,
+    reader: &'a mut dyn Read,
+    writer: &'a mut dyn Write,
+    error: Option<io::Error>,
+}
+
+/// The signature of the compiled code: the tape, its length, and the run context
+type JitFn = unsafe extern "C" fn(*mut u8, usize, *mut RunContext) -> i32;
+
+/// A Brainfuck program compiled to native code
+pub struct JitProgram {
+    module: Option<JITModule>,
+    function: JitFn,
+}
+
+impl JitProgram {
+    /// Compiles `program` to native code for the host machine
+    pub fn compile(program: &CompiledProgram) -> Result<JitProgram, JitError> {
+        log::info!(
+            "JIT compiling program of {} operations",
+            program.ops().len()
+        );
+
+        if let Some(&op) = program.ops().iter().find(|op| op.is_extension()) {
+            return Err(JitError::UnsupportedOp(op));
+        }
+
+        let mut flags = settings::builder();
+
+        flags
+            .set("opt_level", "speed")
+            .map_err(|e| JitError::CompilationFailed(e.to_string()))?;
+
+        let isa = cranelift_native::builder()
+            .map_err(|msg| JitError::UnsupportedHost(msg.to_string()))?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| JitError::UnsupportedHost(e.to_string()))?;
+
+        let mut jit_builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        jit_builder.symbol("bf_output", jit_output as *const u8);
+        jit_builder.symbol("bf_input", jit_input as *const u8);
+
+        let mut module = JITModule::new(jit_builder);
+
+        let function = Self::define(&mut module, program)?;
+
+        log::debug!("Finished JIT compilation");
+
+        Ok(JitProgram {
+            module: Some(module),
+            function,
+        })
+    }
+
+    /// Runs the program on `tape`, reading from `reader` and writing to `writer`.
+    ///
+    /// Moving the data pointer outside of the tape results in a
+    /// [`BrainfuckExecutionError::DataPointerUnderflow`] or a
+    /// [`BrainfuckExecutionError::MemoryError`]
+    pub fn run(
+        &self,
+        tape: &mut [u8],
+        reader: &mut impl Read,
+        writer: &mut impl Write,
+    ) -> BfResult {
+        log::info!(
+            "Running JIT compiled program on a tape of {} cells",
+            tape.len()
+        );
+
+        if tape.is_empty() {
+            return Err(VMMemoryError::OutOfBounds(OutOfBoundsAccess {
+                capacity: 0,
+                access: 0,
+            })
+            .into());
+        }
+
+        let mut ctx = RunContext {
+            data_ptr: 0,
+            reader,
+            writer,
+            error: None,
+        };
+
+        // SAFETY: The compiled code only accesses the tape within the given bounds,
+        // and the context only for the duration of this call
+        let status = unsafe { (self.function)(tape.as_mut_ptr(), tape.len(), &mut ctx) };
+
+        match status {
+            STATUS_OK => Ok(ctx.writer.flush()?),
+            STATUS_IO_ERROR => Err(ctx
+                .error
+                .map(BrainfuckExecutionError::from)
+                .unwrap_or(BrainfuckExecutionError::UnknownError)),
+            STATUS_OUT_OF_BOUNDS if ctx.data_ptr < 0 => {
+                Err(BrainfuckExecutionError::DataPointerUnderflow)
+            }
+            STATUS_OUT_OF_BOUNDS => Err(VMMemoryError::OutOfBounds(OutOfBoundsAccess {
+                capacity: tape.len(),
+                access: ctx.data_ptr as usize,
+            })
+            .into()),
+            _ => Err(BrainfuckExecutionError::UnknownError),
+        }
+    }
+
+    /// Declares and defines the function running `program` in `module`
+    fn define(module: &mut JITModule, program: &CompiledProgram) -> Result<JitFn, JitError> {
+        let ptr_type = module.target_config().pointer_type();
+
+        let mut ctx = module.make_context();
+        ctx.func.signature.params.extend([
+            AbiParam::new(ptr_type),
+            AbiParam::new(ptr_type),
+            AbiParam::new(ptr_type),
+        ]);
+        ctx.func.signature.returns.push(AbiParam::new(types::I32));
+
+        let mut callback_sig = module.make_signature();
+        callback_sig
+            .params
+            .extend([AbiParam::new(ptr_type), AbiParam::new(ptr_type)]);
+        callback_sig.returns.push(AbiParam::new(types::I32));
+
+        let output_id = module.declare_function("bf_output", Linkage::Import, &callback_sig)?;
+        let input_id = module.declare_function("bf_input", Linkage::Import, &callback_sig)?;
+        let main_id = module.declare_function("bf_main", Linkage::Local, &ctx.func.signature)?;
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut fn_ctx);
+
+        let output_fn = module.declare_func_in_func(output_id, builder.func);
+        let input_fn = module.declare_func_in_func(input_id, builder.func);
+
+        let entry = builder.create_block();
+        let exit = builder.create_block();
+        builder.append_block_param(exit, types::I32);
+
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+
+        let tape = builder.block_params(entry)[0];
+        let tape_len = builder.block_params(entry)[1];
+        let run_ctx = builder.block_params(entry)[2];
+
+        let data_ptr = Variable::from_u32(0);
+        builder.declare_var(data_ptr, ptr_type);
+        let zero = builder.ins().iconst(ptr_type, 0);
+        builder.def_var(data_ptr, zero);
+
+        let mem = MemFlags::trusted();
+        let mut loops: Vec<(Block, Block)> = Vec::new();
+
+        let cell_addr = |builder: &mut FunctionBuilder| -> Value {
+            let ptr = builder.use_var(data_ptr);
+            builder.ins().iadd(tape, ptr)
+        };
+
+        let check_status = |builder: &mut FunctionBuilder, status: Value| {
+            let cont = builder.create_block();
+            builder.ins().brif(status, exit, &[status], cont, &[]);
+            builder.seal_block(cont);
+            builder.switch_to_block(cont);
+        };
+
+        for op in program.ops().iter().flat_map(|op| op.expand()) {
+            match op {
+                Op::Add(amount) => {
+                    let addr = cell_addr(&mut builder);
+                    let val = builder.ins().load(types::I8, mem, addr, 0);
+                    let val = builder.ins().iadd_imm(val, amount as u8 as i64);
+                    builder.ins().store(mem, val, addr, 0);
+                }
+                Op::Move(amount) => {
+                    let ptr = builder.use_var(data_ptr);
+                    let ptr = builder.ins().iadd_imm(ptr, amount as i64);
+                    builder.def_var(data_ptr, ptr);
+
+                    // A negative data pointer is larger than any tape length when
+                    // compared unsigned, so this checks both bounds at once
+                    let out_of_bounds =
+                        builder
+                            .ins()
+                            .icmp(IntCC::UnsignedGreaterThanOrEqual, ptr, tape_len);
+
+                    let error = builder.create_block();
+                    let cont = builder.create_block();
+                    builder.ins().brif(out_of_bounds, error, &[], cont, &[]);
+                    builder.seal_block(error);
+                    builder.seal_block(cont);
+
+                    builder.switch_to_block(error);
+                    builder.ins().store(mem, ptr, run_ctx, 0);
+                    let status = builder
+                        .ins()
+                        .iconst(types::I32, STATUS_OUT_OF_BOUNDS as i64);
+                    builder.ins().jump(exit, &[status]);
+
+                    builder.switch_to_block(cont);
+                }
+                Op::Output => {
+                    let addr = cell_addr(&mut builder);
+                    let call = builder.ins().call(output_fn, &[run_ctx, addr]);
+                    let status = builder.inst_results(call)[0];
+                    check_status(&mut builder, status);
+                }
+                Op::Input => {
+                    let addr = cell_addr(&mut builder);
+                    let call = builder.ins().call(input_fn, &[run_ctx, addr]);
+                    let status = builder.inst_results(call)[0];
+                    check_status(&mut builder, status);
+                }
+                Op::SetZero => {
+                    let addr = cell_addr(&mut builder);
+                    let zero = builder.ins().iconst(types::I8, 0);
+                    builder.ins().store(mem, zero, addr, 0);
+                }
+                Op::JumpFwd(_) => {
+                    let body = builder.create_block();
+                    let after = builder.create_block();
+
+                    let addr = cell_addr(&mut builder);
+                    let val = builder.ins().load(types::I8, mem, addr, 0);
+                    builder.ins().brif(val, body, &[], after, &[]);
+
+                    builder.switch_to_block(body);
+                    loops.push((body, after));
+                }
+                Op::JumpBack(_) => {
+                    let Some((body, after)) = loops.pop() else {
+                        unreachable!("Jumps of a compiled program are always matched");
+                    };
+
+                    let addr = cell_addr(&mut builder);
+                    let val = builder.ins().load(types::I8, mem, addr, 0);
+                    builder.ins().brif(val, body, &[], after, &[]);
+
+                    builder.seal_block(body);
+                    builder.seal_block(after);
+                    builder.switch_to_block(after);
+                }
+                _ => {
+                    unreachable!("Extension operations are rejected before compiling")
+                }
+            }
+        }
+
+        let status = builder.ins().iconst(types::I32, STATUS_OK as i64);
+        builder.ins().jump(exit, &[status]);
+
+        builder.seal_block(exit);
+        builder.switch_to_block(exit);
+        let status = builder.block_params(exit)[0];
+        builder.ins().return_(&[status]);
+
+        builder.finalize();
+
+        module.define_function(main_id, &mut ctx)?;
+        module.clear_context(&mut ctx);
+        module.finalize_definitions()?;
+
+        let code = module.get_finalized_function(main_id);
+
+        // SAFETY: The function was declared with exactly this signature
+        Ok(unsafe { mem::transmute::<*const u8, JitFn>(code) })
+    }
+}
+
+impl Drop for JitProgram {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: The compiled function can no longer be called once the program is dropped
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+/// Writes the cell at `cell` to the writer of `ctx` as a raw byte,
+/// in the same way as the interpreter does for byte-sized cells
+extern "C" fn jit_output(ctx: *mut RunContext, cell: *const u8) -> i32 {
+    // SAFETY: Only called by the compiled code, with the context passed to it and a cell on the tape
+    let (ctx, val) = unsafe { (&mut *ctx, *cell) };
+
+    match ctx.writer.write_all(&[val]) {
+        Ok(()) => STATUS_OK,
+        Err(e) => {
+            ctx.error = Some(e);
+            STATUS_IO_ERROR
+        }
+    }
+}
+
+/// Reads a single byte from the reader of `ctx` into `cell`, leaving
+/// it unchanged at the end of the input
+extern "C" fn jit_input(ctx: *mut RunContext, cell: *mut u8) -> i32 {
+    // SAFETY: Only called by the compiled code, with the context passed to it and a cell on the tape
+    let (ctx, cell) = unsafe { (&mut *ctx, &mut *cell) };
+
+    let mut buf = [0_u8; 1];
+
+    match ctx.reader.read(&mut buf) {
+        Ok(1) => {
+            *cell = buf[0];
+            STATUS_OK
+        }
+        Ok(_) => STATUS_OK,
+        Err(e) => {
+            ctx.error = Some(e);
+            STATUS_IO_ERROR
+        }
+    }
+}
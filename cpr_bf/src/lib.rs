@@ -14,30 +14,110 @@
 //! ```
 //! let code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
 //!
-//! let vm = cpr_bf::VMBuilder::new().build();
+//! let mut vm = cpr_bf::VMBuilder::new().build();
 //! vm.run_string(code);
 //! ```
+//!
+//! # Features
+//!
+//! - `std` (enabled by default): Reads from stdin and writes to stdout by default,
+//!   and allows running programs from files. Without it, the crate only depends on
+//!   `core` and `alloc`, and uses the I/O traits from the [`io`] module
+//! - `macros`: Adds the `bf!` macro, which parses Brainfuck source code into a
+//!   [`Program`] at compile time, and reports mismatched brackets as compile errors
+//! - `serde`: Implements `Serialize` and `Deserialize` for programs and VM state
+//! - `jit`: Adds a JIT compiler for Brainfuck programs, see the `jit` module
+//! - `mmap`: Adds a tape backed by an anonymous memory map, see the `tape::mmap` module
+//! - `wasm-codegen`: Adds a code generator emitting WebAssembly modules, see the `codegen::wasm` module
+//! - `wasm-bindgen`: Exposes [`BrainfuckVM::run_string`] to JavaScript, see the `wasm` module
+//! - `capi`: Exposes a VM to C through a generated header, see the `capi` module
+//! - `python`: Exposes [`VMBuilder`] and the VM it builds to Python, see the `python` module
+//! - `arbitrary`: Implements `Arbitrary` for programs, generating only programs without
+//!   mismatched jump instructions. Used by the fuzz targets in the `fuzz` directory
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 pub mod allocators;
+pub mod ast;
+pub mod builder;
+pub mod bytecode;
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod codegen;
+pub mod conformance;
+pub mod debug;
+pub mod dialects;
+pub mod differential;
+pub mod dump;
+pub mod extensions;
+pub mod fmt;
+pub mod io;
+pub mod ir;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod metrics;
+pub mod pipe;
+pub mod pool;
+pub mod profile;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod selfmod;
+pub mod snapshot;
+pub mod tape;
+pub mod trace;
+pub mod validation;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
 
+#[cfg(feature = "macros")]
+pub use cpr_bf_macros::bf;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
 use allocators::DynamicAllocator;
+use bytecode::BytecodeError;
+use core::{
+    any::type_name,
+    convert::TryFrom,
+    fmt::Display,
+    marker::PhantomData,
+    mem,
+    ops::{BitAnd, BitOr, BitXor, Not, Shl, Shr},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use extensions::{ExtensionSet, HostCall, Rng};
+use io::{DefaultReader, DefaultWriter, Read, Write};
+use ir::{CompiledProgram, Op, OptimizationLevel, Pass, Pipeline};
+use metrics::ProgramMetrics;
 use num::{
-    traits::{WrappingAdd, WrappingSub},
-    Unsigned,
+    traits::{CheckedAdd, CheckedSub, SaturatingAdd, SaturatingSub, WrappingAdd, WrappingSub},
+    Bounded, Num,
 };
+use snapshot::SnapshotError;
+#[cfg(feature = "std")]
 use std::{
-    any::type_name,
-    convert::{TryFrom, TryInto},
-    fmt::Display,
     fs::File,
-    io::{self, stdin, stdout, Read, Stdin, Stdout, Write},
-    iter::repeat,
-    marker::PhantomData,
+    io::{BufRead, BufReader},
     path::Path,
 };
+use tape::{image::TapeImageError, Tape};
+use validation::{ValidationReport, ValidationWarning};
 
 /// Represents a single Brainfuck instruction
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Instruction {
     /// Increment the current data pointer by one
     IncrDP,
@@ -62,23 +142,164 @@ pub enum Instruction {
 
     /// If the value in the currently pointer-to cell is not zero, jumps backwards to the previous matching [`Instruction::JumpFwd`] instruction.
     JumpBack,
+
+    /// Defines the procedure up to the matching [`Instruction::ProcEnd`], identified by the value
+    /// in the currently pointed-to cell, and jumps past it. Part of [`ExtensionSet::PBRAIN`]
+    ProcStart,
+
+    /// Returns from the currently running procedure. Part of [`ExtensionSet::PBRAIN`]
+    ProcEnd,
+
+    /// Calls the procedure identified by the value in the currently pointed-to cell.
+    /// Part of [`ExtensionSet::PBRAIN`]
+    ProcCall,
+
+    /// Forks the running thread, giving the new thread a copy of the tape with the
+    /// currently pointed-to cell set to zero. Part of [`ExtensionSet::BRAINFORK`]
+    Fork,
+
+    /// Ends the program, including all threads. Part of [`ExtensionSet::EXTENDED_TYPE_I`]
+    Exit,
+
+    /// Copies the value in the currently pointed-to cell into the storage cell.
+    /// Part of [`ExtensionSet::EXTENDED_TYPE_I`]
+    Store,
+
+    /// Copies the value in the storage cell into the currently pointed-to cell.
+    /// Part of [`ExtensionSet::EXTENDED_TYPE_I`]
+    Load,
+
+    /// Shifts the bits of the currently pointed-to cell one position to the right,
+    /// shifting in a zero. Part of [`ExtensionSet::EXTENDED_TYPE_I`]
+    ShiftRight,
+
+    /// Shifts the bits of the currently pointed-to cell one position to the left,
+    /// shifting in a zero. Part of [`ExtensionSet::EXTENDED_TYPE_I`]
+    ShiftLeft,
+
+    /// Inverts the bits of the currently pointed-to cell. Part of [`ExtensionSet::EXTENDED_TYPE_I`]
+    Not,
+
+    /// Stores the bitwise XOR of the currently pointed-to cell and the storage cell in the
+    /// currently pointed-to cell. Part of [`ExtensionSet::EXTENDED_TYPE_I`]
+    Xor,
+
+    /// Stores the bitwise AND of the currently pointed-to cell and the storage cell in the
+    /// currently pointed-to cell. Part of [`ExtensionSet::EXTENDED_TYPE_I`]
+    And,
+
+    /// Stores the bitwise OR of the currently pointed-to cell and the storage cell in the
+    /// currently pointed-to cell. Part of [`ExtensionSet::EXTENDED_TYPE_I`]
+    Or,
+
+    /// Calls the host function identified by the value in the currently pointed-to cell.
+    /// Part of [`ExtensionSet::HOST_CALL`]
+    HostCall,
+
+    /// Stores a random byte in the currently pointed-to cell. Part of [`ExtensionSet::RANDOM`]
+    Random,
+}
+
+/// Every instruction with its source code character
+const INSTRUCTION_CHARS: &[(Instruction, char)] = &[
+    (Instruction::IncrDP, '>'),
+    (Instruction::DecrDP, '<'),
+    (Instruction::Incr, '+'),
+    (Instruction::Decr, '-'),
+    (Instruction::Output, '.'),
+    (Instruction::Input, ','),
+    (Instruction::JumpFwd, '['),
+    (Instruction::JumpBack, ']'),
+    (Instruction::ProcStart, '('),
+    (Instruction::ProcEnd, ')'),
+    (Instruction::ProcCall, ':'),
+    (Instruction::Fork, 'Y'),
+    (Instruction::Exit, '@'),
+    (Instruction::Store, '$'),
+    (Instruction::Load, '!'),
+    (Instruction::ShiftRight, '}'),
+    (Instruction::ShiftLeft, '{'),
+    (Instruction::Not, '~'),
+    (Instruction::Xor, '^'),
+    (Instruction::And, '&'),
+    (Instruction::Or, '|'),
+    (Instruction::HostCall, '%'),
+    (Instruction::Random, '?'),
+];
+
+impl Instruction {
+    /// Converts the given source code character into an instruction, including the
+    /// instructions of the given extensions. Returns [`None`] for any other character
+    pub fn from_char(c: char, extensions: ExtensionSet) -> Option<Instruction> {
+        INSTRUCTION_CHARS
+            .iter()
+            .find(|&&(instr, instr_char)| {
+                instr_char == c && extensions.contains(instr.required_extensions())
+            })
+            .map(|&(instr, _)| instr)
+    }
+
+    /// The source code character of this instruction
+    pub fn to_char(self) -> char {
+        INSTRUCTION_CHARS
+            .iter()
+            .find(|&&(instr, _)| instr == self)
+            .map(|&(_, instr_char)| instr_char)
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+
+    /// The extensions that need to be enabled to use this instruction.
+    /// Empty for the standard Brainfuck instructions
+    pub fn required_extensions(self) -> ExtensionSet {
+        match self {
+            Instruction::ProcStart | Instruction::ProcEnd | Instruction::ProcCall => {
+                ExtensionSet::PBRAIN
+            }
+            Instruction::Fork => ExtensionSet::BRAINFORK,
+            Instruction::Exit
+            | Instruction::Store
+            | Instruction::Load
+            | Instruction::ShiftRight
+            | Instruction::ShiftLeft
+            | Instruction::Not
+            | Instruction::Xor
+            | Instruction::And
+            | Instruction::Or => ExtensionSet::EXTENDED_TYPE_I,
+            Instruction::HostCall => ExtensionSet::HOST_CALL,
+            Instruction::Random => ExtensionSet::RANDOM,
+            _ => ExtensionSet::NONE,
+        }
+    }
+
+    /// The kind of jump instruction that is missing if this
+    /// instruction is unmatched, for jump instructions only
+    fn missing_partner(self) -> Option<MissingKind> {
+        match self {
+            Instruction::JumpFwd => Some(MissingKind::JumpBack),
+            Instruction::JumpBack => Some(MissingKind::JumpFwd),
+            Instruction::ProcStart => Some(MissingKind::ProcEnd),
+            Instruction::ProcEnd => Some(MissingKind::ProcStart),
+            _ => None,
+        }
+    }
+
+    /// The opening instruction matched by this instruction, for closing jump instructions only
+    fn opening_partner(self) -> Option<Instruction> {
+        match self {
+            Instruction::JumpBack => Some(Instruction::JumpFwd),
+            Instruction::ProcEnd => Some(Instruction::ProcStart),
+            _ => None,
+        }
+    }
 }
 
 impl TryFrom<char> for Instruction {
     type Error = ();
 
+    /// Converts the given character into one of the eight standard Brainfuck instructions.
+    /// See [`Instruction::from_char`] to include extension instructions
     fn try_from(value: char) -> Result<Self, Self::Error> {
-        match value {
-            '>' => Ok(Instruction::IncrDP),
-            '<' => Ok(Instruction::DecrDP),
-            '+' => Ok(Instruction::Incr),
-            '-' => Ok(Instruction::Decr),
-            '.' => Ok(Instruction::Output),
-            ',' => Ok(Instruction::Input),
-            '[' => Ok(Instruction::JumpFwd),
-            ']' => Ok(Instruction::JumpBack),
-            _ => Err(()),
-        }
+        Instruction::from_char(value, ExtensionSet::NONE).ok_or(())
     }
 }
 
@@ -87,554 +308,4282 @@ impl TryFrom<char> for Instruction {
 /// and is instead constructed automatically through the various `run_*` methods
 /// defined on the [`BrainfuckVM`] trait.
 ///
-/// If desired, however, one can be constructed through the [`From<&str>`] trait
-/// implementation defined for [`Program`]
+/// If desired, however, one can be constructed through [`Program::parse`],
+/// or through the [`From<&str>`] trait implementation defined for [`Program`]
+///
+/// During construction, the partner of every jump instruction is looked up
+/// and stored, so that the VM does not have to search for it at runtime.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "SerializedProgram", into = "SerializedProgram")
+)]
 pub struct Program {
     instructions: Vec<Instruction>,
+    jump_table: Vec<Option<usize>>,
+    breakpoints: Vec<usize>,
+    source_len: Option<usize>,
+    source_positions: Vec<SourcePosition>,
 }
 
-impl From<&str> for Program {
-    fn from(input: &str) -> Self {
-        let instructions = input
-            .chars()
-            .filter_map(|c| Instruction::try_from(c).ok())
-            .collect();
-
-        Program { instructions }
-    }
+/// The serialized form of a [`Program`]. The jump table is not serialized,
+/// but rebuilt when deserializing
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Program")]
+struct SerializedProgram {
+    instructions: Vec<Instruction>,
+    breakpoints: Vec<usize>,
+    #[serde(default)]
+    source_len: Option<usize>,
+    #[serde(default)]
+    source_positions: Vec<SourcePosition>,
 }
 
-/// This trait defines types that can be used as the datatype for a single cell of
-/// a Brainfuck VM. Can be implemented manually (although not recommended), but is
-/// already implemented for the default unsigned int types ([`u8`], [`u16`], etc.)
-pub trait BrainfuckCell:
-    Unsigned + Copy + Default + TryInto<u32> + From<u8> + WrappingAdd + WrappingSub + std::fmt::Debug
-{
+#[cfg(feature = "serde")]
+impl From<SerializedProgram> for Program {
+    fn from(value: SerializedProgram) -> Self {
+        Program {
+            source_len: value.source_len,
+            source_positions: value.source_positions,
+            ..Program::from_parts(value.instructions, value.breakpoints)
+        }
+    }
 }
 
-impl<
-        T: Unsigned
-            + Copy
-            + Default
-            + TryInto<u32>
-            + From<u8>
-            + WrappingAdd
-            + WrappingSub
-            + std::fmt::Debug,
-    > BrainfuckCell for T
-{
+#[cfg(feature = "serde")]
+impl From<Program> for SerializedProgram {
+    fn from(value: Program) -> Self {
+        SerializedProgram {
+            instructions: value.instructions,
+            breakpoints: value.breakpoints,
+            source_len: value.source_len,
+            source_positions: value.source_positions,
+        }
+    }
 }
 
-/// An out-of-bounds access error returned by the
-/// Brainfuck VM if an access is attempted outside the
-/// allocated memory region, without dynamic allocation being enabled
-#[derive(Debug)]
-pub struct OutOfBoundsAccess {
-    /// The current maximum capacity of the VM, in number of cells
-    pub capacity: usize,
+impl Program {
+    /// The instructions making up this program
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
 
-    /// The index of the attempted access
-    pub access: usize,
-}
+    /// The index of the jump instruction matching the jump instruction at `index`.
+    /// Returns [`None`] if the instruction at `index` is not a jump instruction,
+    /// or if it is unmatched
+    pub fn jump_target(&self, index: usize) -> Option<usize> {
+        self.jump_table.get(index).copied().flatten()
+    }
 
-/// A general memory error encountered during runtime by the VM
-#[derive(Debug)]
-pub enum VMMemoryError {
-    /// An out-of-bounds access
-    OutOfBounds(OutOfBoundsAccess),
-}
+    /// The indices of the instructions that were preceded by a `#` debug
+    /// instruction in the source code. These are ignored by the normal VM,
+    /// but are used as breakpoints by [`debug::DebuggerVM`]
+    pub fn breakpoints(&self) -> &[usize] {
+        &self.breakpoints
+    }
 
-impl From<VMMemoryError> for BrainfuckExecutionError {
-    fn from(value: VMMemoryError) -> Self {
-        BrainfuckExecutionError::MemoryError(value)
+    /// The length in bytes of the source code this program was parsed from, including
+    /// comments. [`None`] if the program was not parsed from source code, for example
+    /// if it was decoded from bytecode or built with a [`builder::ProgramBuilder`]
+    pub fn source_len(&self) -> Option<usize> {
+        self.source_len
     }
-}
 
-/// A trait representing an object that is capable of
-/// allocating memory for a Brainfuck VM
-pub trait BrainfuckAllocator {
-    /// Ensures that `data` has at least `min_size` cells available for
-    /// both reading and writing. If this function returns [`Result::Ok`],
-    /// `data[min_size - 1]` can be safely read from and written to.
+    /// The position in the source code of the instruction at `index`. [`None`] if
+    /// there is no such instruction, or if the program was not parsed from source code
     ///
-    /// Any new cells created by this function shall be initialized
-    /// to the default value of `T`
-    fn ensure_capacity<T: BrainfuckCell>(
-        data: &mut Vec<T>,
-        min_size: usize,
-    ) -> Result<(), VMMemoryError>;
-}
-
-struct VirtualMachine<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> {
-    data_ptr: usize,
-    data: Vec<T>,
-    alloc: PhantomData<A>,
-    reader: R,
-    writer: W,
-}
-
-/// A builder struct for the default implementation of [`BrainfuckVM`]
-/// Create the default configuration with [`VMBuilder::new()`] or [`VMBuilder::default()`],
-/// customize with the member functions, and build the final VM with [`VMBuilder::build()`]
-pub struct VMBuilder<
-    T: BrainfuckCell = u8,
-    A: BrainfuckAllocator = DynamicAllocator,
-    R: Read = Stdin,
-    W: Write = Stdout,
-> {
-    initial_size: usize,
-    celltype: PhantomData<T>,
-    allocator: PhantomData<A>,
-    reader: R,
-    writer: W,
-}
-
-impl VMBuilder {
-    /// Construct a new VMBuilder with the default initial configuration
-    pub fn new() -> VMBuilder {
-        VMBuilder::default()
+    /// ```
+    /// use cpr_bf::{Program, SourcePosition};
+    ///
+    /// let program = Program::from("Add one:\n  +");
+    ///
+    /// assert_eq!(
+    ///     program.source_position(0),
+    ///     Some(SourcePosition { offset: 11, line: 2, column: 3 })
+    /// );
+    /// ```
+    pub fn source_position(&self, index: usize) -> Option<SourcePosition> {
+        self.source_positions.get(index).copied()
     }
-}
 
-impl Default for VMBuilder {
-    /// Construct a new VMBuilder with the default initial configuration
-    fn default() -> Self {
-        VMBuilder {
-            initial_size: 0,
-            celltype: PhantomData,
-            allocator: PhantomData,
-            reader: stdin(),
-            writer: stdout(),
-        }
+    /// Writes this program back out as Brainfuck source code, without any comments
+    /// or whitespace. Every breakpoint is written as a `#` debug instruction, so that
+    /// parsing the result gives back the same program. This is also what the
+    /// [`Display`] implementation writes.
+    ///
+    /// ```
+    /// use cpr_bf::{builder::ProgramBuilder, Program};
+    ///
+    /// let program = ProgramBuilder::new().add(3).loop_start().breakpoint().add(-1).loop_end().build().unwrap();
+    /// assert_eq!(program.to_source(), "+++[#-]");
+    ///
+    /// let parsed = Program::parse(&program.to_source()).unwrap();
+    /// assert_eq!(parsed.instructions(), program.instructions());
+    /// assert_eq!(parsed.breakpoints(), program.breakpoints());
+    /// ```
+    pub fn to_source(&self) -> String {
+        format!("{}", self)
     }
-}
-
-impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> Display for VMBuilder<T, A, R, W> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "VMBuilder<{}, {}, {}, {}> with initial size {}",
-            type_name::<T>(),
-            type_name::<A>(),
-            type_name::<R>(),
-            type_name::<W>(),
-            self.initial_size
-        )?;
 
-        Ok(())
+    /// Counts the instructions and loops of this program. See [`ProgramMetrics`]
+    ///
+    /// ```
+    /// use cpr_bf::{Instruction, Program};
+    ///
+    /// let metrics = Program::parse("Prints A: ++++++++[>++++++++<-]>+.").unwrap().metrics();
+    ///
+    /// assert_eq!(metrics.count(Instruction::Incr), 17);
+    /// assert_eq!(metrics.loops, 1);
+    /// assert_eq!(metrics.source_len, Some(34));
+    /// ```
+    pub fn metrics(&self) -> ProgramMetrics {
+        metrics::metrics(self)
     }
-}
 
-impl<
-        T: BrainfuckCell + 'static,
-        A: BrainfuckAllocator + 'static,
-        R: Read + 'static,
-        W: Write + 'static,
-    > VMBuilder<T, A, R, W>
-{
-    /// Changes the type of the memory cells to `U`
-    pub fn with_cell_type<U: BrainfuckCell>(self) -> VMBuilder<U, A, R, W> {
-        VMBuilder {
-            initial_size: self.initial_size,
-            celltype: PhantomData::<U>,
-            allocator: self.allocator,
-            reader: self.reader,
-            writer: self.writer,
-        }
+    /// The extensions used by the instructions of this program
+    pub fn extensions(&self) -> ExtensionSet {
+        self.instructions
+            .iter()
+            .fold(ExtensionSet::NONE, |used, instr| {
+                used | instr.required_extensions()
+            })
     }
 
-    /// Changes the used allocator to `U`
-    pub fn with_allocator<U: BrainfuckAllocator>(self) -> VMBuilder<T, U, R, W> {
-        VMBuilder {
-            initial_size: self.initial_size,
-            celltype: self.celltype,
-            allocator: PhantomData::<U>,
-            reader: self.reader,
-            writer: self.writer,
-        }
+    /// Parses the given Brainfuck source code into a [`Program`].
+    ///
+    /// Unlike the [`From<&str>`] implementation, this checks that all jump
+    /// instructions are properly matched, and returns a [`ParseError`] if not.
+    pub fn parse(input: &str) -> Result<Program, ParseError> {
+        Program::parse_with_extensions(input, ExtensionSet::NONE)
     }
 
-    /// Changes the amount of pre-allocated cells to `num_preallocated`
-    pub fn with_preallocated_cells(self, num_preallocated: usize) -> VMBuilder<T, A, R, W> {
-        VMBuilder {
-            initial_size: num_preallocated,
-            ..self
-        }
-    }
+    /// Like [`Program::parse`], but additionally parses the instructions
+    /// of the given [`extensions`](crate::extensions)
+    ///
+    /// ```
+    /// use cpr_bf::{extensions::ExtensionSet, Program};
+    ///
+    /// assert_eq!(Program::parse("+(-):").unwrap().instructions().len(), 2);
+    ///
+    /// let program = Program::parse_with_extensions("+(-):", ExtensionSet::PBRAIN).unwrap();
+    /// assert_eq!(program.instructions().len(), 5);
+    /// ```
+    pub fn parse_with_extensions(
+        input: &str,
+        extensions: ExtensionSet,
+    ) -> Result<Program, ParseError> {
+        let mut parser = SourceParser::new(extensions);
+        let mut position = SourcePosition {
+            offset: 0,
+            line: 1,
+            column: 1,
+        };
 
-    /// Changes the reader used by the VM as input for the running Brainfuck
-    /// programs to `reader`
-    pub fn with_reader<U: Read>(self, reader: U) -> VMBuilder<T, A, U, W> {
-        VMBuilder {
-            initial_size: self.initial_size,
-            celltype: self.celltype,
-            allocator: self.allocator,
-            reader,
-            writer: self.writer,
+        for (offset, c) in input.char_indices() {
+            position.offset = offset;
+
+            parser.push(c, position)?;
+
+            if c == '\n' {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
         }
+
+        parser.finish(input.len())
     }
 
-    /// Changes the writer used by the VM as output for the running Brainfuck programs
-    /// to `writer`
-    pub fn with_writer<U: Write>(self, writer: U) -> VMBuilder<T, A, R, U> {
-        VMBuilder {
-            initial_size: self.initial_size,
-            celltype: self.celltype,
-            allocator: self.allocator,
-            reader: self.reader,
-            writer,
-        }
+    /// Parses the given Brainfuck source code into a [`Program`], rejecting any
+    /// character that is not an instruction, a `#` debug instruction, or whitespace.
+    ///
+    /// Returns a [`ParseError::InvalidCharacter`] with the position of the first
+    /// offending character. See [`Program::parse_strict_with`] to allow comments
+    ///
+    /// ```
+    /// use cpr_bf::{ParseError, Program};
+    ///
+    /// assert!(Program::parse_strict("+[->+<]\n").is_ok());
+    ///
+    /// let Err(ParseError::InvalidCharacter { character, position }) =
+    ///     Program::parse_strict("++\n+x")
+    /// else {
+    ///     panic!("Expected an invalid character");
+    /// };
+    ///
+    /// assert_eq!(character, 'x');
+    /// assert_eq!((position.line, position.column), (2, 2));
+    /// ```
+    pub fn parse_strict(input: &str) -> Result<Program, ParseError> {
+        Program::parse_strict_with(input, CommentSyntax::None)
     }
 
-    /// Builds the [`BrainfuckVM`] with the currently
-    /// stored configuration of this builder
-    pub fn build(self) -> Box<dyn BrainfuckVM> {
-        log::info!("Building Brainfuck VM with configuration: {}", self);
+    /// Like [`Program::parse_strict`], but additionally allows comments in the given syntax.
+    /// Any characters inside comments, including instructions, are ignored
+    ///
+    /// ```
+    /// use cpr_bf::{CommentSyntax, Program};
+    ///
+    /// let program = Program::parse_strict_with("++ ; add two\n. ; print it", CommentSyntax::Line(';'));
+    /// assert_eq!(program.unwrap().instructions().len(), 3);
+    ///
+    /// let program = Program::parse_strict_with("++ { print it. } .", CommentSyntax::Block('{', '}'));
+    /// assert_eq!(program.unwrap().instructions().len(), 3);
+    /// ```
+    pub fn parse_strict_with(input: &str, comments: CommentSyntax) -> Result<Program, ParseError> {
+        let mut parser = SourceParser::new(ExtensionSet::NONE);
+        let mut comment_end = None;
+        let mut position = SourcePosition {
+            offset: 0,
+            line: 1,
+            column: 1,
+        };
 
-        Box::new(VirtualMachine::<T, A, R, W>::new(
-            self.initial_size,
-            self.reader,
-            self.writer,
-        ))
-    }
-}
+        for (offset, c) in input.char_indices() {
+            position.offset = offset;
 
-/// The kind of missing jump instruction
-#[derive(Debug)]
-pub enum MissingKind {
-    JumpFwd,
-    JumpBack,
-}
+            if let Some(end) = comment_end {
+                if c == end {
+                    comment_end = None;
+                }
+            } else if parser.push(c, position)? {
+                // Instructions and breakpoints are handled by the parser
+            } else if let Some(end) = comments.end_of_comment_starting_with(c) {
+                comment_end = Some(end);
+            } else if !c.is_whitespace() {
+                log::debug!("Invalid character {:?} at {}", c, position);
 
-/// A fatal error encountered by the Brainfuck VM during program execution.
-#[derive(Debug)]
-pub enum BrainfuckExecutionError {
-    /// An unknown error
-    UnknownError,
+                return Err(ParseError::InvalidCharacter {
+                    character: c,
+                    position,
+                });
+            }
 
-    /// An error during input or output
-    IOError(io::Error),
+            if c == '\n' {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
+        }
 
-    /// Mismatched jump instructions
-    JumpMismatchError(MissingKind),
+        parser.finish(input.len())
+    }
 
-    /// An error during memory allocation or access
-    MemoryError(VMMemoryError),
+    /// Parses Brainfuck source code from `reader` into a [`Program`], without
+    /// loading the whole source code into memory first.
+    ///
+    /// The source code is processed byte by byte, so it does not need to be
+    /// valid UTF-8. Like [`Program::parse`], unmatched jump instructions are
+    /// reported with their position, where the column counts UTF-8 characters
+    ///
+    /// ```
+    /// use cpr_bf::Program;
+    ///
+    /// let source: &[u8] = b"+[>+\xff<-]";
+    /// let program = Program::parse_reader(source).unwrap();
+    ///
+    /// assert_eq!(program.instructions().len(), 7);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn parse_reader(reader: impl BufRead) -> Result<Program, BrainfuckExecutionError> {
+        Program::parse_reader_with_extensions(reader, ExtensionSet::NONE)
+    }
 
-    /// Overflow in the data pointer
-    DataPointerOverflow,
+    /// Like [`Program::parse_reader`], but additionally parses the
+    /// instructions of the given [`extensions`](crate::extensions)
+    #[cfg(feature = "std")]
+    pub fn parse_reader_with_extensions(
+        mut reader: impl BufRead,
+        extensions: ExtensionSet,
+    ) -> Result<Program, BrainfuckExecutionError> {
+        let mut parser = SourceParser::new(extensions);
+        let mut position = SourcePosition {
+            offset: 0,
+            line: 1,
+            column: 1,
+        };
 
-    /// Underflow in the data pointer
-    DataPointerUnderflow,
-}
+        loop {
+            let buf = reader.fill_buf()?;
 
-impl Display for BrainfuckExecutionError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            BrainfuckExecutionError::UnknownError => write!(f, "Unknown error"),
-            BrainfuckExecutionError::IOError(e) => write!(f, "I/O Error: {}", e),
-            BrainfuckExecutionError::JumpMismatchError(MissingKind::JumpBack) => {
-                write!(f, "Too few closing brackets")
+            if buf.is_empty() {
+                break;
             }
-            BrainfuckExecutionError::JumpMismatchError(MissingKind::JumpFwd) => {
-                write!(f, "Too few opening brackets")
+
+            for &byte in buf {
+                // All instructions are ASCII, so bytes of multi-byte characters never match
+                parser.push(char::from(byte), position)?;
+
+                position.offset += 1;
+
+                // Continuation bytes of multi-byte UTF-8 characters do not start a new column
+                if byte == b'\n' {
+                    position.line += 1;
+                    position.column = 1;
+                } else if byte & 0b1100_0000 != 0b1000_0000 {
+                    position.column += 1;
+                }
             }
-            BrainfuckExecutionError::MemoryError(VMMemoryError::OutOfBounds(a)) => write!(
-                f,
-                "Out of bounds memory access at index {} (max size {})",
-                a.access, a.capacity
-            ),
-            BrainfuckExecutionError::DataPointerOverflow => write!(f, "Data pointer overflow!"),
-            BrainfuckExecutionError::DataPointerUnderflow => write!(f, "Data pointer underflow!"),
+
+            let len = buf.len();
+            reader.consume(len);
         }
+
+        Ok(parser.finish(position.offset)?)
     }
-}
 
-impl std::error::Error for BrainfuckExecutionError {
-    fn cause(&self) -> Option<&dyn std::error::Error> {
-        match self {
-            BrainfuckExecutionError::IOError(e) => Some(e),
-            _ => None,
-        }
+    /// Builds the abstract syntax tree of this program.
+    ///
+    /// See [`ast::Node::from_program`]
+    pub fn to_ast(&self) -> Result<ast::Node, ParseError> {
+        ast::Node::from_program(self)
     }
-}
 
-impl From<()> for BrainfuckExecutionError {
-    fn from(_: ()) -> Self {
-        BrainfuckExecutionError::UnknownError
+    /// Compiles this program with the given optimization level.
+    ///
+    /// See [`CompiledProgram::compile`]
+    pub fn compile(&self, level: OptimizationLevel) -> Result<CompiledProgram, ParseError> {
+        CompiledProgram::compile(self, level)
     }
-}
 
-impl From<io::Error> for BrainfuckExecutionError {
-    fn from(value: io::Error) -> Self {
-        BrainfuckExecutionError::IOError(value)
+    /// Compiles this program, applying exactly the given optimization passes in order.
+    ///
+    /// See [`CompiledProgram::compile_with_passes`]
+    pub fn optimize(&self, passes: &[Pass]) -> Result<CompiledProgram, ParseError> {
+        CompiledProgram::compile_with_passes(self, passes)
     }
-}
 
-type ExecResult = Result<usize, BrainfuckExecutionError>;
+    /// Checks this program without running it.
+    ///
+    /// See the [`validation`] module
+    pub fn validate(&self) -> ValidationReport {
+        validation::validate(self)
+    }
+
+    /// Conservatively estimates how far right the data pointer can travel while
+    /// running this program. Returns the amount of cells the program needs, from the
+    /// cell it starts at up to and including the rightmost cell it can reach.
+    /// This is a safe amount of cells to preallocate, see [`VMBuilder::with_auto_preallocated_cells`].
+    ///
+    /// Returns [`None`] if there is no static bound, which is the case if the program
+    /// has a loop whose iterations can end further right than they started, such as
+    /// `[>]`, calls procedures, or has mismatched jumps. Cells reached in the body of a
+    /// loop are counted, even if the loop might never run
+    ///
+    /// ```
+    /// use cpr_bf::Program;
+    ///
+    /// assert_eq!(Program::from(">>[>+<-]>[<]").tape_extent(), Some(4));
+    /// assert_eq!(Program::from("+[>+]").tape_extent(), None);
+    /// ```
+    pub fn tape_extent(&self) -> Option<usize> {
+        validation::rightmost_cell(self).map(|cell| cell + 1)
+    }
+
+    /// Like [`Program::validate`], but also warns if the program reaches cells beyond the
+    /// end of a tape of the given size. Use this for programs run with one of the
+    /// static allocators, passing the amount of preallocated cells
+    pub fn validate_for_tape(&self, tape_size: usize) -> ValidationReport {
+        let mut report = self.validate();
+
+        if let Some(rightmost_cell) = report.rightmost_cell.filter(|&cell| cell >= tape_size) {
+            report.warnings.push(ValidationWarning::TapeTooSmall {
+                rightmost_cell,
+                tape_size,
+            });
+        }
+
+        report
+    }
+
+    /// Encodes this program in the compact binary format described
+    /// in the [`bytecode`] module
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bytecode::encode(self)
+    }
+
+    /// Decodes a program from the compact binary format described
+    /// in the [`bytecode`] module.
+    ///
+    /// Like the [`From<&str>`] implementation, this does not reject
+    /// mismatched jump instructions
+    pub fn from_bytes(data: &[u8]) -> Result<Program, BytecodeError> {
+        bytecode::decode(data)
+    }
+
+    /// Runs this program on a default VM reading from `input`, and returns an
+    /// iterator over the bytes it writes. The program only runs while the iterator
+    /// is consumed, so it can be stopped early by dropping the iterator.
+    ///
+    /// Any error is yielded as the last item of the iterator
+    ///
+    /// ```
+    /// let program = cpr_bf::Program::parse("+[.]").unwrap();
+    /// let output: Vec<u8> = program
+    ///     .run_iter(std::io::empty())
+    ///     .take(3)
+    ///     .collect::<Result<_, _>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(output, [1, 1, 1]);
+    /// ```
+    pub fn run_iter<R: Read + 'static>(
+        &self,
+        input: R,
+    ) -> impl Iterator<Item = Result<u8, BrainfuckExecutionError>> {
+        let mut vm = VMBuilder::new()
+            .with_reader(input)
+            .with_writer(Vec::new())
+            .with_flush_policy(FlushPolicy::EveryOutput)
+            .build_typed();
+
+        let error = vm.load_program(self).err();
+
+        OutputIter {
+            vm,
+            buffer: VecDeque::new(),
+            error,
+            done: false,
+        }
+    }
+
+    /// Constructs a program from its instructions and breakpoints,
+    /// building the jump table
+    pub(crate) fn from_parts(instructions: Vec<Instruction>, breakpoints: Vec<usize>) -> Program {
+        let jump_table = Program::build_jump_table(&instructions);
+
+        Program {
+            instructions,
+            jump_table,
+            breakpoints,
+            source_len: None,
+            source_positions: Vec::new(),
+        }
+    }
+
+    /// Constructs a program from instructions that were already parsed and checked
+    /// for mismatched jump instructions by the `bf!` macro. Not meant to be called directly
+    #[doc(hidden)]
+    pub fn from_validated(
+        instructions: &[Instruction],
+        breakpoints: &[usize],
+        source_len: usize,
+    ) -> Program {
+        Program {
+            source_len: Some(source_len),
+            ..Program::from_parts(instructions.to_vec(), breakpoints.to_vec())
+        }
+    }
+
+    /// Builds the table mapping each jump instruction, including the brackets of
+    /// procedure definitions, to the index of its matching partner. Unmatched jump
+    /// instructions, as well as all other instructions, are mapped to [`None`]
+    fn build_jump_table(instructions: &[Instruction]) -> Vec<Option<usize>> {
+        let mut jump_table = vec![None; instructions.len()];
+        let mut open_stack: Vec<usize> = Vec::new();
+
+        for (i, &instr) in instructions.iter().enumerate() {
+            match instr {
+                Instruction::JumpFwd | Instruction::ProcStart => open_stack.push(i),
+                Instruction::JumpBack | Instruction::ProcEnd => {
+                    let Some(&open) = open_stack.last() else {
+                        continue;
+                    };
+
+                    if instr.opening_partner() == Some(instructions[open]) {
+                        log::trace!(
+                            "Matched {:?} at {} with {:?} at {}",
+                            instructions[open],
+                            open,
+                            instr,
+                            i
+                        );
+
+                        open_stack.pop();
+                        jump_table[open] = Some(i);
+                        jump_table[i] = Some(open);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        jump_table
+    }
+}
+
+impl Display for Program {
+    /// Writes this program as Brainfuck source code, see [`Program::to_source`]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut breakpoints = self.breakpoints.iter().peekable();
+
+        for (i, instr) in self.instructions.iter().enumerate() {
+            if breakpoints
+                .next_if(|&&breakpoint| breakpoint <= i)
+                .is_some()
+            {
+                write!(f, "#")?;
+            }
+
+            write!(f, "{}", instr.to_char())?;
+        }
+
+        if breakpoints.next().is_some() {
+            write!(f, "#")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Program {
+    /// Generates a program of arbitrary instructions, including those of every extension.
+    /// Every jump instruction and every bracket of a procedure definition has a matching
+    /// partner, so the program never fails with a [`BrainfuckExecutionError::JumpMismatchError`]
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut instructions = Vec::new();
+        let mut open = Vec::new();
+
+        for instr in u.arbitrary_iter::<Instruction>()? {
+            let instr = instr?;
+
+            match instr {
+                Instruction::JumpFwd | Instruction::ProcStart => open.push(instr),
+                Instruction::JumpBack | Instruction::ProcEnd => {
+                    // Closing instructions without an open partner are left out
+                    if open.last().copied() != instr.opening_partner() {
+                        continue;
+                    }
+
+                    open.pop();
+                }
+                _ => {}
+            }
+
+            instructions.push(instr);
+        }
+
+        // Everything that is still open is closed at the end, innermost first
+        instructions.extend(open.into_iter().rev().map(|instr| match instr {
+            Instruction::ProcStart => Instruction::ProcEnd,
+            _ => Instruction::JumpBack,
+        }));
+
+        Ok(Program::from_parts(instructions, Vec::new()))
+    }
+}
+
+impl From<&str> for Program {
+    /// Converts the given Brainfuck source code into a [`Program`].
+    ///
+    /// Mismatched jump instructions are not rejected here, but will
+    /// result in a [`BrainfuckExecutionError::JumpMismatchError`] once
+    /// the program is run. Use [`Program::parse`] to detect them
+    /// while parsing instead.
+    fn from(input: &str) -> Self {
+        let mut instructions = Vec::new();
+        let mut breakpoints = Vec::new();
+        let mut source_positions = Vec::new();
+        let mut position = SourcePosition {
+            offset: 0,
+            line: 1,
+            column: 1,
+        };
+
+        for (offset, c) in input.char_indices() {
+            position.offset = offset;
+
+            if c == '#' {
+                breakpoints.push(instructions.len());
+            } else if let Ok(instr) = Instruction::try_from(c) {
+                instructions.push(instr);
+                source_positions.push(position);
+            }
+
+            if c == '\n' {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
+        }
+
+        breakpoints.dedup();
+
+        Program {
+            source_len: Some(input.len()),
+            source_positions,
+            ..Program::from_parts(instructions, breakpoints)
+        }
+    }
+}
+
+/// Collects the instructions and breakpoints of source code that is parsed one character
+/// at a time, and checks that all jump instructions are properly matched
+struct SourceParser {
+    extensions: ExtensionSet,
+    instructions: Vec<Instruction>,
+    breakpoints: Vec<usize>,
+    source_positions: Vec<SourcePosition>,
+    open_stack: Vec<(Instruction, SourcePosition)>,
+}
+
+impl SourceParser {
+    fn new(extensions: ExtensionSet) -> Self {
+        SourceParser {
+            extensions,
+            instructions: Vec::new(),
+            breakpoints: Vec::new(),
+            source_positions: Vec::new(),
+            open_stack: Vec::new(),
+        }
+    }
+
+    /// Handles the character `c`, found at `position`. Returns whether it was
+    /// an instruction or a `#` debug instruction
+    fn push(&mut self, c: char, position: SourcePosition) -> Result<bool, ParseError> {
+        if c == '#' {
+            self.breakpoints.push(self.instructions.len());
+            return Ok(true);
+        }
+
+        let Some(instr) = Instruction::from_char(c, self.extensions) else {
+            return Ok(false);
+        };
+
+        match instr {
+            Instruction::JumpFwd | Instruction::ProcStart => {
+                self.open_stack.push((instr, position))
+            }
+            Instruction::JumpBack | Instruction::ProcEnd => {
+                let matched = self
+                    .open_stack
+                    .last()
+                    .is_some_and(|&(open, _)| instr.opening_partner() == Some(open));
+
+                if !matched {
+                    log::debug!("Unmatched {:?} at {}", instr, position);
+
+                    return Err(ParseError::JumpMismatch(UnmatchedJump {
+                        missing: instr.missing_partner().unwrap_or(MissingKind::JumpFwd),
+                        position: Some(position),
+                    }));
+                }
+
+                self.open_stack.pop();
+            }
+            _ => {}
+        }
+
+        self.instructions.push(instr);
+        self.source_positions.push(position);
+
+        Ok(true)
+    }
+
+    /// Builds the program, parsed from `source_len` bytes of source code
+    fn finish(mut self, source_len: usize) -> Result<Program, ParseError> {
+        if let Some(&(unmatched, position)) = self.open_stack.first() {
+            log::debug!("Unmatched {:?} at {}", unmatched, position);
+
+            return Err(ParseError::JumpMismatch(UnmatchedJump {
+                missing: unmatched.missing_partner().unwrap_or(MissingKind::JumpBack),
+                position: Some(position),
+            }));
+        }
+
+        self.breakpoints.dedup();
+
+        Ok(Program {
+            source_len: Some(source_len),
+            source_positions: self.source_positions,
+            ..Program::from_parts(self.instructions, self.breakpoints)
+        })
+    }
+}
+
+/// The iterator returned by [`Program::run_iter`]
+struct OutputIter<R: Read> {
+    vm: VirtualMachine<u8, DynamicAllocator, R, Vec<u8>>,
+    buffer: VecDeque<u8>,
+    error: Option<BrainfuckExecutionError>,
+    done: bool,
+}
+
+impl<R: Read> Iterator for OutputIter<R> {
+    type Item = Result<u8, BrainfuckExecutionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(byte) = self.buffer.pop_front() {
+                return Some(Ok(byte));
+            }
+
+            if let Some(e) = self.error.take() {
+                self.done = true;
+                return Some(Err(e));
+            }
+
+            if self.done {
+                return None;
+            }
+
+            match self.vm.step() {
+                ExecutionState::Running => {}
+                ExecutionState::Halted => self.done = true,
+                ExecutionState::Error(e) => self.error = Some(e),
+            }
+
+            self.buffer.extend(self.vm.writer_mut().drain(..));
+        }
+    }
+}
+
+/// A position in Brainfuck source code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourcePosition {
+    /// The byte offset from the start of the source code
+    pub offset: usize,
+
+    /// The line number, starting at 1
+    pub line: usize,
+
+    /// The column within the line in characters, starting at 1
+    pub column: usize,
+}
+
+impl Display for SourcePosition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// The comments allowed in source code parsed with [`Program::parse_strict_with`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommentSyntax {
+    /// No comments are allowed
+    #[default]
+    None,
+
+    /// Comments start with the given character, and run until the end of the line
+    Line(char),
+
+    /// Comments start with the first character, and run until the second character
+    Block(char, char),
+}
+
+impl CommentSyntax {
+    /// The character ending a comment that starts with `c`,
+    /// or [`None`] if `c` does not start a comment
+    fn end_of_comment_starting_with(self, c: char) -> Option<char> {
+        match self {
+            CommentSyntax::Line(start) if c == start => Some('\n'),
+            CommentSyntax::Block(start, end) if c == start => Some(end),
+            _ => None,
+        }
+    }
+}
+
+/// An unmatched jump instruction found while parsing
+#[derive(Debug)]
+pub struct UnmatchedJump {
+    /// The kind of jump instruction that is missing
+    pub missing: MissingKind,
+
+    /// The position of the unmatched jump instruction in the source code, if known.
+    /// For [`MissingKind::JumpBack`] and [`MissingKind::ProcEnd`], this is the
+    /// outermost unclosed opening bracket
+    pub position: Option<SourcePosition>,
+}
+
+/// An error encountered while parsing Brainfuck source code
+#[derive(Debug)]
+pub enum ParseError {
+    /// Mismatched jump instructions
+    JumpMismatch(UnmatchedJump),
+
+    /// A token that is not valid in the parsed dialect, see [`dialects`]
+    InvalidToken {
+        /// The position of the invalid token in the source code
+        position: SourcePosition,
+    },
+
+    /// A character that is not allowed by [`Program::parse_strict`]
+    InvalidCharacter {
+        /// The offending character
+        character: char,
+
+        /// The position of the offending character in the source code
+        position: SourcePosition,
+    },
+}
+
+impl ParseError {
+    /// The position in the source code this error was found at, if known
+    pub fn position(&self) -> Option<SourcePosition> {
+        match self {
+            ParseError::JumpMismatch(unmatched) => unmatched.position,
+            ParseError::InvalidToken { position } => Some(*position),
+            ParseError::InvalidCharacter { position, .. } => Some(*position),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::JumpMismatch(unmatched) => {
+                let bracket = match unmatched.missing {
+                    MissingKind::JumpBack => '[',
+                    MissingKind::JumpFwd => ']',
+                    MissingKind::ProcEnd => '(',
+                    MissingKind::ProcStart => ')',
+                };
+
+                write!(f, "{}", unmatched.missing)?;
+
+                if let Some(position) = unmatched.position {
+                    write!(f, " (unmatched '{}' at {})", bracket, position)?;
+                }
+
+                Ok(())
+            }
+            ParseError::InvalidCharacter {
+                character,
+                position,
+            } => write!(f, "Invalid character {:?} at {}", character, position),
+            ParseError::InvalidToken { position } => write!(f, "Invalid token at {}", position),
+        }
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// This trait defines types that can be used as the datatype for a single cell of
+/// a Brainfuck VM. Can be implemented manually (although not recommended), but is
+/// already implemented for the default int types, both unsigned ([`u8`], [`u16`], etc.)
+/// and signed ([`i8`], [`i16`], etc.)
+pub trait BrainfuckCell:
+    Num
+    + Bounded
+    + Copy
+    + Default
+    + WrappingAdd
+    + WrappingSub
+    + CheckedAdd
+    + CheckedSub
+    + SaturatingAdd
+    + SaturatingSub
+    + Not<Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+    + core::fmt::Debug
+    + Display
+{
+    /// The encoding with which the VM writes cells of this type, unless configured
+    /// otherwise with [`VMBuilder::with_output_encoding`]
+    const OUTPUT_ENCODING: OutputEncoding = OutputEncoding::Unicode;
+
+    /// Converts a byte read from the VM reader into a cell value
+    fn from_byte(byte: u8) -> Self;
+
+    /// Converts the cell value into the code point written to the VM writer.
+    /// Returns [`None`] if the value does not fit in a [`u32`]
+    fn to_code_point(self) -> Option<u32>;
+
+    /// Converts the cell value into a [`u64`], as returned by [`BrainfuckVM::cells_as_u64`].
+    /// Signed values are converted like [`BrainfuckCell::to_code_point`], and values
+    /// wider than 64 bits are truncated
+    fn to_u64(self) -> u64;
+
+    /// Shifts the bits of the cell value one position to the right, shifting in a zero,
+    /// as done by [`Instruction::ShiftRight`]. Signed values are shifted like their
+    /// unsigned counterpart, so that the sign bit is not copied
+    ///
+    /// ```
+    /// use cpr_bf::{extensions::ExtensionSet, BrainfuckCell, BrainfuckVM, Program, VMBuilder};
+    ///
+    /// assert_eq!((-1_i8).shift_right_logical(), 0x7f);
+    ///
+    /// let mut vm = VMBuilder::new()
+    ///     .with_cell_type::<i8>()
+    ///     .with_extensions(ExtensionSet::EXTENDED_TYPE_I)
+    ///     .build_typed();
+    ///
+    /// vm.run_program(&Program::parse_with_extensions("-}", ExtensionSet::EXTENDED_TYPE_I).unwrap())
+    ///     .unwrap();
+    ///
+    /// assert_eq!(vm.tape()[0], 0x7f);
+    /// ```
+    fn shift_right_logical(self) -> Self;
+}
+
+macro_rules! impl_unsigned_cell {
+    ($($t:ty),*) => {
+        $(
+            impl BrainfuckCell for $t {
+                // Only byte-sized cells can hold binary data as is
+                const OUTPUT_ENCODING: OutputEncoding = if <$t>::BITS == 8 {
+                    OutputEncoding::RawByte
+                } else {
+                    OutputEncoding::Unicode
+                };
+
+                fn from_byte(byte: u8) -> Self {
+                    byte.into()
+                }
+
+                fn to_code_point(self) -> Option<u32> {
+                    u32::try_from(self).ok()
+                }
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn shift_right_logical(self) -> Self {
+                    self >> 1
+                }
+            }
+        )*
+    };
+}
+
+// Signed cells are converted to and from their unsigned counterpart of the same width,
+// so that e.g. `-1` in an `i8` cell is written as 255. Input bytes that do not fit
+// in the signed type (only possible for `i8`) wrap around to a negative value
+macro_rules! impl_signed_cell {
+    ($($t:ty => $u:ty),*) => {
+        $(
+            impl BrainfuckCell for $t {
+                fn from_byte(byte: u8) -> Self {
+                    <$u>::from(byte) as $t
+                }
+
+                fn to_code_point(self) -> Option<u32> {
+                    u32::try_from(self as $u).ok()
+                }
+
+                fn to_u64(self) -> u64 {
+                    self as $u as u64
+                }
+
+                fn shift_right_logical(self) -> Self {
+                    (self as $u >> 1) as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_unsigned_cell!(u8, u16, u32, u64, u128, usize);
+impl_signed_cell!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize);
+
+/// Converts `n` to a cell value, wrapping around if `n` does not fit in `T`
+fn wrapping_from_usize<T: BrainfuckCell>(n: usize) -> T {
+    wrapping_from_u64(n as u64)
+}
+
+/// Converts `n` to a cell value, wrapping around if `n` does not fit in `T`
+pub(crate) fn wrapping_from_u64<T: BrainfuckCell>(n: u64) -> T {
+    let mut val = T::zero();
+
+    for bit in (0..(u64::BITS - n.leading_zeros())).rev() {
+        val = val.wrapping_add(&val);
+
+        if n & (1 << bit) != 0 {
+            val = val.wrapping_add(&T::one());
+        }
+    }
+
+    val
+}
+
+/// Converts `n` to a cell value, or returns [`None`] if `n` does not fit in `T`
+fn checked_from_usize<T: BrainfuckCell>(n: usize) -> Option<T> {
+    let mut val = T::zero();
+
+    for bit in (0..(usize::BITS - n.leading_zeros())).rev() {
+        val = val.checked_add(&val)?;
+
+        if n & (1 << bit) != 0 {
+            val = val.checked_add(&T::one())?;
+        }
+    }
+
+    Some(val)
+}
+
+/// An out-of-bounds access error returned by the
+/// Brainfuck VM if an access is attempted outside the
+/// allocated memory region, without dynamic allocation being enabled
+#[derive(Debug)]
+pub struct OutOfBoundsAccess {
+    /// The current maximum capacity of the VM, in number of cells
+    pub capacity: usize,
+
+    /// The index of the attempted access
+    pub access: usize,
+}
+
+/// An error returned by the Brainfuck VM if an access would
+/// grow its memory beyond the maximum amount of cells configured
+/// with [`VMBuilder::with_max_cells`]
+#[derive(Debug)]
+pub struct MemoryLimitExceeded {
+    /// The maximum amount of cells the VM may allocate
+    pub limit: usize,
+
+    /// The index of the attempted access
+    pub access: usize,
+}
+
+/// A general memory error encountered during runtime by the VM
+#[derive(Debug, thiserror::Error)]
+pub enum VMMemoryError {
+    /// An out-of-bounds access
+    #[error(
+        "Out of bounds memory access at index {} (max size {})",
+        .0.access,
+        .0.capacity
+    )]
+    OutOfBounds(OutOfBoundsAccess),
+
+    /// An access beyond the configured memory limit
+    #[error(
+        "Memory access at index {} exceeds the limit of {} cells",
+        .0.access,
+        .0.limit
+    )]
+    LimitExceeded(MemoryLimitExceeded),
+}
+
+/// A trait representing an object that is capable of
+/// allocating memory for a Brainfuck VM.
+///
+/// Every VM owns its allocator, so that allocators can carry configuration, such as the
+/// page size of [`allocators::PagedAllocator`], or keep state of their own between calls. Allocators
+/// implementing [`Default`] can be chosen by type with [`VMBuilder::with_allocator`],
+/// other allocators are passed to the VM with [`VMBuilder::with_allocator_instance`].
+///
+/// Allocators decide when the tape grows, while the [`Tape`] of the VM stores the cells.
+/// Allocators written for earlier versions of this trait, which had no `self` parameter
+/// and worked on a [`Vec`], are migrated by adding `&mut self` to both methods,
+/// replacing the [`Vec`] with a generic [`Tape`], and deriving [`Default`]
+///
+/// ```
+/// use cpr_bf::{tape::Tape, BrainfuckAllocator, BrainfuckCell, BrainfuckVM, VMBuilder, VMMemoryError};
+///
+/// /// Grows the tape like the default allocator, and counts how often it grew
+/// #[derive(Default)]
+/// struct CountingAllocator {
+///     grown: usize,
+/// }
+///
+/// impl BrainfuckAllocator for CountingAllocator {
+///     fn ensure_capacity<T: BrainfuckCell, D: Tape<T>>(
+///         &mut self,
+///         tape: &mut D,
+///         min_size: usize,
+///     ) -> Result<(), VMMemoryError> {
+///         if tape.len() < min_size {
+///             tape.ensure(min_size)?;
+///             self.grown += 1;
+///         }
+///
+///         Ok(())
+///     }
+/// }
+///
+/// let mut vm = VMBuilder::new()
+///     .with_allocator::<CountingAllocator>()
+///     .with_preallocated_cells(0)
+///     .build_typed();
+///
+/// vm.run_string("+>+>+<<").unwrap();
+///
+/// assert_eq!(vm.allocator().grown, 3);
+/// ```
+pub trait BrainfuckAllocator {
+    /// Ensures that `tape` has at least `min_size` cells available for
+    /// both reading and writing. If this function returns [`Result::Ok`],
+    /// the cell at index `min_size - 1` can be safely read from and written to.
+    ///
+    /// Any new cells created by this function shall be initialized
+    /// to the default value of `T`, as [`Tape::ensure`] does
+    fn ensure_capacity<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
+        min_size: usize,
+    ) -> Result<(), VMMemoryError>;
+
+    /// Computes the new data pointer after moving the current `data_ptr`
+    /// by `amount` cells. Negative amounts move the data pointer to the left.
+    ///
+    /// Implementations may extend the tape to the left with [`Tape::prepend`]. The VM keeps
+    /// track of the position of the original first cell. Cells must never be removed.
+    ///
+    /// The default implementation returns [`BrainfuckExecutionError::DataPointerOverflow`]
+    /// or [`BrainfuckExecutionError::DataPointerUnderflow`] if the data pointer
+    /// would leave the range of [`usize`]
+    fn move_data_ptr<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
+        data_ptr: usize,
+        amount: isize,
+    ) -> Result<usize, BrainfuckExecutionError> {
+        let _ = tape;
+
+        if amount >= 0 {
+            data_ptr
+                .checked_add(amount.unsigned_abs())
+                .ok_or(BrainfuckExecutionError::DataPointerOverflow)
+        } else {
+            data_ptr
+                .checked_sub(amount.unsigned_abs())
+                .ok_or(BrainfuckExecutionError::DataPointerUnderflow)
+        }
+    }
+}
+
+impl<A: BrainfuckAllocator> BrainfuckAllocator for &mut A {
+    fn ensure_capacity<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
+        min_size: usize,
+    ) -> Result<(), VMMemoryError> {
+        (**self).ensure_capacity(tape, min_size)
+    }
+
+    fn move_data_ptr<T: BrainfuckCell, D: Tape<T>>(
+        &mut self,
+        tape: &mut D,
+        data_ptr: usize,
+        amount: isize,
+    ) -> Result<usize, BrainfuckExecutionError> {
+        (**self).move_data_ptr(tape, data_ptr, amount)
+    }
+}
+
+/// Describes the state of the VM right before it executes an operation.
+/// Passed to the hooks registered with [`VMBuilder::with_hook`]
+#[derive(Clone, Copy, Debug)]
+pub struct VMEvent<T: BrainfuckCell> {
+    /// The operation that is about to be executed
+    pub op: Op,
+
+    /// The index of the operation that is about to be executed
+    pub instr_ptr: usize,
+
+    /// The current data pointer
+    pub data_ptr: usize,
+
+    /// The value of the cell that the data pointer currently points to
+    pub cell: T,
+}
+
+type Hook<T> = Box<dyn FnMut(&VMEvent<T>) + Send>;
+
+/// A function registered with [`VMBuilder::with_host_function`]
+type HostFunction<T> = Box<dyn FnMut(&mut HostCall<T>) -> BfResult + Send>;
+
+/// A function registered with [`VMBuilder::with_checkpoints`]
+type CheckpointFn<T> = Box<dyn FnMut(&VMSnapshot<T>) + Send>;
+
+/// The checkpoints registered with [`VMBuilder::with_checkpoints`]
+struct Checkpoints<T: BrainfuckCell> {
+    interval: u64,
+    save: CheckpointFn<T>,
+
+    /// The amount of steps after which the last checkpoint was taken, or after which the
+    /// program was loaded or restored, so that no checkpoint is taken twice at one step
+    last: u64,
+}
+
+/// The callbacks of a cell registered with [`VMBuilder::with_mapped_cell`]
+struct MappedCell<T> {
+    read: Box<dyn FnMut() -> T + Send>,
+    write: Box<dyn FnMut(T) + Send>,
+}
+
+/// The default implementation of [`BrainfuckVM`].
+///
+/// Usually constructed as a `Box<dyn BrainfuckVM>` through [`VMBuilder::build`],
+/// but it can also be obtained directly through [`VMBuilder::build_typed`].
+/// This keeps the types of the reader and writer intact, so that they can
+/// be accessed after a program has run.
+pub struct VirtualMachine<
+    T: BrainfuckCell,
+    A: BrainfuckAllocator,
+    R: Read,
+    W: Write,
+    D: Tape<T> = Vec<T>,
+> {
+    data_ptr: usize,
+    data: D,
+    origin: usize,
+    alloc: A,
+    reader: R,
+    writer: W,
+    config: VMConfig,
+    program: Option<CompiledProgram>,
+    instr_ptr: usize,
+    steps: u64,
+    procedures: BTreeMap<u64, usize>,
+    call_stack: Vec<usize>,
+    threads: VecDeque<Thread<D>>,
+    storage: T,
+    rng: Rng,
+    queued_input: VecDeque<u8>,
+    unread_byte: Option<u8>,
+    output_buffer: Vec<u8>,
+    captured_output: Option<Vec<u8>>,
+    stats: RunStats,
+    hooks: Vec<Hook<T>>,
+    host_functions: BTreeMap<u64, HostFunction<T>>,
+    mapped_cells: BTreeMap<isize, MappedCell<T>>,
+    pause_flag: Option<Arc<AtomicBool>>,
+    checkpoints: Option<Checkpoints<T>>,
+
+    /// Whether the cells may have been changed since the last reset, by a program,
+    /// by loading a tape or state, or through [`VirtualMachine::tape_mut`]
+    dirty_tape: bool,
+
+    /// When the loaded program started running, see [`VMBuilder::with_timeout`]
+    #[cfg(all(
+        feature = "std",
+        not(all(target_arch = "wasm32", target_os = "unknown"))
+    ))]
+    started: Option<std::time::Instant>,
+}
+
+/// The counters of a [`VirtualMachine`] that are not part of its
+/// state, but are reported in its [`RunReport`]
+#[derive(Clone, Copy, Debug, Default)]
+struct RunStats {
+    max_data_ptr: isize,
+    bytes_read: u64,
+    bytes_written: u64,
+}
+
+/// A thread of a [`VirtualMachine`] that is waiting to run, with a tape
+/// of the same type as the VM. Exported as a [`ThreadState`]
+struct Thread<D> {
+    tape: D,
+    data_ptr: usize,
+    origin: usize,
+    instr_ptr: usize,
+    procedures: BTreeMap<u64, usize>,
+    call_stack: Vec<usize>,
+}
+
+/// Creates a new tape holding `cells`
+fn tape_from_cells<T: BrainfuckCell, D: Tape<T> + Default>(cells: &[T]) -> D {
+    let mut tape = D::default();
+
+    if let Err(e) = tape.load(cells) {
+        log::error!("Could not copy the cells to a new tape: {}", e);
+    }
+
+    tape
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> VirtualMachine<T, A, R, W> {
+    /// The currently allocated memory cells of the VM.
+    /// The data pointer is an index into these cells, see [`BrainfuckVM::data_ptr`]
+    pub fn memory(&self) -> &[T] {
+        &self.data
+    }
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write, D: Tape<T> + Default>
+    VirtualMachine<T, A, R, W, D>
+{
+    /// The tape storing the memory cells of the VM, see [`VMBuilder::with_tape`].
+    /// The data pointer is an index into this tape, see [`BrainfuckVM::data_ptr`]
+    pub fn tape(&self) -> &D {
+        &self.data
+    }
+
+    /// Mutable access to the tape storing the memory cells of the VM
+    pub fn tape_mut(&mut self) -> &mut D {
+        self.dirty_tape = true;
+        &mut self.data
+    }
+
+    /// The allocator managing the memory of this VM
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Mutable access to the allocator managing the memory of this VM
+    pub fn allocator_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// The reader used by this VM as program input
+    pub fn reader(&self) -> &R {
+        &self.reader
+    }
+
+    /// Mutable access to the reader used by this VM as program input
+    pub fn reader_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Consumes the VM, returning its reader
+    pub fn into_reader(self) -> R {
+        self.reader
+    }
+
+    /// The writer used by this VM as program output
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// Mutable access to the writer used by this VM as program output
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consumes the VM, returning its writer. Any buffered output is written to it first
+    pub fn into_writer(mut self) -> W {
+        if let Err(e) = self.flush_output() {
+            log::error!("Could not flush buffered output: {}", e);
+        }
+
+        self.writer
+    }
+
+    /// Writes all output buffered by the VM to its writer, and flushes the writer.
+    /// See [`VMBuilder::with_flush_policy`]
+    pub fn flush_output(&mut self) -> Result<(), io::Error> {
+        if let Some(captured) = &mut self.captured_output {
+            captured.append(&mut self.output_buffer);
+            return Ok(());
+        }
+
+        if !self.output_buffer.is_empty() {
+            self.writer.write_all(&self.output_buffer)?;
+            self.output_buffer.clear();
+        }
+
+        self.writer.flush()
+    }
+
+    /// Captures the current state of the VM, including its memory
+    /// and the currently loaded program
+    pub fn state(&self) -> VMState<T> {
+        VMState {
+            tape: self.data.to_vec(),
+            data_ptr: self.data_ptr,
+            origin: self.origin,
+            program: self.program.clone(),
+            instr_ptr: self.instr_ptr,
+            steps: self.steps,
+            procedures: self.procedures.clone(),
+            call_stack: self.call_stack.clone(),
+            threads: self
+                .threads
+                .iter()
+                .map(|thread| ThreadState {
+                    tape: thread.tape.to_vec(),
+                    data_ptr: thread.data_ptr,
+                    origin: thread.origin,
+                    instr_ptr: thread.instr_ptr,
+                    procedures: thread.procedures.clone(),
+                    call_stack: thread.call_stack.clone(),
+                })
+                .collect(),
+            storage: self.storage,
+        }
+    }
+
+    /// Replaces the current state of the VM with `state`.
+    /// Execution can then be continued with [`BrainfuckVM::resume`].
+    ///
+    /// Returns an error if a tape can not hold the cells of the state, in which
+    /// case the rest of the state is not restored
+    pub fn set_state(&mut self, state: VMState<T>) -> Result<(), VMMemoryError> {
+        log::info!("Restoring VM state");
+
+        self.dirty_tape = true;
+        self.data.load(&state.tape)?;
+
+        let threads = state
+            .threads
+            .into_iter()
+            .map(|thread| {
+                let mut tape = D::default();
+                tape.load(&thread.tape)?;
+
+                Ok(Thread {
+                    tape,
+                    data_ptr: thread.data_ptr,
+                    origin: thread.origin,
+                    instr_ptr: thread.instr_ptr,
+                    procedures: thread.procedures,
+                    call_stack: thread.call_stack,
+                })
+            })
+            .collect::<Result<_, VMMemoryError>>()?;
+
+        self.data_ptr = state.data_ptr;
+        self.origin = state.origin;
+        self.program = state.program;
+        self.instr_ptr = state.instr_ptr;
+        self.steps = state.steps;
+        self.procedures = state.procedures;
+        self.call_stack = state.call_stack;
+        self.threads = threads;
+        self.storage = state.storage;
+
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.last = state.steps;
+        }
+
+        Ok(())
+    }
+
+    /// Captures the current state of the VM, like [`VirtualMachine::state`].
+    /// The snapshot can be restored any number of times with [`VirtualMachine::restore`]
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new().build_typed();
+    ///
+    /// vm.load_program(&"+++>++".into()).unwrap();
+    /// vm.step();
+    ///
+    /// let snapshot = vm.snapshot();
+    ///
+    /// vm.resume().unwrap();
+    /// assert_eq!(vm.memory(), &[3, 2]);
+    ///
+    /// vm.restore(&snapshot).unwrap();
+    /// assert_eq!(vm.memory(), &[3]);
+    /// ```
+    pub fn snapshot(&self) -> VMSnapshot<T> {
+        self.state()
+    }
+
+    /// Restores the VM to `snapshot`, taken with [`VirtualMachine::snapshot`].
+    /// Unlike [`VirtualMachine::set_state`], the snapshot is left intact,
+    /// so that it can be restored again later
+    pub fn restore(&mut self, snapshot: &VMSnapshot<T>) -> Result<(), VMMemoryError> {
+        self.set_state(snapshot.clone())
+    }
+}
+
+/// A snapshot of the tape, data pointer and instruction pointer of a
+/// [`VirtualMachine`], obtained through [`VirtualMachine::snapshot`]
+pub type VMSnapshot<T> = VMState<T>;
+
+/// A snapshot of the state of a [`VirtualMachine`], obtained through
+/// [`VirtualMachine::state`]. With the `serde` feature enabled, this
+/// can be persisted or sent over the network, to continue execution elsewhere
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VMState<T: BrainfuckCell> {
+    /// The memory cells of the VM
+    pub tape: Vec<T>,
+
+    /// The data pointer
+    pub data_ptr: usize,
+
+    /// The index in `tape` of the cell the data pointer is reset to
+    /// when a new program is loaded
+    pub origin: usize,
+
+    /// The currently loaded program, if any
+    pub program: Option<CompiledProgram>,
+
+    /// The index of the next operation of `program` to execute
+    pub instr_ptr: usize,
+
+    /// The amount of steps executed since `program` was loaded
+    pub steps: u64,
+
+    /// The index of the first operation of every defined procedure,
+    /// by procedure identifier. See [`ExtensionSet::PBRAIN`]
+    pub procedures: BTreeMap<u64, usize>,
+
+    /// The operation indices at which execution continues once the
+    /// currently running procedures return, innermost last
+    pub call_stack: Vec<usize>,
+
+    /// The threads that are waiting to run, in the order they will be run.
+    /// See [`ExtensionSet::BRAINFORK`]
+    pub threads: Vec<ThreadState<T>>,
+
+    /// The storage cell of [`ExtensionSet::EXTENDED_TYPE_I`]
+    pub storage: T,
+}
+
+impl<T: BrainfuckCell> VMState<T> {
+    /// Encodes this state in the binary format described in the [`snapshot`] module
+    pub fn to_bytes(&self) -> Vec<u8> {
+        snapshot::encode(self)
+    }
+
+    /// Decodes a state from the binary format described in the [`snapshot`] module
+    pub fn from_bytes(data: &[u8]) -> Result<VMState<T>, SnapshotError> {
+        snapshot::decode(data)
+    }
+}
+
+/// The state of a thread created with [`ExtensionSet::BRAINFORK`] that is waiting
+/// to run. The fields match those of the running thread in [`VMState`]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThreadState<T: BrainfuckCell> {
+    /// The memory cells of the thread
+    pub tape: Vec<T>,
+
+    /// The data pointer of the thread
+    pub data_ptr: usize,
+
+    /// The index in `tape` of the cell the program started at
+    pub origin: usize,
+
+    /// The index of the next operation the thread executes
+    pub instr_ptr: usize,
+
+    /// The procedures defined by the thread
+    pub procedures: BTreeMap<u64, usize>,
+
+    /// The call stack of the thread
+    pub call_stack: Vec<usize>,
+}
+
+/// What the VM does when a program attempts to read input,
+/// but the reader has reached the end of its input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EofBehavior {
+    /// Leave the current cell unchanged
+    #[default]
+    Unchanged,
+
+    /// Set the current cell to zero
+    Zero,
+
+    /// Set the current cell to `-1`, which is the maximum value
+    /// of the cell type for unsigned cells
+    MaxValue,
+
+    /// Abort the program with [`BrainfuckExecutionError::UnexpectedEof`]
+    Error,
+}
+
+/// What the VM does when a program attempts to read input, but its non-blocking reader
+/// has no input available yet. See [`VMBuilder::with_nonblocking_input`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonBlockingInput {
+    /// Treat the reader as a blocking reader, failing the program with
+    /// [`BrainfuckExecutionError::IOError`] if it has no input available
+    #[default]
+    Disabled,
+
+    /// Store the given value in the current cell, converted to the cell type
+    /// with wrapping, so that `u64::MAX` is stored as `-1`
+    Sentinel(u64),
+
+    /// Stop with [`BrainfuckExecutionError::InputPending`], leaving the instruction
+    /// pointer at the `,`. The read is retried once the program is resumed
+    Yield,
+}
+
+/// How the VM converts between cell values and the bytes
+/// of its reader and writer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IoMode {
+    /// `.` writes the cell as a character, and `,` reads a single byte
+    #[default]
+    Character,
+
+    /// `.` writes the decimal value of the cell, followed by a newline, and `,`
+    /// reads a decimal integer. Anything before the integer, except for a minus sign
+    /// directly in front of it, is skipped. The integer is stored as if it was added
+    /// to a zero cell, so values outside of the range of the cell type are handled
+    /// according to the [`OverflowPolicy`]
+    Numeric,
+}
+
+/// How the VM converts a cell into the bytes it writes for `.`,
+/// see [`VMBuilder::with_output_encoding`]. Not used with [`IoMode::Numeric`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// Write the lowest byte of the cell as is
+    RawByte,
+
+    /// Write values up to 255 as a single ISO-8859-1 byte, which is the same as
+    /// [`OutputEncoding::RawByte`] for these values. Larger values cannot be
+    /// encoded, and are written as `?`
+    Latin1,
+
+    /// Write the cell as a UTF-8 encoded Unicode scalar value. Values that are
+    /// not valid scalar values are written as the replacement character `U+FFFD`
+    Unicode,
+
+    /// Write printable ASCII characters and newlines as is, and escape all other
+    /// values as `\xHH` if they fit in a byte, or as `\u{H...}` otherwise. Backslashes
+    /// are written as `\\`. Useful to inspect output containing control characters
+    Escape,
+}
+
+/// Where the VM echoes the bytes a program reads from its input,
+/// see [`VMBuilder::with_input_echo`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputEcho {
+    /// Do not echo input
+    #[default]
+    Off,
+
+    /// Echo input to the writer of the VM, in between the output of the program.
+    /// Echoed bytes are not counted in [`RunReport::bytes_written`]
+    Writer,
+
+    /// Echo input to stderr, leaving the output of the program unchanged
+    #[cfg(feature = "std")]
+    Stderr,
+}
+
+/// When the VM writes the output of a program, which it collects in an
+/// internal buffer, to its writer. See [`VMBuilder::with_flush_policy`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Flush after every output operation
+    EveryOutput,
+
+    /// Flush whenever a newline is written
+    #[default]
+    OnNewline,
+
+    /// Only flush once the program stops. The buffer grows with
+    /// all output of the program
+    OnProgramEnd,
+
+    /// Flush once the buffer holds at least the given amount of bytes
+    EveryNBytes(usize),
+}
+
+/// What the VM does when incrementing or decrementing a cell
+/// would take its value outside of the range of the cell type.
+///
+/// Runs of `+` and `-` that were fused by the optimizer
+/// (see [`ir::Pass::FuseRuns`]) are checked as a whole. Programs loaded with
+/// [`BrainfuckVM::load_program`] are only optimized with [`ir::Pass::ClearLoops`]
+/// under [`OverflowPolicy::Wrapping`], since clear loops overflow or never finish
+/// under the other policies
+///
+/// ```
+/// use cpr_bf::{ir::OptimizationLevel, BrainfuckExecutionError, BrainfuckVM, OverflowPolicy};
+///
+/// for level in [OptimizationLevel::None, OptimizationLevel::Full] {
+///     let mut vm = cpr_bf::VMBuilder::new()
+///         .with_optimization_level(level)
+///         .with_overflow_policy(OverflowPolicy::Error)
+///         .build();
+///
+///     let error = vm.run_string("+[+]").unwrap_err();
+///
+///     assert!(matches!(error.kind(), BrainfuckExecutionError::CellOverflow));
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wrap around to the other end of the range
+    #[default]
+    Wrapping,
+
+    /// Clamp the value to the minimum or maximum value of the cell type
+    Saturating,
+
+    /// Abort the program with [`BrainfuckExecutionError::CellOverflow`]
+    Error,
+}
+
+/// The non-generic configuration options of a [`VMBuilder`],
+/// which are passed on to the [`VirtualMachine`] it builds
+#[derive(Clone, Copy, Debug, Default)]
+struct VMConfig {
+    initial_size: usize,
+    auto_preallocate: bool,
+    optimization_level: OptimizationLevel,
+    max_steps: Option<u64>,
+    #[cfg(all(
+        feature = "std",
+        not(all(target_arch = "wasm32", target_os = "unknown"))
+    ))]
+    timeout: Option<Duration>,
+    max_cells: Option<usize>,
+    eof_behavior: EofBehavior,
+    nonblocking_input: NonBlockingInput,
+    overflow_policy: OverflowPolicy,
+    io_mode: IoMode,
+    output_encoding: Option<OutputEncoding>,
+    flush_policy: FlushPolicy,
+    input_echo: InputEcho,
+    extensions: ExtensionSet,
+    bang_input: bool,
+    rng_seed: Option<u64>,
+}
+
+/// A builder struct for the default implementation of [`BrainfuckVM`]
+/// Create the default configuration with [`VMBuilder::new()`] or [`VMBuilder::default()`],
+/// customize with the member functions, and build the final VM with [`VMBuilder::build()`]
+pub struct VMBuilder<
+    T: BrainfuckCell = u8,
+    A: BrainfuckAllocator = DynamicAllocator,
+    R: Read = DefaultReader,
+    W: Write = DefaultWriter,
+    D: Tape<T> = Vec<T>,
+> {
+    config: VMConfig,
+    hooks: Vec<Hook<T>>,
+    host_functions: BTreeMap<u64, HostFunction<T>>,
+    mapped_cells: BTreeMap<isize, MappedCell<T>>,
+    pause_flag: Option<Arc<AtomicBool>>,
+    checkpoints: Option<Checkpoints<T>>,
+    celltype: PhantomData<T>,
+    allocator: A,
+    tape: D,
+    reader: R,
+    writer: W,
+}
+
+impl VMBuilder {
+    /// Construct a new VMBuilder with the default initial configuration
+    pub fn new() -> VMBuilder {
+        VMBuilder::default()
+    }
+}
+
+impl Default for VMBuilder {
+    /// Construct a new VMBuilder with the default initial configuration
+    fn default() -> Self {
+        VMBuilder {
+            config: VMConfig::default(),
+            hooks: Vec::new(),
+            host_functions: BTreeMap::new(),
+            mapped_cells: BTreeMap::new(),
+            pause_flag: None,
+            checkpoints: None,
+            celltype: PhantomData,
+            allocator: DynamicAllocator,
+            tape: Vec::new(),
+            reader: io::default_reader(),
+            writer: io::default_writer(),
+        }
+    }
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write, D: Tape<T>> Display
+    for VMBuilder<T, A, R, W, D>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "VMBuilder<{}, {}, {}, {}, {}> with {:?}",
+            type_name::<T>(),
+            type_name::<A>(),
+            type_name::<R>(),
+            type_name::<W>(),
+            type_name::<D>(),
+            self.config
+        )?;
+
+        Ok(())
+    }
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> VMBuilder<T, A, R, W> {
+    /// Changes the type of the memory cells to `U`. Only available
+    /// before a tape is chosen with [`VMBuilder::with_tape`].
+    ///
+    /// Since hooks, host functions and mapped cells access the cells, any hooks,
+    /// host functions and mapped cells registered before calling this are removed
+    pub fn with_cell_type<U: BrainfuckCell>(self) -> VMBuilder<U, A, R, W> {
+        if !self.hooks.is_empty() {
+            log::warn!("Changing cell type, removing {} hooks", self.hooks.len());
+        }
+
+        if !self.host_functions.is_empty() {
+            log::warn!(
+                "Changing cell type, removing {} host functions",
+                self.host_functions.len()
+            );
+        }
+
+        if !self.mapped_cells.is_empty() {
+            log::warn!(
+                "Changing cell type, removing {} mapped cells",
+                self.mapped_cells.len()
+            );
+        }
+
+        if self.checkpoints.is_some() {
+            log::warn!("Changing cell type, removing checkpoints");
+        }
+
+        VMBuilder {
+            config: self.config,
+            hooks: Vec::new(),
+            host_functions: BTreeMap::new(),
+            mapped_cells: BTreeMap::new(),
+            pause_flag: self.pause_flag,
+            checkpoints: None,
+            celltype: PhantomData::<U>,
+            allocator: self.allocator,
+            tape: Vec::new(),
+            reader: self.reader,
+            writer: self.writer,
+        }
+    }
+}
+
+impl<
+        T: BrainfuckCell + 'static,
+        A: BrainfuckAllocator + 'static,
+        R: Read + 'static,
+        W: Write + 'static,
+        D: Tape<T> + Default + 'static,
+    > VMBuilder<T, A, R, W, D>
+{
+    /// Changes the used allocator to the default value of `U`
+    pub fn with_allocator<U: BrainfuckAllocator + Default>(self) -> VMBuilder<T, U, R, W, D> {
+        self.with_allocator_instance(U::default())
+    }
+
+    /// Changes the used allocator to `allocator`, for allocators
+    /// that are configured when they are created.
+    ///
+    /// ```
+    /// use cpr_bf::{allocators::PagedAllocator, BrainfuckVM, VMBuilder};
+    ///
+    /// let mut vm = VMBuilder::new()
+    ///     .with_allocator_instance(PagedAllocator::new(1024))
+    ///     .with_preallocated_cells(0)
+    ///     .build_typed();
+    ///
+    /// vm.run_string(">+").unwrap();
+    ///
+    /// assert_eq!(vm.memory().len(), 1024);
+    /// ```
+    pub fn with_allocator_instance<U: BrainfuckAllocator>(
+        self,
+        allocator: U,
+    ) -> VMBuilder<T, U, R, W, D> {
+        VMBuilder {
+            config: self.config,
+            hooks: self.hooks,
+            host_functions: self.host_functions,
+            mapped_cells: self.mapped_cells,
+            pause_flag: self.pause_flag,
+            checkpoints: self.checkpoints,
+            celltype: self.celltype,
+            allocator,
+            tape: self.tape,
+            reader: self.reader,
+            writer: self.writer,
+        }
+    }
+
+    /// Changes the storage of the memory cells to `tape`, see the [`tape`] module.
+    /// The cells already on `tape` are kept, and the tape is grown to the amount of
+    /// pre-allocated cells when the VM is built
+    pub fn with_tape<U: Tape<T> + Default>(self, tape: U) -> VMBuilder<T, A, R, W, U> {
+        VMBuilder {
+            config: self.config,
+            hooks: self.hooks,
+            host_functions: self.host_functions,
+            mapped_cells: self.mapped_cells,
+            pause_flag: self.pause_flag,
+            checkpoints: self.checkpoints,
+            celltype: self.celltype,
+            allocator: self.allocator,
+            tape,
+            reader: self.reader,
+            writer: self.writer,
+        }
+    }
+
+    /// Changes the amount of pre-allocated cells to `num_preallocated`
+    pub fn with_preallocated_cells(self, num_preallocated: usize) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                initial_size: num_preallocated,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Preallocates cells for every program loaded with [`BrainfuckVM::load_program`], based
+    /// on its estimated [`Program::tape_extent`]. If the extent is known, the tape grows to
+    /// fit it before the program runs, up to the limit set with [`VMBuilder::with_max_cells`].
+    /// Otherwise, only the cells set with [`VMBuilder::with_preallocated_cells`] are available.
+    ///
+    /// This lets the static allocators run programs without choosing their amount of cells
+    /// up front. It should not be combined with the [`allocators::WrappingStaticAllocator`],
+    /// because growing its tape changes where the data pointer wraps around.
+    ///
+    /// ```
+    /// use cpr_bf::{allocators::BoundsCheckingStaticAllocator, BrainfuckVM};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_allocator::<BoundsCheckingStaticAllocator>()
+    ///     .with_preallocated_cells(0)
+    ///     .with_auto_preallocated_cells()
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    ///
+    /// vm.run_string(">>>>+++[<+>-]<.").unwrap();
+    ///
+    /// assert_eq!(vm.into_writer(), [3]);
+    /// ```
+    pub fn with_auto_preallocated_cells(self) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                auto_preallocate: true,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Limits the amount of memory cells the VM may hold to `max_cells`.
+    /// Any access that would require more cells results in a
+    /// [`VMMemoryError::LimitExceeded`], regardless of the used allocator
+    pub fn with_max_cells(self, max_cells: usize) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                max_cells: Some(max_cells),
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Allows reading from a non-blocking reader, which signals that no input is
+    /// available yet by returning an error of kind [`std::io::ErrorKind::WouldBlock`],
+    /// or [`io::Error::WOULD_BLOCK`] without the `std` feature. Instead of failing,
+    /// `,` then behaves as configured by `nonblocking_input`.
+    ///
+    /// With [`NonBlockingInput::Yield`], the VM can run inside an event loop, by
+    /// resuming the program whenever new input arrives. In [`IoMode::Numeric`],
+    /// a number ends once no more input is available
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckExecutionError, BrainfuckVM, NonBlockingInput};
+    /// use std::{collections::VecDeque, io::{self, Read}};
+    ///
+    /// /// Returns whatever input has arrived so far
+    /// struct Arrivals(VecDeque<u8>);
+    ///
+    /// impl Read for Arrivals {
+    ///     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    ///         match self.0.pop_front() {
+    ///             Some(byte) => {
+    ///                 buf[0] = byte;
+    ///                 Ok(1)
+    ///             }
+    ///             None => Err(io::ErrorKind::WouldBlock.into()),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_reader(Arrivals(VecDeque::new()))
+    ///     .with_nonblocking_input(NonBlockingInput::Yield)
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    ///
+    /// let result = vm.run_string(",+.");
+    /// assert!(matches!(result, Err(BrainfuckExecutionError::InputPending)));
+    ///
+    /// vm.reader_mut().0.push_back(b'a');
+    /// vm.resume().unwrap();
+    ///
+    /// assert_eq!(vm.writer(), b"b");
+    /// ```
+    pub fn with_nonblocking_input(
+        self,
+        nonblocking_input: NonBlockingInput,
+    ) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                nonblocking_input,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Changes what the VM does when a program reads input after
+    /// the end of the input has been reached to `eof_behavior`
+    pub fn with_eof_behavior(self, eof_behavior: EofBehavior) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                eof_behavior,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Changes what the VM does when a cell overflows or underflows to `overflow_policy`
+    pub fn with_overflow_policy(self, overflow_policy: OverflowPolicy) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                overflow_policy,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Changes how `.` converts the current cell into the bytes written to the writer.
+    /// If not configured, [`u8`] cells are written as raw bytes, so that programs can
+    /// write binary data, and all other cell types as UTF-8 encoded code points.
+    /// See [`BrainfuckCell::OUTPUT_ENCODING`]
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckVM, OutputEncoding};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new().with_writer(Vec::new()).build_typed();
+    /// vm.run_string("-.").unwrap();
+    ///
+    /// assert_eq!(vm.writer(), &[0xFF]);
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_output_encoding(OutputEncoding::Escape)
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    /// vm.run_string("-.+++++++++++.").unwrap();
+    ///
+    /// assert_eq!(vm.writer(), b"\\xFF\n");
+    /// ```
+    pub fn with_output_encoding(self, output_encoding: OutputEncoding) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                output_encoding: Some(output_encoding),
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Echoes every byte the program reads from its input, exactly as it was read.
+    /// This makes it easier to follow a program reading from a file or a pipe,
+    /// while its output goes to the terminal
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckVM, InputEcho};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_input_echo(InputEcho::Writer)
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    ///
+    /// vm.queue_input(b"ab");
+    /// vm.run_string(",+.,+.").unwrap();
+    ///
+    /// assert_eq!(vm.writer(), b"abbc");
+    /// ```
+    pub fn with_input_echo(self, input_echo: InputEcho) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                input_echo,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Changes when the output of a program is written to the writer of the VM.
+    /// Output is collected in a buffer, and written and flushed according to `flush_policy`.
+    /// Regardless of the policy, the buffer is always flushed before the VM reads from
+    /// its reader, so that prompts are shown before waiting for input, and whenever
+    /// execution stops, including on errors. The buffer can also be flushed manually
+    /// with [`VirtualMachine::flush_output`].
+    ///
+    /// The default is [`FlushPolicy::OnNewline`]
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckVM, ExecutionState, FlushPolicy};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_flush_policy(FlushPolicy::OnProgramEnd)
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    ///
+    /// vm.load_program(&"+.+.".into()).unwrap();
+    /// vm.step();
+    /// vm.step();
+    ///
+    /// assert!(vm.writer().is_empty());
+    ///
+    /// vm.resume().unwrap();
+    ///
+    /// assert_eq!(vm.writer(), &[1, 2]);
+    /// ```
+    pub fn with_flush_policy(self, flush_policy: FlushPolicy) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                flush_policy,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Changes how the VM converts between cell values and the bytes of its
+    /// reader and writer
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckVM, IoMode};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_cell_type::<i32>()
+    ///     .with_io_mode(IoMode::Numeric)
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    ///
+    /// vm.queue_input(b"1200 -5");
+    /// vm.run_string(",+.>,-.").unwrap();
+    ///
+    /// assert_eq!(vm.writer(), b"1201\n-6\n");
+    /// ```
+    pub fn with_io_mode(self, io_mode: IoMode) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                io_mode,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Changes the optimization level with which programs are compiled
+    /// before being run to `level`
+    pub fn with_optimization_level(self, level: OptimizationLevel) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                optimization_level: level,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Enables the instructions of the given [`extensions`](crate::extensions).
+    /// Programs using any other extension are rejected when they are loaded with
+    /// [`BrainfuckExecutionError::ExtensionDisabled`]
+    pub fn with_extensions(self, extensions: ExtensionSet) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                extensions,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Enables the `!` separator convention for programs run from source code with
+    /// [`BrainfuckVM::run_string`], [`BrainfuckVM::run_file`] and [`BrainfuckVM::run_reader`].
+    /// The source code is split at its first `!`: everything before it is the program,
+    /// and everything after it is queued as the input of the program with
+    /// [`BrainfuckVM::queue_input`].
+    ///
+    /// The source code is split before it is parsed, so `!` can no longer be used
+    /// as an instruction of [`ExtensionSet::EXTENDED_TYPE_I`]
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_bang_input(true)
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    ///
+    /// vm.run_string(",+.,+.!ab").unwrap();
+    ///
+    /// assert_eq!(vm.writer(), b"bc");
+    /// ```
+    pub fn with_bang_input(self, bang_input: bool) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                bang_input,
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Seeds the random number generator of [`ExtensionSet::RANDOM`] with `seed`.
+    /// The generator is seeded again whenever a program is loaded, so that every
+    /// run of a program produces the same random numbers.
+    ///
+    /// Without a seed, the generator is seeded differently for every VM, and keeps
+    /// its state between programs. Without the `std` feature, every VM is seeded the same
+    ///
+    /// ```
+    /// use cpr_bf::{extensions::ExtensionSet, BrainfuckVM};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_extensions(ExtensionSet::RANDOM)
+    ///     .with_rng_seed(42)
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    ///
+    /// vm.run_string("?.?.").unwrap();
+    /// vm.run_string("?.?.").unwrap();
+    ///
+    /// let output = vm.writer();
+    /// assert_eq!(output[..2], output[2..]);
+    /// ```
+    pub fn with_rng_seed(self, seed: u64) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                rng_seed: Some(seed),
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Limits the amount of steps the VM may execute for a single program
+    /// to `max_steps`. Once the limit is reached, execution is aborted with
+    /// [`BrainfuckExecutionError::StepLimitExceeded`].
+    ///
+    /// A step corresponds to a single compiled [`Op`]. Depending on the optimization
+    /// level, that can be multiple Brainfuck instructions
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckExecutionError, BrainfuckVM};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new().with_max_steps(1000).build();
+    /// let e = vm.run_string("+[]").unwrap_err();
+    ///
+    /// assert!(matches!(e.kind(), BrainfuckExecutionError::StepLimitExceeded));
+    /// assert_eq!(e.context().unwrap().steps, 1000);
+    /// ```
+    pub fn with_max_steps(self, max_steps: u64) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                max_steps: Some(max_steps),
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Limits the wall-clock time the VM may spend running a single program to `timeout`.
+    /// Once the limit is reached, execution is aborted with
+    /// [`BrainfuckExecutionError::TimeLimitExceeded`].
+    ///
+    /// The time is measured from the first step of the program, and checked every
+    /// 1024 steps. A program waiting for input is only stopped once it continues
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckExecutionError, BrainfuckVM};
+    /// use std::time::Duration;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_timeout(Duration::from_millis(10))
+    ///     .build();
+    ///
+    /// assert!(matches!(
+    ///     vm.run_string("+[]").map_err(BrainfuckExecutionError::into_kind),
+    ///     Err(BrainfuckExecutionError::TimeLimitExceeded)
+    /// ));
+    /// ```
+    #[cfg(all(
+        feature = "std",
+        not(all(target_arch = "wasm32", target_os = "unknown"))
+    ))]
+    pub fn with_timeout(self, timeout: Duration) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            config: VMConfig {
+                timeout: Some(timeout),
+                ..self.config
+            },
+            ..self
+        }
+    }
+
+    /// Registers a hook that is called right before the VM executes an operation.
+    /// Multiple hooks can be registered, and are called in the order they were added.
+    ///
+    /// Hooks must be [`Send`], so that the VM can be moved to another thread.
+    /// Note that the hooks are called for every executed operation, which
+    /// slows down the VM considerably
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    /// use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+    ///
+    /// let executed = Arc::new(AtomicUsize::new(0));
+    /// let counter = executed.clone();
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_hook(move |_| {
+    ///         counter.fetch_add(1, Ordering::Relaxed);
+    ///     })
+    ///     .build();
+    ///
+    /// vm.run_string("++>+").unwrap();
+    ///
+    /// assert_eq!(executed.load(Ordering::Relaxed), 3);
+    /// ```
+    pub fn with_hook(
+        mut self,
+        hook: impl FnMut(&VMEvent<T>) + Send + 'static,
+    ) -> VMBuilder<T, A, R, W, D> {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Registers `function` as the host function identified by `id`, replacing any
+    /// function previously registered with the same identifier. Programs call it with
+    /// the `%` instruction of [`ExtensionSet::HOST_CALL`], which must be enabled through
+    /// [`VMBuilder::with_extensions`], when the current cell holds `id`.
+    ///
+    /// The function gets mutable access to the tape of the running thread, in which the
+    /// current cell is always allocated. An error returned by the function stops the
+    /// program with that error. See the [`extensions`] module for an example
+    pub fn with_host_function(
+        mut self,
+        id: u64,
+        function: impl FnMut(&mut HostCall<T>) -> BfResult + Send + 'static,
+    ) -> VMBuilder<T, A, R, W, D> {
+        self.host_functions.insert(id, Box::new(function));
+        self
+    }
+
+    /// Maps the cell at `position`, relative to the cell programs start at, to the host.
+    /// Replaces any callbacks previously registered for the same cell.
+    ///
+    /// Before an operation reads the cell, `read` is called and its result is stored
+    /// in the cell. After an operation writes the cell, `write` is called with its
+    /// new value. An operation that modifies the cell, such as `+`, does both. Host
+    /// functions of [`ExtensionSet::HOST_CALL`] only see the value read for the current
+    /// cell, and hooks see the value the cell held before the operation.
+    ///
+    /// Note that while any cells are mapped, every operation is checked for accesses of
+    /// mapped cells, and [`ir::Op::Scan`] only moves a single cell per step. Programs
+    /// loaded with [`BrainfuckVM::load_program`] are compiled without [`ir::Pass::DeadLoops`],
+    /// which assumes that cells are zero until the program changes them
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let written = Arc::new(Mutex::new(Vec::new()));
+    /// let log = written.clone();
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_mapped_cell(3, || 42, move |value| log.lock().unwrap().push(value))
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    ///
+    /// // Prints the value read from cell 3, then increments it
+    /// vm.run_string(">>>.+").unwrap();
+    ///
+    /// assert_eq!(vm.writer(), &[42]);
+    /// assert_eq!(*written.lock().unwrap(), [43]);
+    /// ```
+    pub fn with_mapped_cell(
+        mut self,
+        position: isize,
+        read: impl FnMut() -> T + Send + 'static,
+        write: impl FnMut(T) + Send + 'static,
+    ) -> VMBuilder<T, A, R, W, D> {
+        self.mapped_cells.insert(
+            position,
+            MappedCell {
+                read: Box::new(read),
+                write: Box::new(write),
+            },
+        );
+        self
+    }
+
+    /// Allows pausing the VM from another thread, or from a hook, by setting `flag`.
+    ///
+    /// Right before executing the next operation, the VM clears the flag and stops
+    /// with [`BrainfuckExecutionError::Paused`]. The instruction pointer is left at
+    /// that operation, so the program can be continued with [`BrainfuckVM::resume`]
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckExecutionError, BrainfuckVM};
+    /// use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+    ///
+    /// let pause = Arc::new(AtomicBool::new(false));
+    /// let pause_at_output = pause.clone();
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_writer(Vec::new())
+    ///     .with_pause_flag(pause.clone())
+    ///     .with_hook(move |event| {
+    ///         if event.op == cpr_bf::ir::Op::Output {
+    ///             pause_at_output.store(true, Ordering::Relaxed);
+    ///         }
+    ///     })
+    ///     .build_typed();
+    ///
+    /// let result = vm.run_string("+++.>++.");
+    ///
+    /// assert!(matches!(result, Err(BrainfuckExecutionError::Paused)));
+    /// assert_eq!(vm.writer(), &[3]);
+    ///
+    /// while let Err(BrainfuckExecutionError::Paused) = vm.resume() {}
+    ///
+    /// assert_eq!(vm.writer(), &[3, 2]);
+    /// ```
+    pub fn with_pause_flag(self, flag: Arc<AtomicBool>) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            pause_flag: Some(flag),
+            ..self
+        }
+    }
+
+    /// Passes the state of the VM to `save` after every `interval` steps, so that a
+    /// long-running program can be continued from its last checkpoint if it is stopped.
+    /// The checkpoints can be kept in memory, or written to disk in the format of the
+    /// [`snapshot`] module with [`VMState::to_bytes`].
+    ///
+    /// Buffered output is flushed before every checkpoint. Every checkpoint copies all
+    /// cells of the VM, so `interval` should be large compared to the size of the tape
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckVM, VMBuilder};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let checkpoints = Arc::new(Mutex::new(Vec::new()));
+    /// let saved = checkpoints.clone();
+    ///
+    /// let mut vm = VMBuilder::new()
+    ///     .with_writer(Vec::new())
+    ///     .with_checkpoints(10, move |state| saved.lock().unwrap().push(state.to_bytes()))
+    ///     .build_typed();
+    ///
+    /// vm.run_string("++++++++[>++++++++<-]>+.").unwrap();
+    ///
+    /// let checkpoints = checkpoints.lock().unwrap();
+    /// assert_eq!(checkpoints.len() as u64, vm.run_report().steps / 10);
+    ///
+    /// // Continue from the first checkpoint in another VM
+    /// let mut resumed = VMBuilder::new().with_writer(Vec::new()).build_typed();
+    /// resumed.load_state(&checkpoints[0]).unwrap();
+    /// resumed.resume().unwrap();
+    ///
+    /// assert_eq!(resumed.writer(), b"A");
+    /// ```
+    pub fn with_checkpoints(
+        self,
+        interval: u64,
+        save: impl FnMut(&VMSnapshot<T>) + Send + 'static,
+    ) -> VMBuilder<T, A, R, W, D> {
+        VMBuilder {
+            checkpoints: Some(Checkpoints {
+                interval: interval.max(1),
+                save: Box::new(save),
+                last: 0,
+            }),
+            ..self
+        }
+    }
+
+    /// Changes the reader used by the VM as input for the running Brainfuck
+    /// programs to `reader`
+    pub fn with_reader<U: Read>(self, reader: U) -> VMBuilder<T, A, U, W, D> {
+        VMBuilder {
+            config: self.config,
+            hooks: self.hooks,
+            host_functions: self.host_functions,
+            mapped_cells: self.mapped_cells,
+            pause_flag: self.pause_flag,
+            checkpoints: self.checkpoints,
+            celltype: self.celltype,
+            allocator: self.allocator,
+            tape: self.tape,
+            reader,
+            writer: self.writer,
+        }
+    }
+
+    /// Changes the writer used by the VM as output for the running Brainfuck programs
+    /// to `writer`
+    pub fn with_writer<U: Write>(self, writer: U) -> VMBuilder<T, A, R, U, D> {
+        VMBuilder {
+            config: self.config,
+            hooks: self.hooks,
+            host_functions: self.host_functions,
+            mapped_cells: self.mapped_cells,
+            pause_flag: self.pause_flag,
+            checkpoints: self.checkpoints,
+            celltype: self.celltype,
+            allocator: self.allocator,
+            tape: self.tape,
+            reader: self.reader,
+            writer,
+        }
+    }
+
+    /// Builds the [`BrainfuckVM`] with the currently
+    /// stored configuration of this builder.
+    ///
+    /// The reader and writer set with [`VMBuilder::with_reader`] and
+    /// [`VMBuilder::with_writer`] are moved into the VM, and used for all
+    /// input and output of the programs it runs.
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    /// use std::io::{sink, Cursor};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_reader(Cursor::new(b"a".to_vec()))
+    ///     .with_writer(sink())
+    ///     .build();
+    ///
+    /// vm.run_string(",.").unwrap();
+    /// ```
+    pub fn build(self) -> Box<dyn BrainfuckVM> {
+        Box::new(self.build_typed())
+    }
+
+    /// Like [`VMBuilder::build`], but keeps the [`Send`] bound on the boxed VM, so
+    /// that it can be moved to another thread. This requires the reader and writer
+    /// to be [`Send`] as well, which is the case for the default stdin and stdout
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_writer(std::io::sink())
+    ///     .build_send();
+    ///
+    /// let result = std::thread::spawn(move || vm.run_string("+++[>++<-]>."))
+    ///     .join()
+    ///     .unwrap();
+    ///
+    /// assert!(result.is_ok());
+    /// ```
+    pub fn build_send(self) -> Box<dyn BrainfuckVM + Send>
+    where
+        VirtualMachine<T, A, R, W, D>: Send,
+    {
+        Box::new(self.build_typed())
+    }
+
+    /// Builds the [`VirtualMachine`] with the currently
+    /// stored configuration of this builder, without boxing it.
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_writer(Vec::new())
+    ///     .build_typed();
+    ///
+    /// vm.run_string("++++++++[>++++++++<-]>+.").unwrap();
+    ///
+    /// assert_eq!(vm.into_writer(), b"A");
+    /// ```
+    pub fn build_typed(self) -> VirtualMachine<T, A, R, W, D> {
+        log::info!("Building Brainfuck VM with configuration: {}", self);
+
+        VirtualMachine::new(self)
+    }
+}
+
+/// The kind of missing jump instruction
+#[derive(Debug)]
+pub enum MissingKind {
+    JumpFwd,
+    JumpBack,
+
+    /// The opening bracket of a procedure definition, see [`Instruction::ProcStart`]
+    ProcStart,
+
+    /// The closing bracket of a procedure definition, see [`Instruction::ProcEnd`]
+    ProcEnd,
+}
+
+impl Display for MissingKind {
+    /// Describes what is missing, as in "Too few closing brackets"
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MissingKind::JumpBack => write!(f, "Too few closing brackets"),
+            MissingKind::JumpFwd => write!(f, "Too few opening brackets"),
+            MissingKind::ProcEnd => write!(f, "Too few closing parentheses"),
+            MissingKind::ProcStart => write!(f, "Too few opening parentheses"),
+        }
+    }
+}
+
+/// A fatal error encountered by the Brainfuck VM during program execution.
+///
+/// Errors raised while running a program are wrapped in
+/// [`BrainfuckExecutionError::WithContext`], describing where the program failed.
+/// Use [`BrainfuckExecutionError::kind`] to match on the error itself
+///
+/// ```
+/// use cpr_bf::{ir::OptimizationLevel, BrainfuckExecutionError, BrainfuckVM};
+///
+/// let mut vm = cpr_bf::VMBuilder::new()
+///     .with_optimization_level(OptimizationLevel::None)
+///     .build();
+///
+/// let e = vm.run_string("+\n>> <<<").unwrap_err();
+/// assert!(matches!(e.kind(), BrainfuckExecutionError::DataPointerUnderflow));
+///
+/// let context = e.context().unwrap();
+/// assert_eq!(context.instr_ptr, 5);
+/// assert_eq!(context.source_position.unwrap().column, 6);
+/// assert_eq!(context.data_ptr, 0);
+/// assert_eq!(context.steps, 6);
+///
+/// assert_eq!(
+///     e.to_string(),
+///     "Data pointer underflow! at line 2, column 6 (operation 5, data pointer 0, after 6 steps)"
+/// );
+/// ```
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum BrainfuckExecutionError {
+    /// An unknown error
+    #[error("Unknown error")]
+    UnknownError,
+
+    /// An error during input or output
+    #[error("I/O Error: {0}")]
+    IOError(#[from] io::Error),
+
+    /// Mismatched jump instructions
+    #[error("{0}")]
+    JumpMismatchError(MissingKind),
+
+    /// An error during memory allocation or access
+    #[error(transparent)]
+    MemoryError(#[from] VMMemoryError),
+
+    /// An error while parsing the program source code
+    #[error("Parse error: {0}")]
+    ParseError(#[from] ParseError),
+
+    /// Overflow in the data pointer
+    #[error("Data pointer overflow!")]
+    DataPointerOverflow,
+
+    /// Underflow in the data pointer
+    #[error("Data pointer underflow!")]
+    DataPointerUnderflow,
+
+    /// The program executed more steps than the VM allows
+    #[error("Step limit exceeded")]
+    StepLimitExceeded,
+
+    /// The program ran longer than the VM allows, see [`VMBuilder::with_timeout`]
+    #[error("Time limit exceeded")]
+    TimeLimitExceeded,
+
+    /// The program attempted to read input after the end of the input was reached
+    #[error("Unexpected end of input")]
+    UnexpectedEof,
+
+    /// Execution was paused through the flag set with [`VMBuilder::with_pause_flag`].
+    /// The program can be continued with [`BrainfuckVM::resume`]
+    #[error("Execution paused")]
+    Paused,
+
+    /// The program attempted to read input, but its non-blocking reader has no input
+    /// available yet. See [`NonBlockingInput::Yield`]. The program can be continued
+    /// with [`BrainfuckVM::resume`] once input is available
+    #[error("Waiting for input")]
+    InputPending,
+
+    /// A cell overflowed or underflowed, while the VM was configured
+    /// with [`OverflowPolicy::Error`]
+    #[error("Cell overflow!")]
+    CellOverflow,
+
+    /// The program uses the given extensions, which are not enabled
+    /// on the VM. See [`VMBuilder::with_extensions`]
+    #[error("Program uses disabled extensions: {0}")]
+    ExtensionDisabled(ExtensionSet),
+
+    /// The program called a procedure that has not been defined,
+    /// identified by the given cell value. See [`ExtensionSet::PBRAIN`]
+    #[error("Called undefined procedure {0}")]
+    UndefinedProcedure(u64),
+
+    /// The program called a host function that has not been registered,
+    /// identified by the given cell value. See [`ExtensionSet::HOST_CALL`]
+    #[error("Called undefined host function {0}")]
+    UndefinedHostFunction(u64),
+
+    /// The given error was raised while running a program, in the state described
+    /// by `context`. [`BrainfuckExecutionError::Paused`] and
+    /// [`BrainfuckExecutionError::InputPending`] are never wrapped, as the
+    /// program can be continued after them
+    #[error("{error} {context}")]
+    WithContext {
+        /// The error that stopped the program
+        #[source]
+        error: Box<BrainfuckExecutionError>,
+
+        /// The state of the VM when the error was raised
+        context: ErrorContext,
+    },
+}
+
+impl BrainfuckExecutionError {
+    /// The error itself, without the [`ErrorContext`] it might be wrapped in
+    pub fn kind(&self) -> &BrainfuckExecutionError {
+        match self {
+            BrainfuckExecutionError::WithContext { error, .. } => error.kind(),
+            e => e,
+        }
+    }
+
+    /// Like [`BrainfuckExecutionError::kind`], but takes ownership of the error
+    pub fn into_kind(self) -> BrainfuckExecutionError {
+        match self {
+            BrainfuckExecutionError::WithContext { error, .. } => error.into_kind(),
+            e => e,
+        }
+    }
+
+    /// Where the program was when the error was raised. [`None`] if the error was
+    /// not raised while running a program, for example while parsing it
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            BrainfuckExecutionError::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+}
+
+/// The state of the VM when a program failed, see [`BrainfuckExecutionError::context`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// The index of the operation of the [`CompiledProgram`] that failed,
+    /// see [`BrainfuckVM::instr_ptr`]
+    pub instr_ptr: usize,
+
+    /// The position in the source code of the instruction that failed, if known.
+    /// See [`CompiledProgram::source_position`]
+    pub source_position: Option<SourcePosition>,
+
+    /// The index of the cell the data pointer pointed to, see [`BrainfuckVM::data_ptr`]
+    pub data_ptr: usize,
+
+    /// The amount of steps the program executed before it failed, see [`RunReport::steps`]
+    pub steps: u64,
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.source_position {
+            Some(position) => write!(f, "at {} (operation {}, ", position, self.instr_ptr)?,
+            None => write!(f, "at operation {} (", self.instr_ptr)?,
+        }
+
+        write!(
+            f,
+            "data pointer {}, after {} steps)",
+            self.data_ptr, self.steps
+        )
+    }
+}
+
+impl From<()> for BrainfuckExecutionError {
+    fn from(_: ()) -> Self {
+        BrainfuckExecutionError::UnknownError
+    }
+}
+
+type ExecResult = Result<usize, BrainfuckExecutionError>;
+
+impl<T: BrainfuckCell, Alloc: BrainfuckAllocator, R: Read, W: Write, D: Tape<T> + Default>
+    VirtualMachine<T, Alloc, R, W, D>
+{
+    fn new(builder: VMBuilder<T, Alloc, R, W, D>) -> Self {
+        let VMBuilder {
+            config,
+            hooks,
+            host_functions,
+            mapped_cells,
+            pause_flag,
+            checkpoints,
+            celltype: _,
+            allocator: alloc,
+            tape: mut data,
+            reader,
+            writer,
+        } = builder;
+
+        // The cells of a tape given to the builder are kept, and might not be zero
+        let dirty_tape = !data.is_empty();
+
+        if let Err(e) = data.ensure(config.initial_size) {
+            log::error!("Could not preallocate {} cells: {}", config.initial_size, e);
+        }
 
-impl<T: BrainfuckCell, Alloc: BrainfuckAllocator, R: Read, W: Write>
-    VirtualMachine<T, Alloc, R, W>
-{
-    fn new(init_size: usize, reader: R, writer: W) -> Self {
         VirtualMachine {
             data_ptr: 0,
-            data: repeat(T::default()).take(init_size).collect(),
-            alloc: PhantomData,
+            data,
+            origin: 0,
+            alloc,
             reader,
             writer,
+            config,
+            program: None,
+            instr_ptr: 0,
+            steps: 0,
+            procedures: BTreeMap::new(),
+            call_stack: Vec::new(),
+            threads: VecDeque::new(),
+            storage: T::zero(),
+            rng: config.rng_seed.map_or_else(Rng::from_entropy, Rng::new),
+            queued_input: VecDeque::new(),
+            unread_byte: None,
+            output_buffer: Vec::new(),
+            captured_output: None,
+            stats: RunStats::default(),
+            hooks,
+            host_functions,
+            mapped_cells,
+            pause_flag,
+            checkpoints,
+            dirty_tape,
+            #[cfg(all(
+                feature = "std",
+                not(all(target_arch = "wasm32", target_os = "unknown"))
+            ))]
+            started: None,
+        }
+    }
+
+    /// Moves the state of this VM into a VM using `reader` and `writer` for its I/O,
+    /// calls `run` on it, and moves the state back afterwards. The allocator is
+    /// borrowed by the temporary VM instead.
+    /// Output still buffered in this VM is flushed to its own writer first
+    fn with_io<U: Read, V: Write, O>(
+        &mut self,
+        reader: U,
+        writer: V,
+        run: impl FnOnce(&mut VirtualMachine<T, &mut Alloc, U, V, D>) -> O,
+    ) -> Result<O, io::Error> {
+        self.flush_output()?;
+
+        let mut vm = VirtualMachine {
+            data_ptr: self.data_ptr,
+            data: mem::take(&mut self.data),
+            origin: self.origin,
+            alloc: &mut self.alloc,
+            reader,
+            writer,
+            config: self.config,
+            program: self.program.take(),
+            instr_ptr: self.instr_ptr,
+            steps: self.steps,
+            procedures: mem::take(&mut self.procedures),
+            call_stack: mem::take(&mut self.call_stack),
+            threads: mem::take(&mut self.threads),
+            storage: mem::replace(&mut self.storage, T::zero()),
+            rng: self.rng,
+            queued_input: mem::take(&mut self.queued_input),
+            unread_byte: self.unread_byte.take(),
+            output_buffer: mem::take(&mut self.output_buffer),
+            captured_output: self.captured_output.take(),
+            stats: self.stats,
+            hooks: mem::take(&mut self.hooks),
+            host_functions: mem::take(&mut self.host_functions),
+            mapped_cells: mem::take(&mut self.mapped_cells),
+            pause_flag: self.pause_flag.take(),
+            checkpoints: self.checkpoints.take(),
+            dirty_tape: self.dirty_tape,
+            #[cfg(all(
+                feature = "std",
+                not(all(target_arch = "wasm32", target_os = "unknown"))
+            ))]
+            started: self.started,
+        };
+
+        let result = run(&mut vm);
+
+        self.data_ptr = vm.data_ptr;
+        self.data = vm.data;
+        self.origin = vm.origin;
+        self.program = vm.program;
+        self.instr_ptr = vm.instr_ptr;
+        self.steps = vm.steps;
+        self.procedures = vm.procedures;
+        self.call_stack = vm.call_stack;
+        self.threads = vm.threads;
+        self.storage = vm.storage;
+        self.rng = vm.rng;
+        self.queued_input = vm.queued_input;
+        self.unread_byte = vm.unread_byte;
+        self.output_buffer = vm.output_buffer;
+        self.captured_output = vm.captured_output;
+        self.stats = vm.stats;
+        self.hooks = vm.hooks;
+        self.host_functions = vm.host_functions;
+        self.mapped_cells = vm.mapped_cells;
+        self.pause_flag = vm.pause_flag;
+        self.checkpoints = vm.checkpoints;
+        self.dirty_tape = vm.dirty_tape;
+
+        #[cfg(all(
+            feature = "std",
+            not(all(target_arch = "wasm32", target_os = "unknown"))
+        ))]
+        {
+            self.started = vm.started;
+        }
+
+        Ok(result)
+    }
+
+    fn ensure_capacity(&mut self, min_size: usize) -> Result<(), VMMemoryError> {
+        if let Some(limit) = self.config.max_cells {
+            if min_size > limit {
+                log::error!(
+                    "Access at index {} exceeds memory limit of {} cells",
+                    min_size - 1,
+                    limit
+                );
+
+                return Err(VMMemoryError::LimitExceeded(MemoryLimitExceeded {
+                    limit,
+                    access: min_size - 1,
+                }));
+            }
+        }
+
+        self.alloc.ensure_capacity(&mut self.data, min_size)
+    }
+
+    fn exec_move(&mut self, instr_ptr: usize, amount: isize) -> ExecResult {
+        let old_len = self.data.len();
+
+        self.data_ptr = self
+            .alloc
+            .move_data_ptr(&mut self.data, self.data_ptr, amount)?;
+
+        if self.data.len() > old_len {
+            self.origin += self.data.len() - old_len;
+
+            log::trace!("Tape extended to the left, origin now at {}", self.origin);
+
+            if let Some(limit) = self
+                .config
+                .max_cells
+                .filter(|&limit| self.data.len() > limit)
+            {
+                log::error!(
+                    "Extending the tape to the left exceeds memory limit of {} cells",
+                    limit
+                );
+
+                return Err(VMMemoryError::LimitExceeded(MemoryLimitExceeded {
+                    limit,
+                    access: self.data.len() - 1,
+                })
+                .into());
+            }
+        }
+
+        self.stats.max_data_ptr = self
+            .stats
+            .max_data_ptr
+            .max(self.data_ptr as isize - self.origin as isize);
+
+        Ok(instr_ptr + 1)
+    }
+
+    /// The value of the currently pointed-to cell, allocated first if needed. The allocator
+    /// is only asked for capacity if the cell is outside of the tape or the memory limit,
+    /// instead of on every access
+    #[inline]
+    fn current_cell(&mut self) -> Result<T, VMMemoryError> {
+        let allocated = self.data_ptr < self.data.len()
+            && self
+                .config
+                .max_cells
+                .is_none_or(|limit| self.data_ptr < limit);
+
+        if !allocated {
+            self.ensure_capacity(self.data_ptr + 1)?;
+        }
+
+        Ok(self.data.get(self.data_ptr).unwrap_or_default())
+    }
+
+    /// Changes the value of the currently pointed-to cell, allocating it first if needed
+    #[inline]
+    fn set_current_cell(&mut self, value: T) -> Result<(), VMMemoryError> {
+        self.current_cell()?;
+        self.data.set(self.data_ptr, value);
+
+        Ok(())
+    }
+
+    /// Adds `amount` to the cell at `offset` from the data pointer. Moves there and back
+    /// through the allocator, so that the tape is extended or checked like it would be
+    /// by the movements this operation replaces
+    fn exec_add_at(&mut self, instr_ptr: usize, offset: isize, amount: isize) -> ExecResult {
+        self.exec_move(instr_ptr, offset)?;
+        self.exec_add(instr_ptr, amount)?;
+        self.exec_move(instr_ptr, -offset)
+    }
+
+    fn exec_add(&mut self, instr_ptr: usize, amount: isize) -> ExecResult {
+        let policy = self.config.overflow_policy;
+
+        // The cell is read again while it is changed, as a shared tape may have changed it
+        self.current_cell()?;
+        self.data
+            .update(self.data_ptr, |val| Self::add_to_cell(val, amount, policy))?;
+
+        Ok(instr_ptr + 1)
+    }
+
+    fn add_to_cell(
+        val: T,
+        amount: isize,
+        policy: OverflowPolicy,
+    ) -> Result<T, BrainfuckExecutionError> {
+        let magnitude = amount.unsigned_abs();
+
+        match policy {
+            OverflowPolicy::Wrapping => {
+                let delta: T = wrapping_from_usize(magnitude);
+
+                Ok(if amount >= 0 {
+                    val.wrapping_add(&delta)
+                } else {
+                    val.wrapping_sub(&delta)
+                })
+            }
+            OverflowPolicy::Saturating => Ok(match checked_from_usize::<T>(magnitude) {
+                Some(delta) if amount >= 0 => val.saturating_add(&delta),
+                Some(delta) => val.saturating_sub(&delta),
+                None if amount >= 0 => T::max_value(),
+                None => T::min_value(),
+            }),
+            OverflowPolicy::Error => checked_from_usize::<T>(magnitude)
+                .and_then(|delta| {
+                    if amount >= 0 {
+                        val.checked_add(&delta)
+                    } else {
+                        val.checked_sub(&delta)
+                    }
+                })
+                .ok_or_else(|| {
+                    log::error!("Adding {} to cell value {:?} overflows", amount, val);
+                    BrainfuckExecutionError::CellOverflow
+                }),
+        }
+    }
+
+    fn exec_output(&mut self, instr_ptr: usize) -> ExecResult {
+        log::trace!("Outputting value at cell {}", self.data_ptr);
+
+        let val = self.data.get(self.data_ptr).unwrap_or_default();
+
+        if self.config.io_mode == IoMode::Numeric {
+            let number = format!("{}\n", val);
+
+            log::trace!("Found value: {}", val);
+
+            self.write_output(number.as_bytes())?;
+
+            return Ok(instr_ptr + 1);
         }
+
+        let encoding = self.config.output_encoding.unwrap_or(T::OUTPUT_ENCODING);
+
+        let escaped;
+        let mut buf = [0_u8; 4];
+
+        let bytes: &[u8] = match encoding {
+            OutputEncoding::RawByte => {
+                buf[0] = val.to_u64() as u8;
+                &buf[..1]
+            }
+            OutputEncoding::Latin1 => {
+                buf[0] = u8::try_from(val.to_u64()).unwrap_or(b'?');
+                &buf[..1]
+            }
+            OutputEncoding::Unicode => val
+                .to_code_point()
+                .and_then(char::from_u32)
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                .encode_utf8(&mut buf)
+                .as_bytes(),
+            OutputEncoding::Escape => {
+                escaped = match val.to_u64() {
+                    0x5C => "\\\\".into(),
+                    c @ (0x20..=0x7E | 0x0A) => char::from(c as u8).into(),
+                    c @ 0..=0xFF => format!("\\x{:02X}", c),
+                    c => format!("\\u{{{:X}}}", c),
+                };
+
+                escaped.as_bytes()
+            }
+        };
+
+        log::trace!("Found value: {:?}, written as {:?}", val, bytes);
+
+        self.write_output(bytes)?;
+
+        Ok(instr_ptr + 1)
     }
 
-    fn exec_incrdp(&mut self, instr_ptr: usize) -> ExecResult {
-        log::trace!("Old data pointer: {}", self.data_ptr);
+    fn exec_input(&mut self, instr_ptr: usize) -> ExecResult {
+        log::trace!("Reading input into cell {}", self.data_ptr);
 
-        self.data_ptr = self
-            .data_ptr
-            .checked_add(1)
-            .ok_or(BrainfuckExecutionError::DataPointerOverflow)?;
+        let read = match self.config.io_mode {
+            IoMode::Character => self.read_byte().map(|byte| byte.map(T::from_byte)),
+            IoMode::Numeric => self.read_number(),
+        };
+
+        let value = match read {
+            Err(BrainfuckExecutionError::InputPending) => return self.input_pending(instr_ptr),
+            read => read?,
+        };
+
+        if let Some(value) = value {
+            log::trace!("Converted to cell type: {:?}", value);
+
+            self.set_current_cell(value)?;
+        } else {
+            log::debug!("Attempted to read input, but no input was available");
+
+            let eof_value = match self.config.eof_behavior {
+                EofBehavior::Unchanged => None,
+                EofBehavior::Zero => Some(T::zero()),
+                EofBehavior::MaxValue => Some(T::zero().wrapping_sub(&T::one())),
+                EofBehavior::Error => return Err(BrainfuckExecutionError::UnexpectedEof),
+            };
+
+            if let Some(eof_value) = eof_value {
+                log::trace!("Storing EOF value {:?}", eof_value);
+
+                self.set_current_cell(eof_value)?;
+            }
+        }
+
+        Ok(instr_ptr + 1)
+    }
+
+    /// Handles a read for which the non-blocking reader had no input available yet,
+    /// see [`VMBuilder::with_nonblocking_input`]
+    fn input_pending(&mut self, instr_ptr: usize) -> ExecResult {
+        match self.config.nonblocking_input {
+            NonBlockingInput::Sentinel(value) => {
+                log::trace!("No input available, storing sentinel {}", value);
+                self.set_current_cell(wrapping_from_u64(value))?;
+                Ok(instr_ptr + 1)
+            }
+            _ => {
+                // The read is retried once the program is resumed, so it is not counted yet
+                self.steps -= 1;
+                Err(BrainfuckExecutionError::InputPending)
+            }
+        }
+    }
+
+    /// Adds `bytes` written by the program to the output buffer, and flushes
+    /// it if required by the [`FlushPolicy`]
+    fn write_output(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+        self.stats.bytes_written += bytes.len() as u64;
+        self.buffer_output(bytes)
+    }
+
+    /// Adds `bytes` to the output buffer, and flushes it if required by the [`FlushPolicy`]
+    fn buffer_output(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+        self.output_buffer.extend_from_slice(bytes);
+
+        let flush = match self.config.flush_policy {
+            FlushPolicy::EveryOutput => true,
+            FlushPolicy::OnNewline => bytes.contains(&b'\n'),
+            FlushPolicy::OnProgramEnd => false,
+            FlushPolicy::EveryNBytes(n) => self.output_buffer.len() >= n,
+        };
+
+        if flush {
+            self.flush_output()?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a single byte, from the queued input if there is any, or else from the reader.
+    /// Returns [`None`] at the end of the input. While the output is captured by
+    /// [`BrainfuckVM::run_string_with_input`], the reader is not used
+    fn read_byte(&mut self) -> Result<Option<u8>, BrainfuckExecutionError> {
+        if let Some(byte) = self.unread_byte.take() {
+            return Ok(Some(byte));
+        }
+
+        let byte = match self.queued_input.pop_front() {
+            Some(byte) => Some(byte),
+            None if self.captured_output.is_some() => None,
+            None => {
+                self.flush_output()?;
+
+                let mut buf = [0_u8; 1];
+                let num_read = match self.reader.read(&mut buf) {
+                    Err(e)
+                        if self.config.nonblocking_input != NonBlockingInput::Disabled
+                            && io::is_would_block(&e) =>
+                    {
+                        return Err(BrainfuckExecutionError::InputPending);
+                    }
+                    read => read?,
+                };
+
+                (num_read == 1).then_some(buf[0])
+            }
+        };
+
+        if let Some(byte) = byte {
+            log::trace!("Read byte: {}", byte);
+            self.stats.bytes_read += 1;
+            self.echo_input(byte)?;
+        }
+
+        Ok(byte)
+    }
+
+    /// Echoes a byte read from the input, see [`VMBuilder::with_input_echo`]
+    fn echo_input(&mut self, byte: u8) -> Result<(), io::Error> {
+        match self.config.input_echo {
+            InputEcho::Off => Ok(()),
+            InputEcho::Writer => self.buffer_output(&[byte]),
+            #[cfg(feature = "std")]
+            InputEcho::Stderr => std::io::stderr().write_all(&[byte]),
+        }
+    }
+
+    /// Reads a decimal integer for [`IoMode::Numeric`]. The byte following the
+    /// integer is kept, so that it is returned again by the next read.
+    /// Returns [`None`] if the input ends before an integer is found
+    fn read_number(&mut self) -> Result<Option<T>, BrainfuckExecutionError> {
+        let mut negative = false;
+        let mut magnitude: Option<isize> = None;
+
+        loop {
+            let byte = match self.read_byte() {
+                Ok(Some(byte)) => byte,
+                Ok(None) => break,
+                // A number ends once no more input is available
+                Err(BrainfuckExecutionError::InputPending) if magnitude.is_some() => break,
+                Err(e) => return Err(e),
+            };
+
+            match byte {
+                b'0'..=b'9' => {
+                    let digit = isize::from(byte - b'0');
+
+                    magnitude = Some(
+                        magnitude
+                            .unwrap_or(0)
+                            .saturating_mul(10)
+                            .saturating_add(digit),
+                    );
+                }
+                _ if magnitude.is_some() => {
+                    self.unread_byte = Some(byte);
+                    break;
+                }
+                b'-' => negative = true,
+                _ => negative = false,
+            }
+        }
+
+        magnitude
+            .map(|magnitude| {
+                let amount = if negative { -magnitude } else { magnitude };
+
+                Self::add_to_cell(T::zero(), amount, self.config.overflow_policy)
+            })
+            .transpose()
+    }
+
+    fn exec_setzero(&mut self, instr_ptr: usize) -> ExecResult {
+        // Cells that have not been allocated yet are already zero
+        if self.data_ptr < self.data.len() {
+            self.data.set(self.data_ptr, T::zero());
+        }
+
+        Ok(instr_ptr + 1)
+    }
+
+    /// Searches the allocated cells for the first zero cell, moving by `amount` cells at a time.
+    /// When the search would leave the allocated cells, the data pointer is moved once by
+    /// the allocator instead, and the scan is executed again as the next step
+    fn exec_scan(&mut self, instr_ptr: usize, amount: isize) -> ExecResult {
+        let mut ptr = self.data_ptr;
+
+        loop {
+            // Cells that have not been allocated yet are zero
+            if self.data.get(ptr).is_none_or(|cell| cell.is_zero()) {
+                self.data_ptr = ptr;
+                self.stats.max_data_ptr = self
+                    .stats
+                    .max_data_ptr
+                    .max(self.data_ptr as isize - self.origin as isize);
+
+                return Ok(instr_ptr + 1);
+            }
+
+            match ptr
+                .checked_add_signed(amount)
+                .filter(|&next| next < self.data.len())
+            {
+                Some(next) => ptr = next,
+                None => {
+                    self.data_ptr = ptr;
+                    self.exec_move(instr_ptr, amount)?;
+
+                    return Ok(instr_ptr);
+                }
+            }
+        }
+    }
+
+    fn exec_jumpfwd(&mut self, instr_ptr: usize, closing_tag: usize) -> ExecResult {
+        let val = self.data.get(self.data_ptr).unwrap_or_default();
+
+        if val != T::zero() {
+            return Ok(instr_ptr + 1);
+        }
+
+        Ok(closing_tag + 1)
+    }
+
+    fn exec_jumpback(&mut self, instr_ptr: usize, opening_tag: usize) -> ExecResult {
+        let val = self.data.get(self.data_ptr).unwrap_or_default();
+
+        if val == T::zero() {
+            return Ok(instr_ptr + 1);
+        }
+
+        Ok(opening_tag + 1)
+    }
+
+    fn exec_defineproc(&mut self, instr_ptr: usize, end: usize) -> ExecResult {
+        let id = self.data.get(self.data_ptr).unwrap_or_default().to_u64();
+
+        log::trace!("Defining procedure {} at {}", id, instr_ptr + 1);
+
+        self.procedures.insert(id, instr_ptr + 1);
+
+        Ok(end + 1)
+    }
+
+    fn exec_endproc(&mut self, instr_ptr: usize) -> ExecResult {
+        // Procedure bodies are skipped when defined, so they are only reached
+        // through a call. Should the stack be empty anyway, just continue
+        match self.call_stack.pop() {
+            Some(return_to) => {
+                log::trace!("Returning from procedure to {}", return_to);
+                Ok(return_to)
+            }
+            None => Ok(instr_ptr + 1),
+        }
+    }
+
+    fn exec_callproc(&mut self, instr_ptr: usize) -> ExecResult {
+        let id = self.data.get(self.data_ptr).unwrap_or_default().to_u64();
+
+        let Some(&start) = self.procedures.get(&id) else {
+            log::error!("Called undefined procedure {}", id);
+            return Err(BrainfuckExecutionError::UndefinedProcedure(id));
+        };
+
+        log::trace!("Calling procedure {} at {}", id, start);
+
+        self.call_stack.push(instr_ptr + 1);
+
+        Ok(start)
+    }
+
+    fn exec_fork(&mut self, instr_ptr: usize) -> ExecResult {
+        let mut tape: D = tape_from_cells(&self.data.to_vec());
+
+        // Cells that have not been allocated yet are already zero
+        if self.data_ptr < tape.len() {
+            tape.set(self.data_ptr, T::zero());
+        }
+
+        log::trace!(
+            "Forking thread at {}, now running {} threads",
+            instr_ptr,
+            self.threads.len() + 2
+        );
+
+        self.threads.push_back(Thread {
+            tape,
+            data_ptr: self.data_ptr,
+            origin: self.origin,
+            instr_ptr: instr_ptr + 1,
+            procedures: self.procedures.clone(),
+            call_stack: self.call_stack.clone(),
+        });
+
+        Ok(instr_ptr + 1)
+    }
+
+    fn exec_exit(&mut self, instr_ptr: usize) -> ExecResult {
+        log::debug!("Exiting program at {}", instr_ptr);
+
+        self.threads.clear();
+
+        Ok(self
+            .program
+            .as_ref()
+            .map_or(0, |program| program.ops().len()))
+    }
+
+    fn exec_store(&mut self, instr_ptr: usize) -> ExecResult {
+        self.storage = self.data.get(self.data_ptr).unwrap_or_default();
+
+        log::trace!("Stored value {:?}", self.storage);
+
+        Ok(instr_ptr + 1)
+    }
+
+    fn exec_hostcall(&mut self, instr_ptr: usize) -> ExecResult {
+        let id = self.current_cell()?.to_u64();
+
+        let Some(function) = self.host_functions.get_mut(&id) else {
+            log::error!("Called undefined host function {}", id);
+            return Err(BrainfuckExecutionError::UndefinedHostFunction(id));
+        };
+
+        log::trace!("Calling host function {}", id);
+
+        function(&mut HostCall::new(&mut self.data, self.data_ptr))?;
+
+        Ok(instr_ptr + 1)
+    }
+
+    /// Replaces the value in the current cell with the result of `f`
+    fn exec_modify(&mut self, instr_ptr: usize, f: impl FnOnce(T) -> T) -> ExecResult {
+        self.current_cell()?;
+        self.data.update(self.data_ptr, |val| {
+            Ok::<_, BrainfuckExecutionError>(f(val))
+        })?;
+
+        Ok(instr_ptr + 1)
+    }
+
+    /// Moves the data pointer back to the cell programs start at, and the instruction
+    /// pointer back to the start of the loaded program, clearing all other execution state
+    fn reset_execution(&mut self) {
+        self.data_ptr = self.origin;
+        self.instr_ptr = 0;
+        self.steps = 0;
+        self.procedures.clear();
+        self.call_stack.clear();
+        self.threads.clear();
+        self.storage = T::zero();
+        self.stats = RunStats::default();
+
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.last = 0;
+        }
+
+        if let Some(seed) = self.config.rng_seed {
+            self.rng = Rng::new(seed);
+        }
+
+        #[cfg(all(
+            feature = "std",
+            not(all(target_arch = "wasm32", target_os = "unknown"))
+        ))]
+        {
+            self.started = None;
+        }
+    }
+
+    /// Grows the tape to fit the estimated tape extent of `program`,
+    /// see [`VMBuilder::with_auto_preallocated_cells`]
+    fn preallocate_for(&mut self, program: &Program) {
+        let Some(extent) = program.tape_extent() else {
+            log::info!("Tape extent of the program is unknown, not preallocating cells");
+            return;
+        };
+
+        let size = self.origin + extent;
+        let size = self.config.max_cells.map_or(size, |limit| size.min(limit));
+
+        if size > self.data.len() {
+            log::info!("Preallocating {} cells for the program", size);
+
+            if let Err(e) = self.data.ensure(size) {
+                log::warn!("Could not preallocate cells for the program: {}", e);
+            }
+        }
+    }
+
+    /// Executes a single operation of the loaded program, without
+    /// flushing the output once execution stops
+    fn execute_step(&mut self) -> ExecutionState {
+        let Some(program) = &self.program else {
+            return ExecutionState::Halted;
+        };
+
+        let Some(&op) = program.ops().get(self.instr_ptr) else {
+            return ExecutionState::Halted;
+        };
+
+        let num_ops = program.ops().len();
+
+        if let Err(e) = self.check_interrupt() {
+            return ExecutionState::Error(e);
+        }
+
+        self.steps += 1;
+
+        if !self.hooks.is_empty() {
+            let event = VMEvent {
+                op,
+                instr_ptr: self.instr_ptr,
+                data_ptr: self.data_ptr,
+                cell: self.data.get(self.data_ptr).unwrap_or_default(),
+            };
+
+            self.hooks.iter_mut().for_each(|hook| hook(&event));
+        }
+
+        match self.exec(op, self.instr_ptr) {
+            Ok(next) => self.instr_ptr = next,
+            Err(e) => return ExecutionState::Error(e),
+        }
+
+        let finished = self.instr_ptr >= num_ops;
+        let running = self.schedule_next_thread(finished) || !finished;
+
+        if self.checkpoint_due() {
+            self.save_checkpoint();
+        }
+
+        if running {
+            return ExecutionState::Running;
+        }
+
+        log::debug!("Program finished");
+
+        ExecutionState::Halted
+    }
+
+    /// Runs the loaded program in a tight loop, without the per-step bookkeeping for
+    /// hooks and threads. Returns [`None`] once the program forks a thread, after which
+    /// it must continue through [`VirtualMachine::execute_step`], or once the amount of
+    /// executed steps reaches `budget`
+    fn run_fast(&mut self, budget: u64) -> Option<ExecutionState> {
+        let Some(program) = self.program.take() else {
+            return Some(ExecutionState::Halted);
+        };
+
+        let state = self.run_ops(program.ops(), budget);
+
+        self.program = Some(program);
+
+        if state.is_none() && self.checkpoint_due() {
+            self.save_checkpoint();
+        }
+
+        state
+    }
+
+    fn run_ops(&mut self, ops: &[Op], budget: u64) -> Option<ExecutionState> {
+        while let Some(&op) = ops.get(self.instr_ptr) {
+            if self.steps >= budget {
+                return None;
+            }
+
+            if let Err(e) = self.check_interrupt() {
+                return Some(ExecutionState::Error(e));
+            }
+
+            self.steps += 1;
+
+            // The program is taken out of the VM while running, so the end of the
+            // program is not known to `exec_exit` itself
+            let next = match op {
+                Op::Exit => self.exec_exit(self.instr_ptr).map(|_| ops.len()),
+                _ => self.exec(op, self.instr_ptr),
+            };
+
+            match next {
+                Ok(next) => self.instr_ptr = next,
+                Err(e) => return Some(ExecutionState::Error(e)),
+            }
+
+            if !self.threads.is_empty() {
+                self.schedule_next_thread(self.instr_ptr >= ops.len());
+                return None;
+            }
+
+            // The program has to be back in the VM to be part of the checkpoint
+            if self.checkpoint_due() {
+                return None;
+            }
+        }
+
+        log::debug!("Program finished");
+
+        Some(ExecutionState::Halted)
+    }
+
+    /// Whether a checkpoint has to be taken after the current step,
+    /// see [`VMBuilder::with_checkpoints`]
+    #[inline]
+    fn checkpoint_due(&self) -> bool {
+        self.checkpoints.as_ref().is_some_and(|checkpoints| {
+            self.steps > checkpoints.last && self.steps.is_multiple_of(checkpoints.interval)
+        })
+    }
+
+    /// Runs the loaded program until it stops, or until the amount of executed steps
+    /// reaches `budget`, in which case [`ExecutionState::Running`] is returned
+    fn run_until(&mut self, budget: u64) -> ExecutionState {
+        let state = loop {
+            if self.hooks.is_empty() && self.threads.is_empty() {
+                if let Some(state) = self.run_fast(budget) {
+                    break state;
+                }
+            }
+
+            if self.steps >= budget {
+                break ExecutionState::Running;
+            }
+
+            match self.execute_step() {
+                ExecutionState::Running => {}
+                state => break state,
+            }
+        };
+
+        self.stop(state)
+    }
+
+    fn save_checkpoint(&mut self) {
+        log::debug!("Taking checkpoint after {} steps", self.steps);
+
+        // Output written before the checkpoint must not be lost if the program stops
+        if let Err(e) = self.flush_output() {
+            log::error!("Could not flush buffered output: {}", e);
+        }
+
+        let state = self.state();
+
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.last = self.steps;
+            (checkpoints.save)(&state);
+        }
+    }
+
+    /// Checks whether execution has to stop before the next step,
+    /// because it was paused or reached the step limit
+    #[inline]
+    fn check_interrupt(&mut self) -> BfResult {
+        if self
+            .pause_flag
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed) && flag.swap(false, Ordering::Relaxed))
+        {
+            log::info!("Pausing execution at operation {}", self.instr_ptr);
+            return Err(BrainfuckExecutionError::Paused);
+        }
+
+        if self.config.max_steps.is_some_and(|max| self.steps >= max) {
+            log::info!("Step limit of {} reached", self.steps);
+            return Err(BrainfuckExecutionError::StepLimitExceeded);
+        }
+
+        #[cfg(all(
+            feature = "std",
+            not(all(target_arch = "wasm32", target_os = "unknown"))
+        ))]
+        if let Some(timeout) = self.config.timeout {
+            self.check_timeout(timeout)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the program ran for longer than `timeout`. Reading the clock
+    /// is slow compared to a single step, so it is only read every 1024 steps
+    #[cfg(all(
+        feature = "std",
+        not(all(target_arch = "wasm32", target_os = "unknown"))
+    ))]
+    fn check_timeout(&mut self, timeout: Duration) -> BfResult {
+        if !self.steps.is_multiple_of(1024) {
+            return Ok(());
+        }
+
+        let now = std::time::Instant::now();
+        let started = *self.started.get_or_insert(now);
+
+        if now.duration_since(started) > timeout {
+            log::info!("Time limit of {:?} reached", timeout);
+            return Err(BrainfuckExecutionError::TimeLimitExceeded);
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the output once execution stopped in the given state, and adds the
+    /// [`ErrorContext`] to the error the program failed with. A failed flush turns
+    /// a halted or unfinished program into an error
+    fn stop(&mut self, state: ExecutionState) -> ExecutionState {
+        log::debug!("Execution stopped, flushing output");
+
+        match (state, self.flush_output()) {
+            (ExecutionState::Halted | ExecutionState::Running, Err(e)) => {
+                ExecutionState::Error(e.into())
+            }
+            (ExecutionState::Error(e), _) => ExecutionState::Error(self.add_context(e)),
+            (state, _) => state,
+        }
+    }
+
+    /// Wraps `e` in the [`ErrorContext`] of the operation it was raised at, unless
+    /// the program can be continued after it, see [`BrainfuckExecutionError::WithContext`]
+    fn add_context(&self, e: BrainfuckExecutionError) -> BrainfuckExecutionError {
+        if matches!(
+            e,
+            BrainfuckExecutionError::Paused
+                | BrainfuckExecutionError::InputPending
+                | BrainfuckExecutionError::WithContext { .. }
+        ) {
+            return e;
+        }
+
+        let context = ErrorContext {
+            instr_ptr: self.instr_ptr,
+            source_position: self
+                .program
+                .as_ref()
+                .and_then(|program| program.source_position(self.instr_ptr)),
+            data_ptr: self.data_ptr,
+            steps: self.steps,
+        };
+
+        BrainfuckExecutionError::WithContext {
+            error: Box::new(e),
+            context,
+        }
+    }
+
+    /// Exchanges the state of the running thread with `thread`
+    fn swap_thread(&mut self, thread: &mut Thread<D>) {
+        mem::swap(&mut self.data, &mut thread.tape);
+        mem::swap(&mut self.data_ptr, &mut thread.data_ptr);
+        mem::swap(&mut self.origin, &mut thread.origin);
+        mem::swap(&mut self.instr_ptr, &mut thread.instr_ptr);
+        mem::swap(&mut self.procedures, &mut thread.procedures);
+        mem::swap(&mut self.call_stack, &mut thread.call_stack);
+    }
+
+    /// Switches to the next waiting thread, if any. The running thread is
+    /// moved to the back of the queue, unless it has `finished`
+    fn schedule_next_thread(&mut self, finished: bool) -> bool {
+        let Some(mut next) = self.threads.pop_front() else {
+            return false;
+        };
+
+        self.swap_thread(&mut next);
+
+        if finished {
+            log::trace!("Thread finished, {} threads left", self.threads.len() + 1);
+        } else {
+            self.threads.push_back(next);
+        }
 
-        log::trace!("New data pointer: {}", self.data_ptr);
+        true
+    }
 
-        Ok(instr_ptr + 1)
+    #[inline]
+    fn exec(&mut self, op: Op, instr_ptr: usize) -> ExecResult {
+        if self.mapped_cells.is_empty() {
+            self.exec_op(op, instr_ptr)
+        } else {
+            self.exec_mapped(op, instr_ptr)
+        }
     }
 
-    fn exec_decrdp(&mut self, instr_ptr: usize) -> ExecResult {
-        log::trace!("Old data pointer: {}", self.data_ptr);
+    /// Executes `op`, calling the callbacks of the mapped cell it accesses, if any.
+    /// See [`VMBuilder::with_mapped_cell`]
+    fn exec_mapped(&mut self, op: Op, instr_ptr: usize) -> ExecResult {
+        // Any cell the scan passes might be mapped, so it is executed one cell at a time
+        if let Op::Scan(amount) = op {
+            self.load_mapped(0)?;
 
-        self.data_ptr = self
-            .data_ptr
-            .checked_sub(1)
-            .ok_or(BrainfuckExecutionError::DataPointerUnderflow)?;
+            if self
+                .data
+                .get(self.data_ptr)
+                .is_none_or(|cell| cell.is_zero())
+            {
+                return Ok(instr_ptr + 1);
+            }
 
-        log::trace!("New data pointer: {}", self.data_ptr);
+            self.exec_move(instr_ptr, amount)?;
 
-        Ok(instr_ptr + 1)
-    }
+            return Ok(instr_ptr);
+        }
 
-    fn exec_incr(&mut self, instr_ptr: usize) -> ExecResult {
-        log::trace!("Incrementing cell {}", self.data_ptr);
+        let Some((offset, reads, writes)) = mapped_access(op) else {
+            return self.exec_op(op, instr_ptr);
+        };
 
-        Alloc::ensure_capacity(&mut self.data, self.data_ptr + 1)?;
+        if reads {
+            self.load_mapped(offset)?;
+        }
 
-                log::trace!("Previous value: {:?}", self.data[self.data_ptr]);
+        let position = self.position(offset);
+        let next = self.exec_op(op, instr_ptr)?;
 
-                unsafe {
-                    let val = self.data.get_unchecked_mut(self.data_ptr);
-                    *val = val.wrapping_add(&T::one())
-                }
-                
-                log::trace!("New value: {:?}", self.data[self.data_ptr]);
+        if writes {
+            if let Some(cell) = self.mapped_cells.get_mut(&position) {
+                let index = (self.origin as isize).wrapping_add(position) as usize;
+                (cell.write)(self.data.get(index).unwrap_or_default());
+            }
+        }
 
-        Ok(instr_ptr + 1)
+        Ok(next)
     }
 
-    fn exec_decr(&mut self, instr_ptr: usize) -> ExecResult {
-        log::trace!("Decrementing cell {}", self.data_ptr);
+    /// If the cell at `offset` from the data pointer is mapped, reads its value
+    /// and stores it in the cell, allocating the cell first if needed
+    fn load_mapped(&mut self, offset: isize) -> BfResult {
+        let position = self.position(offset);
 
-        Alloc::ensure_capacity(&mut self.data, self.data_ptr + 1)?;
+        let Some(cell) = self.mapped_cells.get_mut(&position) else {
+            return Ok(());
+        };
 
-                log::trace!("Previous value: {:?}", self.data[self.data_ptr]);
+        // Cells to the left of the tape are created by the operation itself
+        let Some(index) = self.data_ptr.checked_add_signed(offset) else {
+            return Ok(());
+        };
 
-                unsafe {
-                    let val = self.data.get_unchecked_mut(self.data_ptr);
-                    *val = val.wrapping_sub(&T::one())
-                }
+        let value = (cell.read)();
 
-                log::trace!("New value: {:?}", self.data[self.data_ptr]);
+        self.ensure_capacity(index + 1)?;
+        self.data.set(index, value);
 
-        Ok(instr_ptr + 1)
+        Ok(())
     }
 
-    fn exec_output(&mut self, instr_ptr: usize) -> ExecResult {
-        log::trace!("Outputting value at cell {}", self.data_ptr);
+    /// The position of the cell at `offset` from the data pointer,
+    /// relative to the cell programs start at
+    fn position(&self, offset: isize) -> isize {
+        (self.data_ptr as isize - self.origin as isize).wrapping_add(offset)
+    }
 
-        let val = self.data.get(self.data_ptr).cloned().unwrap_or_default();
-        let as_char: char = val
-            .try_into()
-            .ok()
-            .and_then(char::from_u32)
-            .unwrap_or(char::REPLACEMENT_CHARACTER);
+    #[inline]
+    fn exec_op(&mut self, op: Op, instr_ptr: usize) -> ExecResult {
+        match op {
+            Op::Add(amount) => self.exec_add(instr_ptr, amount),
+            Op::Move(amount) => self.exec_move(instr_ptr, amount),
+            Op::AddAt(offset, amount) => self.exec_add_at(instr_ptr, offset, amount),
+            Op::Output => self.exec_output(instr_ptr),
+            Op::Input => self.exec_input(instr_ptr),
+            Op::SetZero => self.exec_setzero(instr_ptr),
+            Op::Scan(amount) => self.exec_scan(instr_ptr, amount),
+            Op::JumpFwd(closing_tag) => self.exec_jumpfwd(instr_ptr, closing_tag),
+            Op::JumpBack(opening_tag) => self.exec_jumpback(instr_ptr, opening_tag),
+            Op::DefineProc(end) => self.exec_defineproc(instr_ptr, end),
+            Op::EndProc => self.exec_endproc(instr_ptr),
+            Op::CallProc => self.exec_callproc(instr_ptr),
+            Op::Fork => self.exec_fork(instr_ptr),
+            Op::Exit => self.exec_exit(instr_ptr),
+            Op::Store => self.exec_store(instr_ptr),
+            Op::Load => {
+                let storage = self.storage;
+                self.exec_modify(instr_ptr, |_| storage)
+            }
+            Op::ShiftRight => self.exec_modify(instr_ptr, BrainfuckCell::shift_right_logical),
+            Op::ShiftLeft => self.exec_modify(instr_ptr, |val| val << 1),
+            Op::Not => self.exec_modify(instr_ptr, |val| !val),
+            Op::Xor => {
+                let storage = self.storage;
+                self.exec_modify(instr_ptr, |val| val ^ storage)
+            }
+            Op::And => {
+                let storage = self.storage;
+                self.exec_modify(instr_ptr, |val| val & storage)
+            }
+            Op::Or => {
+                let storage = self.storage;
+                self.exec_modify(instr_ptr, |val| val | storage)
+            }
+            Op::HostCall => self.exec_hostcall(instr_ptr),
+            Op::Random => {
+                let byte = self.rng.next_u64() as u8;
+                self.exec_modify(instr_ptr, |_| T::from_byte(byte))
+            }
+        }
+    }
+}
 
-        log::trace!("Found value: {:?}, as char: {}", val, as_char);
+/// The cell `op` accesses for [`VMBuilder::with_mapped_cell`], as its offset from the
+/// data pointer, and whether the operation reads and writes it. [`None`] if the
+/// operation does not access any cell, or accesses more than one cell like [`Op::Scan`]
+fn mapped_access(op: Op) -> Option<(isize, bool, bool)> {
+    match op {
+        Op::AddAt(offset, _) => Some((offset, true, true)),
+        Op::Add(_)
+        | Op::ShiftRight
+        | Op::ShiftLeft
+        | Op::Not
+        | Op::Xor
+        | Op::And
+        | Op::Or
+        | Op::HostCall => Some((0, true, true)),
+        Op::Input | Op::SetZero | Op::Load | Op::Random => Some((0, false, true)),
+        Op::Output
+        | Op::JumpFwd(_)
+        | Op::JumpBack(_)
+        | Op::DefineProc(_)
+        | Op::CallProc
+        | Op::Store => Some((0, true, false)),
+        Op::Move(_) | Op::Scan(_) | Op::EndProc | Op::Fork | Op::Exit => None,
+    }
+}
 
-        write!(self.writer, "{}", as_char)?;
+/// The result of the execution of a Brainfuck program
+pub type BfResult = Result<(), BrainfuckExecutionError>;
 
-        Ok(instr_ptr + 1)
-    }
+/// Statistics about the execution of a Brainfuck program,
+/// returned by [`BrainfuckVM::run_program_with_report`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunReport {
+    /// The amount of steps executed. See [`VMBuilder::with_max_steps`]
+    pub steps: u64,
 
-    fn exec_input(&mut self, instr_ptr: usize) -> ExecResult {
-        log::trace!("Reading input into cell {}", self.data_ptr);
+    /// The amount of memory cells held by the VM at the end of the execution.
+    /// Since the tape never shrinks, this is also the largest size it reached
+    pub peak_tape_size: usize,
 
-        let mut buf = [0_u8; 1];
-        let num_read = self.reader.read(&mut buf)?;
+    /// The highest data pointer reached, relative to the cell the program started at
+    pub max_data_ptr: isize,
 
-        if num_read == 1 {
-            log::trace!("Read byte: {}", buf[0]);
+    /// The amount of bytes read from the VM reader
+    pub bytes_read: u64,
 
-            Alloc::ensure_capacity(&mut self.data, self.data_ptr + 1)?;
+    /// The amount of bytes written to the VM writer
+    pub bytes_written: u64,
 
-            let conv_buf: T = buf[0].into();
+    /// The time it took to run the program, if it could be measured.
+    /// Requires the `std` feature, and is unavailable on `wasm32-unknown-unknown`
+    pub wall_time: Option<Duration>,
+}
 
-                    log::trace!("Converted to cell type: {:?}", conv_buf);
-                    
-                    unsafe {
-                        *self.data.get_unchecked_mut(self.data_ptr) = conv_buf;
-                    }
+/// The state of a [`BrainfuckVM`] after executing a single step
+/// of a program with [`BrainfuckVM::step`]
+#[derive(Debug)]
+pub enum ExecutionState {
+    /// The program has not finished yet
+    Running,
 
-                } else {
-                    log::debug!("Attempted to read input, but no input was available");
-                }
+    /// The program has finished, or no program is loaded
+    Halted,
 
-        Ok(instr_ptr + 1)
-    }
+    /// The last step resulted in an error.
+    /// The instruction pointer is left at the failing instruction
+    Error(BrainfuckExecutionError),
+}
 
-    fn exec_jumpfwd(&mut self, instr_ptr: usize, instrs: &[Instruction]) -> ExecResult {
-        let val = self.data.get(self.data_ptr).cloned().unwrap_or_default();
+/// Whether the program run with [`BrainfuckVM::run_for_steps`] has finished
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunStatus {
+    /// The program executed all steps it was given, and has not finished yet
+    Pending,
 
-        if val != T::zero() {
-            log::trace!(
-                "Value at cell {} is not zero, not jumping forward",
-                self.data_ptr
-            );
-            return Ok(instr_ptr + 1);
-        }
+    /// The program has finished, or no program is loaded
+    Complete,
+}
 
-        log::trace!("Value at cell {} is zero, jumping forward", self.data_ptr);
+/// This trait represents an object that is able to
+/// run Brainfuck programs, either from a string
+/// of Brainfuck source code or by reading a Brainfuck source file
+///
+/// A default implementation can be constructed using [`VMBuilder`]
+pub trait BrainfuckVM {
+    /// Compiles the given Brainfuck program with the optimization level
+    /// the VM was configured with, and loads it without running it.
+    ///
+    /// Programs loaded while all cells are known to be zero, because the memory of the VM
+    /// has not been changed since it was built or reset and is not shared with other VMs
+    /// through a [`tape::Tape`] such as [`tape::shared::SharedTape`], are also optimized with
+    /// [`Pass::DeadLoops`], see [`OptimizationLevel::zeroed_tape_passes`].
+    ///
+    /// See [`BrainfuckVM::load_compiled`]
+    fn load_program(&mut self, program: &Program) -> BfResult;
 
-        let mut closing_tag = instr_ptr + 1;
-        let mut tag_stack: usize = 1;
+    /// Like [`BrainfuckVM::load_program`], but compiles the program with the passes
+    /// of the given [`Pipeline`], instead of those of the configured optimization level
+    ///
+    /// ```
+    /// use cpr_bf::{ir::{Pass, Pipeline}, BrainfuckVM};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new().with_writer(Vec::new()).build_typed();
+    /// let pipeline = Pipeline::new().with_pass(Pass::DeadLoops);
+    ///
+    /// vm.load_program_with_pipeline(&"[-.]+++.".into(), &pipeline).unwrap();
+    /// vm.resume().unwrap();
+    ///
+    /// assert_eq!(vm.run_report().steps, 4);
+    /// assert_eq!(vm.into_writer(), [3]);
+    /// ```
+    fn load_program_with_pipeline(&mut self, program: &Program, pipeline: &Pipeline) -> BfResult;
 
-        while closing_tag < instrs.len() {
-            match instrs[closing_tag] {
-                Instruction::JumpFwd => {
-                    log::trace!(
-                        "Encountered additional JumpFwd, increasing tag stack {}=>{}",
-                        tag_stack,
-                        tag_stack + 1
-                    );
-                    tag_stack += 1
-                }
-                Instruction::JumpBack => {
-                    log::trace!(
-                        "Encountered JumpBack, decreasing tag stack {}=>{}",
-                        tag_stack,
-                        tag_stack - 1
-                    );
-                    tag_stack -= 1;
-                    if tag_stack == 0 {
-                        log::trace!("Found matching JumpBack at {}", closing_tag);
-                        return Ok(closing_tag);
-                    }
-                }
-                _ => {}
+    /// Loads the given compiled Brainfuck program, replacing any previously
+    /// loaded program. The program can then be run with [`BrainfuckVM::resume`],
+    /// or one instruction at a time with [`BrainfuckVM::step`].
+    ///
+    /// Note that the VM might not be new, so the VM must take
+    /// care of resetting the data pointer and instruction pointer
+    /// back to zero when loading the program.
+    ///
+    /// Returns [`BrainfuckExecutionError::ExtensionDisabled`] if the program uses
+    /// extensions that are not enabled on the VM
+    ///
+    /// ```
+    /// use cpr_bf::{
+    ///     extensions::ExtensionSet, ir::OptimizationLevel, BrainfuckExecutionError,
+    ///     BrainfuckVM, Program, VMBuilder,
+    /// };
+    ///
+    /// let program = Program::parse_with_extensions("+}", ExtensionSet::EXTENDED_TYPE_I).unwrap();
+    /// let compiled = program.compile(OptimizationLevel::Full).unwrap();
+    /// let mut vm = VMBuilder::new().build();
+    ///
+    /// assert!(matches!(
+    ///     vm.load_compiled(&compiled),
+    ///     Err(BrainfuckExecutionError::ExtensionDisabled(ExtensionSet::EXTENDED_TYPE_I))
+    /// ));
+    /// ```
+    fn load_compiled(&mut self, program: &CompiledProgram) -> BfResult;
+
+    /// Executes a single instruction of the currently loaded program
+    fn step(&mut self) -> ExecutionState;
+
+    /// Runs the currently loaded program, starting from the current
+    /// instruction pointer, until it halts
+    fn resume(&mut self) -> BfResult {
+        loop {
+            match self.step() {
+                ExecutionState::Running => {}
+                ExecutionState::Halted => return Ok(()),
+                ExecutionState::Error(e) => return Err(e),
             }
+        }
+    }
 
-            closing_tag += 1;
+    /// Runs the currently loaded program like [`BrainfuckVM::resume`], but executes at
+    /// most `steps` instructions, so that the host can spread a long-running program over
+    /// several calls, such as the frames of a game loop. A [`VirtualMachine`] flushes its
+    /// output before returning, also if the program has not finished yet.
+    ///
+    /// Returns [`RunStatus::Pending`] if the program has not finished yet, after which the
+    /// next call continues where this one stopped. A program that is paused through
+    /// [`VMBuilder::with_pause_flag`], or that waits for input with
+    /// [`NonBlockingInput::Yield`], can also be continued after the error it stopped with
+    ///
+    /// ```
+    /// use cpr_bf::{BrainfuckVM, RunStatus, VMBuilder};
+    ///
+    /// let mut vm = VMBuilder::new().with_writer(Vec::new()).build_typed();
+    /// vm.load_program(&"++++++++[>++++++++<-]>+.".into()).unwrap();
+    ///
+    /// let mut frames = 1;
+    ///
+    /// while vm.run_for_steps(10).unwrap() == RunStatus::Pending {
+    ///     frames += 1;
+    /// }
+    ///
+    /// assert!(frames > 1);
+    /// assert_eq!(vm.writer(), b"A");
+    /// ```
+    fn run_for_steps(&mut self, steps: u64) -> Result<RunStatus, BrainfuckExecutionError> {
+        for _ in 0..steps {
+            match self.step() {
+                ExecutionState::Running => {}
+                ExecutionState::Halted => return Ok(RunStatus::Complete),
+                ExecutionState::Error(e) => return Err(e),
+            }
         }
 
-        log::error!("No matching JumpBack found for JumpFwd at {}", instr_ptr);
+        Ok(RunStatus::Pending)
+    }
 
-        Err(BrainfuckExecutionError::JumpMismatchError(
-            MissingKind::JumpBack,
-        ))
+    /// Compiles the given Brainfuck program with the optimization level
+    /// the VM was configured with, and runs it.
+    ///
+    /// See [`BrainfuckVM::run_compiled`]
+    fn run_program(&mut self, program: &Program) -> BfResult {
+        self.load_program(program)?;
+        self.resume()
     }
 
-    fn exec_jumpback(&mut self, instr_ptr: usize, instrs: &[Instruction]) -> ExecResult {
-        let val = self.data.get(self.data_ptr).cloned().unwrap_or_default();
+    /// Like [`BrainfuckVM::run_program`], but returns a [`RunReport`] with
+    /// statistics about the execution of the program.
+    ///
+    /// If the program fails, the statistics up to the failure are still
+    /// available through [`BrainfuckVM::run_report`]
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new().with_writer(std::io::sink()).build();
+    /// let report = vm
+    ///     .run_program_with_report(&"++>+++[-]<.".into())
+    ///     .unwrap();
+    ///
+    /// assert_eq!(report.max_data_ptr, 1);
+    /// assert_eq!(report.bytes_written, 1);
+    /// ```
+    fn run_program_with_report(
+        &mut self,
+        program: &Program,
+    ) -> Result<RunReport, BrainfuckExecutionError> {
+        self.load_program(program)?;
+        self.resume_with_report()
+    }
 
-        if val == T::zero() {
-            log::trace!("Value at cell {} is zero, not jumping back", self.data_ptr);
-            return Ok(instr_ptr + 1);
-        }
+    /// Like [`BrainfuckVM::resume`], but returns a [`RunReport`] with statistics
+    /// about the execution of the currently loaded program.
+    ///
+    /// See [`BrainfuckVM::run_program_with_report`]
+    fn resume_with_report(&mut self) -> Result<RunReport, BrainfuckExecutionError> {
+        #[cfg(all(
+            feature = "std",
+            not(all(target_arch = "wasm32", target_os = "unknown"))
+        ))]
+        let start = std::time::Instant::now();
 
-        if instr_ptr == 0 {
-            log::error!(
-                "Instruction pointer is already 0, no matching opening bracket can be found"
-            );
+        self.resume()?;
+
+        #[allow(unused_mut)]
+        let mut report = self.run_report();
 
-            return Err(BrainfuckExecutionError::JumpMismatchError(
-                MissingKind::JumpFwd,
-            ));
+        #[cfg(all(
+            feature = "std",
+            not(all(target_arch = "wasm32", target_os = "unknown"))
+        ))]
+        {
+            report.wall_time = Some(start.elapsed());
         }
 
-        let mut opening_tag = instr_ptr - 1;
-        let mut tag_stack: usize = 1;
+        Ok(report)
+    }
 
-        while opening_tag > 0 {
-            match instrs[opening_tag] {
-                Instruction::JumpFwd => {
-                    log::trace!(
-                        "Encountered JumpFwd, decreasing tag stack {}=>{}",
-                        tag_stack,
-                        tag_stack - 1
-                    );
-                    tag_stack -= 1;
-                    if tag_stack == 0 {
-                        log::trace!("Found matching JumpFwd at {}", opening_tag);
-                        return Ok(opening_tag);
-                    }
-                }
-                Instruction::JumpBack => {
-                    log::trace!(
-                        "Encountered additional JumpBack, increasing tag stack {}=>{}",
-                        tag_stack,
-                        tag_stack + 1
-                    );
-                    tag_stack += 1
-                }
-                _ => {}
-            }
+    /// The language extensions enabled on this VM, see [`VMBuilder::with_extensions`]
+    fn extensions(&self) -> ExtensionSet;
 
-            opening_tag -= 1;
-        }
+    /// Whether programs run from source code carry their input after a `!`,
+    /// see [`VMBuilder::with_bang_input`]
+    fn bang_input(&self) -> bool;
 
-        log::error!("No matching JumpFwd found for JumpBack at {}", instr_ptr);
+    /// Queues `input` to be read by programs before any input from the reader.
+    /// Queued input is kept when a program is loaded, until it has been read
+    fn queue_input(&mut self, input: &[u8]);
 
-        Err(BrainfuckExecutionError::JumpMismatchError(
-            MissingKind::JumpFwd,
-        ))
-    }
+    /// The index of the memory cell the data pointer currently points to,
+    /// within the cells returned by [`BrainfuckVM::cells_as_u64`]
+    fn data_ptr(&self) -> usize;
 
-    fn exec(
-        &mut self,
-        instrs: &[Instruction],
-        instr_ptr: usize,
-    ) -> Result<usize, BrainfuckExecutionError> {
-        let instr = instrs[instr_ptr];
+    /// The index of the operation of the loaded [`CompiledProgram`] that is executed next.
+    /// After the program stopped with an error, this is the operation that failed
+    ///
+    /// ```
+    /// use cpr_bf::{ir::OptimizationLevel, BrainfuckVM};
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_optimization_level(OptimizationLevel::None)
+    ///     .with_max_cells(2)
+    ///     .build();
+    ///
+    /// // Only the last '+' accesses the cell beyond the limit
+    /// assert!(vm.run_string("+>>+").is_err());
+    /// assert_eq!(vm.instr_ptr(), 3);
+    /// ```
+    fn instr_ptr(&self) -> usize;
 
-        log::debug!("Executing instruction {}: {:?}", instr_ptr, instr);
+    /// The currently allocated memory cells, converted to [`u64`] with
+    /// [`BrainfuckCell::to_u64`]. To access the cells without conversion,
+    /// use [`VirtualMachine::memory`]
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new().with_cell_type::<i8>().build();
+    ///
+    /// vm.run_string("+>-").unwrap();
+    ///
+    /// assert_eq!(vm.data_ptr(), 1);
+    /// assert_eq!(vm.cells_as_u64()[..2], [1, 255]);
+    /// ```
+    fn cells_as_u64(&self) -> Vec<u64>;
 
-        match instr {
-            Instruction::IncrDP => self.exec_incrdp(instr_ptr),
-            Instruction::DecrDP => self.exec_decrdp(instr_ptr),
-            Instruction::Incr => self.exec_incr(instr_ptr),
-            Instruction::Decr => self.exec_decr(instr_ptr),
-            Instruction::Output => self.exec_output(instr_ptr),
-            Instruction::Input => self.exec_input(instr_ptr),
-            Instruction::JumpFwd => self.exec_jumpfwd(instr_ptr, instrs),
-            Instruction::JumpBack => self.exec_jumpback(instr_ptr, instrs),
-        }
-    }
-}
+    /// Statistics about the execution of the currently loaded program so far.
+    /// The wall time is not measured, and always [`None`]
+    fn run_report(&self) -> RunReport;
 
-/// The result of the execution of a Brainfuck program
-pub type BfResult = Result<(), BrainfuckExecutionError>;
+    /// Encodes all memory cells of the VM as a tape image, see [`tape::image`]
+    fn save_tape(&self) -> Vec<u8>;
 
-/// This trait represents an object that is able to
-/// run Brainfuck programs, either from a string
-/// of Brainfuck source code or by reading a Brainfuck source file
-///
-/// A default implementation can be constructed using [`VMBuilder`]
-pub trait BrainfuckVM {
-    /// Runs the given Brainfuck program on this VM.
+    /// Loads the cells of a tape image, created with [`BrainfuckVM::save_tape`], into the
+    /// memory of the VM. The tape grows to fit the image if needed, and all cells beyond
+    /// the image are reset to zero. The data pointer is left as it is.
+    ///
+    /// Like after running a program, programs loaded afterwards with
+    /// [`BrainfuckVM::load_program`] are not optimized with [`Pass::DeadLoops`], which
+    /// assumes that the tape starts out zeroed, until the memory is reset
+    fn load_tape(&mut self, image: &[u8]) -> Result<(), TapeImageError>;
+
+    /// Encodes the state of the VM, including its memory and the currently loaded
+    /// program, in the format of the [`snapshot`] module
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Replaces the state of the VM with a state created with [`BrainfuckVM::save_state`],
+    /// or with a checkpoint of [`VMBuilder::with_checkpoints`]. Execution can then be
+    /// continued with [`BrainfuckVM::resume`]
+    fn load_state(&mut self, state: &[u8]) -> Result<(), SnapshotError>;
+
+    /// Runs the given compiled Brainfuck program on this VM.
     /// After the program has been run, the memory of the VM
     /// is *not* automatically reset back to zero. (see [`BrainfuckVM::reset_memory`])
-    ///
-    /// Note that the VM might not be new, so the VM must take
-    /// care of resetting the data pointer back to zero before
-    /// running the program
-    fn run_program(&mut self, program: &Program) -> BfResult;
+    fn run_compiled(&mut self, program: &CompiledProgram) -> BfResult {
+        self.load_compiled(program)?;
+        self.resume()
+    }
 
     /// Resets all currently allocated memory cells back to their default
     /// value, as if no program has been run on the VM before.
@@ -642,20 +4591,121 @@ pub trait BrainfuckVM {
     /// of any previous Brainfuck programs.
     fn reset_memory(&mut self);
 
-    /// Compiles and runs the given string of Brainfuck source code.
+    /// Resets the VM to the state it was built in, so that it can be reused for an
+    /// unrelated program. All cells are reset like [`BrainfuckVM::reset_memory`], the
+    /// data pointer is moved back to the cell programs start at, and the instruction
+    /// pointer back to the start of the loaded program. Queued input is discarded.
+    ///
+    /// If `shrink_tape` is set, the tape is also shrunk back to the cells preallocated
+    /// with [`VMBuilder::with_preallocated_cells`], freeing the memory of all cells
+    /// allocated since. Otherwise the tape keeps its size, so that it does not have
+    /// to grow again for the next program
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_preallocated_cells(4)
+    ///     .build();
+    ///
+    /// vm.run_string("+>>>>>>>>+").unwrap();
+    /// assert_eq!(vm.cells_as_u64().len(), 9);
+    ///
+    /// vm.reset_all(false);
+    /// assert_eq!(vm.data_ptr(), 0);
+    /// assert_eq!(vm.cells_as_u64(), [0; 9]);
+    ///
+    /// vm.reset_all(true);
+    /// assert_eq!(vm.cells_as_u64(), [0; 4]);
+    /// ```
+    fn reset_all(&mut self, shrink_tape: bool);
+
+    /// Compiles and runs the given string of Brainfuck source code, including the
+    /// instructions of the extensions enabled on this VM.
+    /// Mismatched jump instructions are reported before the program is run.
+    /// With [`VMBuilder::with_bang_input`], the input after the first `!` is queued first.
+    ///
     /// See [`BrainfuckVM::run_program`]
     fn run_string(&mut self, bf_str: &str) -> BfResult {
         log::info!("Running string of {} bytes", bf_str.len());
 
-        let program: Program = bf_str.into();
+        let bf_str = match bf_str.split_once('!').filter(|_| self.bang_input()) {
+            Some((code, input)) => {
+                self.queue_input(input.as_bytes());
+                code
+            }
+            None => bf_str,
+        };
+
+        let program = Program::parse_with_extensions(bf_str, self.extensions())?;
 
         self.run_program(&program)
     }
 
-    /// Reads the given file into a string, and
-    /// runs the string on this VM.
+    /// Compiles and runs the given string of Brainfuck source code like
+    /// [`BrainfuckVM::run_string`], with `input` as its only input. The reader and
+    /// writer of the VM are not used: the program reaches the end of its input after
+    /// `input`, and everything it writes is returned instead.
+    ///
+    /// Input that was already queued is kept for the next program. If the program fails,
+    /// its output up to the failure is discarded
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_eof_behavior(cpr_bf::EofBehavior::Zero)
+    ///     .build();
+    ///
+    /// let output = vm.run_string_with_input(",[+.,]", b"abc").unwrap();
+    ///
+    /// assert_eq!(output, b"bcd");
+    /// ```
+    fn run_string_with_input(
+        &mut self,
+        bf_str: &str,
+        input: &[u8],
+    ) -> Result<Vec<u8>, BrainfuckExecutionError>;
+
+    /// Like [`BrainfuckVM::run_program`], but uses `reader` and `writer` for the
+    /// input and output of this run only, instead of the reader and writer of the VM.
+    /// All output of the program is flushed to `writer` before this returns, even if
+    /// the program fails.
+    ///
+    /// Everything else is kept, so a single VM can serve many runs with different
+    /// I/O without being rebuilt, keeping its allocated tape and its configuration.
+    /// The memory is not reset between runs, see [`BrainfuckVM::reset_memory`]
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    /// use std::io::Cursor;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new()
+    ///     .with_eof_behavior(cpr_bf::EofBehavior::Zero)
+    ///     .build();
+    ///
+    /// let program = ",[+.,]".into();
+    ///
+    /// for request in [&b"abc"[..], b"xyz"] {
+    ///     let mut response = Vec::new();
+    ///
+    ///     vm.run_program_with_io(&program, &mut Cursor::new(request), &mut response)
+    ///         .unwrap();
+    ///
+    ///     assert_eq!(response, request.iter().map(|b| b + 1).collect::<Vec<_>>());
+    /// }
+    /// ```
+    fn run_program_with_io(
+        &mut self,
+        program: &Program,
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> BfResult;
+
+    /// Parses the given file, and runs it on this VM.
     ///
-    /// See [`BrainfuckVM::run_string`]
+    /// See [`BrainfuckVM::run_reader`]
+    #[cfg(feature = "std")]
     fn run_file(&mut self, file: &mut File) -> BfResult {
         log::info!(
             "Running file of size {}",
@@ -665,18 +4715,61 @@ pub trait BrainfuckVM {
                 .unwrap_or("{unknown size}".to_owned())
         );
 
-        let mut program_str = String::new();
-        file.read_to_string(&mut program_str)?;
+        self.run_reader(file)
+    }
+
+    /// Parses Brainfuck source code from the given reader with
+    /// [`Program::parse_reader_with_extensions`], using the extensions enabled
+    /// on this VM, and runs it. This allows running programs from any source,
+    /// such as sockets or compressed streams.
+    ///
+    /// With [`VMBuilder::with_bang_input`], the whole reader is read before parsing,
+    /// and the input after the first `!` is queued first.
+    ///
+    /// See [`BrainfuckVM::run_program`]
+    ///
+    /// ```
+    /// use cpr_bf::BrainfuckVM;
+    ///
+    /// let mut vm = cpr_bf::VMBuilder::new().with_writer(Vec::new()).build_typed();
+    /// let mut source: &[u8] = b"++++++++[>++++++++<-]>+.";
+    ///
+    /// vm.run_reader(&mut source).unwrap();
+    ///
+    /// assert_eq!(vm.writer(), b"A");
+    /// ```
+    #[cfg(feature = "std")]
+    fn run_reader(&mut self, reader: &mut dyn Read) -> BfResult {
+        if self.bang_input() {
+            let mut source = Vec::new();
+            reader.read_to_end(&mut source)?;
+
+            let code = match source.iter().position(|&byte| byte == b'!') {
+                Some(separator) => {
+                    self.queue_input(&source[separator + 1..]);
+                    &source[..separator]
+                }
+                None => &source[..],
+            };
+
+            let program = Program::parse_reader_with_extensions(code, self.extensions())?;
 
-        self.run_string(&program_str)
+            return self.run_program(&program);
+        }
+
+        let program =
+            Program::parse_reader_with_extensions(BufReader::new(reader), self.extensions())?;
+
+        self.run_program(&program)
     }
 
     /// Opens the file pointed to by the given path,
     /// and attempts to run its contents on this VM.
     ///
     /// See [`BrainfuckVM::run_file`]
+    #[cfg(feature = "std")]
     fn run_from_path(&mut self, path: &Path) -> BfResult {
-        log::info!("Running program at path {:?}", path);
+        log::info!("Running program at path {}", path.display());
 
         let mut file = File::open(path)?;
 
@@ -684,33 +4777,226 @@ pub trait BrainfuckVM {
     }
 }
 
-impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> BrainfuckVM
-    for VirtualMachine<T, A, R, W>
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write, D: Tape<T> + Default> BrainfuckVM
+    for VirtualMachine<T, A, R, W, D>
 {
     fn reset_memory(&mut self) {
         log::info!("Resetting VM memory cells");
 
-        self.data.iter_mut().for_each(|cell| *cell = T::default());
+        self.data.clear_cells();
+        self.dirty_tape = false;
     }
 
-    fn run_program(&mut self, program: &Program) -> Result<(), BrainfuckExecutionError> {
-        log::info!("Running program");
+    fn load_program(&mut self, program: &Program) -> BfResult {
+        let mut pipeline = Pipeline::new();
 
-        if program.instructions.is_empty() {
-            log::info!("Program empty, returning");
-            return Ok(());
+        let level = self.config.optimization_level;
+        let wrapping = self.config.overflow_policy == OverflowPolicy::Wrapping;
+
+        // Mapped cells, cells changed since the last reset, and cells of tapes that are
+        // shared with other VMs, are not zero before the program changes them
+        let passes =
+            if self.mapped_cells.is_empty() && !self.dirty_tape && !self.data.may_be_nonzero() {
+                level.zeroed_tape_passes()
+            } else {
+                level.passes()
+            };
+
+        // Clear loops only always terminate if the cells wrap around
+        for &pass in passes {
+            if pass != Pass::ClearLoops || wrapping {
+                pipeline = pipeline.with_pass(pass);
+            }
+        }
+
+        self.load_program_with_pipeline(program, &pipeline)
+    }
+
+    fn load_program_with_pipeline(&mut self, program: &Program, pipeline: &Pipeline) -> BfResult {
+        let disabled = program.extensions().difference(self.config.extensions);
+
+        if !disabled.is_empty() {
+            log::error!("Program uses disabled extensions: {}", disabled);
+            return Err(BrainfuckExecutionError::ExtensionDisabled(disabled));
+        }
+
+        let compiled = pipeline.compile(program).map_err(|e| match e {
+            ParseError::JumpMismatch(unmatched) => {
+                BrainfuckExecutionError::JumpMismatchError(unmatched.missing)
+            }
+            e => BrainfuckExecutionError::ParseError(e),
+        })?;
+
+        if self.config.auto_preallocate {
+            self.preallocate_for(program);
+        }
+
+        self.load_compiled(&compiled)
+    }
+
+    fn load_compiled(&mut self, program: &CompiledProgram) -> BfResult {
+        log::info!("Loading program");
+
+        let disabled = program.extensions().difference(self.config.extensions);
+
+        if !disabled.is_empty() {
+            log::error!("Program uses disabled extensions: {}", disabled);
+            return Err(BrainfuckExecutionError::ExtensionDisabled(disabled));
+        }
+
+        self.program = Some(program.clone());
+        self.dirty_tape = true;
+        self.reset_execution();
+
+        Ok(())
+    }
+
+    fn reset_all(&mut self, shrink_tape: bool) {
+        log::info!("Resetting VM");
+
+        if shrink_tape {
+            self.data.truncate(0);
+
+            if let Err(e) = self.data.ensure(self.config.initial_size) {
+                log::error!(
+                    "Could not preallocate {} cells: {}",
+                    self.config.initial_size,
+                    e
+                );
+            }
+
+            self.origin = 0;
+            self.dirty_tape = false;
+        } else {
+            self.reset_memory();
         }
 
-        self.data_ptr = 0;
-        let mut instr_ptr = 0;
+        self.queued_input.clear();
+        self.unread_byte = None;
+        self.reset_execution();
+    }
+
+    fn data_ptr(&self) -> usize {
+        self.data_ptr
+    }
+
+    fn instr_ptr(&self) -> usize {
+        self.instr_ptr
+    }
+
+    fn extensions(&self) -> ExtensionSet {
+        self.config.extensions
+    }
+
+    fn bang_input(&self) -> bool {
+        self.config.bang_input
+    }
+
+    fn queue_input(&mut self, input: &[u8]) {
+        self.queued_input.extend(input);
+    }
+
+    fn run_string_with_input(
+        &mut self,
+        bf_str: &str,
+        input: &[u8],
+    ) -> Result<Vec<u8>, BrainfuckExecutionError> {
+        // Flushed before the input is swapped, so that a failed flush leaves it untouched
+        self.flush_output()?;
+
+        let queued = mem::replace(&mut self.queued_input, input.iter().copied().collect());
+        let unread = self.unread_byte.take();
+
+        self.captured_output = Some(Vec::new());
 
-        while instr_ptr < program.instructions.len() {
-            instr_ptr = self.exec(&program.instructions, instr_ptr)?;
+        let result = self.run_string(bf_str);
+        let output = self.captured_output.take().unwrap_or_default();
+
+        self.queued_input = queued;
+        self.unread_byte = unread;
+
+        result.map(|()| output)
+    }
+
+    fn run_program_with_io(
+        &mut self,
+        program: &Program,
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> BfResult {
+        self.with_io(reader, writer, |vm| {
+            let result = vm.run_program(program);
+            let flushed = vm.flush_output();
+
+            result.and(flushed.map_err(BrainfuckExecutionError::from))
+        })?
+    }
+
+    fn cells_as_u64(&self) -> Vec<u64> {
+        (0..self.data.len())
+            .map(|index| self.data.get(index).unwrap_or_default().to_u64())
+            .collect()
+    }
+
+    fn save_tape(&self) -> Vec<u8> {
+        tape::image::encode(&self.data)
+    }
+
+    fn load_tape(&mut self, image: &[u8]) -> Result<(), TapeImageError> {
+        let cells = tape::image::decode(image)?;
+
+        self.ensure_capacity(cells.len())?;
+        self.data.clear_cells();
+
+        for (index, value) in cells.into_iter().enumerate() {
+            self.data.set(index, value);
         }
 
-        log::debug!("Flushing writer");
-        self.writer.flush()?;
+        self.dirty_tape = true;
+
+        Ok(())
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.state().to_bytes()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), SnapshotError> {
+        self.set_state(VMState::from_bytes(state)?)?;
 
         Ok(())
     }
+
+    fn run_report(&self) -> RunReport {
+        RunReport {
+            steps: self.steps,
+            peak_tape_size: self.data.len(),
+            max_data_ptr: self.stats.max_data_ptr,
+            bytes_read: self.stats.bytes_read,
+            bytes_written: self.stats.bytes_written,
+            wall_time: None,
+        }
+    }
+
+    fn step(&mut self) -> ExecutionState {
+        match self.execute_step() {
+            ExecutionState::Running => ExecutionState::Running,
+            state => self.stop(state),
+        }
+    }
+
+    fn resume(&mut self) -> BfResult {
+        match self.run_until(u64::MAX) {
+            ExecutionState::Error(e) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn run_for_steps(&mut self, steps: u64) -> Result<RunStatus, BrainfuckExecutionError> {
+        match self.run_until(self.steps.saturating_add(steps)) {
+            ExecutionState::Running => Ok(RunStatus::Pending),
+            ExecutionState::Halted => Ok(RunStatus::Complete),
+            ExecutionState::Error(e) => Err(e),
+        }
+    }
 }
@@ -0,0 +1,98 @@
+//! Contains metrics about the structure of Brainfuck programs
+//!
+//! [`Program::metrics`] counts the instructions and loops of a program in a single pass,
+//! for tools that report on programs without running them.
+//!
+//! ```
+//! use cpr_bf::{Instruction, Program};
+//!
+//! let metrics = Program::from("+[>+[-]<-]").metrics();
+//!
+//! assert_eq!(metrics.instructions, 10);
+//! assert_eq!(metrics.loops, 2);
+//! assert_eq!(metrics.max_loop_depth, 2);
+//! assert_eq!(
+//!     metrics.instruction_counts,
+//!     [
+//!         (Instruction::IncrDP, 1),
+//!         (Instruction::DecrDP, 1),
+//!         (Instruction::Incr, 2),
+//!         (Instruction::Decr, 2),
+//!         (Instruction::JumpFwd, 2),
+//!         (Instruction::JumpBack, 2),
+//!     ]
+//! );
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::{Instruction, Program, INSTRUCTION_CHARS};
+
+/// Metrics about a [`Program`], returned by [`Program::metrics`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramMetrics {
+    /// How often every instruction occurs in the program. Only contains the
+    /// instructions that occur at least once, in the order of [`Instruction`]
+    pub instruction_counts: Vec<(Instruction, usize)>,
+
+    /// The total amount of instructions
+    pub instructions: usize,
+
+    /// The amount of loops, which is the amount of [`Instruction::JumpFwd`]s
+    pub loops: usize,
+
+    /// The deepest nesting of loops. Zero if the program has no loops
+    pub max_loop_depth: usize,
+
+    /// The length in bytes of the source code of the program, see [`Program::source_len`]
+    pub source_len: Option<usize>,
+}
+
+impl ProgramMetrics {
+    /// How often `instr` occurs in the program
+    pub fn count(&self, instr: Instruction) -> usize {
+        self.instruction_counts
+            .iter()
+            .find(|&&(counted, _)| counted == instr)
+            .map_or(0, |&(_, count)| count)
+    }
+}
+
+/// Collects the metrics of `program`, see [`Program::metrics`]
+pub(crate) fn metrics(program: &Program) -> ProgramMetrics {
+    let mut counts = [0; INSTRUCTION_CHARS.len()];
+    let mut loops = 0;
+    let mut loop_depth: usize = 0;
+    let mut max_loop_depth = 0;
+
+    for &instr in program.instructions() {
+        if let Some(index) = INSTRUCTION_CHARS.iter().position(|&(i, _)| i == instr) {
+            counts[index] += 1;
+        }
+
+        match instr {
+            Instruction::JumpFwd => {
+                loops += 1;
+                loop_depth += 1;
+                max_loop_depth = max_loop_depth.max(loop_depth);
+            }
+            Instruction::JumpBack => loop_depth = loop_depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    let instruction_counts: Vec<_> = INSTRUCTION_CHARS
+        .iter()
+        .zip(counts)
+        .filter(|&(_, count)| count > 0)
+        .map(|(&(instr, _), count)| (instr, count))
+        .collect();
+
+    ProgramMetrics {
+        instruction_counts,
+        instructions: program.instructions().len(),
+        loops,
+        max_loop_depth,
+        source_len: program.source_len(),
+    }
+}
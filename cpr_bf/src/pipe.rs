@@ -0,0 +1,361 @@
+//! Contains a pipeline that connects the output of Brainfuck programs to the input of others
+//!
+//! A [`VMPipeline`] runs a chain of programs like a Unix pipeline: everything a stage
+//! writes with `.` is read by the next stage with `,`. The first stage reads from the
+//! reader of the pipeline, and the output of the last stage is written to its writer.
+//!
+//! The stages are connected by bounded buffers, and run cooperatively on the current
+//! thread. A stage that would write to a full buffer waits until the next stage has
+//! read from it, and a stage that would read from an empty buffer waits until the
+//! previous stage has written to it. Once a stage stops, the next stage reaches the
+//! end of its input after reading everything that is left in the buffer, and the
+//! previous stage fails with an I/O error as soon as it writes any more output.
+//!
+//! ```
+//! use cpr_bf::{pipe::VMPipeline, EofBehavior, ExecutionState, VMBuilder};
+//! use std::io::Cursor;
+//!
+//! let mut pipeline = VMPipeline::new(Cursor::new(b"HAL".to_vec()), Vec::new());
+//!
+//! // Both stages increment every byte of their input
+//! for _ in 0..2 {
+//!     let builder = VMBuilder::new().with_eof_behavior(EofBehavior::Zero);
+//!     pipeline.add_stage(builder, &",[+.,]".into()).unwrap();
+//! }
+//!
+//! pipeline.run();
+//!
+//! assert!(matches!(pipeline.state(1), Some(ExecutionState::Halted)));
+//! assert_eq!(pipeline.writer(), b"JCN");
+//! ```
+
+use alloc::{collections::VecDeque, rc::Rc, vec, vec::Vec};
+use core::cell::RefCell;
+
+use crate::{
+    io::{self, Read, Write},
+    ir::Op,
+    BrainfuckAllocator, BrainfuckCell, BrainfuckExecutionError, BrainfuckVM, ExecutionState,
+    FlushPolicy, Program, VMBuilder, VirtualMachine,
+};
+
+/// The buffer between two stages of a [`VMPipeline`]
+#[derive(Debug, Default)]
+struct Pipe {
+    buffer: VecDeque<u8>,
+
+    /// Whether the writing side stopped, so no more data will be written
+    closed: bool,
+
+    /// Whether the reading side stopped, so writing fails
+    broken: bool,
+}
+
+/// The reading end of a buffer between two stages of a [`VMPipeline`]. Reads
+/// whatever is currently buffered, and returns 0 bytes once the buffer is empty
+#[derive(Debug)]
+pub struct PipeReader(Rc<RefCell<Pipe>>);
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let mut pipe = self.0.borrow_mut();
+        let len = buf.len().min(pipe.buffer.len());
+
+        for (dest, byte) in buf.iter_mut().zip(pipe.buffer.drain(..len)) {
+            *dest = byte;
+        }
+
+        Ok(len)
+    }
+}
+
+/// The writing end of a buffer between two stages of a [`VMPipeline`]. Fails to
+/// write once the next stage has stopped
+#[derive(Debug)]
+pub struct PipeWriter(Rc<RefCell<Pipe>>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        let mut pipe = self.0.borrow_mut();
+
+        if pipe.broken {
+            return Err(broken_pipe());
+        }
+
+        pipe.buffer.extend(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+/// The VM running a stage of a [`VMPipeline`]
+pub type StageVM<T, A> = VirtualMachine<T, A, PipeReader, PipeWriter>;
+
+/// A stage of a [`VMPipeline`], with the state it stopped in
+struct Stage<T: BrainfuckCell, A: BrainfuckAllocator> {
+    vm: StageVM<T, A>,
+    state: ExecutionState,
+}
+
+/// A chain of VMs, where the output of every VM is the input of the next.
+/// See the [module documentation](self)
+pub struct VMPipeline<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> {
+    stages: Vec<Stage<T, A>>,
+    reader: R,
+    writer: W,
+    input: Rc<RefCell<Pipe>>,
+    output: Rc<RefCell<Pipe>>,
+    capacity: usize,
+    steps_per_turn: u64,
+}
+
+impl<T: BrainfuckCell + 'static, A: BrainfuckAllocator + 'static, R: Read, W: Write>
+    VMPipeline<T, A, R, W>
+{
+    /// Creates a pipeline without any stages, reading from `reader` and writing to `writer`.
+    ///
+    /// Every buffer holds up to 4096 bytes, and every stage executes up to
+    /// 10000 steps before the next stage gets its turn
+    pub fn new(reader: R, writer: W) -> Self {
+        let output = Rc::new(RefCell::new(Pipe::default()));
+
+        VMPipeline {
+            stages: Vec::new(),
+            reader,
+            writer,
+            input: output.clone(),
+            output,
+            capacity: 4096,
+            steps_per_turn: 10_000,
+        }
+    }
+
+    /// Changes the amount of bytes every buffer between two stages holds.
+    /// A single output operation can exceed this amount, if it writes more than
+    /// one byte while the buffer is nearly full
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        VMPipeline {
+            capacity: capacity.max(1),
+            ..self
+        }
+    }
+
+    /// Changes the amount of steps every stage executes before the next stage
+    /// gets its turn. At least one step is always executed
+    pub fn with_steps_per_turn(self, steps_per_turn: u64) -> Self {
+        VMPipeline {
+            steps_per_turn: steps_per_turn.max(1),
+            ..self
+        }
+    }
+
+    /// Adds a stage running `program` to the end of the pipeline, and returns its index.
+    ///
+    /// The VM of the stage is built from `builder`, replacing its reader and writer with
+    /// the buffers connecting it to the previous and next stages. Its output is flushed
+    /// after every output operation, regardless of its flush policy
+    pub fn add_stage<U: Read + 'static, V: Write + 'static>(
+        &mut self,
+        builder: VMBuilder<T, A, U, V>,
+        program: &Program,
+    ) -> Result<usize, BrainfuckExecutionError> {
+        let input = self.output.clone();
+        let output = Rc::new(RefCell::new(Pipe::default()));
+
+        let mut vm = builder
+            .with_reader(PipeReader(input))
+            .with_writer(PipeWriter(output.clone()))
+            .with_flush_policy(FlushPolicy::EveryOutput)
+            .build_typed();
+
+        vm.load_program(program)?;
+
+        self.output = output;
+        self.stages.push(Stage {
+            vm,
+            state: ExecutionState::Running,
+        });
+
+        Ok(self.stages.len() - 1)
+    }
+
+    /// Gives every running stage one turn, from the first stage to the last.
+    /// Returns the amount of stages that are still running afterwards.
+    ///
+    /// A stage ends its turn early once it has to wait for its input or for
+    /// room in its output buffer. Failing to read from the reader of the pipeline
+    /// fails the first stage, and failing to write to its writer fails the last stage
+    pub fn run_round(&mut self) -> usize {
+        for index in 0..self.stages.len() {
+            if matches!(self.stages[index].state, ExecutionState::Running) {
+                self.run_turn(index);
+            }
+
+            if !matches!(self.stages[index].state, ExecutionState::Running) {
+                self.stop_stage(index);
+            }
+        }
+
+        self.running()
+    }
+
+    /// Runs rounds until every stage has stopped
+    pub fn run(&mut self) {
+        log::info!("Running pipeline of {} stages", self.stages.len());
+
+        while self.run_round() > 0 {}
+    }
+
+    /// The state the stage with the given index is in. [`ExecutionState::Running`]
+    /// until its program stops
+    pub fn state(&self, index: usize) -> Option<&ExecutionState> {
+        self.stages.get(index).map(|stage| &stage.state)
+    }
+
+    /// The VM of the stage with the given index
+    pub fn vm(&self, index: usize) -> Option<&StageVM<T, A>> {
+        self.stages.get(index).map(|stage| &stage.vm)
+    }
+
+    /// The amount of stages whose programs are still running
+    pub fn running(&self) -> usize {
+        self.stages
+            .iter()
+            .filter(|stage| matches!(stage.state, ExecutionState::Running))
+            .count()
+    }
+
+    /// The writer the output of the last stage is written to
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// Consumes the pipeline, returning its writer
+    pub fn into_writer(self) -> W {
+        self.writer
+    }
+
+    /// Runs the stage with the given index for up to one turn
+    fn run_turn(&mut self, index: usize) {
+        for _ in 0..self.steps_per_turn {
+            if index == 0 {
+                if let Err(e) = self.fill_input() {
+                    self.stages[0].state = ExecutionState::Error(e.into());
+                    return;
+                }
+            }
+
+            let stage = &mut self.stages[index];
+
+            if waits_for_output(stage, self.capacity) || waits_for_input(stage) {
+                break;
+            }
+
+            match stage.vm.step() {
+                ExecutionState::Running => {}
+                ExecutionState::Error(BrainfuckExecutionError::Paused) => break,
+                state => {
+                    stage.state = state;
+                    break;
+                }
+            }
+        }
+
+        if index == self.stages.len() - 1 {
+            if let Err(e) = self.drain_output() {
+                self.stages[index].state = ExecutionState::Error(e.into());
+            }
+        }
+    }
+
+    /// Closes the buffers of a stage that stopped, so that the next stage reaches
+    /// the end of its input, and the previous stage fails to write
+    fn stop_stage(&mut self, index: usize) {
+        let stage = &self.stages[index];
+
+        stage.vm.writer.0.borrow_mut().closed = true;
+
+        let mut input = stage.vm.reader.0.borrow_mut();
+        input.broken = true;
+        input.buffer.clear();
+    }
+
+    /// Reads from the reader of the pipeline into the input buffer of the first stage,
+    /// if the stage is about to read from it while it is empty
+    fn fill_input(&mut self) -> Result<(), io::Error> {
+        let mut input = self.input.borrow_mut();
+
+        if input.closed || !input.buffer.is_empty() || !next_op_is(&self.stages[0], Op::Input) {
+            return Ok(());
+        }
+
+        let mut buf = vec![0_u8; self.capacity];
+        let len = self.reader.read(&mut buf)?;
+
+        input.buffer.extend(&buf[..len]);
+        input.closed = len == 0;
+
+        Ok(())
+    }
+
+    /// Writes everything the last stage wrote to the writer of the pipeline
+    fn drain_output(&mut self) -> Result<(), io::Error> {
+        let mut output = self.output.borrow_mut();
+
+        if output.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let (front, back) = output.buffer.as_slices();
+
+        self.writer.write_all(front)?;
+        self.writer.write_all(back)?;
+        self.writer.flush()?;
+
+        output.buffer.clear();
+
+        Ok(())
+    }
+}
+
+/// The error returned when writing to a buffer whose reading stage has stopped
+fn broken_pipe() -> io::Error {
+    #[cfg(feature = "std")]
+    return io::Error::from(std::io::ErrorKind::BrokenPipe);
+
+    #[cfg(not(feature = "std"))]
+    return io::Error::new("broken pipe");
+}
+
+/// Whether the next operation of the stage is of the same kind as `op`
+fn next_op_is<T: BrainfuckCell, A: BrainfuckAllocator>(stage: &Stage<T, A>, op: Op) -> bool {
+    stage
+        .vm
+        .program
+        .as_ref()
+        .and_then(|program| program.ops().get(stage.vm.instr_ptr))
+        .is_some_and(|next| core::mem::discriminant(next) == core::mem::discriminant(&op))
+}
+
+/// Whether the stage is about to write while its output buffer is full
+fn waits_for_output<T: BrainfuckCell, A: BrainfuckAllocator>(
+    stage: &Stage<T, A>,
+    capacity: usize,
+) -> bool {
+    next_op_is(stage, Op::Output) && stage.vm.writer.0.borrow().buffer.len() >= capacity
+}
+
+/// Whether the stage is about to read while no input is available yet
+fn waits_for_input<T: BrainfuckCell, A: BrainfuckAllocator>(stage: &Stage<T, A>) -> bool {
+    let input = stage.vm.reader.0.borrow();
+
+    next_op_is(stage, Op::Input)
+        && input.buffer.is_empty()
+        && !input.closed
+        && stage.vm.unread_byte.is_none()
+        && stage.vm.queued_input.is_empty()
+}
@@ -0,0 +1,174 @@
+//! Contains a round-robin scheduler for running many Brainfuck programs at once
+//!
+//! The [`VMPool`] owns several VMs, each with its own program, reader and writer,
+//! and interleaves their execution on the current thread. Every round, each
+//! running VM executes up to a fixed amount of steps before the next VM gets its
+//! turn, so that long-running programs cannot starve the others.
+//!
+//! ```
+//! use cpr_bf::{pool::VMPool, ExecutionState, VMBuilder};
+//!
+//! let mut pool = VMPool::new(3);
+//!
+//! let fast = pool
+//!     .add_program(
+//!         Box::new(VMBuilder::new().with_writer(Vec::new()).build_typed()),
+//!         &"+++.".into(),
+//!     )
+//!     .unwrap();
+//!
+//! let slow = pool
+//!     .add_program(
+//!         Box::new(VMBuilder::new().with_writer(Vec::new()).build_typed()),
+//!         &"++++++++[>++++++++<-]>+.".into(),
+//!     )
+//!     .unwrap();
+//!
+//! // Both programs have run for at most 3 steps
+//! assert_eq!(pool.run_round(), 1);
+//! assert!(matches!(pool.state(fast), Some(ExecutionState::Halted)));
+//!
+//! pool.run();
+//!
+//! assert_eq!(pool.vm(fast).unwrap().writer(), &[3]);
+//! assert_eq!(pool.vm(slow).unwrap().writer(), b"A");
+//! ```
+//!
+//! Note that a VM that reads from a blocking reader blocks the whole pool
+//! until input is available. Input can instead be provided between rounds
+//! with [`BrainfuckVM::queue_input`], or the VM can read from a non-blocking
+//! reader with [`crate::NonBlockingInput::Yield`].
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{BrainfuckExecutionError, BrainfuckVM, ExecutionState, Program, RunStatus};
+
+/// A VM of a [`VMPool`], with the state it stopped in
+struct PoolEntry<V: BrainfuckVM + ?Sized> {
+    vm: Box<V>,
+    state: ExecutionState,
+}
+
+/// A round-robin scheduler for VMs, see the [module documentation](self)
+///
+/// Every VM added to the pool is identified by the index returned when it was added.
+/// By default, the pool holds boxed [`BrainfuckVM`] trait objects, as returned by
+/// [`crate::VMBuilder::build`]. It can also hold VMs of a single concrete type,
+/// such as [`crate::VirtualMachine`], to keep access to their readers and writers
+pub struct VMPool<V: BrainfuckVM + ?Sized = dyn BrainfuckVM> {
+    entries: Vec<Option<PoolEntry<V>>>,
+    steps_per_turn: u64,
+}
+
+impl<V: BrainfuckVM + ?Sized> VMPool<V> {
+    /// Creates an empty pool, in which every VM executes up to `steps_per_turn`
+    /// steps before the next VM gets its turn. At least one step is always executed
+    pub fn new(steps_per_turn: u64) -> Self {
+        VMPool {
+            entries: Vec::new(),
+            steps_per_turn: steps_per_turn.max(1),
+        }
+    }
+
+    /// Adds a VM to the pool, and returns its index. The VM should already have a
+    /// program loaded with [`BrainfuckVM::load_program`], which runs from its current
+    /// instruction pointer once the VM gets its turn
+    pub fn add(&mut self, vm: Box<V>) -> usize {
+        self.entries.push(Some(PoolEntry {
+            vm,
+            state: ExecutionState::Running,
+        }));
+
+        self.entries.len() - 1
+    }
+
+    /// Loads `program` into `vm` and adds it to the pool, see [`VMPool::add`]
+    pub fn add_program(
+        &mut self,
+        mut vm: Box<V>,
+        program: &Program,
+    ) -> Result<usize, BrainfuckExecutionError> {
+        vm.load_program(program)?;
+
+        Ok(self.add(vm))
+    }
+
+    /// Removes the VM with the given index from the pool, and returns it.
+    /// The indices of the other VMs do not change
+    pub fn remove(&mut self, index: usize) -> Option<Box<V>> {
+        self.entries.get_mut(index)?.take().map(|entry| entry.vm)
+    }
+
+    /// Gives every running VM one turn, in the order they were added.
+    /// Returns the amount of VMs that are still running afterwards.
+    ///
+    /// A VM stops running once its program halts or fails. A VM that is paused
+    /// through [`crate::VMBuilder::with_pause_flag`], or that waits for input with
+    /// [`crate::NonBlockingInput::Yield`], ends its turn early, but keeps running
+    /// in the next round
+    pub fn run_round(&mut self) -> usize {
+        for entry in self.entries.iter_mut().flatten() {
+            if !matches!(entry.state, ExecutionState::Running) {
+                continue;
+            }
+
+            match entry.vm.run_for_steps(self.steps_per_turn) {
+                Ok(RunStatus::Pending)
+                | Err(BrainfuckExecutionError::Paused | BrainfuckExecutionError::InputPending) => {}
+                Ok(RunStatus::Complete) => entry.state = ExecutionState::Halted,
+                Err(e) => entry.state = ExecutionState::Error(e),
+            }
+        }
+
+        self.running()
+    }
+
+    /// Runs rounds until every VM has stopped
+    pub fn run(&mut self) {
+        log::info!("Running pool of {} VMs", self.len());
+
+        while self.run_round() > 0 {}
+    }
+
+    /// The state the VM with the given index is in. [`ExecutionState::Running`]
+    /// until its program stops
+    pub fn state(&self, index: usize) -> Option<&ExecutionState> {
+        self.entry(index).map(|entry| &entry.state)
+    }
+
+    /// The VM with the given index
+    pub fn vm(&self, index: usize) -> Option<&V> {
+        self.entry(index).map(|entry| &*entry.vm)
+    }
+
+    /// Mutable access to the VM with the given index
+    pub fn vm_mut(&mut self, index: usize) -> Option<&mut V> {
+        self.entries
+            .get_mut(index)?
+            .as_mut()
+            .map(|entry| &mut *entry.vm)
+    }
+
+    /// The amount of VMs in the pool
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    /// Whether the pool holds no VMs
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The amount of VMs whose programs are still running
+    pub fn running(&self) -> usize {
+        self.entries
+            .iter()
+            .flatten()
+            .filter(|entry| matches!(entry.state, ExecutionState::Running))
+            .count()
+    }
+
+    fn entry(&self, index: usize) -> Option<&PoolEntry<V>> {
+        self.entries.get(index)?.as_ref()
+    }
+}
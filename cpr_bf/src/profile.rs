@@ -0,0 +1,184 @@
+//! Contains an execution profiler for the Brainfuck Virtual Machine
+//!
+//! The [`Profiler`] wraps a [`VirtualMachine`], and records how often every
+//! instruction of a program is executed. From these counts, the resulting
+//! [`Profile`] derives how many iterations every loop ran, and how much of
+//! the total execution time was spent inside it.
+//!
+//! ```
+//! use cpr_bf::{profile::Profiler, Program, VMBuilder};
+//!
+//! let program = Program::parse("+++[>++[-]<-]").unwrap();
+//! let mut profiler = Profiler::new(VMBuilder::new().build_typed());
+//!
+//! profiler.run_program(&program).unwrap();
+//!
+//! let hottest = profiler.profile().hottest_loops()[0];
+//!
+//! assert_eq!((hottest.start, hottest.end), (3, 12));
+//! assert_eq!(hottest.iterations, 3);
+//! ```
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    io::{Read, Write},
+    ir::OptimizationLevel,
+    BfResult, BrainfuckAllocator, BrainfuckCell, BrainfuckVM, ExecutionState, Instruction, Program,
+    VirtualMachine,
+};
+
+/// The execution counts of a single loop of a [`Profile`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoopProfile {
+    /// The instruction index of the opening bracket of the loop
+    pub start: usize,
+
+    /// The instruction index of the closing bracket of the loop
+    pub end: usize,
+
+    /// The amount of times the loop was reached
+    pub entries: u64,
+
+    /// The amount of times the body of the loop was run
+    pub iterations: u64,
+
+    /// The amount of instructions executed inside the loop,
+    /// including its brackets and any nested loops
+    pub instructions_executed: u64,
+}
+
+/// The execution counts recorded by a [`Profiler`]
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    instruction_counts: Vec<u64>,
+    loops: Vec<LoopProfile>,
+}
+
+impl Profile {
+    /// Builds the profile of `program` from the execution count of every instruction
+    fn from_counts(program: &Program, instruction_counts: Vec<u64>) -> Profile {
+        let loops = program
+            .instructions()
+            .iter()
+            .enumerate()
+            .filter(|(_, instr)| **instr == Instruction::JumpFwd)
+            .filter_map(|(start, _)| {
+                let end = program.jump_target(start)?;
+
+                Some(LoopProfile {
+                    start,
+                    end,
+                    entries: instruction_counts[start],
+                    // Every run of the body ends at the closing bracket
+                    iterations: instruction_counts[end],
+                    instructions_executed: instruction_counts[start..=end].iter().sum(),
+                })
+            })
+            .collect();
+
+        Profile {
+            instruction_counts,
+            loops,
+        }
+    }
+
+    /// The amount of times every instruction of the program was executed, by instruction index
+    pub fn instruction_counts(&self) -> &[u64] {
+        &self.instruction_counts
+    }
+
+    /// The total amount of instructions executed
+    pub fn total_executed(&self) -> u64 {
+        self.instruction_counts.iter().sum()
+    }
+
+    /// All loops of the program, in the order they appear in the source code
+    pub fn loops(&self) -> &[LoopProfile] {
+        &self.loops
+    }
+
+    /// All loops of the program that were reached at least once, ordered
+    /// by the amount of instructions executed inside them, highest first
+    pub fn hottest_loops(&self) -> Vec<&LoopProfile> {
+        let mut loops: Vec<&LoopProfile> = self.loops.iter().filter(|l| l.entries > 0).collect();
+
+        loops.sort_by(|a, b| {
+            b.instructions_executed
+                .cmp(&a.instructions_executed)
+                .then(a.start.cmp(&b.start))
+        });
+
+        loops
+    }
+}
+
+/// A profiler wrapping a [`VirtualMachine`]
+pub struct Profiler<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> {
+    vm: VirtualMachine<T, A, R, W>,
+    profile: Profile,
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> Profiler<T, A, R, W> {
+    /// Wraps the given VM in a profiler
+    pub fn new(vm: VirtualMachine<T, A, R, W>) -> Self {
+        Profiler {
+            vm,
+            profile: Profile::default(),
+        }
+    }
+
+    /// Runs the given program, recording the execution counts of its instructions.
+    ///
+    /// The program is compiled without any optimizations, so that every
+    /// instruction is counted separately. The recorded counts are available
+    /// through [`Profiler::profile`] afterwards, even if the program failed
+    pub fn run_program(&mut self, program: &Program) -> BfResult {
+        log::info!("Profiling program");
+
+        let compiled = program.compile(OptimizationLevel::None)?;
+        let mut counts = vec![0_u64; program.instructions().len()];
+
+        self.vm.load_compiled(&compiled)?;
+
+        let result = loop {
+            let instr_ptr = self.vm.instr_ptr;
+
+            match self.vm.step() {
+                ExecutionState::Running => counts[instr_ptr] += 1,
+                ExecutionState::Halted => {
+                    if let Some(count) = counts.get_mut(instr_ptr) {
+                        *count += 1;
+                    }
+
+                    break Ok(());
+                }
+                ExecutionState::Error(e) => break Err(e),
+            }
+        };
+
+        self.profile = Profile::from_counts(program, counts);
+
+        result
+    }
+
+    /// The profile of the last program run with [`Profiler::run_program`]
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// The wrapped VM
+    pub fn vm(&self) -> &VirtualMachine<T, A, R, W> {
+        &self.vm
+    }
+
+    /// Mutable access to the wrapped VM
+    pub fn vm_mut(&mut self) -> &mut VirtualMachine<T, A, R, W> {
+        &mut self.vm
+    }
+
+    /// Consumes the profiler, returning the wrapped VM
+    pub fn into_inner(self) -> VirtualMachine<T, A, R, W> {
+        self.vm
+    }
+}
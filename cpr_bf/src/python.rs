@@ -0,0 +1,284 @@
+//! Contains the Python bindings of this crate, enabled with the `python` feature
+//!
+//! Build the crate as a dynamic library to use these bindings, for example to run
+//! programs from a notebook, and rename the library to `cpr_bf.so` (`cpr_bf.pyd`
+//! on Windows) so that Python can import it as an extension module:
+//!
+//! ```sh
+//! cargo rustc -p cpr_bf --lib --release --features python --crate-type cdylib
+//! ```
+//!
+//! The module is named `cpr_bf`, and
+//! exposes a `VMBuilder` configuring a `VM`, which runs programs and exposes its tape.
+//!
+//! As with the JavaScript bindings, the input of a program is passed in as bytes,
+//! and its output is returned as bytes. Failing programs raise a `BrainfuckError`.
+//!
+//! ```python
+//! import cpr_bf
+//!
+//! vm = cpr_bf.VMBuilder().with_eof_behavior("zero").with_max_steps(100_000).build()
+//!
+//! assert vm.run_string(",[+.,]", b"HAL") == b"IBM"
+//! assert vm.data_ptr == 0
+//! assert vm.tape == [0]
+//! ```
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+use std::{
+    io,
+    sync::{Mutex, PoisonError},
+};
+
+use crate::{
+    extensions::ExtensionSet, ir::OptimizationLevel, BrainfuckCell, BrainfuckVM, EofBehavior,
+    VMBuilder,
+};
+
+create_exception!(
+    cpr_bf,
+    BrainfuckError,
+    PyException,
+    "Raised when a Brainfuck program fails to parse or run"
+);
+
+/// The cell types that can be chosen from Python
+#[derive(Clone, Copy, Debug, Default)]
+enum CellType {
+    #[default]
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+/// Configures a `VM`. Every `with_*` method changes the builder, and returns it
+/// so that calls can be chained. The builder can build any amount of VMs
+#[pyclass(name = "VMBuilder", module = "cpr_bf")]
+#[derive(Debug, Default)]
+struct PyVMBuilder {
+    cell_type: CellType,
+    eof_behavior: EofBehavior,
+    optimization_level: OptimizationLevel,
+    extensions: ExtensionSet,
+    max_cells: Option<usize>,
+    max_steps: Option<u64>,
+    rng_seed: Option<u64>,
+}
+
+#[pymethods]
+impl PyVMBuilder {
+    /// Creates a builder with the default configuration
+    #[new]
+    fn new() -> Self {
+        PyVMBuilder::default()
+    }
+
+    /// Changes the type of the cells: "u8" (the default), "u16", "u32" or "u64"
+    fn with_cell_type<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        cell_type: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        slf.cell_type = match cell_type {
+            "u8" => CellType::U8,
+            "u16" => CellType::U16,
+            "u32" => CellType::U32,
+            "u64" => CellType::U64,
+            _ => return Err(invalid_option("cell type", cell_type)),
+        };
+
+        Ok(slf)
+    }
+
+    /// Changes what happens when a program reads at the end of its input:
+    /// "unchanged" (the default), "zero", "max" or "error"
+    fn with_eof_behavior<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        eof_behavior: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        slf.eof_behavior = match eof_behavior {
+            "unchanged" => EofBehavior::Unchanged,
+            "zero" => EofBehavior::Zero,
+            "max" => EofBehavior::MaxValue,
+            "error" => EofBehavior::Error,
+            _ => return Err(invalid_option("EOF behavior", eof_behavior)),
+        };
+
+        Ok(slf)
+    }
+
+    /// Changes how far programs are optimized: "none", "basic" or "full" (the default)
+    fn with_optimization_level<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        level: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        slf.optimization_level = match level {
+            "none" => OptimizationLevel::None,
+            "basic" => OptimizationLevel::Basic,
+            "full" => OptimizationLevel::Full,
+            _ => return Err(invalid_option("optimization level", level)),
+        };
+
+        Ok(slf)
+    }
+
+    /// Enables the given language extensions: "pbrain", "brainfork",
+    /// "extended_type_i" and "random"
+    fn with_extensions<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        extensions: Vec<String>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        for extension in extensions {
+            slf.extensions |= match extension.as_str() {
+                "pbrain" => ExtensionSet::PBRAIN,
+                "brainfork" => ExtensionSet::BRAINFORK,
+                "extended_type_i" => ExtensionSet::EXTENDED_TYPE_I,
+                "random" => ExtensionSet::RANDOM,
+                _ => return Err(invalid_option("extension", &extension)),
+            };
+        }
+
+        Ok(slf)
+    }
+
+    /// Limits the tape to the given amount of cells
+    fn with_max_cells(mut slf: PyRefMut<'_, Self>, max_cells: usize) -> PyRefMut<'_, Self> {
+        slf.max_cells = Some(max_cells);
+        slf
+    }
+
+    /// Stops programs after the given amount of steps, so that programs
+    /// that never halt raise an error instead of hanging the notebook
+    fn with_max_steps(mut slf: PyRefMut<'_, Self>, max_steps: u64) -> PyRefMut<'_, Self> {
+        slf.max_steps = Some(max_steps);
+        slf
+    }
+
+    /// Seeds the generator of the "random" extension, so that runs are reproducible
+    fn with_rng_seed(mut slf: PyRefMut<'_, Self>, seed: u64) -> PyRefMut<'_, Self> {
+        slf.rng_seed = Some(seed);
+        slf
+    }
+
+    /// Builds a `VM` with the current configuration
+    fn build(&self) -> PyVM {
+        let vm = match self.cell_type {
+            CellType::U8 => self.build_with_cell_type::<u8>(),
+            CellType::U16 => self.build_with_cell_type::<u16>(),
+            CellType::U32 => self.build_with_cell_type::<u32>(),
+            CellType::U64 => self.build_with_cell_type::<u64>(),
+        };
+
+        PyVM { vm: Mutex::new(vm) }
+    }
+}
+
+impl PyVMBuilder {
+    fn build_with_cell_type<T: BrainfuckCell + Send + 'static>(
+        &self,
+    ) -> Box<dyn BrainfuckVM + Send> {
+        let mut builder = VMBuilder::new()
+            .with_cell_type::<T>()
+            .with_eof_behavior(self.eof_behavior)
+            .with_optimization_level(self.optimization_level)
+            .with_extensions(self.extensions)
+            .with_reader(io::empty())
+            .with_writer(io::sink());
+
+        if let Some(max_cells) = self.max_cells {
+            builder = builder.with_max_cells(max_cells);
+        }
+
+        if let Some(max_steps) = self.max_steps {
+            builder = builder.with_max_steps(max_steps);
+        }
+
+        if let Some(seed) = self.rng_seed {
+            builder = builder.with_rng_seed(seed);
+        }
+
+        builder.build_send()
+    }
+}
+
+/// A Brainfuck VM built with a `VMBuilder`. The tape is kept between runs
+#[pyclass(name = "VM", module = "cpr_bf")]
+struct PyVM {
+    /// Python objects may be shared between threads. Every method borrows
+    /// the VM mutably, so the mutex is never actually locked
+    vm: Mutex<Box<dyn BrainfuckVM + Send>>,
+}
+
+#[pymethods]
+impl PyVM {
+    /// Runs the given Brainfuck source code with `input` as its only input,
+    /// and returns everything it wrote. Raises a `BrainfuckError` if it fails
+    #[pyo3(signature = (code, input = None))]
+    fn run_string<'py>(
+        &mut self,
+        py: Python<'py>,
+        code: &str,
+        input: Option<&[u8]>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let output = self
+            .vm()
+            .run_string_with_input(code, input.unwrap_or_default())
+            .map_err(|e| BrainfuckError::new_err(e.to_string()))?;
+
+        Ok(PyBytes::new(py, &output))
+    }
+
+    /// The values of the cells of the tape, which grows as programs move
+    /// the data pointer beyond its end
+    #[getter]
+    fn tape(&mut self) -> Vec<u64> {
+        self.vm().cells_as_u64()
+    }
+
+    /// The index of the cell the data pointer points to. This cell might be
+    /// beyond the end of the tape, if it has not been allocated yet
+    #[getter]
+    fn data_ptr(&mut self) -> usize {
+        self.vm().data_ptr()
+    }
+
+    /// Resets the VM to the state it was built in, with all cells set to zero and the
+    /// data pointer at the first cell. Also shrinks the tape back to its initial size
+    /// if `shrink_tape` is true
+    #[pyo3(signature = (shrink_tape = false))]
+    fn reset(&mut self, shrink_tape: bool) {
+        self.vm().reset_all(shrink_tape);
+    }
+}
+
+impl PyVM {
+    fn vm(&mut self) -> &mut dyn BrainfuckVM {
+        &mut **self.vm.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The error raised when an option of a `VMBuilder` is given an unknown value
+fn invalid_option(option: &str, value: &str) -> PyErr {
+    PyValueError::new_err(format!("Unknown {}: {:?}", option, value))
+}
+
+/// The `cpr_bf` Python module
+#[pymodule]
+fn cpr_bf(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyVMBuilder>()?;
+    m.add_class::<PyVM>()?;
+    m.add("BrainfuckError", m.py().get_type::<BrainfuckError>())?;
+
+    Ok(())
+}
@@ -0,0 +1,222 @@
+//! Contains an interpreter for self-modifying Brainfuck
+//!
+//! In self-modifying Brainfuck, the program and its data share the same tape.
+//! Every byte of the source code is loaded into a cell, after which the data
+//! pointer starts at the first cell after the program. The instruction pointer
+//! then reads its instructions from the tape itself, so a program can change
+//! its own code by writing to the cells to the left of its data.
+//!
+//! Cells that do not hold one of the eight standard Brainfuck instructions are
+//! skipped, and the program ends once the instruction pointer moves past the last
+//! allocated cell. Since the code can change at any time, matching brackets are
+//! searched for when a jump is taken, instead of when the program is loaded.
+//!
+//! The [`SelfModifyingVM`] wraps a [`VirtualMachine`], and uses its cell type,
+//! allocator, reader, writer and configuration. Language extensions, optimizations
+//! and hooks do not apply to self-modifying programs.
+//!
+//! ```
+//! use cpr_bf::{selfmod::SelfModifyingVM, VMBuilder};
+//!
+//! let mut vm = SelfModifyingVM::new(VMBuilder::new().with_writer(Vec::new()).build_typed());
+//!
+//! // Increments its own `-` into a `.`, which then prints itself
+//! vm.run_string("<+-").unwrap();
+//!
+//! assert_eq!(vm.into_inner().into_writer(), b".");
+//! ```
+
+use crate::{
+    extensions::ExtensionSet,
+    io::{Read, Write},
+    BfResult, BrainfuckAllocator, BrainfuckCell, BrainfuckExecutionError, ExecResult,
+    ExecutionState, Instruction, MissingKind, RunStats, VirtualMachine,
+};
+
+/// Runs self-modifying Brainfuck programs on the tape of a [`VirtualMachine`]
+pub struct SelfModifyingVM<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> {
+    vm: VirtualMachine<T, A, R, W>,
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> SelfModifyingVM<T, A, R, W> {
+    /// Wraps `vm`, so that it runs self-modifying programs
+    pub fn new(vm: VirtualMachine<T, A, R, W>) -> Self {
+        SelfModifyingVM { vm }
+    }
+
+    /// Unwraps the underlying [`VirtualMachine`]
+    pub fn into_inner(self) -> VirtualMachine<T, A, R, W> {
+        self.vm
+    }
+
+    /// The memory cells of the VM, including the code of the program
+    pub fn memory(&self) -> &[T] {
+        self.vm.memory()
+    }
+
+    /// The index of the memory cell the data pointer currently points to
+    pub fn data_ptr(&self) -> usize {
+        self.vm.data_ptr
+    }
+
+    /// The index of the memory cell holding the next instruction
+    pub fn instr_ptr(&self) -> usize {
+        self.vm.instr_ptr
+    }
+
+    /// Clears the memory of the VM and loads every byte of `source` into
+    /// a cell, ready to be run with [`SelfModifyingVM::resume`]
+    pub fn load_source(&mut self, source: &str) -> BfResult {
+        log::info!("Loading self-modifying program of {} bytes", source.len());
+
+        let vm = &mut self.vm;
+
+        vm.data.iter_mut().for_each(|cell| *cell = T::default());
+        vm.program = None;
+        vm.instr_ptr = vm.origin;
+        vm.data_ptr = vm.origin + source.len();
+        vm.steps = 0;
+        vm.stats = RunStats::default();
+
+        vm.ensure_capacity(vm.data_ptr)?;
+
+        for (cell, byte) in vm.data[vm.origin..].iter_mut().zip(source.bytes()) {
+            *cell = T::from_byte(byte);
+        }
+
+        Ok(())
+    }
+
+    /// Loads `source` with [`SelfModifyingVM::load_source`], and runs it until it halts
+    pub fn run_string(&mut self, source: &str) -> BfResult {
+        self.load_source(source)?;
+        self.resume()
+    }
+
+    /// Runs the loaded program, starting from the current instruction pointer, until it halts
+    pub fn resume(&mut self) -> BfResult {
+        loop {
+            match self.step() {
+                ExecutionState::Running => {}
+                ExecutionState::Halted => return Ok(()),
+                ExecutionState::Error(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Executes the instruction in the cell the instruction pointer points to
+    pub fn step(&mut self) -> ExecutionState {
+        match self.execute_step() {
+            ExecutionState::Running => ExecutionState::Running,
+            state => self.vm.stop(state),
+        }
+    }
+
+    fn execute_step(&mut self) -> ExecutionState {
+        let instr_ptr = self.vm.instr_ptr;
+
+        let Some(&cell) = self.vm.data.get(instr_ptr) else {
+            log::debug!("Program finished");
+            return ExecutionState::Halted;
+        };
+
+        let Some(instr) = decode(cell) else {
+            self.vm.instr_ptr += 1;
+            return ExecutionState::Running;
+        };
+
+        if let Err(e) = self.vm.check_interrupt() {
+            return ExecutionState::Error(e);
+        }
+
+        self.vm.steps += 1;
+
+        match self.exec(instr, instr_ptr) {
+            Ok(next) => {
+                self.vm.instr_ptr = next;
+                ExecutionState::Running
+            }
+            Err(e) => ExecutionState::Error(e),
+        }
+    }
+
+    fn exec(&mut self, instr: Instruction, instr_ptr: usize) -> ExecResult {
+        match instr {
+            Instruction::IncrDP => self.exec_move(instr_ptr, 1),
+            Instruction::DecrDP => self.exec_move(instr_ptr, -1),
+            Instruction::Incr => self.vm.exec_add(instr_ptr, 1),
+            Instruction::Decr => self.vm.exec_add(instr_ptr, -1),
+            Instruction::Output => self.vm.exec_output(instr_ptr),
+            Instruction::Input => self.vm.exec_input(instr_ptr),
+            Instruction::JumpFwd if self.current_cell().is_zero() => {
+                self.matching_jump_back(instr_ptr)
+            }
+            Instruction::JumpBack if !self.current_cell().is_zero() => {
+                self.matching_jump_fwd(instr_ptr)
+            }
+            _ => Ok(instr_ptr + 1),
+        }
+    }
+
+    /// Moves the data pointer. If the allocator extends the tape to the left,
+    /// the code moves along with the rest of the tape
+    fn exec_move(&mut self, instr_ptr: usize, amount: isize) -> ExecResult {
+        let old_origin = self.vm.origin;
+        let next = self.vm.exec_move(instr_ptr, amount)?;
+
+        Ok(next + (self.vm.origin - old_origin))
+    }
+
+    fn current_cell(&self) -> T {
+        self.vm
+            .data
+            .get(self.vm.data_ptr)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Finds the `]` matching the `[` at `instr_ptr`, and returns the index after it
+    fn matching_jump_back(&self, instr_ptr: usize) -> ExecResult {
+        let mut depth = 0_usize;
+
+        for (i, &cell) in self.vm.data.iter().enumerate().skip(instr_ptr + 1) {
+            match decode(cell) {
+                Some(Instruction::JumpFwd) => depth += 1,
+                Some(Instruction::JumpBack) if depth == 0 => return Ok(i + 1),
+                Some(Instruction::JumpBack) => depth -= 1,
+                _ => {}
+            }
+        }
+
+        log::error!("No matching closing bracket for cell {}", instr_ptr);
+        Err(BrainfuckExecutionError::JumpMismatchError(
+            MissingKind::JumpBack,
+        ))
+    }
+
+    /// Finds the `[` matching the `]` at `instr_ptr`, and returns the index after it
+    fn matching_jump_fwd(&self, instr_ptr: usize) -> ExecResult {
+        let mut depth = 0_usize;
+
+        for (i, &cell) in self.vm.data[..instr_ptr].iter().enumerate().rev() {
+            match decode(cell) {
+                Some(Instruction::JumpBack) => depth += 1,
+                Some(Instruction::JumpFwd) if depth == 0 => return Ok(i + 1),
+                Some(Instruction::JumpFwd) => depth -= 1,
+                _ => {}
+            }
+        }
+
+        log::error!("No matching opening bracket for cell {}", instr_ptr);
+        Err(BrainfuckExecutionError::JumpMismatchError(
+            MissingKind::JumpFwd,
+        ))
+    }
+}
+
+/// The standard Brainfuck instruction stored in `cell`, if any
+fn decode<T: BrainfuckCell>(cell: T) -> Option<Instruction> {
+    cell.to_code_point()
+        .and_then(char::from_u32)
+        .and_then(|c| Instruction::from_char(c, ExtensionSet::NONE))
+}
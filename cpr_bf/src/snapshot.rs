@@ -0,0 +1,298 @@
+//! Contains a binary format for the state of a VM, used for checkpoints
+//!
+//! A [`VMState`] stored in this format can be written to disk, so that a long-running
+//! program can be continued after the process running it stopped. States are saved
+//! with [`crate::BrainfuckVM::save_state`], or periodically with
+//! [`crate::VMBuilder::with_checkpoints`], and restored with
+//! [`crate::BrainfuckVM::load_state`].
+//!
+//! The format consists of:
+//! - The magic bytes `BFS`, followed by a single version byte
+//! - The width of a cell in bytes, as a single byte
+//! - The tape, as the length of a [`crate::tape::image`] followed by the image
+//! - The data pointer, the origin and the instruction pointer
+//! - The amount of executed steps, as 8 bytes in little endian
+//! - A single byte that is 1 if a program is loaded, followed by the length of
+//!   the program in the compiled program format of [`crate::bytecode`], and the program
+//! - The number of procedures, followed by the identifier of every procedure as
+//!   8 bytes in little endian, and the index of its first operation
+//! - The number of entries on the call stack, followed by the entries
+//! - The storage cell, in little endian in the width of a cell
+//! - The number of waiting threads, followed by their tape, data pointer, origin,
+//!   instruction pointer, procedures and call stack, stored like those of the VM
+//!
+//! All other numbers are encoded as unsigned LEB128 variable length integers.
+//! The positions of operations in the source code of the program are not stored,
+//! and neither are the input and output of the VM.
+//!
+//! ```
+//! use cpr_bf::{BrainfuckVM, VMBuilder};
+//!
+//! let mut vm = VMBuilder::new().with_writer(Vec::new()).build_typed();
+//!
+//! vm.load_program(&"++++++++[>++++++++<-]>+.".into()).unwrap();
+//! vm.step();
+//!
+//! let state = vm.save_state();
+//!
+//! let mut resumed = VMBuilder::new().with_writer(Vec::new()).build_typed();
+//! resumed.load_state(&state).unwrap();
+//! resumed.resume().unwrap();
+//!
+//! assert_eq!(resumed.writer(), b"A");
+//! ```
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::mem;
+
+use crate::{
+    bytecode::{read_varint, write_varint, BytecodeError},
+    ir::CompiledProgram,
+    tape::image::{self, read_cell, write_cell, TapeImageError},
+    BrainfuckCell, ThreadState, VMMemoryError, VMState,
+};
+
+/// The magic bytes every state starts with
+pub const MAGIC: &[u8; 3] = b"BFS";
+
+/// The latest version of the state format
+pub const VERSION: u8 = 1;
+
+/// An error encountered while decoding a state
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    /// The data does not start with [`MAGIC`]
+    #[error("Not a VM state")]
+    InvalidMagic,
+
+    /// The state was written in an unsupported version of the format
+    #[error("Unsupported VM state version {0}")]
+    UnsupportedVersion(u8),
+
+    /// The cells of the state have a different width than the cells of the VM
+    #[error("VM state has {found}-byte cells, but the VM has {expected}-byte cells")]
+    CellWidthMismatch {
+        /// The width of the cells of the VM, in bytes
+        expected: usize,
+
+        /// The width of the cells of the state, in bytes
+        found: u8,
+    },
+
+    /// The data ended unexpectedly
+    #[error("Unexpected end of VM state")]
+    UnexpectedEnd,
+
+    /// A variable length integer does not fit in a [`usize`]
+    #[error("Invalid integer in VM state")]
+    InvalidInteger,
+
+    /// There is additional data after the end of the state
+    #[error("Trailing data after end of VM state")]
+    TrailingData,
+
+    /// A tape of the state is invalid
+    #[error("Invalid tape in VM state: {0}")]
+    Tape(#[from] TapeImageError),
+
+    /// The program of the state is invalid
+    #[error("Invalid program in VM state: {0}")]
+    Program(#[from] BytecodeError),
+
+    /// The tape of the VM can not hold the cells of the state
+    #[error("Could not restore the tape: {0}")]
+    Memory(#[from] VMMemoryError),
+}
+
+/// Returns whether `data` starts with the state [`MAGIC`]
+pub fn is_state(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encodes `state` in the state format
+pub fn encode<T: BrainfuckCell>(state: &VMState<T>) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.push(mem::size_of::<T>() as u8);
+
+    write_bytes(&mut out, &image::encode(&state.tape));
+    write_varint(&mut out, state.data_ptr);
+    write_varint(&mut out, state.origin);
+    write_varint(&mut out, state.instr_ptr);
+    out.extend_from_slice(&state.steps.to_le_bytes());
+
+    match &state.program {
+        Some(program) => {
+            out.push(1);
+            write_bytes(&mut out, &program.to_bytes());
+        }
+        None => out.push(0),
+    }
+
+    write_procedures(&mut out, &state.procedures);
+    write_call_stack(&mut out, &state.call_stack);
+    write_cell(&mut out, state.storage);
+
+    write_varint(&mut out, state.threads.len());
+
+    for thread in &state.threads {
+        write_bytes(&mut out, &image::encode(&thread.tape));
+        write_varint(&mut out, thread.data_ptr);
+        write_varint(&mut out, thread.origin);
+        write_varint(&mut out, thread.instr_ptr);
+        write_procedures(&mut out, &thread.procedures);
+        write_call_stack(&mut out, &thread.call_stack);
+    }
+
+    log::debug!("Encoded VM state in {} bytes", out.len());
+
+    out
+}
+
+/// Decodes a state from the state format
+pub fn decode<T: BrainfuckCell>(data: &[u8]) -> Result<VMState<T>, SnapshotError> {
+    let rest = data
+        .strip_prefix(MAGIC)
+        .ok_or(SnapshotError::InvalidMagic)?;
+
+    let (&version, rest) = rest.split_first().ok_or(SnapshotError::UnexpectedEnd)?;
+
+    if !(1..=VERSION).contains(&version) {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let (&width, mut rest) = rest.split_first().ok_or(SnapshotError::UnexpectedEnd)?;
+
+    if usize::from(width) != mem::size_of::<T>() {
+        return Err(SnapshotError::CellWidthMismatch {
+            expected: mem::size_of::<T>(),
+            found: width,
+        });
+    }
+
+    let tape = image::decode(read_bytes(&mut rest)?)?;
+    let data_ptr = varint(&mut rest)?;
+    let origin = varint(&mut rest)?;
+    let instr_ptr = varint(&mut rest)?;
+    let steps = read_u64(&mut rest)?;
+
+    let (&has_program, remaining) = rest.split_first().ok_or(SnapshotError::UnexpectedEnd)?;
+    rest = remaining;
+
+    let program = match has_program {
+        0 => None,
+        _ => Some(CompiledProgram::from_bytes(read_bytes(&mut rest)?)?),
+    };
+
+    let procedures = read_procedures(&mut rest)?;
+    let call_stack = read_call_stack(&mut rest)?;
+    let storage = read_cell(take(&mut rest, usize::from(width))?);
+
+    let num_threads = varint(&mut rest)?;
+    let mut threads = Vec::new();
+
+    for _ in 0..num_threads {
+        threads.push(ThreadState {
+            tape: image::decode(read_bytes(&mut rest)?)?,
+            data_ptr: varint(&mut rest)?,
+            origin: varint(&mut rest)?,
+            instr_ptr: varint(&mut rest)?,
+            procedures: read_procedures(&mut rest)?,
+            call_stack: read_call_stack(&mut rest)?,
+        });
+    }
+
+    if !rest.is_empty() {
+        return Err(SnapshotError::TrailingData);
+    }
+
+    log::debug!("Decoded VM state at operation {}", instr_ptr);
+
+    Ok(VMState {
+        tape,
+        data_ptr,
+        origin,
+        program,
+        instr_ptr,
+        steps,
+        procedures,
+        call_stack,
+        threads,
+        storage,
+    })
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+fn write_procedures(out: &mut Vec<u8>, procedures: &BTreeMap<u64, usize>) {
+    write_varint(out, procedures.len());
+
+    for (&id, &start) in procedures {
+        out.extend_from_slice(&id.to_le_bytes());
+        write_varint(out, start);
+    }
+}
+
+fn write_call_stack(out: &mut Vec<u8>, call_stack: &[usize]) {
+    write_varint(out, call_stack.len());
+
+    for &entry in call_stack {
+        write_varint(out, entry);
+    }
+}
+
+/// Reads a variable length integer, see [`read_varint`]
+fn varint(data: &mut &[u8]) -> Result<usize, SnapshotError> {
+    read_varint(data).map_err(|e| match e {
+        BytecodeError::UnexpectedEnd => SnapshotError::UnexpectedEnd,
+        _ => SnapshotError::InvalidInteger,
+    })
+}
+
+/// Splits the first `len` bytes off `data`
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], SnapshotError> {
+    let (bytes, rest) = data
+        .split_at_checked(len)
+        .ok_or(SnapshotError::UnexpectedEnd)?;
+    *data = rest;
+
+    Ok(bytes)
+}
+
+fn read_bytes<'a>(data: &mut &'a [u8]) -> Result<&'a [u8], SnapshotError> {
+    let len = varint(data)?;
+    take(data, len)
+}
+
+fn read_u64(data: &mut &[u8]) -> Result<u64, SnapshotError> {
+    let bytes = take(data, mem::size_of::<u64>())?;
+
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+}
+
+fn read_procedures(data: &mut &[u8]) -> Result<BTreeMap<u64, usize>, SnapshotError> {
+    let len = varint(data)?;
+    let mut procedures = BTreeMap::new();
+
+    for _ in 0..len {
+        let id = read_u64(data)?;
+        procedures.insert(id, varint(data)?);
+    }
+
+    Ok(procedures)
+}
+
+fn read_call_stack(data: &mut &[u8]) -> Result<Vec<usize>, SnapshotError> {
+    let len = varint(data)?;
+    let mut call_stack = Vec::new();
+
+    for _ in 0..len {
+        call_stack.push(varint(data)?);
+    }
+
+    Ok(call_stack)
+}
@@ -0,0 +1,230 @@
+//! Contains a small binary format for the cells of a tape, called a tape image
+//!
+//! Tape images let one program prepare memory for the next: the cells a VM leaves
+//! behind are saved with [`crate::BrainfuckVM::save_tape`], and loaded into another
+//! VM with [`crate::BrainfuckVM::load_tape`].
+//!
+//! The format consists of:
+//! - The magic bytes `BFT`, followed by a single version byte
+//! - The width of a cell in bytes, as a single byte
+//! - The number of cells on the tape, followed by segments until all cells are covered.
+//!   Every segment is the number of zero cells it starts with, followed by the number
+//!   of cells after those, and their values
+//!
+//! Cell values are stored in little endian, in the width of a cell. Signed values are
+//! stored in two's complement. All other numbers are encoded as unsigned LEB128 variable
+//! length integers, like in the [`crate::bytecode`] format.
+//!
+//! ```
+//! use cpr_bf::{BrainfuckVM, VMBuilder};
+//!
+//! let mut first = VMBuilder::new().build();
+//! first.run_string("++++++++[>++++++++<-]>+").unwrap();
+//!
+//! let image = first.save_tape();
+//!
+//! let mut second = VMBuilder::new().with_writer(Vec::new()).build_typed();
+//! second.load_tape(&image).unwrap();
+//! second.run_string(">.").unwrap();
+//!
+//! assert_eq!(second.writer(), b"A");
+//! ```
+
+use alloc::vec::Vec;
+use core::{iter::repeat_n, mem};
+
+use super::Tape;
+use crate::{
+    bytecode::{read_varint, write_varint, BytecodeError},
+    BrainfuckCell, VMMemoryError,
+};
+
+/// The magic bytes every tape image starts with
+pub const MAGIC: &[u8; 3] = b"BFT";
+
+/// The latest version of the tape image format
+pub const VERSION: u8 = 1;
+
+/// An error encountered while decoding or loading a tape image
+#[derive(Debug, thiserror::Error)]
+pub enum TapeImageError {
+    /// The data does not start with [`MAGIC`]
+    #[error("Not a tape image")]
+    InvalidMagic,
+
+    /// The image was written in an unsupported version of the format
+    #[error("Unsupported tape image version {0}")]
+    UnsupportedVersion(u8),
+
+    /// The cells of the image have a different width than the cells of the tape
+    #[error("Tape image has {found}-byte cells, but the tape has {expected}-byte cells")]
+    CellWidthMismatch {
+        /// The width of the cells of the tape, in bytes
+        expected: usize,
+
+        /// The width of the cells of the image, in bytes
+        found: u8,
+    },
+
+    /// The data ended unexpectedly
+    #[error("Unexpected end of tape image")]
+    UnexpectedEnd,
+
+    /// A variable length integer does not fit in a [`usize`]
+    #[error("Invalid integer in tape image")]
+    InvalidInteger,
+
+    /// The segments of the image hold more cells than the image has
+    #[error("Tape image segments exceed its number of cells")]
+    InvalidLength,
+
+    /// There is additional data after the last segment
+    #[error("Trailing data after end of tape image")]
+    TrailingData,
+
+    /// The cells of the image do not fit in memory
+    #[error("Tape image does not fit in memory")]
+    TooLarge,
+
+    /// The tape can not hold the cells of the image
+    #[error(transparent)]
+    Memory(#[from] VMMemoryError),
+}
+
+/// Returns whether `data` starts with the tape image [`MAGIC`]
+pub fn is_tape_image(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encodes the cells of `tape` as a tape image
+pub fn encode<T: BrainfuckCell, D: Tape<T> + ?Sized>(tape: &D) -> Vec<u8> {
+    let cell = |index| tape.get(index).unwrap_or_default();
+    let len = tape.len();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.push(mem::size_of::<T>() as u8);
+    write_varint(&mut out, len);
+
+    let mut index = 0;
+
+    while index < len {
+        let zeros_start = index;
+
+        while index < len && cell(index) == T::default() {
+            index += 1;
+        }
+
+        let values_start = index;
+
+        while index < len && cell(index) != T::default() {
+            index += 1;
+        }
+
+        write_varint(&mut out, values_start - zeros_start);
+        write_varint(&mut out, index - values_start);
+
+        for value in (values_start..index).map(cell) {
+            write_cell(&mut out, value);
+        }
+    }
+
+    log::debug!("Encoded {} cells in {} bytes", len, out.len());
+
+    out
+}
+
+/// Decodes the cells of a tape image.
+///
+/// The number of cells is not trusted, so an image that does not fit in
+/// memory results in [`TapeImageError::TooLarge`]
+///
+/// ```
+/// use cpr_bf::tape::image::{self, TapeImageError};
+///
+/// // 2^53 zero cells
+/// let data = b"BFT\x01\x01\x80\x80\x80\x80\x80\x80\x80\x10\x80\x80\x80\x80\x80\x80\x80\x10\x00";
+///
+/// assert!(matches!(image::decode::<u8>(data), Err(TapeImageError::TooLarge)));
+/// ```
+pub fn decode<T: BrainfuckCell>(data: &[u8]) -> Result<Vec<T>, TapeImageError> {
+    let rest = data
+        .strip_prefix(MAGIC)
+        .ok_or(TapeImageError::InvalidMagic)?;
+
+    let (&version, rest) = rest.split_first().ok_or(TapeImageError::UnexpectedEnd)?;
+
+    if !(1..=VERSION).contains(&version) {
+        return Err(TapeImageError::UnsupportedVersion(version));
+    }
+
+    let (&width, mut rest) = rest.split_first().ok_or(TapeImageError::UnexpectedEnd)?;
+
+    if usize::from(width) != mem::size_of::<T>() {
+        return Err(TapeImageError::CellWidthMismatch {
+            expected: mem::size_of::<T>(),
+            found: width,
+        });
+    }
+
+    let len = varint(&mut rest)?;
+    let mut cells = Vec::new();
+
+    while cells.len() < len {
+        let zeros = varint(&mut rest)?;
+        let values = varint(&mut rest)?;
+
+        if zeros.saturating_add(values) > len - cells.len() {
+            return Err(TapeImageError::InvalidLength);
+        }
+
+        if values > rest.len() / usize::from(width) {
+            return Err(TapeImageError::UnexpectedEnd);
+        }
+
+        cells
+            .try_reserve(zeros + values)
+            .map_err(|_| TapeImageError::TooLarge)?;
+        cells.extend(repeat_n(T::default(), zeros));
+
+        for _ in 0..values {
+            let (value, remaining) = rest
+                .split_at_checked(usize::from(width))
+                .ok_or(TapeImageError::UnexpectedEnd)?;
+            rest = remaining;
+
+            cells.push(read_cell(value));
+        }
+    }
+
+    if !rest.is_empty() {
+        return Err(TapeImageError::TrailingData);
+    }
+
+    log::debug!("Decoded {} cells", cells.len());
+
+    Ok(cells)
+}
+
+/// Reads a variable length integer, see [`read_varint`]
+fn varint(data: &mut &[u8]) -> Result<usize, TapeImageError> {
+    read_varint(data).map_err(|e| match e {
+        BytecodeError::UnexpectedEnd => TapeImageError::UnexpectedEnd,
+        _ => TapeImageError::InvalidInteger,
+    })
+}
+
+pub(crate) fn write_cell<T: BrainfuckCell>(out: &mut Vec<u8>, value: T) {
+    for byte in 0..mem::size_of::<T>() as u32 {
+        out.push(((value >> (byte * 8)) & T::from_byte(0xff)).to_u64() as u8);
+    }
+}
+
+pub(crate) fn read_cell<T: BrainfuckCell>(bytes: &[u8]) -> T {
+    (0_u32..)
+        .zip(bytes)
+        .fold(T::default(), |value, (byte, &bits)| {
+            value | (T::from_byte(bits) << (byte * 8))
+        })
+}
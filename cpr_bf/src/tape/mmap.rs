@@ -0,0 +1,239 @@
+//! Contains a tape backed by an anonymous memory map, enabled with the `mmap` feature
+//!
+//! A [`MmapTape`] reserves address space for all of its cells when it first grows, but
+//! the operating system only commits a page of memory once a cell on it is written,
+//! and hands out pages that are already zeroed. Growing the tape therefore neither
+//! zeroes nor copies any cells, which suits programs that address hundreds of
+//! megabytes of cells. Only cell types implementing [`MmapCell`] can be stored on it.
+//!
+//! ```
+//! use cpr_bf::{tape::{mmap::MmapTape, Tape}, BrainfuckVM, VMBuilder};
+//!
+//! let mut vm = VMBuilder::new()
+//!     .with_tape(MmapTape::with_capacity(1 << 30))
+//!     .with_preallocated_cells(500_000_000)
+//!     .build_typed();
+//!
+//! vm.run_string(">>+").unwrap();
+//!
+//! assert_eq!(vm.tape().len(), 500_000_000);
+//! assert_eq!(vm.tape().get(2), Some(1));
+//! ```
+
+use alloc::vec::Vec;
+use core::{fmt::Debug, marker::PhantomData, mem, slice};
+
+use memmap2::{MmapMut, MmapOptions};
+
+use super::Tape;
+use crate::{BrainfuckCell, OutOfBoundsAccess, VMMemoryError};
+
+/// Cell types that can be stored on a [`MmapTape`]
+///
+/// # Safety
+///
+/// A value consisting of only zero bytes must be a valid value of the type, equal to its
+/// default value, and the type must not be aligned to more than the page size of the
+/// operating system. This holds for all primitive integers
+pub unsafe trait MmapCell: BrainfuckCell {}
+
+macro_rules! impl_mmap_cell {
+    ($($t:ty),*) => {
+        $(
+            // SAFETY: Zero is the default value of every primitive integer, and
+            // all bit patterns are valid for them
+            unsafe impl MmapCell for $t {}
+        )*
+    };
+}
+
+impl_mmap_cell!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// A tape backed by an anonymous memory map, see the [module documentation](self).
+///
+/// The tape can hold at most [`MmapTape::capacity`] cells, and returns
+/// [`VMMemoryError::OutOfBounds`] when it is asked to grow beyond that.
+/// Its memory is mapped when the tape first grows, and released again
+/// when it is truncated to zero cells
+pub struct MmapTape<T: MmapCell> {
+    map: Option<MmapMut>,
+    capacity: usize,
+    len: usize,
+    cell: PhantomData<T>,
+}
+
+impl<T: MmapCell> MmapTape<T> {
+    /// The amount of cells reserved by [`MmapTape::new`]
+    pub const DEFAULT_CAPACITY: usize = 1 << 30;
+
+    /// Creates an empty tape that can grow up to [`MmapTape::DEFAULT_CAPACITY`] cells
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// Creates an empty tape that can grow up to `capacity` cells.
+    /// Only address space is reserved for these cells, not memory
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: None,
+            capacity,
+            len: 0,
+            cell: PhantomData,
+        }
+    }
+
+    /// The maximum amount of cells on the tape
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// All cells on the tape
+    pub fn as_slice(&self) -> &[T] {
+        match &self.map {
+            // SAFETY: The map is page aligned and holds `capacity` cells, of which only
+            // the first `len` are used. Every byte is either zero or part of a written cell
+            Some(map) => unsafe { slice::from_raw_parts(map.as_ptr().cast(), self.len) },
+            None => &[],
+        }
+    }
+
+    /// All cells on the tape, mutably
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        match &mut self.map {
+            // SAFETY: See `as_slice`
+            Some(map) => unsafe { slice::from_raw_parts_mut(map.as_mut_ptr().cast(), self.len) },
+            None => &mut [],
+        }
+    }
+
+    /// Maps the memory for all cells, zeroed by the operating system
+    fn reserve(&self) -> Result<MmapMut, VMMemoryError> {
+        let out_of_bounds = || {
+            VMMemoryError::OutOfBounds(OutOfBoundsAccess {
+                capacity: 0,
+                access: self.capacity.saturating_sub(1),
+            })
+        };
+
+        let bytes = self
+            .capacity
+            .checked_mul(mem::size_of::<T>())
+            .ok_or_else(out_of_bounds)?;
+
+        log::trace!("Mapping {} bytes for the tape", bytes);
+
+        MmapOptions::new()
+            .len(bytes)
+            .no_reserve_swap()
+            .map_anon()
+            .map_err(|e| {
+                log::error!("Could not map memory for the tape: {}", e);
+                out_of_bounds()
+            })
+    }
+}
+
+impl<T: MmapCell> Default for MmapTape<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: MmapCell> Debug for MmapTape<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MmapTape")
+            .field("len", &self.len)
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: MmapCell> Tape<T> for MmapTape<T> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn get(&self, index: usize) -> Option<T> {
+        self.as_slice().get(index).copied()
+    }
+
+    #[inline]
+    fn set(&mut self, index: usize, value: T) {
+        self.as_mut_slice()[index] = value;
+    }
+
+    fn ensure(&mut self, len: usize) -> Result<(), VMMemoryError> {
+        if len <= self.len {
+            return Ok(());
+        }
+
+        if len > self.capacity {
+            return Err(VMMemoryError::OutOfBounds(OutOfBoundsAccess {
+                capacity: self.capacity,
+                access: len - 1,
+            }));
+        }
+
+        if self.map.is_none() {
+            self.map = Some(self.reserve()?);
+        }
+
+        // Cells beyond the end of the tape are always zero
+        self.len = len;
+
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+
+        if len == 0 {
+            self.map = None;
+        } else {
+            self.as_mut_slice()[len..].fill(T::default());
+        }
+
+        self.len = len;
+    }
+
+    fn prepend(&mut self, amount: usize) -> Result<(), VMMemoryError> {
+        let len = self.len;
+
+        self.ensure(len.saturating_add(amount))?;
+
+        let cells = self.as_mut_slice();
+
+        cells.copy_within(0..len, amount);
+        cells[..amount].fill(T::default());
+
+        Ok(())
+    }
+
+    fn clear_cells(&mut self) {
+        if self.map.is_none() {
+            return;
+        }
+
+        // A fresh map only commits the pages that are written again
+        match self.reserve() {
+            Ok(map) => self.map = Some(map),
+            Err(_) => self.as_mut_slice().fill(T::default()),
+        }
+    }
+
+    fn to_vec(&self) -> Vec<T> {
+        self.as_slice().to_vec()
+    }
+
+    fn load(&mut self, cells: &[T]) -> Result<(), VMMemoryError> {
+        self.truncate(0);
+        self.ensure(cells.len())?;
+        self.as_mut_slice().copy_from_slice(cells);
+
+        Ok(())
+    }
+}
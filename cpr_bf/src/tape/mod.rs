@@ -0,0 +1,221 @@
+//! Contains the [`Tape`] trait, the storage of the memory cells of a Brainfuck VM
+//!
+//! A tape only stores cells. When it grows is decided by the allocator of the VM,
+//! see [`crate::BrainfuckAllocator`], so that every allocator works with every tape.
+//! The default tape is a [`Vec`], other tapes are chosen with [`crate::VMBuilder::with_tape`].
+//! Tapes used by a VM also implement [`Default`], creating an empty tape. The VM creates
+//! such tapes for the threads of [`crate::extensions::ExtensionSet::BRAINFORK`], which
+//! start out as a copy of the tape of the thread that forked them.
+//!
+//! With the `mmap` feature, the `mmap` module adds a tape backed by an anonymous
+//! memory map, for programs that use a very large amount of cells. The [`shared`] module
+//! contains tapes that several VMs use at the same time, and the [`image`] module a
+//! binary format to save the cells of a tape in.
+//!
+//! ```
+//! use cpr_bf::{tape::Tape, BrainfuckVM, VMBuilder, VMMemoryError};
+//!
+//! /// A tape that stores only the cells that are not zero
+//! #[derive(Clone, Default)]
+//! struct SparseTape {
+//!     cells: std::collections::BTreeMap<usize, u8>,
+//!     len: usize,
+//! }
+//!
+//! impl Tape<u8> for SparseTape {
+//!     fn len(&self) -> usize {
+//!         self.len
+//!     }
+//!
+//!     fn get(&self, index: usize) -> Option<u8> {
+//!         (index < self.len).then(|| self.cells.get(&index).copied().unwrap_or(0))
+//!     }
+//!
+//!     fn set(&mut self, index: usize, value: u8) {
+//!         match value {
+//!             0 => self.cells.remove(&index),
+//!             _ => self.cells.insert(index, value),
+//!         };
+//!     }
+//!
+//!     fn ensure(&mut self, len: usize) -> Result<(), VMMemoryError> {
+//!         self.len = self.len.max(len);
+//!         Ok(())
+//!     }
+//!
+//!     fn truncate(&mut self, len: usize) {
+//!         self.cells.retain(|&index, _| index < len);
+//!         self.len = self.len.min(len);
+//!     }
+//! }
+//!
+//! let mut vm = VMBuilder::new()
+//!     .with_tape(SparseTape::default())
+//!     .build_typed();
+//!
+//! vm.run_string("+>>>>>>>>>>++").unwrap();
+//!
+//! assert_eq!(vm.tape().len(), 11);
+//! assert_eq!(vm.tape().cells.len(), 2);
+//! ```
+
+use alloc::vec::Vec;
+use core::iter::repeat_n;
+
+use crate::{BrainfuckCell, VMMemoryError};
+
+pub mod image;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod shared;
+
+/// The storage of the memory cells of a Brainfuck VM, see the [module documentation](self).
+///
+/// The cells of a tape are indexed from zero up to [`Tape::len`]. The VM only writes to
+/// cells within the tape, and treats cells beyond its end as zero
+pub trait Tape<T: BrainfuckCell> {
+    /// The amount of cells on the tape
+    fn len(&self) -> usize;
+
+    /// Whether the tape has no cells
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value of the cell at `index`, or [`None`] if it is beyond the end of the tape
+    fn get(&self, index: usize) -> Option<T>;
+
+    /// Changes the value of the cell at `index`. Implementations may panic
+    /// if `index` is beyond the end of the tape
+    fn set(&mut self, index: usize, value: T);
+
+    /// Replaces the value of the cell at `index` with the result of `f`, which is called
+    /// with its current value. The cell is left unchanged if `f` returns an error.
+    /// Tapes shared between threads change the cell at once, so that no other VM can
+    /// change it in between. Implementations may panic if `index` is beyond the end of the tape
+    fn update<E>(&mut self, index: usize, f: impl FnOnce(T) -> Result<T, E>) -> Result<(), E>
+    where
+        Self: Sized,
+    {
+        let value = f(self.get(index).unwrap_or_default())?;
+        self.set(index, value);
+
+        Ok(())
+    }
+
+    /// Whether the cells may be nonzero at any time, even right after the VM using the tape
+    /// cleared them, because others, such as other VMs sharing the tape, can change them.
+    /// The VM then never removes loops that it would otherwise know to be skipped,
+    /// see [`crate::ir::Pass::DeadLoops`]. Returns `false` by default
+    fn may_be_nonzero(&self) -> bool {
+        false
+    }
+
+    /// Grows the tape to at least `len` cells, initialized to the default value of `T`.
+    /// Does nothing if the tape is already large enough. Returns an error
+    /// if the tape can not hold that many cells
+    fn ensure(&mut self, len: usize) -> Result<(), VMMemoryError>;
+
+    /// Removes all cells from `len` onwards. Does nothing if the tape
+    /// has `len` cells or less
+    fn truncate(&mut self, len: usize);
+
+    /// Inserts `amount` cells, initialized to the default value of `T`, at the front of
+    /// the tape, moving all other cells `amount` cells to the right.
+    ///
+    /// The default implementation grows the tape, and moves every cell one by one
+    fn prepend(&mut self, amount: usize) -> Result<(), VMMemoryError> {
+        let len = self.len();
+
+        self.ensure(len.saturating_add(amount))?;
+
+        for index in (0..len).rev() {
+            let value = self.get(index).unwrap_or_default();
+            self.set(index + amount, value);
+        }
+
+        for index in 0..amount.min(len) {
+            self.set(index, T::default());
+        }
+
+        Ok(())
+    }
+
+    /// Sets every cell to the default value of `T`, without changing the size of the tape
+    fn clear_cells(&mut self) {
+        for index in 0..self.len() {
+            self.set(index, T::default());
+        }
+    }
+
+    /// Copies all cells of the tape into a [`Vec`]
+    fn to_vec(&self) -> Vec<T> {
+        (0..self.len())
+            .map(|index| self.get(index).unwrap_or_default())
+            .collect()
+    }
+
+    /// Replaces all cells of the tape with `cells`
+    fn load(&mut self, cells: &[T]) -> Result<(), VMMemoryError> {
+        self.truncate(0);
+        self.ensure(cells.len())?;
+
+        for (index, &value) in cells.iter().enumerate() {
+            self.set(index, value);
+        }
+
+        Ok(())
+    }
+}
+
+/// The default tape, storing all cells contiguously
+impl<T: BrainfuckCell> Tape<T> for Vec<T> {
+    #[inline]
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    #[inline]
+    fn get(&self, index: usize) -> Option<T> {
+        self.as_slice().get(index).copied()
+    }
+
+    #[inline]
+    fn set(&mut self, index: usize, value: T) {
+        self[index] = value;
+    }
+
+    fn ensure(&mut self, len: usize) -> Result<(), VMMemoryError> {
+        if Vec::len(self) < len {
+            self.resize(len, T::default());
+        }
+
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+        self.shrink_to_fit();
+    }
+
+    fn prepend(&mut self, amount: usize) -> Result<(), VMMemoryError> {
+        self.splice(0..0, repeat_n(T::default(), amount));
+
+        Ok(())
+    }
+
+    fn clear_cells(&mut self) {
+        self.fill(T::default());
+    }
+
+    fn to_vec(&self) -> Vec<T> {
+        self.clone()
+    }
+
+    fn load(&mut self, cells: &[T]) -> Result<(), VMMemoryError> {
+        self.clear();
+        self.extend_from_slice(cells);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! Contains tapes that are shared between several VMs
+//!
+//! Every VM built with a clone of a [`SharedTape`] uses the same cells, so that
+//! Brainfuck programs can communicate through memory. The VMs take turns on the
+//! current thread, for example in a [`crate::pool::VMPool`]. VMs that run on
+//! different threads share a [`SyncSharedTape`] instead, which locks the cells
+//! for every access.
+//!
+//! ```
+//! use cpr_bf::{pool::VMPool, tape::shared::SharedTape, VMBuilder};
+//!
+//! let tape = SharedTape::new(Vec::<u8>::new());
+//! let mut pool = VMPool::new(4);
+//!
+//! // Waits until cell 3 is set, then prints cell 1
+//! let consumer = pool
+//!     .add_program(
+//!         Box::new(
+//!             VMBuilder::new()
+//!                 .with_tape(tape.clone())
+//!                 .with_writer(Vec::new())
+//!                 .build_typed(),
+//!         ),
+//!         &">>>>>+[<<[>>-<<-]>>]<<<<.".into(),
+//!     )
+//!     .unwrap();
+//!
+//! // Stores 'A' in cell 1, then sets cell 3
+//! pool.add_program(
+//!     Box::new(
+//!         VMBuilder::new()
+//!             .with_tape(tape.clone())
+//!             .with_writer(Vec::new())
+//!             .build_typed(),
+//!     ),
+//!     &"++++++++[>++++++++<-]>+>>+".into(),
+//! )
+//! .unwrap();
+//!
+//! pool.run();
+//!
+//! assert_eq!(pool.vm(consumer).unwrap().writer(), b"A");
+//! assert_eq!(tape.borrow()[1], b'A');
+//! ```
+//!
+//! Every change to the size of the tape is visible to all VMs sharing it. A VM using
+//! the [`crate::allocators::DoublyInfiniteAllocator`] moves all cells when it grows the
+//! tape to the left, and resetting or restoring the memory of a VM, such as with
+//! [`crate::BrainfuckVM::reset_all`], changes the cells of every VM sharing the tape.
+//! Since other VMs can change the cells at any time, programs loaded on a shared tape
+//! with [`crate::BrainfuckVM::load_program`] are never optimized with
+//! [`crate::ir::Pass::DeadLoops`].
+//! Tapes created with [`Default`], such as the tapes of the threads of
+//! [`crate::extensions::ExtensionSet::BRAINFORK`], are not shared.
+
+use alloc::{rc::Rc, vec::Vec};
+use core::cell::{Ref, RefCell, RefMut};
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use super::Tape;
+use crate::{BrainfuckCell, VMMemoryError};
+
+/// A tape shared by all of its clones, see the [module documentation](self).
+///
+/// Wraps another tape, which stores the cells. A VM sharing the tape panics if it
+/// accesses the cells while they are borrowed with [`SharedTape::borrow_mut`], or
+/// written while they are borrowed with [`SharedTape::borrow`]
+///
+/// ```
+/// use cpr_bf::{tape::shared::SharedTape, BrainfuckVM, VMBuilder};
+///
+/// let tape = SharedTape::new(Vec::<u8>::new());
+/// let mut first = VMBuilder::new().with_tape(tape.clone()).build_typed();
+/// let mut second = VMBuilder::new()
+///     .with_tape(tape.clone())
+///     .with_writer(Vec::new())
+///     .build_typed();
+///
+/// // The loop is not skipped, since the first VM changed the cell
+/// first.run_string("+++").unwrap();
+/// second.run_string("[>+<-]>.").unwrap();
+///
+/// assert_eq!(second.writer().as_slice(), [3]);
+/// ```
+#[derive(Debug, Default)]
+pub struct SharedTape<D>(Rc<RefCell<D>>);
+
+impl<D> SharedTape<D> {
+    /// Shares the cells of `tape`
+    pub fn new(tape: D) -> Self {
+        SharedTape(Rc::new(RefCell::new(tape)))
+    }
+
+    /// Borrows the wrapped tape
+    pub fn borrow(&self) -> Ref<'_, D> {
+        self.0.borrow()
+    }
+
+    /// Borrows the wrapped tape mutably
+    pub fn borrow_mut(&self) -> RefMut<'_, D> {
+        self.0.borrow_mut()
+    }
+
+    /// Whether `self` and `other` share the same cells
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Clones share the cells of the original tape
+impl<D> Clone for SharedTape<D> {
+    fn clone(&self) -> Self {
+        SharedTape(self.0.clone())
+    }
+}
+
+impl<T: BrainfuckCell, D: Tape<T>> Tape<T> for SharedTape<D> {
+    fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    fn get(&self, index: usize) -> Option<T> {
+        self.0.borrow().get(index)
+    }
+
+    fn set(&mut self, index: usize, value: T) {
+        self.0.borrow_mut().set(index, value);
+    }
+
+    fn update<E>(&mut self, index: usize, f: impl FnOnce(T) -> Result<T, E>) -> Result<(), E> {
+        self.0.borrow_mut().update(index, f)
+    }
+
+    fn may_be_nonzero(&self) -> bool {
+        true
+    }
+
+    fn ensure(&mut self, len: usize) -> Result<(), VMMemoryError> {
+        self.0.borrow_mut().ensure(len)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.0.borrow_mut().truncate(len);
+    }
+
+    fn prepend(&mut self, amount: usize) -> Result<(), VMMemoryError> {
+        self.0.borrow_mut().prepend(amount)
+    }
+
+    fn clear_cells(&mut self) {
+        self.0.borrow_mut().clear_cells();
+    }
+
+    fn to_vec(&self) -> Vec<T> {
+        self.0.borrow().to_vec()
+    }
+
+    fn load(&mut self, cells: &[T]) -> Result<(), VMMemoryError> {
+        self.0.borrow_mut().load(cells)
+    }
+}
+
+/// A tape shared by all of its clones, that can be used by VMs on different threads.
+/// See the [module documentation](self).
+///
+/// Wraps another tape, which stores the cells. Every access of a VM locks the cells
+/// for only that access, so other VMs can change a cell between two steps of a program.
+/// Operations that change a cell based on its value, such as `+`, read and write it
+/// while holding the lock, so that no changes of other VMs are lost. The host can lock
+/// the cells with [`SyncSharedTape::lock`] to inspect or change several cells at once,
+/// which blocks every VM that accesses them in the meantime
+///
+/// ```
+/// use cpr_bf::{ir::OptimizationLevel, tape::shared::SyncSharedTape, BrainfuckVM, VMBuilder};
+///
+/// let tape = SyncSharedTape::new(vec![0_u32; 2]);
+///
+/// std::thread::scope(|scope| {
+///     for program in ["+".repeat(10_000), ">+++++".into(), "+".repeat(10_000)] {
+///         let mut vm = VMBuilder::new()
+///             .with_cell_type::<u32>()
+///             .with_optimization_level(OptimizationLevel::None)
+///             .with_tape(tape.clone())
+///             .with_writer(std::io::sink())
+///             .build_typed();
+///
+///         scope.spawn(move || vm.run_string(&program).unwrap());
+///     }
+/// });
+///
+/// assert_eq!(*tape.lock(), [20_000, 5]);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct SyncSharedTape<D>(Arc<Mutex<D>>);
+
+#[cfg(feature = "std")]
+impl<D> SyncSharedTape<D> {
+    /// Shares the cells of `tape`
+    pub fn new(tape: D) -> Self {
+        SyncSharedTape(Arc::new(Mutex::new(tape)))
+    }
+
+    /// Locks the wrapped tape, blocking every VM that accesses it until the guard is dropped
+    pub fn lock(&self) -> MutexGuard<'_, D> {
+        // A VM that panicked while holding the lock leaves the tape usable
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether `self` and `other` share the same cells
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Clones share the cells of the original tape
+#[cfg(feature = "std")]
+impl<D> Clone for SyncSharedTape<D> {
+    fn clone(&self) -> Self {
+        SyncSharedTape(self.0.clone())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: BrainfuckCell, D: Tape<T>> Tape<T> for SyncSharedTape<D> {
+    fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn get(&self, index: usize) -> Option<T> {
+        self.lock().get(index)
+    }
+
+    fn set(&mut self, index: usize, value: T) {
+        self.lock().set(index, value);
+    }
+
+    fn update<E>(&mut self, index: usize, f: impl FnOnce(T) -> Result<T, E>) -> Result<(), E> {
+        self.lock().update(index, f)
+    }
+
+    fn may_be_nonzero(&self) -> bool {
+        true
+    }
+
+    fn ensure(&mut self, len: usize) -> Result<(), VMMemoryError> {
+        self.lock().ensure(len)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.lock().truncate(len);
+    }
+
+    fn prepend(&mut self, amount: usize) -> Result<(), VMMemoryError> {
+        self.lock().prepend(amount)
+    }
+
+    fn clear_cells(&mut self) {
+        self.lock().clear_cells();
+    }
+
+    fn to_vec(&self) -> Vec<T> {
+        self.lock().to_vec()
+    }
+
+    fn load(&mut self, cells: &[T]) -> Result<(), VMMemoryError> {
+        self.lock().load(cells)
+    }
+}
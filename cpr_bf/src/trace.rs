@@ -0,0 +1,509 @@
+//! Contains an execution trace recorder and replayer for the Brainfuck Virtual Machine
+//!
+//! The [`Tracer`] wraps a [`VirtualMachine`], and records every instruction it executes
+//! in a [`Trace`], along with how far the instruction moved the data pointer, and how
+//! it changed the current cell. The trace can be stored in a compact binary format.
+//!
+//! The [`Replayer`] re-drives a VM from a trace, without running the program. It can
+//! step forwards and backwards through the trace, so that the state of the VM can be
+//! inspected at any point of a long run, for example right before it failed.
+//!
+//! ```
+//! use cpr_bf::{trace::{Replayer, Trace, Tracer}, BrainfuckVM, Program, VMBuilder};
+//!
+//! let program = Program::parse("++[>+++<-]>.").unwrap();
+//! let mut tracer = Tracer::new(VMBuilder::new().with_writer(Vec::new()).build_typed());
+//!
+//! tracer.run_program(&program).unwrap();
+//!
+//! let trace = Trace::from_bytes(&tracer.trace().to_bytes()).unwrap();
+//! let mut replayer = Replayer::new(VMBuilder::new().build_typed(), trace);
+//!
+//! replayer.load_program(&program).unwrap();
+//! replayer.seek(10);
+//!
+//! assert_eq!(replayer.vm().memory(), &[1, 3]);
+//! assert_eq!(replayer.vm().instr_ptr(), 3);
+//!
+//! replayer.seek(replayer.trace().len());
+//!
+//! assert_eq!(replayer.vm().memory(), &[0, 6]);
+//! ```
+//!
+//! The binary format consists of:
+//! - The magic bytes `BFT`, followed by a single version byte
+//! - The instruction pointer of the VM when the recording stopped
+//! - The number of entries, followed by the entries themselves. Every entry starts
+//!   with a tag byte, whose lowest three bits tell whether the instruction did not
+//!   directly follow the previous one, whether it moved the data pointer, and whether
+//!   it changed the current cell. The distance from the expected instruction index,
+//!   the data pointer delta and the cell delta follow, for each bit that is set
+//!
+//! All numbers are encoded as unsigned LEB128 variable length integers, like in the
+//! [`crate::bytecode`] format. Signed numbers are zigzag encoded first.
+//!
+//! Cells are converted with [`BrainfuckCell::to_u64`], so changes of cells
+//! wider than 64 bits are truncated. Programs that fork threads with
+//! [`crate::extensions::ExtensionSet::BRAINFORK`] cannot be traced, as only
+//! the tape of the running thread is recorded. Likewise, changes that host functions
+//! of [`crate::extensions::ExtensionSet::HOST_CALL`] make to cells other than the
+//! current cell are not recorded.
+
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::{
+    bytecode::{read_i64_varint, read_varint, write_i64_varint, write_varint, BytecodeError},
+    io::{Read, Write},
+    ir::OptimizationLevel,
+    wrapping_from_u64, BfResult, BrainfuckAllocator, BrainfuckCell, BrainfuckVM, ExecutionState,
+    Program, VirtualMachine,
+};
+
+/// The magic bytes every trace starts with
+pub const MAGIC: &[u8; 3] = b"BFT";
+
+/// The latest version of the trace format
+pub const VERSION: u8 = 1;
+
+const TAG_JUMP: u8 = 0b001;
+const TAG_MOVE: u8 = 0b010;
+const TAG_CELL: u8 = 0b100;
+
+/// A single executed instruction of a [`Trace`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The index of the executed instruction
+    pub instr_ptr: usize,
+
+    /// How many cells the instruction moved the data pointer.
+    /// Negative amounts are moves to the left
+    pub ptr_delta: isize,
+
+    /// How much the instruction changed the cell the data pointer pointed to
+    /// before it was executed, wrapping around. Negative amounts are decrements
+    pub cell_delta: i64,
+}
+
+/// The instructions executed by a program, recorded by a [`Tracer`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trace {
+    entries: Vec<TraceEntry>,
+    final_instr_ptr: usize,
+}
+
+impl Trace {
+    /// The executed instructions, in the order they were executed
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    /// The amount of executed instructions
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no instructions were executed
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The instruction pointer of the VM when the recording stopped. If the program
+    /// failed, this is the index of the instruction that failed
+    pub fn final_instr_ptr(&self) -> usize {
+        self.final_instr_ptr
+    }
+
+    /// Encodes the trace in the binary format described in the [module documentation](self)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::from(*MAGIC);
+        out.push(VERSION);
+
+        write_varint(&mut out, self.final_instr_ptr);
+        write_varint(&mut out, self.entries.len());
+
+        let mut expected = 0;
+
+        for entry in &self.entries {
+            let fields = [
+                (TAG_JUMP, entry.instr_ptr as i64 - expected as i64),
+                (TAG_MOVE, entry.ptr_delta as i64),
+                (TAG_CELL, entry.cell_delta),
+            ];
+
+            let set = fields.iter().filter(|(_, val)| *val != 0);
+
+            out.push(set.clone().fold(0, |tag, (bit, _)| tag | bit));
+
+            for &(_, val) in set {
+                write_i64_varint(&mut out, val);
+            }
+
+            expected = entry.instr_ptr + 1;
+        }
+
+        out
+    }
+
+    /// Decodes a trace from the binary format described in the [module documentation](self)
+    pub fn from_bytes(data: &[u8]) -> Result<Trace, TraceError> {
+        let rest = data.strip_prefix(MAGIC).ok_or(TraceError::InvalidMagic)?;
+        let (&version, mut rest) = rest.split_first().ok_or(TraceError::UnexpectedEnd)?;
+
+        if version != VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+
+        let final_instr_ptr = read_varint(&mut rest).map_err(integer_error)?;
+        let num_entries = read_varint(&mut rest).map_err(integer_error)?;
+
+        let mut entries = Vec::new();
+        let mut expected: usize = 0;
+
+        for _ in 0..num_entries {
+            let (&tag, remaining) = rest.split_first().ok_or(TraceError::UnexpectedEnd)?;
+            rest = remaining;
+
+            if tag & !(TAG_JUMP | TAG_MOVE | TAG_CELL) != 0 {
+                return Err(TraceError::InvalidTag(tag));
+            }
+
+            let mut field = |bit: u8| match tag & bit {
+                0 => Ok(0),
+                _ => read_i64_varint(&mut rest).map_err(integer_error),
+            };
+
+            let jump = isize::try_from(field(TAG_JUMP)?).map_err(|_| TraceError::InvalidInteger)?;
+            let ptr_delta =
+                isize::try_from(field(TAG_MOVE)?).map_err(|_| TraceError::InvalidInteger)?;
+            let cell_delta = field(TAG_CELL)?;
+
+            let instr_ptr = expected
+                .checked_add_signed(jump)
+                .ok_or(TraceError::InvalidInteger)?;
+
+            entries.push(TraceEntry {
+                instr_ptr,
+                ptr_delta,
+                cell_delta,
+            });
+
+            expected = instr_ptr.wrapping_add(1);
+        }
+
+        if !rest.is_empty() {
+            return Err(TraceError::TrailingData);
+        }
+
+        Ok(Trace {
+            entries,
+            final_instr_ptr,
+        })
+    }
+}
+
+/// An error encountered while decoding a [`Trace`]
+#[derive(Debug)]
+pub enum TraceError {
+    /// The data does not start with [`MAGIC`]
+    InvalidMagic,
+
+    /// The data was written in an unsupported version of the format
+    UnsupportedVersion(u8),
+
+    /// An entry has a tag with unknown bits set
+    InvalidTag(u8),
+
+    /// The data ended unexpectedly
+    UnexpectedEnd,
+
+    /// A variable length integer does not fit in its type
+    InvalidInteger,
+
+    /// There is additional data after the end of the trace
+    TrailingData,
+}
+
+impl Display for TraceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TraceError::InvalidMagic => write!(f, "Not a Brainfuck execution trace"),
+            TraceError::UnsupportedVersion(v) => write!(f, "Unsupported trace version {}", v),
+            TraceError::InvalidTag(tag) => write!(f, "Invalid entry tag {} in trace", tag),
+            TraceError::UnexpectedEnd => write!(f, "Unexpected end of trace"),
+            TraceError::InvalidInteger => write!(f, "Invalid integer in trace"),
+            TraceError::TrailingData => write!(f, "Trailing data after end of trace"),
+        }
+    }
+}
+
+impl core::error::Error for TraceError {}
+
+/// A trace recorder wrapping a [`VirtualMachine`]
+pub struct Tracer<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> {
+    vm: VirtualMachine<T, A, R, W>,
+    trace: Trace,
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> Tracer<T, A, R, W> {
+    /// Wraps the given VM in a trace recorder
+    pub fn new(vm: VirtualMachine<T, A, R, W>) -> Self {
+        Tracer {
+            vm,
+            trace: Trace::default(),
+        }
+    }
+
+    /// Runs the given program, recording every instruction it executes.
+    ///
+    /// The program is compiled without any optimizations, so that every
+    /// instruction is recorded separately. The recorded trace is available
+    /// through [`Tracer::trace`] afterwards, even if the program failed
+    pub fn run_program(&mut self, program: &Program) -> BfResult {
+        log::info!("Tracing program");
+
+        let compiled = program.compile(OptimizationLevel::None)?;
+        let num_ops = compiled.ops().len();
+        let mut entries = Vec::new();
+
+        self.vm.load_compiled(&compiled)?;
+
+        let result = loop {
+            let instr_ptr = self.vm.instr_ptr;
+            let position = self.position();
+            let old = self.cell_at(position);
+
+            let state = self.vm.step();
+
+            if !matches!(state, ExecutionState::Error(_)) && instr_ptr < num_ops {
+                entries.push(TraceEntry {
+                    instr_ptr,
+                    ptr_delta: self.position() - position,
+                    cell_delta: cell_delta(old, self.cell_at(position)),
+                });
+            }
+
+            match state {
+                ExecutionState::Running => {}
+                ExecutionState::Halted => break Ok(()),
+                ExecutionState::Error(e) => break Err(e),
+            }
+        };
+
+        log::debug!("Recorded trace of {} instructions", entries.len());
+
+        self.trace = Trace {
+            entries,
+            final_instr_ptr: self.vm.instr_ptr,
+        };
+
+        result
+    }
+
+    /// The position of the data pointer, relative to the cell programs start at.
+    /// Unlike the data pointer itself, this does not change when the tape is
+    /// extended to the left
+    fn position(&self) -> isize {
+        self.vm.data_ptr as isize - self.vm.origin as isize
+    }
+
+    /// The value of the cell at `position`. Cells that have not been allocated yet are zero
+    fn cell_at(&self, position: isize) -> T {
+        self.vm
+            .origin
+            .checked_add_signed(position)
+            .and_then(|index| self.vm.data.get(index))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The trace of the last program run with [`Tracer::run_program`]
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// The wrapped VM
+    pub fn vm(&self) -> &VirtualMachine<T, A, R, W> {
+        &self.vm
+    }
+
+    /// Mutable access to the wrapped VM
+    pub fn vm_mut(&mut self) -> &mut VirtualMachine<T, A, R, W> {
+        &mut self.vm
+    }
+
+    /// Consumes the tracer, returning the wrapped VM
+    pub fn into_inner(self) -> VirtualMachine<T, A, R, W> {
+        self.vm
+    }
+}
+
+/// A replayer that re-drives a [`VirtualMachine`] from a [`Trace`]
+///
+/// Replaying only restores the tape, data pointer and instruction pointer of the VM,
+/// without executing any instructions. The program does not read any input or write
+/// any output while it is replayed
+pub struct Replayer<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> {
+    vm: VirtualMachine<T, A, R, W>,
+    trace: Trace,
+    position: usize,
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator, R: Read, W: Write> Replayer<T, A, R, W> {
+    /// Wraps the given VM in a replayer of `trace`
+    pub fn new(vm: VirtualMachine<T, A, R, W>, trace: Trace) -> Self {
+        Replayer {
+            vm,
+            trace,
+            position: 0,
+        }
+    }
+
+    /// Loads the program the trace was recorded from into the VM, and moves
+    /// back to the start of the trace. The VM is reset like
+    /// [`BrainfuckVM::reset_all`], without shrinking its tape
+    pub fn load_program(&mut self, program: &Program) -> BfResult {
+        log::info!("Loading program into replayer");
+
+        let compiled = program.compile(OptimizationLevel::None)?;
+
+        self.vm.reset_all(false);
+        self.vm.load_compiled(&compiled)?;
+        self.position = 0;
+
+        Ok(())
+    }
+
+    /// Applies the next entry of the trace to the VM.
+    /// Returns the applied entry, or [`None`] at the end of the trace
+    pub fn step_forward(&mut self) -> Option<TraceEntry> {
+        let entry = *self.trace.entries.get(self.position)?;
+
+        let cell = self.cell_mut(0);
+        *cell = apply_delta(*cell, entry.cell_delta);
+
+        self.vm.data_ptr = self.cell_index(entry.ptr_delta);
+        self.position += 1;
+        self.sync_instr_ptr();
+
+        Some(entry)
+    }
+
+    /// Reverts the last applied entry of the trace.
+    /// Returns the reverted entry, or [`None`] at the start of the trace
+    pub fn step_back(&mut self) -> Option<TraceEntry> {
+        let entry = *self.trace.entries.get(self.position.checked_sub(1)?)?;
+
+        self.vm.data_ptr = self.cell_index(-entry.ptr_delta);
+
+        let cell = self.cell_mut(0);
+        *cell = apply_delta(*cell, entry.cell_delta.wrapping_neg());
+
+        self.position -= 1;
+        self.sync_instr_ptr();
+
+        Some(entry)
+    }
+
+    /// Steps forwards or backwards until `position` entries of the trace are applied,
+    /// or the end of the trace is reached
+    pub fn seek(&mut self, position: usize) {
+        while self.position > position && self.step_back().is_some() {}
+        while self.position < position && self.step_forward().is_some() {}
+    }
+
+    /// The amount of entries of the trace applied to the VM
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The replayed trace
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// The wrapped VM
+    pub fn vm(&self) -> &VirtualMachine<T, A, R, W> {
+        &self.vm
+    }
+
+    /// Consumes the replayer, returning the wrapped VM
+    pub fn into_inner(self) -> VirtualMachine<T, A, R, W> {
+        self.vm
+    }
+
+    /// Moves the instruction pointer to the next instruction to be applied
+    fn sync_instr_ptr(&mut self) {
+        self.vm.instr_ptr = self
+            .trace
+            .entries
+            .get(self.position)
+            .map_or(self.trace.final_instr_ptr, |entry| entry.instr_ptr);
+        self.vm.steps = self.position as u64;
+    }
+
+    /// The index of the cell `offset` cells away from the data pointer,
+    /// extending the tape with zero cells if it is not allocated yet
+    fn cell_index(&mut self, offset: isize) -> usize {
+        let index = self.vm.data_ptr as isize + offset;
+
+        if index < 0 {
+            let missing = index.unsigned_abs();
+
+            self.vm
+                .data
+                .splice(0..0, core::iter::repeat_n(T::zero(), missing));
+            self.vm.origin += missing;
+            self.vm.data_ptr += missing;
+
+            return 0;
+        }
+
+        let index = index as usize;
+
+        if index >= self.vm.data.len() {
+            self.vm.data.resize(index + 1, T::zero());
+        }
+
+        index
+    }
+
+    /// The cell `offset` cells away from the data pointer, see [`Replayer::cell_index`]
+    fn cell_mut(&mut self, offset: isize) -> &mut T {
+        let index = self.cell_index(offset);
+
+        &mut self.vm.data[index]
+    }
+}
+
+/// The change from `old` to `new`, wrapping around. Whichever of
+/// incrementing and decrementing is shorter is used
+fn cell_delta<T: BrainfuckCell>(old: T, new: T) -> i64 {
+    let up = new.wrapping_sub(&old).to_u64();
+    let down = old.wrapping_sub(&new).to_u64();
+
+    if up <= down && up <= i64::MAX as u64 {
+        up as i64
+    } else {
+        (down as i64).wrapping_neg()
+    }
+}
+
+/// Changes `cell` by `delta`, wrapping around
+fn apply_delta<T: BrainfuckCell>(cell: T, delta: i64) -> T {
+    let magnitude = wrapping_from_u64::<T>(delta.unsigned_abs());
+
+    if delta < 0 {
+        cell.wrapping_sub(&magnitude)
+    } else {
+        cell.wrapping_add(&magnitude)
+    }
+}
+
+/// Maps an error of the variable length integers shared with the [`crate::bytecode`] format
+fn integer_error(e: BytecodeError) -> TraceError {
+    match e {
+        BytecodeError::UnexpectedEnd => TraceError::UnexpectedEnd,
+        _ => TraceError::InvalidInteger,
+    }
+}
@@ -0,0 +1,148 @@
+//! Contains a static checker for Brainfuck programs
+//!
+//! [`Program::validate`] checks a program without running it, and returns a
+//! [`ValidationReport`] describing the problems found. Besides mismatched jumps,
+//! it finds how deeply the loops of the program are nested, and which cells the
+//! program can reach regardless of its input.
+//!
+//! ```
+//! use cpr_bf::Program;
+//!
+//! let report = Program::from(">>+[>+[-]<-]>>.").validate_for_tape(3);
+//!
+//! assert!(report.is_valid());
+//! assert_eq!(report.max_loop_depth, 2);
+//! assert_eq!(report.rightmost_cell, Some(4));
+//! assert_eq!(report.warnings.len(), 1);
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::{Instruction, MissingKind, Program};
+
+/// The result of [`Program::validate`]
+#[derive(Debug)]
+pub struct ValidationReport {
+    /// The kind of jump instruction missing to balance the jumps of the program,
+    /// or [`None`] if every jump instruction is matched
+    pub unmatched: Option<MissingKind>,
+
+    /// The deepest nesting of loops in the program. Zero if the program has no loops
+    pub max_loop_depth: usize,
+
+    /// The rightmost cell the program can reach, relative to the cell it starts at.
+    /// See [`Program::tape_extent`]
+    pub rightmost_cell: Option<usize>,
+
+    /// Potential problems that do not make the program invalid
+    pub warnings: Vec<ValidationWarning>,
+}
+
+impl ValidationReport {
+    /// Whether the program can be compiled and run, which is the case
+    /// if every jump instruction is matched
+    pub fn is_valid(&self) -> bool {
+        self.unmatched.is_none()
+    }
+}
+
+/// A potential problem found by [`Program::validate_for_tape`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationWarning {
+    /// The program reaches a cell beyond the end of the tape.
+    /// With a static allocator, this fails once the program reaches that cell
+    TapeTooSmall {
+        /// See [`ValidationReport::rightmost_cell`]
+        rightmost_cell: usize,
+
+        /// The amount of cells on the tape
+        tape_size: usize,
+    },
+}
+
+impl Display for ValidationWarning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ValidationWarning::TapeTooSmall {
+                rightmost_cell,
+                tape_size,
+            } => write!(
+                f,
+                "Program reaches cell {}, but the tape only has {} cells",
+                rightmost_cell, tape_size
+            ),
+        }
+    }
+}
+
+/// Checks `program`, see [`Program::validate`]
+pub(crate) fn validate(program: &Program) -> ValidationReport {
+    let mut open_stack = Vec::new();
+    let mut unmatched = None;
+
+    for &instr in program.instructions() {
+        match instr {
+            Instruction::JumpFwd | Instruction::ProcStart => open_stack.push(instr),
+            Instruction::JumpBack | Instruction::ProcEnd => match open_stack.pop() {
+                Some(open) if Some(open) == instr.opening_partner() => {}
+                _ => unmatched = unmatched.or(instr.missing_partner()),
+            },
+            _ => {}
+        }
+    }
+
+    let max_loop_depth = program.metrics().max_loop_depth;
+
+    if let Some(&open) = open_stack.first() {
+        unmatched = unmatched.or(open.missing_partner());
+    }
+
+    log::debug!(
+        "Validated program of {} instructions, max loop depth {}",
+        program.instructions().len(),
+        max_loop_depth
+    );
+
+    ValidationReport {
+        unmatched,
+        max_loop_depth,
+        rightmost_cell: rightmost_cell(program),
+        warnings: Vec::new(),
+    }
+}
+
+/// Finds the rightmost cell `program` can reach, relative to the
+/// cell it starts at. See [`Program::tape_extent`]
+pub(crate) fn rightmost_cell(program: &Program) -> Option<usize> {
+    // An upper bound of the data pointer, relative to the cell the program starts at
+    let mut upper: isize = 0;
+    let mut rightmost: isize = 0;
+    let mut loop_starts = Vec::new();
+
+    for &instr in program.instructions() {
+        match instr {
+            Instruction::IncrDP => {
+                upper += 1;
+                rightmost = rightmost.max(upper);
+            }
+            Instruction::DecrDP => upper -= 1,
+            Instruction::JumpFwd | Instruction::ProcStart => loop_starts.push(upper),
+            Instruction::JumpBack | Instruction::ProcEnd => {
+                let start = loop_starts.pop()?;
+
+                // If an iteration can end further right than it started,
+                // the loop can move the data pointer arbitrarily far
+                if upper > start {
+                    return None;
+                }
+
+                upper = start;
+            }
+            Instruction::ProcCall => return None,
+            _ => {}
+        }
+    }
+
+    loop_starts.is_empty().then_some(rightmost as usize)
+}
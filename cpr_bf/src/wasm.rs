@@ -0,0 +1,42 @@
+//! Contains the JavaScript bindings of this crate, enabled with the `wasm-bindgen` feature
+//!
+//! Build the crate for `wasm32-unknown-unknown` as a dynamic library to use these
+//! bindings, for example in a browser playground, and generate the JavaScript glue
+//! with `wasm-bindgen`:
+//!
+//! ```sh
+//! cargo rustc -p cpr_bf --lib --release --target wasm32-unknown-unknown \
+//!     --features wasm-bindgen --crate-type cdylib
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/cpr_bf.wasm
+//! ```
+//!
+//! As there is no standard input
+//! or output in the browser, the input of the program is passed in as a string,
+//! and its output is returned as a string.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+
+use crate::{BrainfuckVM, VMBuilder};
+
+/// Runs the given Brainfuck source code on a VM with the default configuration,
+/// reading from `input`. Returns everything the program wrote, with invalid
+/// UTF-8 replaced by the replacement character.
+///
+/// Throws an error containing its description if the program fails
+#[wasm_bindgen(js_name = runString)]
+pub fn run_string(code: &str, input: &str) -> Result<String, JsError> {
+    let mut vm = VMBuilder::new()
+        .with_reader(Cursor::new(input.as_bytes().to_vec()))
+        .with_writer(Vec::new())
+        .build_typed();
+
+    vm.run_string(code)
+        .map_err(|e| JsError::new(&e.to_string()))?;
+
+    Ok(String::from_utf8_lossy(&vm.into_writer()).into_owned())
+}
@@ -0,0 +1,110 @@
+//! Procedural macros for the `cpr_bf` crate
+//!
+//! These are re-exported by `cpr_bf` when its `macros` feature is enabled,
+//! and should be used through it instead of depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, LitStr};
+
+/// Parses a string literal of Brainfuck source code into a `cpr_bf::Program` at
+/// compile time. Mismatched brackets are reported as compile errors, instead of
+/// when the program is run.
+///
+/// Only the eight standard Brainfuck instructions are parsed. Like
+/// `cpr_bf::Program::parse`, `#` marks a breakpoint, and any other character
+/// is a comment.
+///
+/// ```
+/// use cpr_bf::{bf, BrainfuckVM};
+///
+/// let program = bf!("++++++++[>++++++++<-]>+.");
+///
+/// let mut vm = cpr_bf::VMBuilder::new().with_writer(Vec::new()).build_typed();
+/// vm.run_program(&program).unwrap();
+///
+/// assert_eq!(vm.into_writer(), b"A");
+/// ```
+///
+/// ```compile_fail
+/// let program = cpr_bf::bf!("+[>+<-]]");
+/// ```
+#[proc_macro]
+pub fn bf(input: TokenStream) -> TokenStream {
+    let source = parse_macro_input!(input as LitStr);
+
+    match parse(&source.value()) {
+        Ok(tokens) => tokens.into(),
+        Err(msg) => syn::Error::new(source.span(), msg)
+            .to_compile_error()
+            .into(),
+    }
+}
+
+/// Parses `source`, and generates the expression building the program.
+/// Returns an error message if the brackets in `source` are mismatched
+fn parse(source: &str) -> Result<TokenStream2, String> {
+    let mut instructions = Vec::new();
+    let mut breakpoints: Vec<usize> = Vec::new();
+    let mut open_stack = Vec::new();
+    let (mut line, mut column) = (1_usize, 1_usize);
+
+    for c in source.chars() {
+        let instr = match c {
+            '>' => Some(quote!(IncrDP)),
+            '<' => Some(quote!(DecrDP)),
+            '+' => Some(quote!(Incr)),
+            '-' => Some(quote!(Decr)),
+            '.' => Some(quote!(Output)),
+            ',' => Some(quote!(Input)),
+            '[' => {
+                open_stack.push((line, column));
+                Some(quote!(JumpFwd))
+            }
+            ']' => {
+                if open_stack.pop().is_none() {
+                    return Err(format!(
+                        "Too few opening brackets (unmatched ']' at line {}, column {})",
+                        line, column
+                    ));
+                }
+
+                Some(quote!(JumpBack))
+            }
+            '#' => {
+                if breakpoints.last() != Some(&instructions.len()) {
+                    breakpoints.push(instructions.len());
+                }
+
+                None
+            }
+            _ => None,
+        };
+
+        instructions.extend(instr.map(|instr| quote!(::cpr_bf::Instruction::#instr)));
+
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    if let Some(&(line, column)) = open_stack.first() {
+        return Err(format!(
+            "Too few closing brackets (unmatched '[' at line {}, column {})",
+            line, column
+        ));
+    }
+
+    let source_len = source.len();
+
+    Ok(quote! {{
+        const INSTRUCTIONS: &[::cpr_bf::Instruction] = &[#(#instructions),*];
+        const BREAKPOINTS: &[usize] = &[#(#breakpoints),*];
+
+        ::cpr_bf::Program::from_validated(INSTRUCTIONS, BREAKPOINTS, #source_len)
+    }})
+}
@@ -1,18 +1,216 @@
-use std::path::PathBuf;
+use std::{
+    num::{ParseFloatError, ParseIntError},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
 
-use clap::{Parser, ValueEnum};
+use clap::{
+    error::ErrorKind, parser::ValueSource, Args, CommandFactory, FromArgMatches, Parser,
+    Subcommand, ValueEnum,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, about, version)]
 pub(crate) struct CLIArgs {
-    /// The file to run
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Without a subcommand, the program is run like with the run subcommand
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    /// The verbosity of the logger
+    #[cfg(not(debug_assertions))]
+    #[arg(value_enum, short, long, global = true, default_value_t = LogLevel::Warn)]
+    pub verbosity: LogLevel,
+
+    /// The verbosity of the logger
+    #[cfg(debug_assertions)]
+    #[arg(value_enum, short, long, global = true, default_value_t = LogLevel::Info)]
+    pub verbosity: LogLevel,
+}
+
+impl CLIArgs {
+    /// Parses the command line arguments, exiting on errors. The arguments of the run
+    /// subcommand are accepted without a subcommand, but not in front of another subcommand
+    pub(crate) fn parse_args() -> Self {
+        let matches = CLIArgs::command().get_matches();
+
+        if let Some((name, sub_matches)) = matches.subcommand() {
+            let misplaced = matches.ids().find(|&id| {
+                matches.value_source(id.as_str()) == Some(ValueSource::CommandLine)
+                    && !sub_matches.ids().any(|sub_id| sub_id == id)
+            });
+
+            if let Some(id) = misplaced {
+                CLIArgs::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        format!(
+                            "the argument '{}' cannot be used with the '{}' subcommand",
+                            id, name
+                        ),
+                    )
+                    .exit();
+            }
+        }
+
+        CLIArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+
+    /// The subcommand to execute, which is run if none was given
+    pub(crate) fn into_command(self) -> Command {
+        self.command
+            .unwrap_or_else(|| Command::Run(Box::new(self.run)))
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Run a program, or start an interactive session if no program is given.
+    /// This is the default if no subcommand is given
+    Run(Box<RunArgs>),
+
+    /// Parse and validate a program without running it, and print a summary of it
+    Check(CheckArgs),
+
+    /// Format the source code of a program, and print it to stdout
+    Fmt(FmtArgs),
+
+    /// Compile a program into bytecode, or into C, Rust or WebAssembly source code
+    Compile(CompileArgs),
+}
+
+#[derive(Args, Debug)]
+#[group(id = "program", args = ["filename", "stdin_program", "eval", "resume"], multiple = false)]
+pub(crate) struct RunArgs {
+    /// The file to run. Either Brainfuck source code, or a bytecode file created with the compile subcommand.
+    /// With '-', the program is read from stdin, and reads its own input from the terminal instead.
+    /// Starts an interactive session if empty
     #[arg()]
-    pub filename: PathBuf,
+    pub filename: Option<PathBuf>,
+
+    /// Read the program from stdin, like giving '-' as the file to run
+    #[arg(long)]
+    pub stdin_program: bool,
+
+    /// Run the given source code, instead of a program file
+    #[arg(short, long, value_name = "PROGRAM")]
+    pub eval: Option<String>,
+
+    /// Continue the program stored in the given checkpoint, written with --checkpoint, instead of running a program file.
+    /// The VM has to use the same cell size as the checkpointed run. Input that was already read is not read again
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["debug", "profile", "self_modifying", "load_tape", "cache"])]
+    pub resume: Option<PathBuf>,
+
+    /// Regularly save the state of the running program to the given file, so that it can be continued with --resume
+    /// if the run is stopped. The file is replaced by every new checkpoint
+    #[arg(long, value_name = "FILE", requires = "program", conflicts_with_all = ["debug", "profile", "self_modifying"])]
+    pub checkpoint: Option<PathBuf>,
+
+    /// The amount of steps between two checkpoints written with --checkpoint.
+    /// Every checkpoint copies the whole tape, so this should not be too small for programs using many cells
+    #[arg(
+        long,
+        value_name = "STEPS",
+        requires = "checkpoint",
+        default_value_t = 1_000_000_000
+    )]
+    pub checkpoint_every: u64,
+
+    /// Instead of running the program directly, step through it in a terminal debugger.
+    /// The program reads from the input file, or from an empty input if none is given, and its output is shown in the debugger.
+    /// Not available in builds without the terminal feature
+    #[arg(long, conflicts_with = "output")]
+    pub debug: bool,
+
+    /// Print statistics about the execution of the program to stderr afterwards
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Print the tape to stderr after the program stops, or write it to the given file.
+    /// The tape is shown as a hexdump, up to the last non-zero cell or the data pointer
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "-",
+        conflicts_with_all = ["debug", "profile", "self_modifying"]
+    )]
+    pub dump_tape: Option<PathBuf>,
+
+    /// Load the tape from the given tape image before the program runs, such as one written with --save-tape.
+    /// The image must have the same cell size as the VM
+    #[arg(long, value_name = "FILE", requires = "program", conflicts_with_all = ["debug", "profile", "self_modifying"])]
+    pub load_tape: Option<PathBuf>,
+
+    /// Write the tape to the given file as a tape image after the program stops, so that a later run can continue
+    /// with it through --load-tape. The format is described in the documentation of the cpr_bf::tape::image module
+    #[arg(long, value_name = "FILE", requires = "program", conflicts_with_all = ["debug", "profile", "self_modifying"])]
+    pub save_tape: Option<PathBuf>,
+
+    /// Once the program halts, exit with the value of the current cell modulo 256, instead of 0
+    #[arg(long, requires = "program", conflicts_with_all = ["debug", "profile", "self_modifying"])]
+    pub exit_cell: bool,
+
+    /// How errors and statistics are reported. In JSON mode, a single JSON object describing
+    /// the outcome of the program, including the statistics of --stats, is written to stderr once it stops
+    #[arg(
+        value_enum,
+        long,
+        default_value_t = OutputFormat::Text,
+        requires = "program",
+        conflicts_with_all = ["debug", "profile", "self_modifying"]
+    )]
+    pub format: OutputFormat,
+
+    /// Count how often every instruction is executed while running the program,
+    /// and print the hottest loops to stderr afterwards. Runs the program without optimizations
+    #[arg(long, conflicts_with = "debug")]
+    pub profile: bool,
+
+    /// Run the program as self-modifying Brainfuck: its source code is loaded onto the tape,
+    /// and executed from there. Changing those cells changes the running program
+    #[arg(long, conflicts_with_all = ["debug", "profile"])]
+    pub self_modifying: bool,
+
+    /// Split the source code of the program at its first '!', and use everything after it
+    /// as the input of the program, before any other input
+    #[arg(long, conflicts_with_all = ["debug", "profile", "self_modifying"])]
+    pub bang_input: bool,
+
+    /// The dialect of the source code of the program. Also enables the extensions
+    /// of the dialect, which are needed to run bytecode files using them
+    #[arg(value_enum, long, default_value_t = Dialect::Brainfuck)]
+    pub dialect: Dialect,
 
     /// The file from which running programs take their input. Defaults to stdin if empty
     #[arg(short, long)]
     pub input: Option<PathBuf>,
 
+    /// Use the given text as the input of the program, instead of a file or stdin. Supports the escape
+    /// sequences \n, \r, \t, \0, \\, \' and \", and \xHH for arbitrary bytes
+    #[arg(
+        long,
+        value_name = "INPUT",
+        requires = "program",
+        conflicts_with_all = ["input", "raw_input", "debug"]
+    )]
+    pub input_string: Option<InputString>,
+
+    /// Put the terminal into raw mode while the program runs, so that it receives every keypress
+    /// immediately instead of line by line. Ctrl-C stops the program once it reads input.
+    /// Not available in builds without the terminal feature
+    #[arg(long, requires = "program", conflicts_with_all = ["input", "debug", "profile"])]
+    pub raw_input: bool,
+
+    /// Echo every byte the program reads from its input, either in between its output or to stderr.
+    /// Useful to follow a program reading from a file or a pipe
+    #[arg(value_enum, long, default_value_t = InputEcho::Off)]
+    pub echo_input: InputEcho,
+
     /// The file to which running programs write their output. Defaults to stdout if empty
     #[arg(short, long)]
     pub output: Option<PathBuf>,
@@ -21,23 +219,159 @@ pub(crate) struct CLIArgs {
     #[arg(value_enum, short, long, default_value_t = CellSize::U8)]
     pub cellsize: CellSize,
 
-    /// The amount of preallocated memory cells. If a static allocator is used, this is also the total amount of available memory
-    #[arg(short, long, default_value_t = 16)]
-    pub preallocated: usize,
+    /// The amount of preallocated memory cells. If a static allocator is used, this is also the total amount of available memory.
+    /// With the wrapping allocator, this is the size of the tape the data pointer wraps around.
+    /// With 'auto', the amount of cells is estimated from the program, and 16 if it can not be estimated
+    #[arg(short, long, default_value = "16")]
+    pub preallocated: Preallocated,
 
-    /// The memory allocator to use
+    /// The maximum amount of memory cells the program may use. Unlimited if empty
+    #[arg(long)]
+    pub max_cells: Option<usize>,
+
+    /// The memory allocator to use. The paged allocator grows the memory in pages of 4096 cells
     #[arg(value_enum, short, long, default_value_t = Allocator::Dynamic)]
     pub allocator: Allocator,
 
-    /// The verbosity of the logger
-    #[cfg(not(debug_assertions))]
-    #[arg(value_enum, short, long, default_value_t = LogLevel::Warn)]
-    pub verbosity: LogLevel,
+    /// What happens when the program reads input after the end of the input has been reached
+    #[arg(value_enum, long, default_value_t = EofBehavior::Unchanged)]
+    pub eof: EofBehavior,
 
-    /// The verbosity of the logger
-    #[cfg(debug_assertions)]
-    #[arg(value_enum, short, long, default_value_t = LogLevel::Info)]
-    pub verbosity: LogLevel,
+    /// What happens when a cell is incremented or decremented beyond the range of its type
+    #[arg(value_enum, long, default_value_t = OverflowPolicy::Wrapping)]
+    pub overflow: OverflowPolicy,
+
+    /// How cells are written and read. In numeric mode, '.' prints the decimal value of the cell
+    /// on its own line, and ',' reads a decimal integer
+    #[arg(value_enum, long, default_value_t = IoMode::Character)]
+    pub io_mode: IoMode,
+
+    /// How '.' converts cells into output bytes. Defaults to raw bytes for 8-bit unsigned cells,
+    /// and to UTF-8 encoded code points otherwise
+    #[arg(value_enum, long)]
+    pub output_encoding: Option<OutputEncoding>,
+
+    #[command(flatten)]
+    pub optimization: OptimizationArgs,
+
+    /// Cache compiled programs in the given directory. Running the same source code again with the same
+    /// dialect and optimization passes loads the compiled program from there, instead of parsing and optimizing it
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["debug", "profile", "self_modifying"])]
+    pub cache: Option<PathBuf>,
+
+    /// Stop the program once it executed this many steps, and exit with code 3.
+    /// A step is a single compiled operation, which can be multiple instructions depending on the optimization level
+    #[arg(long, value_name = "STEPS")]
+    pub max_steps: Option<u64>,
+
+    /// Stop the program once it ran for this many seconds, and exit with code 4.
+    /// A program waiting for input is only stopped once it continues
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CheckArgs {
+    /// The file to check. Either Brainfuck source code, or a bytecode file. Reads from stdin if '-'
+    pub filename: PathBuf,
+
+    /// The dialect of the source code of the program
+    #[arg(value_enum, long, default_value_t = Dialect::Brainfuck)]
+    pub dialect: Dialect,
+
+    /// Split the source code of the program at its first '!', and ignore everything after it,
+    /// like the input of programs run with --bang-input
+    #[arg(long)]
+    pub bang_input: bool,
+
+    /// Warn if the program can reach a cell beyond a tape of this many cells
+    #[arg(long)]
+    pub tape_size: Option<usize>,
+
+    /// How the summary and errors are printed. In JSON mode, a single JSON object is printed to stdout
+    #[arg(value_enum, long, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct FmtArgs {
+    /// The source file to format. Reads from stdin if '-'
+    pub filename: PathBuf,
+
+    /// Strip everything but the instructions and breakpoints, instead of formatting
+    #[arg(long)]
+    pub minify: bool,
+
+    /// The amount of spaces every nested loop body is indented by
+    #[arg(long, default_value_t = 4)]
+    pub indent: usize,
+
+    /// The width at which lines of instructions are wrapped, including their indentation
+    #[arg(long, default_value_t = 80)]
+    pub width: usize,
+
+    /// Overwrite the source file with the result, instead of printing it
+    #[arg(long)]
+    pub write: bool,
+
+    /// The dialect of the source code, whose extension instructions are kept.
+    /// Ook! source code can not be formatted
+    #[arg(value_enum, long, default_value_t = Dialect::Brainfuck)]
+    pub dialect: Dialect,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CompileArgs {
+    /// The file to compile. Either Brainfuck source code, or a bytecode file. Reads from stdin if '-'
+    pub filename: PathBuf,
+
+    /// The file the compiled program is written to
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// The format the program is compiled to. Source code targets use the given cell size.
+    /// The C and WebAssembly targets have a fixed tape of the maximum amount of cells (or 30000), while the Rust target
+    /// starts with the preallocated amount of cells and grows its tape like the dynamic allocator
+    #[arg(value_enum, long, default_value_t = Target::Bytecode)]
+    pub target: Target,
+
+    /// The dialect of the source code of the program
+    #[arg(value_enum, long, default_value_t = Dialect::Brainfuck)]
+    pub dialect: Dialect,
+
+    /// The size of each individual memory cell of the generated source code
+    #[arg(value_enum, short, long, default_value_t = CellSize::U8)]
+    pub cellsize: CellSize,
+
+    /// The amount of cells the tape of the Rust target starts with.
+    /// With 'auto', the amount of cells is estimated from the program, and 16 if it can not be estimated
+    #[arg(short, long, default_value = "16")]
+    pub preallocated: Preallocated,
+
+    /// The size of the tape of the C and WebAssembly targets. 30000 if empty
+    #[arg(long)]
+    pub max_cells: Option<usize>,
+
+    #[command(flatten)]
+    pub optimization: OptimizationArgs,
+}
+
+/// The optimization passes with which programs are compiled
+#[derive(Args, Debug)]
+pub(crate) struct OptimizationArgs {
+    /// The optimization level with which the program is compiled
+    #[arg(value_enum, short = 'O', long = "optimization", default_value_t = OptimizationLevel::Full)]
+    pub level: OptimizationLevel,
+
+    /// Apply exactly these optimization passes in the given order, instead of those
+    /// of the optimization level
+    #[arg(
+        value_enum,
+        long = "opt",
+        value_delimiter = ',',
+        conflicts_with = "level"
+    )]
+    pub passes: Vec<Pass>,
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -47,13 +381,290 @@ pub(crate) enum CellSize {
     U32,
     U64,
     U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+}
+
+impl CellSize {
+    /// The cell type used in generated source code, if supported
+    pub(crate) fn codegen_cell_type(&self) -> Option<cpr_bf::codegen::CellType> {
+        match self {
+            CellSize::U8 => Some(cpr_bf::codegen::CellType::U8),
+            CellSize::U16 => Some(cpr_bf::codegen::CellType::U16),
+            CellSize::U32 => Some(cpr_bf::codegen::CellType::U32),
+            CellSize::U64 => Some(cpr_bf::codegen::CellType::U64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Preallocated {
+    Auto,
+    Cells(usize),
+}
+
+impl Preallocated {
+    /// The amount of cells preallocated if it can not be estimated
+    const DEFAULT_AUTO: usize = 16;
+
+    /// The amount of cells to preallocate for `program`
+    pub(crate) fn cells_for(self, program: &cpr_bf::Program) -> usize {
+        match self {
+            Preallocated::Auto => program.tape_extent().unwrap_or(Self::DEFAULT_AUTO),
+            Preallocated::Cells(cells) => cells,
+        }
+    }
+
+    /// The amount of cells preallocated when the VM is built,
+    /// before the tape grows to fit the program in 'auto' mode
+    pub(crate) fn initial_cells(self) -> usize {
+        match self {
+            Preallocated::Auto => Self::DEFAULT_AUTO,
+            Preallocated::Cells(cells) => cells,
+        }
+    }
+}
+
+impl FromStr for Preallocated {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Preallocated::Auto),
+            _ => s.parse().map(Preallocated::Cells),
+        }
+    }
+}
+
+/// Parses the seconds given to --timeout, which may be fractional
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s.parse().map_err(|e: ParseFloatError| e.to_string())?;
+
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+/// The input given with --input-string, with its escape sequences replaced
+#[derive(Debug, Clone)]
+pub(crate) struct InputString(pub Vec<u8>);
+
+impl FromStr for InputString {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = Vec::with_capacity(s.len());
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                continue;
+            }
+
+            let byte = match chars.next() {
+                Some('n') => b'\n',
+                Some('r') => b'\r',
+                Some('t') => b'\t',
+                Some('0') => b'\0',
+                Some('\\') => b'\\',
+                Some('\'') => b'\'',
+                Some('"') => b'"',
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+
+                    if hex.len() != 2 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(format!("invalid escape sequence '\\x{}'", hex));
+                    }
+
+                    u8::from_str_radix(&hex, 16).map_err(|e| e.to_string())?
+                }
+                Some(other) => return Err(format!("unknown escape sequence '\\{}'", other)),
+                None => return Err("unfinished escape sequence at the end".to_string()),
+            };
+
+            bytes.push(byte);
+        }
+
+        Ok(InputString(bytes))
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum Target {
+    Bytecode,
+    C,
+    Rust,
+    Wasm,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum Dialect {
+    Brainfuck,
+    Ook,
+    /// Brainfuck with procedures: `(` and `)` define a procedure identified by the current cell, `:` calls it
+    #[value(name = "pbrain")]
+    PBrain,
+    /// Brainfuck with threads: `Y` forks the running thread, giving the new thread a copy of the tape with the current cell set to zero
+    Brainfork,
+    /// Brainfuck with the Extended Type I instructions `@$!}{~^&|`, operating on a storage cell
+    #[value(name = "extended-type-i")]
+    ExtendedTypeI,
+}
+
+impl From<Dialect> for cpr_bf::dialects::Dialect {
+    fn from(dialect: Dialect) -> Self {
+        match dialect {
+            Dialect::Brainfuck => cpr_bf::dialects::Dialect::Brainfuck,
+            Dialect::Ook => cpr_bf::dialects::Dialect::Ook,
+            Dialect::PBrain => cpr_bf::dialects::Dialect::PBrain,
+            Dialect::Brainfork => cpr_bf::dialects::Dialect::Brainfork,
+            Dialect::ExtendedTypeI => cpr_bf::dialects::Dialect::ExtendedTypeI,
+        }
+    }
 }
 
 #[derive(Debug, Clone, ValueEnum)]
 pub(crate) enum Allocator {
     Dynamic,
+    DoublyInfinite,
+    Paged,
     StaticChecked,
     StaticUnchecked,
+    StaticWrapping,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum EofBehavior {
+    Unchanged,
+    Zero,
+    MaxValue,
+    Error,
+}
+
+impl From<EofBehavior> for cpr_bf::EofBehavior {
+    fn from(eof_behavior: EofBehavior) -> Self {
+        match eof_behavior {
+            EofBehavior::Unchanged => cpr_bf::EofBehavior::Unchanged,
+            EofBehavior::Zero => cpr_bf::EofBehavior::Zero,
+            EofBehavior::MaxValue => cpr_bf::EofBehavior::MaxValue,
+            EofBehavior::Error => cpr_bf::EofBehavior::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum OverflowPolicy {
+    Wrapping,
+    Saturating,
+    Error,
+}
+
+impl From<OverflowPolicy> for cpr_bf::OverflowPolicy {
+    fn from(policy: OverflowPolicy) -> Self {
+        match policy {
+            OverflowPolicy::Wrapping => cpr_bf::OverflowPolicy::Wrapping,
+            OverflowPolicy::Saturating => cpr_bf::OverflowPolicy::Saturating,
+            OverflowPolicy::Error => cpr_bf::OverflowPolicy::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum IoMode {
+    Character,
+    Numeric,
+}
+
+impl From<IoMode> for cpr_bf::IoMode {
+    fn from(io_mode: IoMode) -> Self {
+        match io_mode {
+            IoMode::Character => cpr_bf::IoMode::Character,
+            IoMode::Numeric => cpr_bf::IoMode::Numeric,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum InputEcho {
+    Off,
+    Output,
+    Stderr,
+}
+
+impl From<InputEcho> for cpr_bf::InputEcho {
+    fn from(echo: InputEcho) -> Self {
+        match echo {
+            InputEcho::Off => cpr_bf::InputEcho::Off,
+            InputEcho::Output => cpr_bf::InputEcho::Writer,
+            InputEcho::Stderr => cpr_bf::InputEcho::Stderr,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum OutputEncoding {
+    RawByte,
+    Latin1,
+    Unicode,
+    Escape,
+}
+
+impl From<OutputEncoding> for cpr_bf::OutputEncoding {
+    fn from(encoding: OutputEncoding) -> Self {
+        match encoding {
+            OutputEncoding::RawByte => cpr_bf::OutputEncoding::RawByte,
+            OutputEncoding::Latin1 => cpr_bf::OutputEncoding::Latin1,
+            OutputEncoding::Unicode => cpr_bf::OutputEncoding::Unicode,
+            OutputEncoding::Escape => cpr_bf::OutputEncoding::Escape,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum OptimizationLevel {
+    None,
+    Basic,
+    Full,
+}
+
+impl From<OptimizationLevel> for cpr_bf::ir::OptimizationLevel {
+    fn from(level: OptimizationLevel) -> Self {
+        match level {
+            OptimizationLevel::None => cpr_bf::ir::OptimizationLevel::None,
+            OptimizationLevel::Basic => cpr_bf::ir::OptimizationLevel::Basic,
+            OptimizationLevel::Full => cpr_bf::ir::OptimizationLevel::Full,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub(crate) enum Pass {
+    DeadLoops,
+    FuseRuns,
+    ClearLoops,
+    ScanLoops,
+    FuseOffsets,
+}
+
+impl From<Pass> for cpr_bf::ir::Pass {
+    fn from(pass: Pass) -> Self {
+        match pass {
+            Pass::DeadLoops => cpr_bf::ir::Pass::DeadLoops,
+            Pass::FuseRuns => cpr_bf::ir::Pass::FuseRuns,
+            Pass::ClearLoops => cpr_bf::ir::Pass::ClearLoops,
+            Pass::ScanLoops => cpr_bf::ir::Pass::ScanLoops,
+            Pass::FuseOffsets => cpr_bf::ir::Pass::FuseOffsets,
+        }
+    }
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use cpr_bf::{
+    debug::{CellAccess, DebuggerVM, StopReason, WatchMode},
+    dump::{self, DumpOptions},
+    BrainfuckAllocator, BrainfuckCell, ExecutionState, FlushPolicy, Program, VMBuilder,
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+/// The amount of rows of the tape shown above and below the row of the data pointer
+const TAPE_CONTEXT_ROWS: usize = 1;
+
+/// The amount of instructions executed between redraws while continuing
+const STEPS_PER_FRAME: u64 = 10_000;
+
+/// The key bindings, shown at the bottom of the screen
+const HELP: &str =
+    "[s] step  [c] continue / pause  [b] toggle breakpoint  [w] toggle watchpoint  [q] quit";
+
+type Debugger<T, A> = DebuggerVM<T, A, Box<dyn Read>, Vec<u8>>;
+
+/// Runs `program` in a terminal debugger built from `builder`.
+///
+/// The program reads from the file at `input`, or from an empty input if none is given,
+/// as stdin is used by the terminal. Its output is shown in the debugger
+pub(crate) fn run_debugger<T, A, R, W>(
+    builder: VMBuilder<T, A, R, W>,
+    program: &Program,
+    input: Option<&Path>,
+) -> ExitCode
+where
+    T: BrainfuckCell + 'static,
+    A: BrainfuckAllocator + 'static,
+    R: Read + 'static,
+    W: Write + 'static,
+{
+    let reader: Box<dyn Read> = match input {
+        Some(path) => match File::open(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                log::error!("Could not open input file: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Box::new(io::empty()),
+    };
+
+    let vm = builder
+        .with_reader(reader)
+        .with_writer(Vec::new())
+        .with_flush_policy(FlushPolicy::EveryOutput)
+        .build_typed();
+
+    let mut debugger = DebuggerVM::new(vm);
+
+    if let Err(e) = debugger.load_program(program) {
+        log::error!("Could not load program: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let mut terminal = match ratatui::try_init() {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            log::error!("Could not initialize terminal: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Log messages would be written over the terminal UI
+    let log_level = log::max_level();
+    log::set_max_level(log::LevelFilter::Off);
+
+    let result = App::new(debugger).run(&mut terminal);
+
+    ratatui::restore();
+    log::set_max_level(log_level);
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("Error while running debugger: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The state of the terminal debugger
+struct App<T: BrainfuckCell, A: BrainfuckAllocator> {
+    debugger: Debugger<T, A>,
+    status: String,
+    continuing: bool,
+}
+
+impl<T: BrainfuckCell, A: BrainfuckAllocator> App<T, A> {
+    fn new(debugger: Debugger<T, A>) -> Self {
+        App {
+            debugger,
+            status: String::from("Paused"),
+            continuing: false,
+        }
+    }
+
+    /// Handles input and redraws the screen until the user quits
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if self.continuing {
+                if let Some(reason) = self.debugger.continue_for(STEPS_PER_FRAME) {
+                    self.stop(reason);
+                }
+
+                // Any key pauses a running program
+                if !event::poll(Duration::ZERO)? {
+                    continue;
+                }
+            }
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if self.continuing {
+                self.continuing = false;
+                self.status = String::from("Paused");
+
+                if key.code != KeyCode::Char('q') {
+                    continue;
+                }
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('s') => self.step(),
+                KeyCode::Char('c') => {
+                    self.continuing = true;
+                    self.status = String::from("Running");
+                }
+                KeyCode::Char('b') => self.toggle_breakpoint(),
+                KeyCode::Char('w') => self.toggle_watchpoint(),
+                _ => {}
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        self.status = match self.debugger.step() {
+            ExecutionState::Running => String::from("Paused"),
+            ExecutionState::Halted => String::from("Halted"),
+            ExecutionState::Error(e) => format!("Error: {}", e),
+        };
+    }
+
+    fn stop(&mut self, reason: StopReason<T>) {
+        self.continuing = false;
+        self.status = match reason {
+            StopReason::Breakpoint(instr_ptr) => format!("Hit breakpoint at {}", instr_ptr),
+            StopReason::Watchpoint(hit) => match hit.access {
+                CellAccess::Read => {
+                    format!("Cell {} read at {}: {:?}", hit.cell, hit.instr_ptr, hit.new)
+                }
+                CellAccess::Write => format!(
+                    "Cell {} written at {}: {:?} -> {:?}",
+                    hit.cell, hit.instr_ptr, hit.old, hit.new
+                ),
+            },
+            StopReason::Halted => String::from("Halted"),
+            StopReason::Error(e) => format!("Error: {}", e),
+        };
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        let instr_ptr = self.debugger.instr_ptr();
+
+        if !self.debugger.remove_breakpoint(instr_ptr) {
+            self.debugger.add_breakpoint(instr_ptr);
+        }
+    }
+
+    /// Toggles a watchpoint on reads and writes of the current cell
+    fn toggle_watchpoint(&mut self) {
+        let data_ptr = self.debugger.data_ptr();
+        let cells = data_ptr..data_ptr + 1;
+
+        self.status = if self.debugger.remove_watchpoint(cells.clone()) {
+            format!("Stopped watching cell {}", data_ptr)
+        } else {
+            self.debugger.add_watchpoint(cells, WatchMode::ReadWrite);
+            format!("Watching cell {}", data_ptr)
+        };
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [source, tape, output, status] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(TAPE_CONTEXT_ROWS as u16 * 2 + 3),
+            Constraint::Length(8),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+
+        self.draw_source(frame, source);
+        self.draw_tape(frame, tape);
+        self.draw_output(frame, output);
+
+        let status_text = vec![
+            Line::from(format!(
+                "ip={}  ptr={}  {}",
+                self.debugger.instr_ptr(),
+                self.debugger.data_ptr(),
+                self.status
+            )),
+            Line::styled(HELP, Style::new().fg(Color::DarkGray)),
+        ];
+
+        frame.render_widget(Paragraph::new(status_text), status);
+    }
+
+    /// Draws the instructions of the program, highlighting the next
+    /// instruction and all breakpoints
+    fn draw_source(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::new().borders(Borders::ALL).title(" Source ");
+        let inner = block.inner(area);
+        let width = usize::from(inner.width.max(1));
+
+        let instructions = self.debugger.instructions();
+        let instr_ptr = self.debugger.instr_ptr();
+        let breakpoints: Vec<usize> = self.debugger.breakpoints().collect();
+
+        let lines: Vec<Line> = instructions
+            .chunks(width)
+            .enumerate()
+            .map(|(line_idx, chunk)| {
+                Line::from(
+                    chunk
+                        .iter()
+                        .enumerate()
+                        .map(|(i, instr)| {
+                            let index = line_idx * width + i;
+                            let mut style = Style::new();
+
+                            if breakpoints.contains(&index) {
+                                style = style.fg(Color::Red);
+                            }
+
+                            if index == instr_ptr {
+                                style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+                            }
+
+                            Span::styled(instr.to_char().to_string(), style)
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+
+        // Keep the next instruction in the middle of the view
+        let scroll = (instr_ptr / width).saturating_sub(usize::from(inner.height) / 2);
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(block)
+                .scroll((scroll as u16, 0)),
+            area,
+        );
+    }
+
+    /// Draws the rows of the tape around the data pointer, highlighting the current cell
+    fn draw_tape(&self, frame: &mut Frame, area: Rect) {
+        let options = DumpOptions::new()
+            .with_context_rows(TAPE_CONTEXT_ROWS)
+            .with_trimmed_zeros(false);
+
+        let tape = dump::format_tape(self.debugger.tape(), self.debugger.data_ptr(), options);
+
+        frame.render_widget(
+            Paragraph::new(tape).block(Block::new().borders(Borders::ALL).title(" Tape ")),
+            area,
+        );
+    }
+
+    /// Draws the output of the program so far, scrolled to the end
+    fn draw_output(&self, frame: &mut Frame, area: Rect) {
+        let output = String::from_utf8_lossy(self.debugger.vm().writer());
+        let visible = area.height.saturating_sub(2) as usize;
+        let scroll = output.lines().count().saturating_sub(visible);
+
+        frame.render_widget(
+            Paragraph::new(output.into_owned())
+                .block(Block::new().borders(Borders::ALL).title(" Output "))
+                .scroll((scroll as u16, 0)),
+            area,
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Errors pointing at a position in the source code, printed like rustc does
+
+use std::error::Error;
+use std::fmt::{self, Display};
+
+use cpr_bf::{BrainfuckExecutionError, ParseError, SourcePosition};
+
+/// The amount of characters shown on either side of the caret. Minified programs
+/// are often a single line, which is cut off around the position instead
+const CONTEXT_CHARS: usize = 40;
+
+/// The source code of a program, with where it was read from
+#[derive(Clone, Copy)]
+pub(crate) struct SourceFile<'a> {
+    /// The file name, or a description such as "<stdin>"
+    pub origin: &'a str,
+    pub text: &'a str,
+}
+
+/// An error at a position in the source code. Displayed as the message, followed by
+/// the line of the source code at the position with a caret under the offending
+/// character, and an optional note
+pub(crate) struct Diagnostic<'a> {
+    message: String,
+    file: SourceFile<'a>,
+    position: SourcePosition,
+    note: Option<String>,
+}
+
+impl<'a> Diagnostic<'a> {
+    /// The diagnostic for a program in `file` that could not be loaded.
+    /// [`None`] if the error is not a [`ParseError`] with a known position
+    pub(crate) fn for_load_error(file: SourceFile<'a>, e: &(dyn Error + 'static)) -> Option<Self> {
+        let parse_error = e.downcast_ref::<ParseError>()?;
+
+        let message = match parse_error {
+            ParseError::JumpMismatch(unmatched) => unmatched.missing.to_string(),
+            ParseError::InvalidToken { .. } => "Invalid token".to_string(),
+            ParseError::InvalidCharacter { character, .. } => {
+                format!("Invalid character {:?}", character)
+            }
+        };
+
+        Some(Diagnostic {
+            message,
+            file,
+            position: parse_error.position()?,
+            note: None,
+        })
+    }
+
+    /// The diagnostic for a program in `file` that failed while running.
+    /// [`None`] if the position of the failing instruction is not known
+    pub(crate) fn for_runtime_error(
+        file: SourceFile<'a>,
+        e: &BrainfuckExecutionError,
+    ) -> Option<Self> {
+        let context = e.context()?;
+
+        Some(Diagnostic {
+            message: e.kind().to_string(),
+            file,
+            position: context.source_position?,
+            note: Some(format!(
+                "stopped at operation {} with the data pointer at cell {}, after {} steps",
+                context.instr_ptr, context.data_ptr, context.steps
+            )),
+        })
+    }
+}
+
+impl Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line_number = self.position.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        let line: Vec<char> = self
+            .file
+            .text
+            .lines()
+            .nth(self.position.line.saturating_sub(1))
+            .unwrap_or_default()
+            .chars()
+            .collect();
+
+        let column = self.position.column.saturating_sub(1).min(line.len());
+        let start = column.saturating_sub(CONTEXT_CHARS);
+        let end = line.len().min(column + CONTEXT_CHARS);
+
+        let prefix = if start > 0 { "..." } else { "" };
+        let suffix = if end < line.len() { "..." } else { "" };
+
+        // Tabs are kept, so that the caret lines up with the character above it
+        let indent: String = line[start..column]
+            .iter()
+            .map(|&c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter, self.file.origin, self.position.line, self.position.column
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(
+            f,
+            "{} | {}{}{}",
+            line_number,
+            prefix,
+            line[start..end].iter().collect::<String>(),
+            suffix
+        )?;
+        writeln!(f, "{} | {}{}^", gutter, " ".repeat(prefix.len()), indent)?;
+
+        if let Some(note) = &self.note {
+            writeln!(f, "{} = note: {}", gutter, note)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! The machine-readable output written with --format json
+
+use std::error::Error as StdError;
+
+use cpr_bf::{validation::ValidationReport, ParseError, Program, RunReport, SourcePosition};
+use serde::Serialize;
+
+/// The outcome of running or checking a program, written as a single line of JSON
+#[derive(Serialize, Debug)]
+pub(crate) struct Output {
+    /// Whether the program halted, or passed the check
+    pub success: bool,
+
+    /// The exit code of the CLI
+    pub exit_code: u8,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+}
+
+impl Output {
+    /// The output of a program that could not be loaded
+    pub(crate) fn load_error(error: &(dyn StdError + 'static), exit_code: u8) -> Self {
+        Output {
+            success: false,
+            exit_code,
+            error: Some(Error::from_load_error(error)),
+            stats: None,
+            summary: None,
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Could not serialize output")
+    }
+}
+
+/// Why a program could not be loaded or run
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum Error {
+    /// The program could not be read, or is not a valid bytecode file
+    Load { message: String },
+
+    /// The source code of the program could not be parsed
+    Parse {
+        message: String,
+        position: Option<Position>,
+    },
+
+    /// The program stopped with an error while running
+    Runtime {
+        message: String,
+
+        /// The index of the compiled operation that failed
+        instr_ptr: usize,
+
+        /// The position of the failing instruction in the source code, if known
+        position: Option<Position>,
+
+        /// The index of the current cell
+        data_ptr: usize,
+    },
+}
+
+impl Error {
+    fn from_load_error(error: &(dyn StdError + 'static)) -> Self {
+        let Some(parse_error) = error.downcast_ref::<ParseError>() else {
+            return Error::Load {
+                message: error.to_string(),
+            };
+        };
+
+        Error::Parse {
+            message: parse_error.to_string(),
+            position: parse_error.position().map(Position::from),
+        }
+    }
+}
+
+/// A position in the source code of a program, see [`SourcePosition`]
+#[derive(Serialize, Debug)]
+pub(crate) struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl From<SourcePosition> for Position {
+    fn from(position: SourcePosition) -> Self {
+        Position {
+            offset: position.offset,
+            line: position.line,
+            column: position.column,
+        }
+    }
+}
+
+/// The execution statistics of a program, see [`RunReport`]
+#[derive(Serialize, Debug)]
+pub(crate) struct Stats {
+    pub steps: u64,
+    pub peak_tape_size: usize,
+    pub max_data_ptr: isize,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub wall_time_secs: Option<f64>,
+}
+
+impl From<RunReport> for Stats {
+    fn from(report: RunReport) -> Self {
+        Stats {
+            steps: report.steps,
+            peak_tape_size: report.peak_tape_size,
+            max_data_ptr: report.max_data_ptr,
+            bytes_read: report.bytes_read,
+            bytes_written: report.bytes_written,
+            wall_time_secs: report.wall_time.map(|time| time.as_secs_f64()),
+        }
+    }
+}
+
+/// The summary printed by the check subcommand
+#[derive(Serialize, Debug)]
+pub(crate) struct Summary {
+    pub instructions: usize,
+    pub loops: usize,
+    pub max_loop_depth: usize,
+
+    /// The amount of cells the program can reach, if known
+    pub tape_extent: Option<usize>,
+    pub warnings: Vec<String>,
+}
+
+impl Summary {
+    pub(crate) fn new(program: &Program, report: &ValidationReport) -> Self {
+        let metrics = program.metrics();
+
+        Summary {
+            instructions: metrics.instructions,
+            loops: metrics.loops,
+            max_loop_depth: report.max_loop_depth,
+            tape_extent: report
+                .rightmost_cell
+                .map(|rightmost_cell| rightmost_cell + 1),
+            warnings: report.warnings.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
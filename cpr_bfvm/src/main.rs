@@ -1,65 +1,127 @@
 mod cli_args;
+#[cfg(feature = "terminal")]
+mod debugger;
+mod diagnostic;
+mod json;
+mod profile;
+mod repl;
+mod terminal;
 
-use std::fs::File;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
-use clap::Parser;
-use cli_args::CLIArgs;
-use cpr_bf::{allocators::*, VMBuilder};
-use simplelog::{ColorChoice, ConfigBuilder, TermLogger, TerminalMode};
+use cli_args::{CLIArgs, Command};
+use cpr_bf::{
+    allocators::*,
+    bytecode,
+    cache::ProgramCache,
+    codegen::{c::CGenerator, rust::RustGenerator, wasm::WasmGenerator},
+    dialects::Dialect,
+    dump::{self, DumpOptions},
+    fmt::FormatStyle,
+    io::{Read, Write},
+    ir::{CompiledProgram, OptimizationLevel, Pass, Pipeline},
+    selfmod::SelfModifyingVM,
+    BfResult, BrainfuckAllocator, BrainfuckCell, BrainfuckExecutionError, BrainfuckVM, Program,
+    RunReport, VMBuilder,
+};
+use diagnostic::{Diagnostic, SourceFile};
+use simplelog::ConfigBuilder;
 
 macro_rules! assign_allocator_and_build {
-    ($args:expr, $builder:expr) => {
+    ($args:expr, $builder:expr, $finish:expr $(, $arg:expr)*) => {
         match $args.allocator {
-            cli_args::Allocator::Dynamic => $builder.with_allocator::<DynamicAllocator>().build(),
-            cli_args::Allocator::StaticChecked => $builder
-                .with_allocator::<BoundsCheckingStaticAllocator>()
-                .build(),
+            cli_args::Allocator::Dynamic => $finish($builder.with_allocator::<DynamicAllocator>() $(, $arg)*),
+            cli_args::Allocator::Paged => $finish($builder.with_allocator::<PagedAllocator>() $(, $arg)*),
+            cli_args::Allocator::DoublyInfinite => {
+                $finish($builder.with_allocator::<DoublyInfiniteAllocator>() $(, $arg)*)
+            }
+            cli_args::Allocator::StaticChecked => {
+                $finish($builder.with_allocator::<BoundsCheckingStaticAllocator>() $(, $arg)*)
+            }
             cli_args::Allocator::StaticUnchecked => {
-                $builder.with_allocator::<StaticAllocator>().build()
+                $finish($builder.with_allocator::<StaticAllocator>() $(, $arg)*)
+            }
+            cli_args::Allocator::StaticWrapping => {
+                $finish($builder.with_allocator::<WrappingStaticAllocator>() $(, $arg)*)
             }
         }
     };
 }
 
 macro_rules! assign_cellsize_and_build {
-    ($args:expr, $builder:expr) => {
+    ($args:expr, $builder:expr, $finish:expr $(, $arg:expr)*) => {
         match $args.cellsize {
             cli_args::CellSize::U8 => {
-                assign_allocator_and_build!($args, $builder.with_cell_type::<u8>())
+                assign_allocator_and_build!($args, $builder.with_cell_type::<u8>(), $finish $(, $arg)*)
             }
             cli_args::CellSize::U16 => {
-                assign_allocator_and_build!($args, $builder.with_cell_type::<u16>())
+                assign_allocator_and_build!($args, $builder.with_cell_type::<u16>(), $finish $(, $arg)*)
             }
             cli_args::CellSize::U32 => {
-                assign_allocator_and_build!($args, $builder.with_cell_type::<u32>())
+                assign_allocator_and_build!($args, $builder.with_cell_type::<u32>(), $finish $(, $arg)*)
             }
             cli_args::CellSize::U64 => {
-                assign_allocator_and_build!($args, $builder.with_cell_type::<u64>())
+                assign_allocator_and_build!($args, $builder.with_cell_type::<u64>(), $finish $(, $arg)*)
             }
             cli_args::CellSize::U128 => {
-                assign_allocator_and_build!($args, $builder.with_cell_type::<u128>())
+                assign_allocator_and_build!($args, $builder.with_cell_type::<u128>(), $finish $(, $arg)*)
+            }
+            cli_args::CellSize::I8 => {
+                assign_allocator_and_build!($args, $builder.with_cell_type::<i8>(), $finish $(, $arg)*)
+            }
+            cli_args::CellSize::I16 => {
+                assign_allocator_and_build!($args, $builder.with_cell_type::<i16>(), $finish $(, $arg)*)
+            }
+            cli_args::CellSize::I32 => {
+                assign_allocator_and_build!($args, $builder.with_cell_type::<i32>(), $finish $(, $arg)*)
+            }
+            cli_args::CellSize::I64 => {
+                assign_allocator_and_build!($args, $builder.with_cell_type::<i64>(), $finish $(, $arg)*)
+            }
+            cli_args::CellSize::I128 => {
+                assign_allocator_and_build!($args, $builder.with_cell_type::<i128>(), $finish $(, $arg)*)
             }
         }
     };
 }
 
 macro_rules! assign_input_and_build {
-    ($args:expr, $builder:expr) => {
-        match $args.input {
-            Some(input) => {
+    ($args:expr, $builder:expr, $finish:expr $(, $arg:expr)*) => {
+        match ($args.input, $args.input_string) {
+            (Some(input), _) => {
                 assign_cellsize_and_build!(
                     $args,
-                    $builder.with_reader(File::open(input).expect("Could not open input file"))
+                    $builder.with_reader(File::open(input).expect("Could not open input file")),
+                    $finish
+                    $(, $arg)*
                 )
             }
-            None => assign_cellsize_and_build!($args, $builder),
+            (None, Some(cli_args::InputString(input))) => assign_cellsize_and_build!(
+                $args,
+                $builder.with_reader(io::Cursor::new(input)),
+                $finish
+                $(, $arg)*
+            ),
+            // A program read from stdin reads its input from the terminal instead
+            (None, None) => assign_cellsize_and_build!(
+                $args,
+                $builder.with_reader(terminal::TerminalReader::new(
+                    $args.raw_input,
+                    $args.filename.as_deref() == Some(Path::new("-"))
+                )),
+                $finish
+                $(, $arg)*
+            ),
         }
     };
 }
 
 macro_rules! assign_output_and_build {
-    ($args:expr, $builder:expr) => {
+    ($args:expr, $builder:expr, $finish:expr $(, $arg:expr)*) => {
         match $args.output {
             Some(output) => {
                 let output_file = File::options()
@@ -68,47 +130,851 @@ macro_rules! assign_output_and_build {
                     .write(true)
                     .open(output)
                     .expect("Could not open output file");
-                assign_input_and_build!($args, $builder.with_writer(output_file))
+                assign_input_and_build!($args, $builder.with_writer(output_file), $finish $(, $arg)*)
             }
-            None => assign_input_and_build!($args, $builder),
+            None => assign_input_and_build!(
+                $args,
+                $builder.with_writer(terminal::TerminalWriter::new($args.raw_input)),
+                $finish
+                $(, $arg)*
+            ),
         }
     };
 }
 
+/// Configures a [`VMBuilder`] with the options in `$args`, and passes it to
+/// `$finish`, which is generic over the type of the builder, followed by any `$arg`s
 macro_rules! process_args_and_build_vm {
-    ($args:expr) => {{
-        let vm_builder = VMBuilder::new().with_preallocated_cells($args.preallocated);
-        assign_output_and_build!($args, vm_builder)
+    ($args:expr, $finish:expr $(, $arg:expr)*) => {{
+        let mut vm_builder = VMBuilder::new()
+            .with_preallocated_cells($args.preallocated.initial_cells())
+            .with_optimization_level($args.optimization.level.clone().into())
+            .with_eof_behavior($args.eof.clone().into())
+            .with_overflow_policy($args.overflow.clone().into())
+            .with_io_mode($args.io_mode.clone().into())
+            .with_input_echo($args.echo_input.clone().into())
+            .with_extensions(Dialect::from($args.dialect.clone()).extensions())
+            .with_bang_input($args.bang_input);
+
+        if let Some(encoding) = $args.output_encoding.clone() {
+            vm_builder = vm_builder.with_output_encoding(encoding.into());
+        }
+
+        if let cli_args::Preallocated::Auto = $args.preallocated {
+            vm_builder = vm_builder.with_auto_preallocated_cells();
+        }
+
+        if let Some(max_cells) = $args.max_cells {
+            vm_builder = vm_builder.with_max_cells(max_cells);
+        }
+
+        if let Some(max_steps) = $args.max_steps {
+            vm_builder = vm_builder.with_max_steps(max_steps);
+        }
+
+        if let Some(timeout) = $args.timeout {
+            vm_builder = vm_builder.with_timeout(timeout);
+        }
+
+        assign_output_and_build!($args, vm_builder, $finish $(, $arg)*)
     }};
 }
 
+/// Reads the file at `path`, or everything from stdin if `path` is '-'
+fn read_program_file(path: &Path) -> io::Result<Vec<u8>> {
+    if path != Path::new("-") {
+        return fs::read(path);
+    }
+
+    log::debug!("Reading program from stdin");
+
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+/// Reads the source code at `path` like [`read_program_file`]
+fn read_source_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    Ok(String::from_utf8(read_program_file(path)?)?)
+}
+
+/// A program loaded with [`load_program`]
+struct LoadedProgram {
+    program: Program,
+
+    /// The Brainfuck source code of the program, if any
+    source: Option<String>,
+
+    /// The input following the '!' separator of the source code, see --bang-input
+    input: Vec<u8>,
+}
+
+/// Loads the program in `data`, which is either source code in the given
+/// dialect or the contents of a bytecode file.
+///
+/// With `bang_input`, source code is split at its first '!', and everything
+/// after it is returned as the input of the program
+fn load_program(
+    data: &[u8],
+    dialect: Dialect,
+    bang_input: bool,
+) -> Result<LoadedProgram, Box<dyn Error>> {
+    if bytecode::is_bytecode(data) {
+        log::debug!("Loading bytecode file");
+        return Ok(LoadedProgram {
+            program: Program::from_bytes(data)?,
+            source: None,
+            input: Vec::new(),
+        });
+    }
+
+    let (source, input) = split_input(String::from_utf8(data.to_vec())?, bang_input);
+    let program = dialect.parse(&source)?;
+
+    let source = match dialect {
+        Dialect::Ook => None,
+        _ => Some(source),
+    };
+
+    Ok(LoadedProgram {
+        program,
+        source,
+        input,
+    })
+}
+
+/// A program loaded with [`load_cached`]
+struct CachedProgram {
+    compiled: CompiledProgram,
+
+    /// The input following the '!' separator of the source code, see --bang-input
+    input: Vec<u8>,
+}
+
+/// Loads the program in `data` like [`load_program`], and compiles it with `pipeline`
+/// through the program cache in `cache_dir`. Source code compiled before is loaded from
+/// the cache, instead of being parsed and optimized again.
+///
+/// Every compiled program is stored in `{key}.bfo`, next to its source code in `{key}.bf`.
+/// The source code is compared before using the compiled program, as programs with
+/// different source code can have the same key.
+///
+/// Returns [`None`] for bytecode files, which are not cached
+fn load_cached(
+    data: &[u8],
+    cache_dir: &Path,
+    dialect: Dialect,
+    bang_input: bool,
+    pipeline: Pipeline,
+) -> Result<Option<CachedProgram>, Box<dyn Error>> {
+    if bytecode::is_bytecode(data) {
+        log::debug!("Not caching bytecode file");
+        return Ok(None);
+    }
+
+    let (source, input) = split_input(String::from_utf8(data.to_vec())?, bang_input);
+
+    let mut cache = ProgramCache::new()
+        .with_dialect(dialect)
+        .with_pipeline(pipeline);
+
+    let key = cache.key(&source);
+    let cache_file = cache_dir.join(format!("{:016x}.bfo", key));
+    let source_file = cache_dir.join(format!("{:016x}.bf", key));
+
+    match fs::read(&source_file) {
+        Ok(cached) if cached == source.as_bytes() => {
+            match fs::read(&cache_file).map(|data| CompiledProgram::from_bytes(&data)) {
+                Ok(Ok(compiled)) => {
+                    log::info!("Loaded compiled program from {}", cache_file.display());
+                    return Ok(Some(CachedProgram { compiled, input }));
+                }
+                Ok(Err(e)) => log::warn!(
+                    "Ignoring invalid cached program {}: {}",
+                    cache_file.display(),
+                    e
+                ),
+                Err(_) => log::debug!("Program not found in cache"),
+            }
+        }
+        Ok(_) => log::warn!(
+            "Cached program {} was compiled from different source code, replacing it",
+            cache_file.display()
+        ),
+        Err(_) => log::debug!("Program not found in cache"),
+    }
+
+    let compiled = cache.get_or_compile(&source)?.clone();
+
+    // The source code is removed first and written last, so that it never
+    // belongs to a compiled program of other source code
+    let result = fs::create_dir_all(cache_dir)
+        .and_then(|()| match fs::remove_file(&source_file) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        })
+        .and_then(|()| fs::write(&cache_file, compiled.to_bytes()))
+        .and_then(|()| fs::write(&source_file, &source));
+
+    match result {
+        Ok(()) => log::info!("Cached compiled program in {}", cache_file.display()),
+        Err(e) => log::warn!("Could not cache compiled program: {}", e),
+    }
+
+    Ok(Some(CachedProgram { compiled, input }))
+}
+
+/// Warns about everything in `program` that might fail on a static tape of the given size
+fn warn_for_tape(program: &Program, cells: usize) {
+    for warning in program.validate_for_tape(cells).warnings {
+        log::warn!("{}", warning);
+    }
+}
+
+/// With `bang_input`, splits `source` at its first '!', and returns everything
+/// after it as the input of the program
+fn split_input(mut source: String, bang_input: bool) -> (String, Vec<u8>) {
+    let mut input = Vec::new();
+
+    if let Some(separator) = source.find('!').filter(|_| bang_input) {
+        input = source.split_off(separator + 1).into_bytes();
+        source.truncate(separator);
+    }
+
+    (source, input)
+}
+
+/// Formats or minifies the source file given to the fmt subcommand
+fn format_file(args: &cli_args::FmtArgs) -> Result<(), Box<dyn Error>> {
+    let dialect = Dialect::from(args.dialect.clone());
+
+    if dialect == Dialect::Ook {
+        return Err("Ook! source code can not be formatted".into());
+    }
+
+    if args.write && args.filename == Path::new("-") {
+        return Err("Source code read from stdin can not be overwritten".into());
+    }
+
+    let source = read_source_file(&args.filename)?;
+
+    let result = if args.minify {
+        cpr_bf::fmt::minify_with_extensions(&source, dialect.extensions())
+    } else {
+        let style = FormatStyle::new()
+            .with_indent(args.indent)
+            .with_line_width(args.width)
+            .with_extensions(dialect.extensions());
+
+        cpr_bf::fmt::format(&source, style)
+    };
+
+    if args.write {
+        fs::write(&args.filename, result)?;
+    } else {
+        print!("{}", result);
+    }
+
+    Ok(())
+}
+
+/// Parses and validates the program given to the check subcommand, and summarizes it
+fn check_file(args: &cli_args::CheckArgs) -> Result<json::Summary, Box<dyn Error>> {
+    let data = read_program_file(&args.filename)?;
+    let LoadedProgram { program, .. } =
+        load_program(&data, args.dialect.clone().into(), args.bang_input)?;
+
+    let report = match args.tape_size {
+        Some(tape_size) => program.validate_for_tape(tape_size),
+        None => program.validate(),
+    };
+
+    if let Some(missing) = report.unmatched {
+        return Err(format!("Missing {:?} instruction", missing).into());
+    }
+
+    Ok(json::Summary::new(&program, &report))
+}
+
+/// Prints the result of [`check_file`] in the given format
+fn print_check_result(
+    format: &cli_args::OutputFormat,
+    result: Result<json::Summary, Box<dyn Error>>,
+) -> ExitCode {
+    let summary = match (format, result) {
+        (cli_args::OutputFormat::Json, result) => {
+            let output = match result {
+                Ok(summary) => json::Output {
+                    success: true,
+                    exit_code: 0,
+                    error: None,
+                    stats: None,
+                    summary: Some(summary),
+                },
+                Err(e) => json::Output::load_error(e.as_ref(), 1),
+            };
+
+            println!("{}", output.to_json());
+            return ExitCode::from(output.exit_code);
+        }
+        (cli_args::OutputFormat::Text, Err(e)) => {
+            log::error!("Invalid program: {}", e);
+            return ExitCode::FAILURE;
+        }
+        (cli_args::OutputFormat::Text, Ok(summary)) => summary,
+    };
+
+    for warning in &summary.warnings {
+        log::warn!("{}", warning);
+    }
+
+    let extent = match summary.tape_extent {
+        Some(tape_extent) => format!("{} cells", tape_extent),
+        None => "unknown".to_string(),
+    };
+
+    print!(
+        "Instructions:   {}\n\
+         Loops:          {}\n\
+         Max loop depth: {}\n\
+         Tape extent:    {}\n",
+        summary.instructions, summary.loops, summary.max_loop_depth, extent
+    );
+
+    ExitCode::SUCCESS
+}
+
+/// Reports that the program could not be loaded, in the format selected with --format.
+/// Parse errors in `file` are shown with the offending line of the source code
+fn report_load_error(
+    format: &cli_args::OutputFormat,
+    e: &(dyn Error + 'static),
+    file: Option<SourceFile>,
+) -> ExitCode {
+    let diagnostic = file.and_then(|file| Diagnostic::for_load_error(file, e));
+
+    match (format, diagnostic) {
+        (cli_args::OutputFormat::Text, Some(diagnostic)) => eprint!("{}", diagnostic),
+        (cli_args::OutputFormat::Text, None) => log::error!("Could not load program: {}", e),
+        (cli_args::OutputFormat::Json, _) => {
+            eprintln!("{}", json::Output::load_error(e, 1).to_json())
+        }
+    }
+
+    ExitCode::FAILURE
+}
+
+/// Describes where the program given to the run subcommand was read from
+fn source_origin(args: &cli_args::RunArgs) -> String {
+    match (&args.eval, &args.filename) {
+        (Some(_), _) => "<eval>".to_string(),
+        (None, Some(filename)) if filename == Path::new("-") => "<stdin>".to_string(),
+        (None, Some(filename)) => filename.display().to_string(),
+        (None, None) => "<repl>".to_string(),
+    }
+}
+
+/// Compiles the program given to the compile subcommand, and writes it to its output file
+fn compile_file(args: &cli_args::CompileArgs) -> Result<(), Box<dyn Error>> {
+    let data = read_program_file(&args.filename)?;
+    let LoadedProgram { program, .. } = load_program(&data, args.dialect.clone().into(), false)?;
+
+    log::info!("Compiling program to {:?}", args.target);
+
+    fs::write(&args.output, compile(&program, args)?)?;
+
+    Ok(())
+}
+
+/// Runs `source` as self-modifying Brainfuck
+fn run_self_modifying<T, A, R, W>(builder: VMBuilder<T, A, R, W>, source: &str) -> ExitCode
+where
+    T: BrainfuckCell + 'static,
+    A: BrainfuckAllocator + 'static,
+    R: Read + 'static,
+    W: Write + 'static,
+{
+    let mut vm = SelfModifyingVM::new(builder.build_typed());
+
+    log::info!("Running self-modifying program");
+
+    if let Err(e) = vm.run_string(source) {
+        log::error!("Error during brainfuck execution: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    log::info!("Program execution finished successfully");
+    ExitCode::SUCCESS
+}
+
+/// The optimization passes selected with --opt, or else those of the optimization level.
+/// Unless the program runs on a `zeroed_tape`, passes that assume so are left out, and
+/// unless cells are `wrapping`, so are passes that assume clear loops always terminate
+fn pipeline(args: &cli_args::OptimizationArgs, zeroed_tape: bool, wrapping: bool) -> Pipeline {
+    let level = OptimizationLevel::from(args.level.clone());
+
+    let passes: Vec<Pass> = if !args.passes.is_empty() {
+        args.passes.iter().cloned().map(Pass::from).collect()
+    } else if zeroed_tape {
+        level.zeroed_tape_passes().to_vec()
+    } else {
+        level.passes().to_vec()
+    };
+
+    passes
+        .into_iter()
+        .filter(|&pass| zeroed_tape || pass != Pass::DeadLoops)
+        .filter(|&pass| wrapping || pass != Pass::ClearLoops)
+        .fold(Pipeline::new(), |pipeline, pass| pipeline.with_pass(pass))
+}
+
+/// The exit code when a program reaches the limit set with --max-steps
+const EXIT_STEP_LIMIT: u8 = 3;
+
+/// The exit code when a program reaches the limit set with --timeout
+const EXIT_TIME_LIMIT: u8 = 4;
+
+/// The tape size of generated source code if no maximum amount of cells is given
+const DEFAULT_CODEGEN_TAPE_SIZE: usize = 30000;
+
+/// Compiles `program` into the target format selected by `args`
+fn compile(program: &Program, args: &cli_args::CompileArgs) -> Result<Vec<u8>, Box<dyn Error>> {
+    let cell_type = || {
+        args.cellsize.codegen_cell_type().ok_or_else(|| {
+            format!(
+                "Cell size {:?} is not supported by {:?}",
+                args.cellsize, args.target
+            )
+        })
+    };
+
+    let tape_size = args.max_cells.unwrap_or(DEFAULT_CODEGEN_TAPE_SIZE);
+
+    match args.target {
+        cli_args::Target::Bytecode => Ok(program.to_bytes()),
+        cli_args::Target::C => {
+            let compiled = pipeline(&args.optimization, true, true).compile(program)?;
+
+            Ok(CGenerator::new()
+                .with_cell_type(cell_type()?)
+                .with_tape_size(tape_size)
+                .generate(&compiled)?
+                .into_bytes())
+        }
+        cli_args::Target::Rust => {
+            let compiled = pipeline(&args.optimization, true, true).compile(program)?;
+
+            Ok(RustGenerator::new()
+                .with_cell_type(cell_type()?)
+                .with_tape_size(args.preallocated.cells_for(program))
+                .generate(&compiled)?
+                .into_bytes())
+        }
+        cli_args::Target::Wasm => {
+            let compiled = pipeline(&args.optimization, true, true).compile(program)?;
+
+            Ok(WasmGenerator::new()
+                .with_cell_type(cell_type()?)
+                .with_tape_size(tape_size)
+                .generate(&compiled)?)
+        }
+    }
+}
+
+/// Formats the execution statistics printed with --stats
+fn format_report(report: &RunReport) -> String {
+    let mut out = format!(
+        "\nSteps executed:   {}\n\
+         Peak tape size:   {} cells\n\
+         Max data pointer: {}\n\
+         Bytes read:       {}\n\
+         Bytes written:    {}\n",
+        report.steps,
+        report.peak_tape_size,
+        report.max_data_ptr,
+        report.bytes_read,
+        report.bytes_written
+    );
+
+    if let Some(wall_time) = report.wall_time {
+        out.push_str(&format!("Wall time:        {:.3?}\n", wall_time));
+    }
+
+    out
+}
+
+/// Formats the tape printed with --dump-tape
+fn format_tape(cells: &[u64], data_ptr: usize) -> String {
+    format!(
+        "Tape ({} cells, data pointer at cell {}):\n{}",
+        cells.len(),
+        data_ptr,
+        dump::format_tape(cells, data_ptr, DumpOptions::new())
+    )
+}
+
 fn main() -> ExitCode {
-    let args = CLIArgs::parse();
+    let args = CLIArgs::parse_args();
 
+    // The local time offset can not be determined on every platform, such as WASI.
+    // Log times are in UTC there
     let logconfig = ConfigBuilder::new()
         .set_time_format_rfc3339()
         .set_time_offset_to_local()
-        .expect("Could not set time offset to local")
+        .unwrap_or_else(|builder| builder)
         .build();
 
-    TermLogger::init(
-        args.verbosity.clone().into(),
-        logconfig,
-        TerminalMode::Stderr,
-        ColorChoice::Auto,
-    )
-    .expect("Could not initialize logger");
+    init_logger(args.verbosity.clone().into(), logconfig);
+
+    match args.into_command() {
+        Command::Run(run_args) => run_file(*run_args),
+        Command::Check(check_args) => {
+            print_check_result(&check_args.format, check_file(&check_args))
+        }
+        Command::Fmt(fmt_args) => match format_file(&fmt_args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                log::error!("Could not format program: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Compile(compile_args) => match compile_file(&compile_args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                log::error!("Could not compile program: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+/// Logs to stderr, in color if stderr is a terminal
+#[cfg(feature = "terminal")]
+fn init_logger(level: log::LevelFilter, config: simplelog::Config) {
+    use simplelog::{ColorChoice, TermLogger, TerminalMode};
+
+    TermLogger::init(level, config, TerminalMode::Stderr, ColorChoice::Auto)
+        .expect("Could not initialize logger");
+}
+
+/// Logs to stderr, without color
+#[cfg(not(feature = "terminal"))]
+fn init_logger(level: log::LevelFilter, config: simplelog::Config) {
+    simplelog::WriteLogger::init(level, config, io::stderr()).expect("Could not initialize logger");
+}
+
+/// Runs the program given to the run subcommand, or starts an interactive session
+fn run_file(mut args: cli_args::RunArgs) -> ExitCode {
+    // Stdin and stdout only need special handling if the terminal is actually in raw mode
+    let raw_mode = terminal::RawMode::enable_if(args.raw_input);
+    args.raw_input = raw_mode.is_some();
+
+    if args.stdin_program {
+        args.filename = Some("-".into());
+    }
+
+    if let Some(path) = args.resume.clone() {
+        let state = match fs::read(&path) {
+            Ok(state) => state,
+            Err(e) => return report_load_error(&args.format, &e, None),
+        };
+
+        log::info!("Resuming program from {}", path.display());
+
+        return run(args, &[], raw_mode, None, |vm| {
+            vm.load_state(&state)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+        });
+    }
+
+    let data = match (&args.eval, &args.filename) {
+        (Some(program), _) => Ok(program.clone().into_bytes()),
+        (None, Some(filename)) => read_program_file(filename),
+        (None, None) => {
+            if args.debug {
+                log::error!("A program file is required");
+                return ExitCode::FAILURE;
+            }
+
+            log::info!("Starting interactive session");
+            return process_args_and_build_vm!(args, repl::run_repl);
+        }
+    };
+
+    let data = match data {
+        Ok(data) => data,
+        Err(e) => return report_load_error(&args.format, &e, None),
+    };
+
+    if args.self_modifying {
+        let source = match String::from_utf8(data) {
+            Ok(source) => source,
+            Err(e) => {
+                log::error!("Could not load program: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        return process_args_and_build_vm!(args, run_self_modifying, &source);
+    }
+
+    let origin = source_origin(&args);
+    let file = std::str::from_utf8(&data).ok().map(|text| SourceFile {
+        origin: &origin,
+        text,
+    });
+
+    // The wrapping allocator never runs out of cells, so only the other static allocators are checked
+    let static_cells = match (&args.allocator, args.preallocated) {
+        (
+            cli_args::Allocator::StaticChecked | cli_args::Allocator::StaticUnchecked,
+            cli_args::Preallocated::Cells(cells),
+        ) => Some(cells),
+        _ => None,
+    };
+
+    if let Some(cache_dir) = &args.cache {
+        let dialect = args.dialect.clone().into();
+
+        match load_cached(
+            &data,
+            cache_dir,
+            dialect,
+            args.bang_input,
+            pipeline(
+                &args.optimization,
+                args.load_tape.is_none(),
+                matches!(args.overflow, cli_args::OverflowPolicy::Wrapping),
+            ),
+        ) {
+            Ok(Some(CachedProgram { compiled, input })) => {
+                // Cached programs are not parsed, unless the source is needed for the check
+                if let Some(cells) = static_cells {
+                    if let Ok(loaded) = load_program(&data, dialect, args.bang_input) {
+                        warn_for_tape(&loaded.program, cells);
+                    }
+                }
+
+                return run(args, &input, raw_mode, file, |vm| {
+                    vm.load_compiled(&compiled)
+                });
+            }
+            Ok(None) => {}
+            Err(e) => return report_load_error(&args.format, e.as_ref(), file),
+        }
+    }
+
+    let LoadedProgram {
+        program,
+        source,
+        input,
+    } = match load_program(&data, args.dialect.clone().into(), args.bang_input) {
+        Ok(loaded) => loaded,
+        Err(e) => return report_load_error(&args.format, e.as_ref(), file),
+    };
+
+    if args.debug {
+        #[cfg(feature = "terminal")]
+        {
+            log::info!("Starting debugger");
+            let input = args.input.clone();
+            return process_args_and_build_vm!(
+                args,
+                debugger::run_debugger,
+                &program,
+                input.as_deref()
+            );
+        }
+
+        #[cfg(not(feature = "terminal"))]
+        {
+            log::error!(
+                "The debugger requires the terminal feature, which this build does not have"
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if args.profile {
+        return process_args_and_build_vm!(
+            args,
+            profile::run_profiled,
+            &program,
+            source.as_deref(),
+            Dialect::from(args.dialect.clone()).extensions()
+        );
+    }
+
+    if let Some(cells) = static_cells {
+        warn_for_tape(&program, cells);
+    }
+
+    let passes = pipeline(
+        &args.optimization,
+        args.load_tape.is_none(),
+        matches!(args.overflow, cli_args::OverflowPolicy::Wrapping),
+    );
+
+    run(args, &input, raw_mode, file, |vm| {
+        vm.load_program_with_pipeline(&program, &passes)
+    })
+}
+
+/// Builds the VM, which writes a checkpoint to the given file after every given amount of steps
+fn build_vm<T, A, R, W>(
+    builder: VMBuilder<T, A, R, W>,
+    checkpoint: Option<(PathBuf, u64)>,
+) -> Box<dyn BrainfuckVM>
+where
+    T: BrainfuckCell + 'static,
+    A: BrainfuckAllocator + 'static,
+    R: Read + 'static,
+    W: Write + 'static,
+{
+    let Some((path, interval)) = checkpoint else {
+        return builder.build();
+    };
+
+    builder
+        .with_checkpoints(interval, move |state| {
+            if let Err(e) = write_checkpoint(&path, &state.to_bytes()) {
+                log::error!("Could not write checkpoint to {}: {}", path.display(), e);
+            }
+        })
+        .build()
+}
+
+/// Replaces the checkpoint at `path`. The checkpoint is written to a temporary file
+/// first, so that the previous checkpoint is kept if writing it fails halfway
+fn write_checkpoint(path: &Path, state: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
 
+    fs::write(&temp, state)?;
+    fs::rename(&temp, path)?;
+
+    log::debug!("Wrote checkpoint to {}", path.display());
+
+    Ok(())
+}
+
+/// Builds the VM configured by `args`, loads the program with `load`, and runs
+/// it with `input` queued. Leaves raw mode once the program stops. Errors are
+/// shown with the offending line of `file`, if their position is known
+fn run(
+    args: cli_args::RunArgs,
+    input: &[u8],
+    raw_mode: Option<terminal::RawMode>,
+    file: Option<SourceFile>,
+    load: impl FnOnce(&mut dyn BrainfuckVM) -> BfResult,
+) -> ExitCode {
     log::info!("Assigning VM options and building");
 
-    let mut vm = process_args_and_build_vm!(args);
+    let stats = args.stats;
+    let dump_tape = args.dump_tape.clone();
+    let save_tape = args.save_tape.clone();
+    let load_tape = args.load_tape.clone();
+    let checkpoint = args
+        .checkpoint
+        .clone()
+        .map(|path| (path, args.checkpoint_every));
+    let exit_cell = args.exit_cell;
+    let format = args.format.clone();
+    let (max_steps, timeout) = (args.max_steps, args.timeout);
+    let mut vm = process_args_and_build_vm!(args, build_vm, checkpoint);
+    vm.queue_input(input);
+
+    if let Some(path) = load_tape {
+        if let Err(e) = fs::read(&path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|image| Ok(vm.load_tape(&image)?))
+        {
+            log::error!("Could not load the tape from {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
 
     log::info!("Running program");
-    if let Err(e) = vm.run_from_path(&args.filename) {
-        log::error!("Error during brainfuck execution: {}", e);
-        return ExitCode::FAILURE;
+    let result = load(vm.as_mut()).and_then(|()| vm.resume_with_report());
+    drop(raw_mode);
+
+    let report = match &result {
+        Ok(report) => *report,
+        Err(_) => vm.run_report(),
+    };
+
+    if stats && matches!(format, cli_args::OutputFormat::Text) {
+        eprint!("{}", format_report(&report));
     }
 
-    log::info!("Program execution finished successfully");
-    ExitCode::SUCCESS
+    if let Some(path) = dump_tape {
+        let tape = format_tape(&vm.cells_as_u64(), vm.data_ptr());
+
+        if path == Path::new("-") {
+            eprint!("\n{}", tape);
+        } else if let Err(e) = fs::write(&path, tape) {
+            log::error!("Could not write the tape to {}: {}", path.display(), e);
+        }
+    }
+
+    if let Some(path) = save_tape {
+        if let Err(e) = fs::write(&path, vm.save_tape()) {
+            log::error!("Could not save the tape to {}: {}", path.display(), e);
+        }
+    }
+
+    let exit_code = match &result {
+        Ok(_) if exit_cell => {
+            let cell = vm.cells_as_u64().get(vm.data_ptr()).copied().unwrap_or(0);
+
+            log::info!("Program halted with {} in the current cell", cell);
+            cell as u8
+        }
+        Ok(_) => 0,
+        Err(e) => match e.kind() {
+            BrainfuckExecutionError::StepLimitExceeded => EXIT_STEP_LIMIT,
+            BrainfuckExecutionError::TimeLimitExceeded => EXIT_TIME_LIMIT,
+            _ => 1,
+        },
+    };
+
+    match (format, result) {
+        (cli_args::OutputFormat::Json, result) => {
+            let output = json::Output {
+                success: result.is_ok(),
+                exit_code,
+                error: result.err().map(|e| json::Error::Runtime {
+                    message: e.kind().to_string(),
+                    instr_ptr: vm.instr_ptr(),
+                    position: e
+                        .context()
+                        .and_then(|context| context.source_position)
+                        .map(json::Position::from),
+                    data_ptr: vm.data_ptr(),
+                }),
+                stats: Some(report.into()),
+                summary: None,
+            };
+
+            eprintln!("{}", output.to_json());
+        }
+        (_, Ok(_)) => log::info!("Program execution finished successfully"),
+        (_, Err(e)) => match e.kind() {
+            BrainfuckExecutionError::StepLimitExceeded => log::error!(
+                "Program stopped after reaching the limit of {} steps",
+                max_steps.unwrap_or_default()
+            ),
+            BrainfuckExecutionError::TimeLimitExceeded => log::error!(
+                "Program stopped after reaching the time limit of {:?}",
+                timeout.unwrap_or_default()
+            ),
+            _ => match file.and_then(|file| Diagnostic::for_runtime_error(file, &e)) {
+                Some(diagnostic) => eprint!("{}", diagnostic),
+                None => log::error!("Error during brainfuck execution: {}", e),
+            },
+        },
+    }
+
+    ExitCode::from(exit_code)
 }
@@ -0,0 +1,115 @@
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+use cpr_bf::{
+    extensions::ExtensionSet,
+    profile::{LoopProfile, Profile, Profiler},
+    BrainfuckAllocator, BrainfuckCell, Instruction, Program, SourcePosition, VMBuilder,
+};
+
+/// The amount of loops shown in the report
+const REPORTED_LOOPS: usize = 10;
+
+/// Runs `program` on a VM built from `builder` while profiling it, and prints
+/// the hottest loops to stderr afterwards. If the source code of the program is
+/// given, the loops are reported with their position in the source code, which
+/// is parsed with the given extensions
+pub(crate) fn run_profiled<T, A, R, W>(
+    builder: VMBuilder<T, A, R, W>,
+    program: &Program,
+    source: Option<&str>,
+    extensions: ExtensionSet,
+) -> ExitCode
+where
+    T: BrainfuckCell + 'static,
+    A: BrainfuckAllocator + 'static,
+    R: Read + 'static,
+    W: Write + 'static,
+{
+    let mut profiler = Profiler::new(builder.build_typed());
+
+    log::info!("Running program with profiling");
+    let result = profiler.run_program(program);
+
+    let positions = source.map(|source| instruction_positions(source, extensions));
+    eprint!(
+        "{}",
+        format_report(profiler.profile(), positions.as_deref())
+    );
+
+    if let Err(e) = result {
+        log::error!("Error during brainfuck execution: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    log::info!("Program execution finished successfully");
+    ExitCode::SUCCESS
+}
+
+/// The position in `source` of every instruction, by instruction index
+fn instruction_positions(source: &str, extensions: ExtensionSet) -> Vec<SourcePosition> {
+    let mut positions = Vec::new();
+    let mut position = SourcePosition {
+        offset: 0,
+        line: 1,
+        column: 1,
+    };
+
+    for (offset, c) in source.char_indices() {
+        position.offset = offset;
+
+        if Instruction::from_char(c, extensions).is_some() {
+            positions.push(position);
+        }
+
+        if c == '\n' {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+    }
+
+    positions
+}
+
+/// Formats the total amount of executed instructions, and the hottest loops of `profile`
+fn format_report(profile: &Profile, positions: Option<&[SourcePosition]>) -> String {
+    let total = profile.total_executed();
+    let hottest = profile.hottest_loops();
+
+    let mut report = format!("\nProfile: {} instructions executed\n", total);
+
+    if hottest.is_empty() {
+        report.push_str("No loops were executed\n");
+        return report;
+    }
+
+    report.push_str(&format!(
+        "{:<32} {:>12} {:>14} {:>7}\n",
+        "Loop", "Iterations", "Instructions", "Share"
+    ));
+
+    for lp in hottest.into_iter().take(REPORTED_LOOPS) {
+        report.push_str(&format!(
+            "{:<32} {:>12} {:>14} {:>6.2}%\n",
+            format_location(lp, positions),
+            lp.iterations,
+            lp.instructions_executed,
+            lp.instructions_executed as f64 * 100.0 / total as f64
+        ));
+    }
+
+    report
+}
+
+/// Formats where `lp` is located, either in the source code or as instruction indices
+fn format_location(lp: &LoopProfile, positions: Option<&[SourcePosition]>) -> String {
+    match positions.and_then(|p| Some((p.get(lp.start)?, p.get(lp.end)?))) {
+        Some((start, end)) => format!(
+            "{}:{} (offset {}..={})",
+            start.line, start.column, start.offset, end.offset
+        ),
+        None => format!("instructions {}..={}", lp.start, lp.end),
+    }
+}
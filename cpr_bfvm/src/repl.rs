@@ -0,0 +1,129 @@
+use std::io::{self, BufRead, Read, Write};
+use std::process::ExitCode;
+
+use cpr_bf::{
+    BrainfuckAllocator, BrainfuckCell, BrainfuckVM, Instruction, MissingKind, ParseError, Program,
+    VMBuilder, VirtualMachine,
+};
+
+/// The amount of cells shown on either side of the data pointer
+const TAPE_WINDOW: usize = 5;
+
+/// Reads Brainfuck snippets from stdin line by line, and runs them on a
+/// persistent VM built from `builder`. Snippets with unclosed loops or
+/// procedure definitions are continued on the next line. Procedures are
+/// only available within the snippet that defines them
+pub(crate) fn run_repl<T, A, R, W>(builder: VMBuilder<T, A, R, W>) -> ExitCode
+where
+    T: BrainfuckCell + 'static,
+    A: BrainfuckAllocator + 'static,
+    R: Read + 'static,
+    W: Write + 'static,
+{
+    let mut vm = builder.build_typed();
+    let mut snippet = String::new();
+
+    loop {
+        print!("{}", if snippet.is_empty() { "bf> " } else { "... " });
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) => {
+                println!();
+                return ExitCode::SUCCESS;
+            }
+            Ok(_) => snippet.push_str(&line),
+            Err(e) => {
+                log::error!("Could not read from stdin: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+
+        let program = match Program::parse_with_extensions(&snippet, vm.extensions()) {
+            Ok(program) => program,
+            Err(ParseError::JumpMismatch(unmatched))
+                if matches!(
+                    unmatched.missing,
+                    MissingKind::JumpBack | MissingKind::ProcEnd
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => {
+                println!("error: {}", e);
+                snippet.clear();
+                continue;
+            }
+        };
+
+        snippet.clear();
+
+        let result = run_snippet(&mut vm, &program);
+
+        // Output of the snippet does not necessarily end with a newline
+        if program.instructions().contains(&Instruction::Output) {
+            println!();
+        }
+
+        if let Err(e) = result {
+            println!("error: {}", e);
+        }
+
+        println!("{}", format_tape(&vm));
+    }
+}
+
+/// Runs `program` on `vm`, keeping the data pointer where the previous snippet left it
+fn run_snippet<T, A, R, W>(
+    vm: &mut VirtualMachine<T, A, R, W>,
+    program: &Program,
+) -> Result<(), cpr_bf::BrainfuckExecutionError>
+where
+    T: BrainfuckCell,
+    A: BrainfuckAllocator,
+    R: Read,
+    W: Write,
+{
+    let data_ptr = vm.state().data_ptr;
+
+    vm.load_program(program)?;
+
+    let mut state = vm.state();
+    state.data_ptr = data_ptr;
+    vm.set_state(state)?;
+
+    vm.resume()
+}
+
+/// Formats the cells around the data pointer, marking the current cell
+fn format_tape<T, A, R, W>(vm: &VirtualMachine<T, A, R, W>) -> String
+where
+    T: BrainfuckCell,
+    A: BrainfuckAllocator,
+    R: Read,
+    W: Write,
+{
+    let state = vm.state();
+    let start = state.data_ptr.saturating_sub(TAPE_WINDOW);
+    let end = state.data_ptr + TAPE_WINDOW + 1;
+
+    let cells: Vec<String> = (start..end)
+        .map(|i| {
+            let cell = state.tape.get(i).copied().unwrap_or_default();
+
+            if i == state.data_ptr {
+                format!("[{:?}]", cell)
+            } else {
+                format!("{:?}", cell)
+            }
+        })
+        .collect();
+
+    format!(
+        "ptr={} | {}",
+        state.data_ptr as isize - state.origin as isize,
+        cells.join(" ")
+    )
+}
@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::{self, Read, Stdout, Write};
+
+#[cfg(feature = "terminal")]
+use ratatui::crossterm::terminal;
+
+/// The byte the terminal sends for Ctrl-C while in raw mode
+const CTRL_C: u8 = 0x03;
+
+/// Keeps the terminal in raw mode for --raw-input, so that every keypress is
+/// passed to the program immediately. Restores the terminal when dropped
+pub(crate) struct RawMode;
+
+impl RawMode {
+    /// Puts the terminal into raw mode if `raw_input` is set. Failures are
+    /// logged, after which the program runs with the terminal unchanged
+    pub(crate) fn enable_if(raw_input: bool) -> Option<RawMode> {
+        if !raw_input {
+            return None;
+        }
+
+        #[cfg(feature = "terminal")]
+        match terminal::enable_raw_mode() {
+            Ok(()) => Some(RawMode),
+            Err(e) => {
+                log::warn!("Could not enable raw terminal input: {}", e);
+                None
+            }
+        }
+
+        #[cfg(not(feature = "terminal"))]
+        {
+            log::warn!(
+                "Raw terminal input requires the terminal feature, which this build does not have"
+            );
+            None
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(feature = "terminal")]
+        if let Err(e) = terminal::disable_raw_mode() {
+            log::error!("Could not restore the terminal: {}", e);
+        }
+    }
+}
+
+/// Reads from stdin, or from the terminal if stdin held the program. In raw mode, the terminal
+/// no longer turns Ctrl-C into a signal, so reading it instead stops the program with an error
+pub(crate) struct TerminalReader {
+    /// Opened once the program first reads its input
+    source: Option<Box<dyn Read>>,
+    from_tty: bool,
+    raw: bool,
+}
+
+impl TerminalReader {
+    /// Reads from stdin, or with `from_tty` from the controlling terminal. If the terminal
+    /// can not be opened, a warning is logged and the program reads an empty input
+    pub(crate) fn new(raw: bool, from_tty: bool) -> Self {
+        TerminalReader {
+            source: None,
+            from_tty,
+            raw,
+        }
+    }
+}
+
+impl Read for TerminalReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let source = self.source.get_or_insert_with(|| open_input(self.from_tty));
+        let num_read = source.read(buf)?;
+
+        if self.raw && buf[..num_read].contains(&CTRL_C) {
+            return Err(io::Error::other("Interrupted by Ctrl-C"));
+        }
+
+        Ok(num_read)
+    }
+}
+
+/// Opens stdin, or the terminal with `from_tty`. See [`TerminalReader::new`]
+fn open_input(from_tty: bool) -> Box<dyn Read> {
+    if !from_tty {
+        return Box::new(io::stdin());
+    }
+
+    match open_tty() {
+        Ok(tty) => Box::new(tty),
+        Err(e) => {
+            log::warn!("Could not open the terminal for input: {}", e);
+            Box::new(io::empty())
+        }
+    }
+}
+
+/// Opens the controlling terminal for reading, regardless of where stdin points to
+fn open_tty() -> io::Result<File> {
+    if cfg!(windows) {
+        File::open("CONIN$")
+    } else if cfg!(unix) {
+        File::open("/dev/tty")
+    } else {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Writes to stdout. In raw mode, the terminal no longer returns the cursor to the
+/// start of the line on a newline, so every newline is written as "\r\n" instead
+pub(crate) struct TerminalWriter {
+    stdout: Stdout,
+    raw: bool,
+}
+
+impl TerminalWriter {
+    pub(crate) fn new(raw: bool) -> Self {
+        TerminalWriter {
+            stdout: io::stdout(),
+            raw,
+        }
+    }
+}
+
+impl Write for TerminalWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.raw {
+            return self.stdout.write(buf);
+        }
+
+        for line in buf.split_inclusive(|&byte| byte == b'\n') {
+            match line.strip_suffix(b"\n") {
+                Some(line) => {
+                    self.stdout.write_all(line)?;
+                    self.stdout.write_all(b"\r\n")?;
+                }
+                None => self.stdout.write_all(line)?,
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
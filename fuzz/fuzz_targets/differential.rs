@@ -0,0 +1,21 @@
+//! Runs arbitrary programs on arbitrary input on every execution backend,
+//! and fails if the backends disagree on the result
+//!
+//! Run with `cargo fuzz run differential` from the root of the repository
+
+#![no_main]
+
+use cpr_bf::{differential::DifferentialTest, Program};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (Program, Vec<u8>)| {
+    let (program, input) = input;
+
+    let test = DifferentialTest::new()
+        .with_max_cells(1 << 12)
+        .with_max_steps(100_000);
+
+    if let Err(mismatch) = test.run(&program, &input) {
+        panic!("{}", mismatch);
+    }
+});
@@ -0,0 +1,41 @@
+//! Runs arbitrary programs on arbitrary input, with every extension they use enabled.
+//! Programs may fail, for example by running out of steps, but the VM must never panic
+//!
+//! Run with `cargo fuzz run run` from the root of the repository
+
+#![no_main]
+
+use std::io;
+
+use arbitrary::Arbitrary;
+use cpr_bf::{ir::OptimizationLevel, BrainfuckVM, EofBehavior, Program, VMBuilder};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    program: Program,
+    input: Vec<u8>,
+    optimize: bool,
+}
+
+fuzz_target!(|input: Input| {
+    let level = match input.optimize {
+        true => OptimizationLevel::Full,
+        false => OptimizationLevel::None,
+    };
+
+    let mut vm = VMBuilder::new()
+        .with_extensions(input.program.extensions())
+        .with_optimization_level(level)
+        .with_eof_behavior(EofBehavior::Zero)
+        .with_max_steps(100_000)
+        .with_max_cells(1 << 16)
+        .with_rng_seed(0)
+        .with_reader(io::empty())
+        .with_writer(io::sink())
+        .build_typed();
+
+    vm.queue_input(&input.input);
+
+    let _ = vm.run_program(&input.program);
+});